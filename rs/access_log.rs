@@ -0,0 +1,765 @@
+//! Structured access logging, separate from diagnostic logging.
+//!
+//! Diagnostics (`log` / `env_logger`) stay on stderr. The access log is
+//! its own stream: one line per proxied request, in one of three
+//! formats, written to stdout, stderr, or a file with size/time based
+//! rotation and optional gzip compression of rotated files.
+//!
+//! * `json`     — one JSON object per line (JSON Lines).
+//! * `common`   — NCSA Common Log Format.
+//! * `combined` — Combined Log Format (CLF + referer + user-agent).
+//! * anything else is a template using `{field}` placeholders, e.g.
+//!   `"{method} {host}{path} {status} {latency_ms}ms {header:user-agent}"`.
+//!
+//! Header values are only captured for names listed in `headers`, and
+//! names in the redaction list are replaced by `[REDACTED]` before they
+//! reach any output.
+
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
+
+/// Headers that are always redacted, on top of user-configured ones.
+pub const DEFAULT_REDACT: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+/// Placeholder written instead of a redacted header value.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Line format of the access log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Common,
+    Combined,
+    /// User template with `{field}` / `{header:name}` placeholders.
+    Template(String),
+}
+
+impl Format {
+    /// `json`, `common` (alias `clf`), `combined`, or a template.
+    pub fn parse(s: &str) -> Format {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "json" | "jsonl" => Format::Json,
+            "common" | "clf" => Format::Common,
+            "combined" => Format::Combined,
+            _ => Format::Template(s.to_string()),
+        }
+    }
+}
+
+/// Where access-log lines go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Stdout,
+    Stderr,
+    File(PathBuf),
+}
+
+impl Output {
+    /// `stdout` / `-`, `stderr`, or a file path.
+    pub fn parse(s: &str) -> Output {
+        match s.trim() {
+            "stdout" | "-" => Output::Stdout,
+            "stderr" => Output::Stderr,
+            p => Output::File(PathBuf::from(p)),
+        }
+    }
+}
+
+/// Time-based rotation period for file output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotateEvery {
+    Hourly,
+    Daily,
+}
+
+impl RotateEvery {
+    /// `hourly` / `daily`; `never` / empty → `None`.
+    pub fn parse(s: &str) -> Result<Option<RotateEvery>, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "never" => Ok(None),
+            "hourly" => Ok(Some(RotateEvery::Hourly)),
+            "daily" => Ok(Some(RotateEvery::Daily)),
+            other => Err(format!("unknown rotation '{}' (expected hourly|daily|never)", other)),
+        }
+    }
+
+    fn period_secs(self) -> u64 {
+        match self {
+            RotateEvery::Hourly => 3600,
+            RotateEvery::Daily => 86_400,
+        }
+    }
+}
+
+/// File rotation settings. Ignored for stdout/stderr output.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    /// Rotate once the file would grow past this many bytes.
+    pub max_bytes: Option<u64>,
+    pub every: Option<RotateEvery>,
+    /// Number of rotated files to keep (older ones are deleted).
+    pub keep: usize,
+    /// Gzip rotated files (`access.log.<stamp>.gz`).
+    pub compress: bool,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self { max_bytes: None, every: None, keep: 7, compress: false }
+    }
+}
+
+/// Parse a byte size like `1048576`, `512K`, `10M`, `1G` (binary units).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let upper = t.to_ascii_uppercase();
+    let upper = upper.trim_end_matches('B');
+    let (num, mult) = match upper.chars().last() {
+        Some('K') => (&upper[..upper.len() - 1], 1u64 << 10),
+        Some('M') => (&upper[..upper.len() - 1], 1u64 << 20),
+        Some('G') => (&upper[..upper.len() - 1], 1u64 << 30),
+        _ => (upper, 1),
+    };
+    num.trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(mult))
+        .ok_or_else(|| format!("invalid size '{}' (expected e.g. 10M)", t))
+}
+
+/// Access-log configuration, assembled from CLI flags.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub format: Format,
+    pub output: Output,
+    /// Request header names (case-insensitive) to capture.
+    pub headers: Vec<String>,
+    /// Extra header names to redact (merged with [`DEFAULT_REDACT`]).
+    pub redact: Vec<String>,
+    pub rotation: Rotation,
+}
+
+/// One request, as handed to the access log.
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub ts: Option<SystemTime>,
+    pub request_id: Option<String>,
+    pub trace_id: Option<String>,
+    pub remote: Option<String>,
+    pub method: String,
+    pub host: String,
+    /// Path and query, as received.
+    pub uri: String,
+    /// `HTTP/1.1`, `HTTP/2.0`, ...
+    pub proto: String,
+    pub status: u16,
+    pub bytes: Option<u64>,
+    pub latency_ms: u64,
+    pub route: Option<String>,
+    pub namespace: Option<String>,
+    pub target: Option<String>,
+    pub error: Option<String>,
+    /// Captured request headers, in configured order (already redacted).
+    pub headers: Vec<(String, String)>,
+}
+
+impl Entry {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The access-log writer. Cheap to share behind an `Arc`; each line is
+/// written under a short mutex so lines never interleave.
+pub struct AccessLog {
+    format: Format,
+    headers: Vec<String>,
+    redact: Vec<String>,
+    sink: Mutex<Sink>,
+    rng: AtomicU64,
+}
+
+enum Sink {
+    Stdout,
+    Stderr,
+    File(RotatingFile),
+}
+
+impl AccessLog {
+    /// Open the configured output. Fails if the log file can't be opened.
+    pub fn open(cfg: Config) -> std::io::Result<AccessLog> {
+        let sink = match cfg.output {
+            Output::Stdout => Sink::Stdout,
+            Output::Stderr => Sink::Stderr,
+            Output::File(path) => Sink::File(RotatingFile::open(path, cfg.rotation)?),
+        };
+        let mut redact: Vec<String> = DEFAULT_REDACT.iter().map(|s| s.to_string()).collect();
+        redact.extend(cfg.redact.iter().map(|s| s.trim().to_ascii_lowercase()));
+        // Combined format always needs these two.
+        let mut headers: Vec<String> = cfg.headers.iter().map(|h| h.trim().to_ascii_lowercase()).collect();
+        if cfg.format == Format::Combined {
+            for h in ["referer", "user-agent"] {
+                if !headers.iter().any(|x| x == h) {
+                    headers.push(h.to_string());
+                }
+            }
+        }
+        if let Format::Template(t) = &cfg.format {
+            for name in template_headers(t) {
+                if !headers.contains(&name) {
+                    headers.push(name);
+                }
+            }
+        }
+        headers.retain(|h| !h.is_empty());
+        let seed = std::collections::hash_map::RandomState::new().build_hasher().finish() | 1;
+        Ok(AccessLog { format: cfg.format, headers, redact, sink: Mutex::new(sink), rng: AtomicU64::new(seed) })
+    }
+
+    /// Whether lines go to stdout (the proxy then keeps its own banner
+    /// off stdout so the stream stays machine-readable).
+    pub fn writes_to_stdout(&self) -> bool {
+        matches!(*self.sink.lock().unwrap_or_else(|p| p.into_inner()), Sink::Stdout)
+    }
+
+    /// Header names (lowercase) the proxy should capture per request.
+    pub fn header_names(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// Capture the configured headers from `lookup`, redacting as needed.
+    pub fn capture_headers<'a>(&self, lookup: impl Fn(&str) -> Option<&'a str>) -> Vec<(String, String)> {
+        self.headers
+            .iter()
+            .filter_map(|name| {
+                let v = lookup(name)?;
+                let v = if self.is_redacted(name) { REDACTED.to_string() } else { v.to_string() };
+                Some((name.clone(), v))
+            })
+            .collect()
+    }
+
+    pub fn is_redacted(&self, header: &str) -> bool {
+        self.redact.iter().any(|r| r.eq_ignore_ascii_case(header))
+    }
+
+    /// Sampling decision for a route's `sample` rate.
+    pub fn sampled(&self, rate: f64) -> bool {
+        if rate >= 1.0 {
+            return true;
+        }
+        if rate <= 0.0 {
+            return false;
+        }
+        // xorshift64 — good enough for sampling, lock-free.
+        let mut x = self.rng.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng.store(x, Ordering::Relaxed);
+        (x >> 11) as f64 / (1u64 << 53) as f64 <= rate
+    }
+
+    /// Format `entry` and write it as one line.
+    pub fn log(&self, entry: &Entry) {
+        let mut line = self.format_line(entry);
+        line.push('\n');
+        let mut sink = self.sink.lock().unwrap_or_else(|p| p.into_inner());
+        let res = match &mut *sink {
+            Sink::Stdout => std::io::stdout().lock().write_all(line.as_bytes()),
+            Sink::Stderr => std::io::stderr().lock().write_all(line.as_bytes()),
+            Sink::File(f) => f.write_line(line.as_bytes()),
+        };
+        if let Err(e) = res {
+            log::warn!("[access-log] write failed: {}", e);
+        }
+    }
+
+    pub fn format_line(&self, e: &Entry) -> String {
+        match &self.format {
+            Format::Json => format_json(e),
+            Format::Common => format_common(e),
+            Format::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                format_common(e),
+                e.header("referer").unwrap_or("-"),
+                e.header("user-agent").unwrap_or("-")
+            ),
+            Format::Template(t) => format_template(t, e),
+        }
+    }
+}
+
+fn format_json(e: &Entry) -> String {
+    let mut obj = Map::new();
+    obj.insert("ts".into(), Value::String(iso_time(e.ts)));
+    obj.insert("request_id".into(), e.request_id.clone().map(Value::String).unwrap_or(Value::Null));
+    obj.insert("trace_id".into(), e.trace_id.clone().map(Value::String).unwrap_or(Value::Null));
+    obj.insert("remote".into(), e.remote.clone().map(Value::String).unwrap_or(Value::Null));
+    obj.insert("method".into(), Value::String(e.method.clone()));
+    obj.insert("host".into(), Value::String(e.host.clone()));
+    obj.insert("uri".into(), Value::String(e.uri.clone()));
+    obj.insert("proto".into(), Value::String(e.proto.clone()));
+    obj.insert("status".into(), Value::from(e.status));
+    obj.insert("bytes".into(), e.bytes.map(Value::from).unwrap_or(Value::Null));
+    obj.insert("latency_ms".into(), Value::from(e.latency_ms));
+    obj.insert("route".into(), e.route.clone().map(Value::String).unwrap_or(Value::Null));
+    obj.insert("namespace".into(), e.namespace.clone().map(Value::String).unwrap_or(Value::Null));
+    obj.insert("target".into(), e.target.clone().map(Value::String).unwrap_or(Value::Null));
+    obj.insert("error".into(), e.error.clone().map(Value::String).unwrap_or(Value::Null));
+    if !e.headers.is_empty() {
+        let h: Map<String, Value> = e.headers.iter().map(|(k, v)| (k.clone(), Value::String(v.clone()))).collect();
+        obj.insert("headers".into(), Value::Object(h));
+    }
+    Value::Object(obj).to_string()
+}
+
+fn format_common(e: &Entry) -> String {
+    format!(
+        "{} - - [{}] \"{} {} {}\" {} {}",
+        e.remote.as_deref().unwrap_or("-"),
+        clf_time(e.ts),
+        e.method,
+        e.uri,
+        e.proto,
+        e.status,
+        e.bytes.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string())
+    )
+}
+
+/// Expand `{field}` / `{header:name}` placeholders. Unknown fields
+/// expand to `-`; `{{` and `}}` are literal braces.
+fn format_template(t: &str, e: &Entry) -> String {
+    let mut out = String::with_capacity(t.len() + 32);
+    let mut chars = t.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                for nc in chars.by_ref() {
+                    if nc == '}' {
+                        break;
+                    }
+                    spec.push(nc);
+                }
+                out.push_str(&template_field(&spec, e));
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn template_field(spec: &str, e: &Entry) -> String {
+    let opt = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+    if let Some(name) = spec.strip_prefix("header:") {
+        return e.header(name).unwrap_or("-").to_string();
+    }
+    match spec {
+        "time" => clf_time(e.ts),
+        "ts" | "time_iso" => iso_time(e.ts),
+        "request_id" => opt(&e.request_id),
+        "trace_id" => opt(&e.trace_id),
+        "remote" => opt(&e.remote),
+        "method" => e.method.clone(),
+        "host" => e.host.clone(),
+        "path" | "uri" => e.uri.clone(),
+        "proto" => e.proto.clone(),
+        "status" => e.status.to_string(),
+        "bytes" => e.bytes.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string()),
+        "latency_ms" => e.latency_ms.to_string(),
+        "route" => opt(&e.route),
+        "namespace" => opt(&e.namespace),
+        "target" => opt(&e.target),
+        "error" => opt(&e.error),
+        _ => "-".to_string(),
+    }
+}
+
+/// Lowercased header names referenced as `{header:name}` in a template.
+fn template_headers(t: &str) -> Vec<String> {
+    t.split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(spec, _)| spec))
+        .filter_map(|spec| spec.strip_prefix("header:"))
+        .map(|h| h.to_ascii_lowercase())
+        .collect()
+}
+
+fn datetime(ts: Option<SystemTime>) -> time::OffsetDateTime {
+    let secs = ts
+        .unwrap_or_else(SystemTime::now)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    time::OffsetDateTime::from_unix_timestamp(secs.as_secs() as i64)
+        .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+        + time::Duration::nanoseconds(secs.subsec_nanos() as i64)
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// `18/Oct/2026:18:12:22 +0000`
+fn clf_time(ts: Option<SystemTime>) -> String {
+    let t = datetime(ts);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        t.day(),
+        MONTHS[u8::from(t.month()) as usize - 1],
+        t.year(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+/// `2026-10-18T18:12:22.123Z`
+pub fn iso_time(ts: Option<SystemTime>) -> String {
+    let t = datetime(ts);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second(),
+        t.millisecond()
+    )
+}
+
+/// `20261018-181222` — suffix for rotated files (sorts chronologically).
+fn file_stamp(ts: SystemTime) -> String {
+    let t = datetime(Some(ts));
+    format!(
+        "{}{:02}{:02}-{:02}{:02}{:02}",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Rotating file
+// ---------------------------------------------------------------------------
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// Start of the current rotation period (Unix secs), if time-rotated.
+    period: Option<u64>,
+    rotation: Rotation,
+    /// Rotated files to compress and prune, one at a time, off the
+    /// request path.
+    rotated: mpsc::Sender<PathBuf>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, rotation: Rotation) -> std::io::Result<RotatingFile> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        let period = rotation.every.map(|e| now_secs() / e.period_secs());
+        let (rotated, queue) = mpsc::channel::<PathBuf>();
+        let (base, housekeeping) = (path.clone(), rotation.clone());
+        // Ends when the file is dropped, after what's queued.
+        std::thread::spawn(move || {
+            for file in queue {
+                if housekeeping.compress
+                    && let Err(e) = compress_file(&file)
+                {
+                    log::warn!("[access-log] compress {} failed: {}", file.display(), e);
+                }
+                prune_rotated(&base, housekeeping.keep);
+            }
+        });
+        Ok(RotatingFile { path, file, size, period, rotation, rotated })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let period_changed = match (self.rotation.every, self.period) {
+            (Some(every), Some(p)) => now_secs() / every.period_secs() != p,
+            _ => false,
+        };
+        let too_big = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| self.size > 0 && self.size + line.len() as u64 > max);
+        if period_changed || too_big {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        let mut rotated = sibling(&self.path, &file_stamp(SystemTime::now()));
+        // Two rotations within the same second: add a counter.
+        let mut n = 1;
+        while rotated.exists() || gz_path(&rotated).exists() {
+            rotated = sibling(&self.path, &format!("{}-{}", file_stamp(SystemTime::now()), n));
+            n += 1;
+        }
+        std::fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.period = self.rotation.every.map(|e| now_secs() / e.period_secs());
+        let _ = self.rotated.send(rotated);
+        Ok(())
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".gz");
+    path.with_file_name(name)
+}
+
+/// Gzip `path` to `path.gz`, through a `.gz.tmp` that
+/// [`rotated_files`] doesn't list until it's complete.
+fn compress_file(path: &Path) -> std::io::Result<()> {
+    let mut input = File::open(path)?;
+    let (gz, tmp) = (gz_path(path), sibling(&gz_path(path), "tmp"));
+    let mut enc = flate2::write::GzEncoder::new(File::create(&tmp)?, flate2::Compression::default());
+    std::io::copy(&mut input, &mut enc)?;
+    enc.finish()?;
+    std::fs::rename(&tmp, &gz)?;
+    std::fs::remove_file(path)
+}
+
+/// Rotated siblings of `path` (`<name>.<stamp>[.gz]`, not a `.gz.tmp`
+/// still being written), oldest first.
+fn rotated_files(path: &Path) -> Vec<PathBuf> {
+    let Some(dir) = path.parent().map(|d| if d.as_os_str().is_empty() { Path::new(".") } else { d }) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|rd| {
+            rd.filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.file_name()
+                        .map(|n| n.to_string_lossy())
+                        .is_some_and(|n| n.starts_with(&prefix) && !n.ends_with(".tmp"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // Oldest first: by stamp, then by the counter a second rotation
+    // within the same second adds (`{stamp}-{n}`).
+    files.sort_by_cached_key(|p| {
+        let name = p.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let rest = name[prefix.len()..].trim_end_matches(".gz");
+        match rest.rsplit_once('-').filter(|(stamp, _)| stamp.contains('-')) {
+            Some((stamp, n)) => (stamp.to_string(), n.parse::<u32>().unwrap_or(0)),
+            None => (rest.to_string(), 0),
+        }
+    });
+    files
+}
+
+fn prune_rotated(path: &Path, keep: usize) {
+    let files = rotated_files(path);
+    if files.len() > keep {
+        for old in &files[..files.len() - keep] {
+            let _ = std::fs::remove_file(old);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn entry() -> Entry {
+        Entry {
+            ts: Some(UNIX_EPOCH + Duration::from_millis(1_792_346_981_923)),
+            request_id: Some("req-1".into()),
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".into()),
+            remote: Some("127.0.0.1".into()),
+            method: "GET".into(),
+            host: "3000.fbi.com".into(),
+            uri: "/api?x=1".into(),
+            proto: "HTTP/1.1".into(),
+            status: 200,
+            bytes: Some(12),
+            latency_ms: 3,
+            route: Some("port-as-host".into()),
+            namespace: Some("default".into()),
+            target: Some("localhost:3000".into()),
+            error: None,
+            headers: vec![("user-agent".into(), "curl/8".into())],
+        }
+    }
+
+    fn log_with(format: Format, headers: &[&str]) -> AccessLog {
+        AccessLog::open(Config {
+            format,
+            output: Output::Stderr,
+            headers: headers.iter().map(|s| s.to_string()).collect(),
+            redact: vec!["x-secret".into()],
+            rotation: Rotation::default(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn common_and_combined_formats() {
+        let log = log_with(Format::Common, &[]);
+        assert_eq!(
+            log.format_line(&entry()),
+            "127.0.0.1 - - [18/Oct/2026:18:09:41 +0000] \"GET /api?x=1 HTTP/1.1\" 200 12"
+        );
+        let log = log_with(Format::Combined, &[]);
+        assert!(log.format_line(&entry()).ends_with("200 12 \"-\" \"curl/8\""));
+        assert_eq!(log.header_names(), ["referer", "user-agent"]);
+    }
+
+    #[test]
+    fn json_format_has_all_fields() {
+        let log = log_with(Format::Json, &["user-agent"]);
+        let v: Value = serde_json::from_str(&log.format_line(&entry())).unwrap();
+        assert_eq!(v["ts"], "2026-10-18T18:09:41.923Z");
+        assert_eq!(v["route"], "port-as-host");
+        assert_eq!(v["request_id"], "req-1");
+        assert_eq!(v["status"], 200);
+        assert_eq!(v["error"], Value::Null);
+        assert_eq!(v["headers"]["user-agent"], "curl/8");
+    }
+
+    #[test]
+    fn template_format_expands_fields_and_headers() {
+        let log = log_with(Format::parse("{method} {host}{path} {status} {{{latency_ms}ms}} {header:User-Agent} {nope}"), &[]);
+        assert_eq!(log.format_line(&entry()), "GET 3000.fbi.com/api?x=1 200 {3ms} curl/8 -");
+        assert_eq!(log.header_names(), ["user-agent"]);
+    }
+
+    #[test]
+    fn capture_headers_redacts_sensitive_values() {
+        let log = log_with(Format::Json, &["Authorization", "X-Secret", "Accept"]);
+        let got = log.capture_headers(|name| match name {
+            "authorization" => Some("Bearer abc"),
+            "x-secret" => Some("s3cr3t"),
+            "accept" => Some("*/*"),
+            _ => None,
+        });
+        assert_eq!(
+            got,
+            vec![
+                ("authorization".to_string(), REDACTED.to_string()),
+                ("x-secret".to_string(), REDACTED.to_string()),
+                ("accept".to_string(), "*/*".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn sampling_bounds() {
+        let log = log_with(Format::Json, &[]);
+        assert!(log.sampled(1.0));
+        assert!(!log.sampled(0.0));
+        let hits = (0..10_000).filter(|_| log.sampled(0.25)).count();
+        assert!((2000..3000).contains(&hits), "hits={hits}");
+    }
+
+    #[test]
+    fn parse_helpers() {
+        assert_eq!(parse_size("10M").unwrap(), 10 << 20);
+        assert_eq!(parse_size("512k").unwrap(), 512 << 10);
+        assert_eq!(parse_size("2GB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("18446744073709551615G").is_err());
+        assert_eq!(Output::parse("-"), Output::Stdout);
+        assert_eq!(Output::parse("/var/log/x.log"), Output::File("/var/log/x.log".into()));
+        assert_eq!(RotateEvery::parse("daily").unwrap(), Some(RotateEvery::Daily));
+        assert!(RotateEvery::parse("weekly").is_err());
+        assert_eq!(Format::parse("CLF"), Format::Common);
+    }
+
+    #[test]
+    fn size_rotation_compresses_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("fbi-access-log-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("access.log");
+        let log = AccessLog::open(Config {
+            format: Format::Common,
+            output: Output::File(path.clone()),
+            headers: vec![],
+            redact: vec![],
+            rotation: Rotation { max_bytes: Some(10), every: None, keep: 1, compress: true },
+        })
+        .unwrap();
+        // A compression still being written is not a rotated file.
+        let partial = dir.join("access.log.20000101-000000.gz.tmp");
+        std::fs::write(&partial, "").unwrap();
+
+        // Rotations within one second get a counter; the newest is kept.
+        let mut kept = Vec::new();
+        let mut previous = Vec::new();
+        for _ in 0..3 {
+            previous = kept.clone();
+            log.log(&entry());
+            // Wait for the background compress/prune to settle.
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                kept = rotated_files(&path);
+                if kept.len() <= 1 && kept.iter().all(|p| p.to_string_lossy().ends_with(".gz")) {
+                    break;
+                }
+                assert!(Instant::now() < deadline, "rotation did not settle: {kept:?}");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        assert_eq!(kept.len(), 1, "{kept:?}");
+        assert_ne!(kept, previous);
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(current.lines().count(), 1);
+        assert!(partial.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
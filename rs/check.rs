@@ -0,0 +1,548 @@
+//! Offline validation of routes files, for `fbi-proxy check`.
+//!
+//! [`check`] loads a list of sources the way the server merges them —
+//! each compiled under its own namespace, in order — but instead of
+//! stopping at the first problem it collects every parse error, unknown
+//! or deprecated key, compile error, failing route test and lint finding
+//! as a [`Diagnostic`] pinned
+//! to `file:line:col`, so editors and CI logs can jump straight to it.
+//!
+//! The parsers report positions for parse errors. Compile errors and test
+//! failures only name a rule or a test index, so those are located by
+//! scanning the source text for the rule's `name:` entry (and the field
+//! at fault within it) or the n-th item under `tests:`. The scan
+//! understands the block-style YAML routes files are written in; for
+//! anything it can't place (including everything in JSON and TOML files)
+//! it falls back to the enclosing rule, then to the top of the file.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::include;
+use crate::lint;
+use crate::routes::{self, CompileError, CompiledRoute};
+use crate::schema::{self, FindingKind, Format, Step};
+
+/// One routes file to check.
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// Shown in diagnostics; `include:` entries resolve relative to it.
+    pub path: String,
+    /// Namespace its rules compile under (the conf.d file stem).
+    pub namespace: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem at a 1-based `line` / `col` of `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}: {}", self.path, self.line, self.col, self.severity.as_str(), self.message)
+    }
+}
+
+/// Result of [`check`].
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Every rule that compiled, in evaluation order.
+    pub routes: Vec<CompiledRoute>,
+    /// Errors first (in source order), then schema and lint warnings.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Error).count()
+    }
+}
+
+/// Parse, compile, test and lint `sources` as one merged rule set.
+/// Unknown keys are warnings, or errors when `strict`.
+///
+/// Route tests only run when every source parsed and compiled: against
+/// a partial set they would fail for the wrong reason. Lint runs on
+/// whatever compiled.
+pub fn check(sources: &[Source], domain_filter: Option<&str>, strict: bool) -> Report {
+    let mut report = Report::default();
+    let mut warnings = Vec::new();
+    let mut files = Vec::with_capacity(sources.len());
+    for src in sources {
+        let format = Format::from_path(Path::new(&src.path)).unwrap_or(Format::Yaml);
+        let file = match schema::parse(&src.text, format) {
+            Ok(parsed) => {
+                for f in parsed.findings {
+                    if strict && f.kind == FindingKind::UnknownKey {
+                        report.diagnostics.push(src.diagnostic((f.line, f.col), Severity::Error, f.message));
+                    } else {
+                        warnings.push(src.diagnostic((f.line, f.col), Severity::Warning, f.message));
+                    }
+                }
+                parsed.file
+            }
+            Err(e) => {
+                report.diagnostics.push(src.diagnostic(e.at.unwrap_or((1, 1)), Severity::Error, e.message));
+                continue;
+            }
+        };
+        let file = match include::resolve(file, Path::new(&src.path), strict) {
+            Ok(f) => f,
+            Err(e) => {
+                let at = locate_top_key(&lines(&src.text), "include");
+                report.diagnostics.push(src.diagnostic(at, Severity::Error, e));
+                continue;
+            }
+        };
+        for r in &file.routes {
+            match file.rule(r).and_then(|cfg| routes::compile_in_namespace(vec![cfg], &src.namespace)) {
+                Ok(compiled) => report.routes.extend(compiled),
+                Err(e) => {
+                    let at = locate_compile_error(&src.text, &e);
+                    report.diagnostics.push(src.diagnostic(at, Severity::Error, e.to_string()));
+                }
+            }
+        }
+        files.push((src, file));
+    }
+    for (src, file) in &files {
+        let names: Vec<&str> = file.disable_defaults.iter().map(String::as_str).collect();
+        for name in routes::disable_defaults(&mut report.routes, &names) {
+            let at = locate_top_key(&lines(&src.text), "disable_defaults");
+            let message = format!("disable_defaults: no bundled rule named {}", name);
+            report.diagnostics.push(src.diagnostic(at, Severity::Error, message));
+        }
+    }
+    routes::sort_by_priority(&mut report.routes);
+
+    if report.diagnostics.is_empty() {
+        for (src, file) in &files {
+            let tests = file.tests.iter().enumerate().map(|(i, t)| (src.path.as_str(), i, t));
+            for f in routes::run_tests(&report.routes, tests, domain_filter) {
+                let at = locate_test(&src.text, f.index);
+                let message = format!("test #{} ({}): {}", f.index + 1, f.request, f.reason);
+                report.diagnostics.push(src.diagnostic(at, Severity::Error, message));
+            }
+        }
+    }
+
+    report.diagnostics.extend(warnings);
+    for l in lint::lint(&report.routes) {
+        let found = sources
+            .iter()
+            .filter(|s| s.namespace == l.namespace)
+            .find_map(|s| locate_route(&lines(&s.text), &l.route).map(|item| (s, (item.name + 1, item.col + 1))));
+        if let Some((src, at)) = found {
+            report.diagnostics.push(src.diagnostic(at, Severity::Warning, l.to_string()));
+        }
+    }
+    report
+}
+
+impl Source {
+    fn diagnostic(&self, (line, col): (usize, usize), severity: Severity, message: String) -> Diagnostic {
+        Diagnostic { path: self.path.clone(), line, col, severity, message }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Locating rules and tests in source text
+// ---------------------------------------------------------------------------
+
+fn lines(text: &str) -> Vec<&str> {
+    text.lines().collect()
+}
+
+/// A `key: value` line, possibly a list item (`- key: value`).
+struct KeyLine<'a> {
+    /// 0-based byte column of the key.
+    col: usize,
+    key: &'a str,
+    /// 0-based byte column where the value starts.
+    value_col: usize,
+    value: &'a str,
+    item: bool,
+}
+
+fn key_line(line: &str) -> Option<KeyLine<'_>> {
+    let rest = line.trim_start_matches(' ');
+    let mut col = line.len() - rest.len();
+    let mut rest = rest;
+    let mut item = false;
+    if let Some(r) = rest.strip_prefix('-').filter(|r| r.is_empty() || r.starts_with(' ')) {
+        item = true;
+        let trimmed = r.trim_start_matches(' ');
+        col += 1 + r.len() - trimmed.len();
+        rest = trimmed;
+    }
+    let colon = rest.find(':')?;
+    let key = &rest[..colon];
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return None;
+    }
+    let after = &rest[colon + 1..];
+    if !(after.is_empty() || after.starts_with(' ')) {
+        return None;
+    }
+    let value = after.trim_start_matches(' ');
+    Some(KeyLine { col, key, value_col: col + colon + 1 + after.len() - value.len(), value: value.trim_end(), item })
+}
+
+fn indent(line: &str) -> Option<usize> {
+    let t = line.trim_start();
+    (!t.is_empty() && !t.starts_with('#')).then(|| line.len() - t.len())
+}
+
+/// 1-based line/col for a 0-based line index and byte column.
+fn pos(lines: &[&str], line: usize, byte_col: usize) -> (usize, usize) {
+    let text = lines.get(line).copied().unwrap_or("");
+    (line + 1, text.get(..byte_col).map_or(byte_col, |s| s.chars().count()) + 1)
+}
+
+/// 1-based position of a top-level key, or the file start.
+fn locate_top_key(lines: &[&str], key: &str) -> (usize, usize) {
+    lines
+        .iter()
+        .position(|l| indent(l) == Some(0) && key_line(l).is_some_and(|k| k.key == key && !k.item))
+        .map_or((1, 1), |i| (i + 1, 1))
+}
+
+/// 1-based position of what `path` (e.g. `routes[0].header`) names: the
+/// key or list item itself, else the deepest part of the path found.
+/// Flow-style (`{ a: b }`) values aren't looked into.
+pub(crate) fn locate_path(text: &str, path: &[Step]) -> (usize, usize) {
+    let lines = lines(text);
+    let mut at = (1, 1);
+    // The block the next step looks in.
+    let (mut from, mut to) = (0, lines.len());
+    for step in path {
+        match step {
+            Step::Key(key) => {
+                let mut keys = (from..to).filter_map(|i| key_line(lines[i]).map(|k| (i, k)));
+                let Some(col) = keys.next().map(|(_, k)| k.col) else { break };
+                let Some((i, k)) = (from..to)
+                    .filter_map(|i| key_line(lines[i]).map(|k| (i, k)))
+                    .find(|(_, k)| k.key == key && k.col == col)
+                else {
+                    break;
+                };
+                at = pos(&lines, i, k.col);
+                // A list may sit at its key's own indent.
+                let is_item = |l: &str| l.trim_start().strip_prefix('-').is_some_and(|r| r.is_empty() || r.starts_with(' '));
+                from = i + 1;
+                to = (from..to)
+                    .find(|&j| indent(lines[j]).is_some_and(|n| n < k.col || (n == k.col && !is_item(lines[j]))))
+                    .unwrap_or(to);
+            }
+            Step::Index(index) => {
+                let items: Vec<(usize, usize)> = (from..to)
+                    .filter_map(|i| {
+                        let trimmed = lines[i].trim_start();
+                        let rest = trimmed.strip_prefix('-').filter(|r| r.is_empty() || r.starts_with(' '))?;
+                        Some((i, lines[i].len() - trimmed.len(), lines[i].len() - rest.trim_start().len()))
+                    })
+                    .scan(None, |dash, (i, d, content)| Some((*dash.get_or_insert(d) == d).then_some((i, content))))
+                    .flatten()
+                    .collect();
+                let Some(&(i, content)) = items.get(*index) else { break };
+                at = pos(&lines, i, content);
+                from = i;
+                to = items.get(index + 1).map_or(to, |&(j, _)| j);
+            }
+        }
+    }
+    at
+}
+
+/// Line span of one rule under the top-level `routes:` key.
+struct RouteItem {
+    /// First line of the list item.
+    start: usize,
+    /// Line of its `name:` key.
+    name: usize,
+    /// One past its last line.
+    end: usize,
+    /// Column of its keys.
+    col: usize,
+}
+
+fn locate_route(lines: &[&str], route: &str) -> Option<RouteItem> {
+    let mut in_routes = false;
+    for (i, line) in lines.iter().enumerate() {
+        if indent(line) == Some(0) && !line.starts_with('-') {
+            in_routes = key_line(line).is_some_and(|k| k.key == "routes" && !k.item);
+            continue;
+        }
+        let Some(k) = key_line(line).filter(|k| in_routes && k.key == "name") else { continue };
+        if k.value.trim_matches(|c| c == '"' || c == '\'') != route {
+            continue;
+        }
+        let start = (0..=i)
+            .rev()
+            .find(|&j| key_line(lines[j]).is_some_and(|o| o.item && o.col == k.col))
+            .unwrap_or(i);
+        let end = (i + 1..lines.len())
+            .find(|&j| match indent(lines[j]) {
+                Some(n) if n < k.col => true,
+                Some(_) => key_line(lines[j]).is_some_and(|o| o.item && o.col == k.col),
+                None => false,
+            })
+            .unwrap_or(lines.len());
+        return Some(RouteItem { start, name: i, end, col: k.col });
+    }
+    None
+}
+
+/// Where within a rule a compile error points: the keys that may hold
+/// the offending text (first found wins) and the text itself, if known.
+fn compile_error_anchor(e: &CompileError) -> (&str, Vec<String>, Option<String>) {
+    let for_location = |location: &str| match location {
+        "match pattern" => vec!["match".to_string()],
+        "target template" => vec!["target".to_string(), "proxy".to_string()],
+        "path" => vec!["path".to_string()],
+        l => match l.strip_prefix("header '").and_then(|h| h.strip_suffix('\'')) {
+            Some(h) => vec![h.to_string(), "headers".to_string()],
+            None => vec![],
+        },
+    };
+    match e {
+        CompileError::InvalidPlaceholder { route, placeholder, .. } => {
+            (route, vec!["match".into()], Some(format!("{{{}}}", placeholder)))
+        }
+        CompileError::UnknownKind { route, name, kind } => (route, vec!["match".into()], Some(format!("{{{}:{}}}", name, kind))),
+        CompileError::DuplicatePlaceholder { route, name } => (route, vec!["match".into()], Some(format!("{{{}", name))),
+        CompileError::InvalidRegex { route, .. } => (route, vec!["match".into()], None),
+        CompileError::UndeclaredPlaceholder { route, name, location } => {
+            (route, for_location(location), Some(format!("{{{}", name)))
+        }
+        CompileError::UnbalancedBraces { route, location } => (route, for_location(location), None),
+        CompileError::UndefinedVariable { route, name, location } => {
+            (route, for_location(location), Some(format!("${{{}", name)))
+        }
+        CompileError::UnresolvedSecret { route, reference, location, .. } => {
+            (route, for_location(location), Some(reference.clone()))
+        }
+        CompileError::InvalidField { route, field, .. } => {
+            // `access_log.sample` → the nested `sample:`, else `access_log:`.
+            let mut keys: Vec<String> = field.rsplit('.').map(String::from).collect();
+            if keys == ["target"] {
+                keys.push("proxy".into());
+            }
+            keys.dedup();
+            (route, keys, None)
+        }
+    }
+}
+
+/// Position of a compile error: the offending placeholder if it can be
+/// found, else the field's value, else the rule's `name:`.
+fn locate_compile_error(text: &str, e: &CompileError) -> (usize, usize) {
+    let lines = lines(text);
+    let (route, keys, needle) = compile_error_anchor(e);
+    let Some(item) = locate_route(&lines, route) else { return (1, 1) };
+    let mut fallback = None;
+    for key in &keys {
+        let Some((i, k)) = (item.start..item.end)
+            .filter_map(|i| key_line(lines[i]).map(|k| (i, k)))
+            .find(|(_, k)| k.key == key)
+        else {
+            continue;
+        };
+        // The value may continue on deeper-indented lines (block maps,
+        // folded strings).
+        let block_end = (i + 1..item.end)
+            .find(|&j| indent(lines[j]).is_some_and(|n| n <= k.col))
+            .unwrap_or(item.end);
+        if let Some(needle) = &needle {
+            for j in i..block_end {
+                let from = if j == i { k.value_col } else { 0 };
+                if let Some(off) = lines[j].get(from..).and_then(|s| s.find(needle.as_str())) {
+                    return pos(&lines, j, from + off);
+                }
+            }
+        }
+        fallback.get_or_insert(pos(&lines, i, k.value_col));
+    }
+    fallback.unwrap_or_else(|| pos(&lines, item.name, item.col))
+}
+
+/// Position of the `index`-th entry under the top-level `tests:` key.
+fn locate_test(text: &str, index: usize) -> (usize, usize) {
+    let lines = lines(text);
+    let Some(tests) = lines.iter().position(|l| indent(l) == Some(0) && key_line(l).is_some_and(|k| k.key == "tests" && !k.item))
+    else {
+        return (1, 1);
+    };
+    let mut item_col = None;
+    let mut seen = 0;
+    for (i, line) in lines.iter().enumerate().skip(tests + 1) {
+        if indent(line) == Some(0) && !line.starts_with('-') {
+            break;
+        }
+        let trimmed = line.trim_start();
+        if !(trimmed == "-" || trimmed.starts_with("- ")) {
+            continue;
+        }
+        let dash = line.len() - trimmed.len();
+        if *item_col.get_or_insert(dash) != dash {
+            continue;
+        }
+        if seen == index {
+            let content = trimmed[1..].trim_start();
+            return pos(&lines, i, line.len() - content.len());
+        }
+        seen += 1;
+    }
+    pos(&lines, tests, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(path: &str, text: &str) -> Source {
+        let namespace = path.trim_end_matches(".yaml").to_string();
+        Source { path: path.into(), namespace, text: text.into() }
+    }
+
+    fn bundled() -> Source {
+        Source { path: "routes.yaml".into(), namespace: "default".into(), text: include_str!("../routes.yaml").into() }
+    }
+
+    fn render(report: &Report) -> Vec<String> {
+        report.diagnostics.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn reports_every_compile_error_with_its_position() {
+        let text = "\
+routes:
+  - name: web
+    match: \"{name:foo}.{domain}\"
+    target: localhost:3000
+  - match: api.{domain}
+    name: api
+    target: \"localhost:{port}\"
+  - name: logs
+    match: logs.{domain}
+    target: localhost:4000
+    headers:
+      X-Env: \"{env}\"
+  - name: sampled
+    match: s.{domain}
+    target: localhost:5000
+    access_log: { sample: 2 }
+";
+        let report = check(&[bundled(), source("app.yaml", text)], Some("fbi.com"), false);
+        let out = render(&report);
+        assert_eq!(report.errors(), 4, "{out:#?}");
+        assert!(out[0].starts_with("app.yaml:3:13: error: route 'web': unknown placeholder kind ':foo'"), "{out:#?}");
+        assert!(out[1].starts_with("app.yaml:7:24: error: route 'api': placeholder '{port}'"), "{out:#?}");
+        assert!(out[2].starts_with("app.yaml:12:15: error: route 'logs': placeholder '{env}'"), "{out:#?}");
+        assert!(out[3].starts_with("app.yaml:16:17: error: route 'sampled': invalid access_log.sample"), "{out:#?}");
+        // The rules that compiled are still reported.
+        assert!(report.routes.iter().any(|r| r.namespace == "default"));
+    }
+
+    #[test]
+    fn undefined_variables_and_bad_includes_are_located() {
+        let text = "vars:\n  port: \"3000\"\nroutes:\n  - name: web\n    match:\n      host: app.{domain}\n    action:\n      proxy: \"${FBI_PROXY_TEST_HOST}:${port}\"\n";
+        let report = check(&[source("app.yaml", text)], None, false);
+        assert_eq!(
+            render(&report),
+            vec!["app.yaml:8:15: error: route 'web': ${FBI_PROXY_TEST_HOST} in target template is not set (no `vars:` entry, environment variable or `:-` default)".to_string()]
+        );
+        let report = check(&[source("inc.yaml", "routes: []\ninclude: [fbi-proxy-test-missing.yaml]\n")], None, false);
+        let out = render(&report);
+        assert!(out[0].starts_with("inc.yaml:2:1: error: include fbi-proxy-test-missing.yaml: read: "), "{out:#?}");
+    }
+
+    #[test]
+    fn parse_errors_keep_serde_positions_and_other_files_still_check() {
+        let bad = "routes:\n  - name: web\n    matc: x\n    target: y\n";
+        let good = "routes:\n  - name: web\n    match: { host: \"app.{domain}\", path: / }\n    action: { proxy: localhost:3000 }\n";
+        let report = check(&[bundled(), source("bad.yaml", bad), source("good.yaml", good)], None, false);
+        assert_eq!(
+            render(&report),
+            vec!["bad.yaml:2:5: error: routes[0]: missing field `match`".to_string()]
+        );
+        assert!(report.routes.iter().any(|r| r.namespace == "good"));
+    }
+
+    #[test]
+    fn failing_tests_point_at_their_entry_and_lints_at_the_rule() {
+        let text = "\
+routes:
+  - name: echo
+    match: \"echo.{domain}\"
+    target: localhost:3777
+tests:
+  - host: fbi.com
+    expect: landing
+  -   host: echo.fbi.com
+      expect: { route: echo }
+";
+        let report = check(&[bundled(), source("app.yaml", text)], Some("fbi.com"), false);
+        let out = render(&report);
+        assert_eq!(out.len(), 3, "{out:#?}");
+        assert!(
+            out[0].starts_with("app.yaml:8:7: error: test #2 (GET echo.fbi.com/): expected route 'echo'"),
+            "{out:#?}"
+        );
+        assert!(out[1].starts_with("app.yaml:1:1: warning: routes file version 1 is deprecated;"), "{out:#?}");
+        assert!(out[2].starts_with("app.yaml:2:5: warning: app/echo: shadowed:"), "{out:#?}");
+        assert_eq!(report.errors(), 1);
+    }
+
+    #[test]
+    fn unknown_keys_are_located_and_fail_only_in_strict_mode() {
+        let text = "\
+version: 2
+routes:
+  - name: web
+    match:
+      host: app.{domain}
+    action:
+      proxy: localhost:3000
+      header:
+        X-Env: dev
+tests:
+  - host: app.fbi.com
+    expect: { route: web }
+";
+        let lenient = check(&[source("app.yaml", text)], Some("fbi.com"), false);
+        assert_eq!(render(&lenient), vec!["app.yaml:8:7: warning: unknown key `routes[0].action.header`".to_string()]);
+        assert!(!lenient.routes.is_empty());
+        let strict = check(&[source("app.yaml", text)], Some("fbi.com"), true);
+        assert_eq!(render(&strict), vec!["app.yaml:8:7: error: unknown key `routes[0].action.header`".to_string()]);
+    }
+
+    #[test]
+    fn bundled_defaults_check_clean() {
+        let report = check(&[bundled()], Some("fbi.com"), false);
+        assert!(report.diagnostics.is_empty(), "{:#?}", report.diagnostics);
+        let parsed = routes::parse_yaml(include_str!("../routes.yaml")).unwrap();
+        assert_eq!(report.routes.len(), parsed.routes.len());
+    }
+}
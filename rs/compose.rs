@@ -0,0 +1,535 @@
+//! Compose-style rule management for the `up` / `down` / `ps` / `config`
+//! subcommands.
+//!
+//! A project ships an `fbi-proxy.yaml` whose top-level `name` is its
+//! namespace. The running proxy publishes its ephemeral admin port to
+//! `runtime.json` (next to conf.d); [`read_runtime`] finds it and
+//! [`AdminClient`] drives the `/rules` endpoints over loopback HTTP.
+//! This mirrors `ts/rulesCli.ts` so the standalone binary can manage its
+//! own rules; when no proxy is running the binary falls back to editing
+//! conf.d fragments directly.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde::{Deserialize, Serialize};
+
+use crate::confd::Status;
+use crate::lint::Lint;
+use crate::route_stats::RouteCounters;
+use crate::routes::{self, CompiledRoute, RouteConfig, RouteTest, RoutesFile};
+use crate::unix_ms;
+
+pub const DEFAULT_FILE: &str = "fbi-proxy.yaml";
+
+/// Admin API calls are loopback-only; anything slower is a wedged proxy.
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A project's `fbi-proxy.yaml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComposeFile {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub priority: Option<i32>,
+    #[serde(default)]
+    pub disable_defaults: Vec<String>,
+    /// Expanded by the proxy; a `${NAME}` they don't define is taken
+    /// from this process's environment (the admin API doesn't read the
+    /// proxy's).
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    #[serde(default)]
+    pub tests: Vec<RouteTest>,
+}
+
+impl ComposeFile {
+    /// The fragment `up` applies: everything but `name`, with `vars`
+    /// completed from the environment (or the `:-` default) for `match`,
+    /// `path` and `target`.
+    pub fn to_routes_file(&self) -> RoutesFile {
+        let mut vars = self.vars.clone();
+        let templates = self.routes.iter().flat_map(|r| [Some(&r.r#match), r.path.as_ref(), Some(&r.target)].into_iter().flatten());
+        for (name, default) in templates.flat_map(|t| routes::var_refs(t)) {
+            if !vars.contains_key(name)
+                && let Some(value) = std::env::var(name).ok().or_else(|| default.map(String::from))
+            {
+                vars.insert(name.to_string(), value);
+            }
+        }
+        RoutesFile {
+            version: 1,
+            priority: self.priority,
+            disable_defaults: self.disable_defaults.clone(),
+            vars,
+            include: Vec::new(),
+            routes: self.routes.clone(),
+            tests: self.tests.clone(),
+        }
+    }
+}
+
+/// Read and validate a compose file. Rules are compiled up front so a
+/// typo is reported against the file rather than by the admin API.
+pub fn load_compose(file: &Path) -> Result<ComposeFile, String> {
+    let src = match std::fs::read_to_string(file) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "{} not found. Create one (compose-style):\n  name: my-app\n  routes:\n    - name: web\n      match: fbi.com\n      path: /\n      target: localhost:3000",
+                file.display()
+            ));
+        }
+        Err(e) => return Err(format!("read {}: {}", file.display(), e)),
+    };
+    let compose: ComposeFile = serde_yaml::from_str(&src).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let fragment = compose.to_routes_file();
+    for r in &fragment.routes {
+        fragment.rule(r).and_then(|r| routes::compile(vec![r])).map_err(|e| format!("{}: {}", file.display(), e))?;
+    }
+    Ok(compose)
+}
+
+/// The namespace: explicit `-p` > compose `name` > the compose file's
+/// directory name (like docker compose).
+pub fn resolve_namespace(explicit: Option<&str>, compose_name: Option<&str>, file: &Path) -> String {
+    if let Some(ns) = explicit.or(compose_name).filter(|s| !s.is_empty()) {
+        return ns.to_string();
+    }
+    let abs = std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+    abs.parent()
+        .and_then(|d| d.file_name())
+        .and_then(|n| n.to_str())
+        .unwrap_or("default")
+        .to_string()
+}
+
+/// What the running proxy publishes to `runtime.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeInfo {
+    pub admin_port: u16,
+    pub proxy_port: u16,
+    pub pid: u32,
+    pub conf_dir: PathBuf,
+}
+
+/// `runtime.json` sits beside conf.d.
+pub fn runtime_json_path(conf_dir: &Path) -> PathBuf {
+    conf_dir.parent().unwrap_or_else(|| Path::new(".")).join("runtime.json")
+}
+
+/// The running proxy's runtime info, or `None` if none has published
+/// one for `conf_dir`.
+pub fn read_runtime(conf_dir: &Path) -> Result<Option<RuntimeInfo>, String> {
+    let path = runtime_json_path(conf_dir);
+    let src = match std::fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("read {}: {}", path.display(), e)),
+    };
+    serde_json::from_str(&src).map(Some).map_err(|e| format!("could not parse {}: {}", path.display(), e))
+}
+
+/// A rule as reported by `GET /rules`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RuleInfo {
+    pub namespace: String,
+    pub name: String,
+    #[serde(rename = "match")]
+    pub match_pattern: String,
+    pub path: Option<String>,
+    pub target: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub hits: u64,
+    #[serde(default)]
+    pub errors: u64,
+    #[serde(default)]
+    pub last_hit_ms: Option<u64>,
+    #[serde(default)]
+    pub last_status: Option<u16>,
+    /// Evaluation-order priority; omitted when 0.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// Served from the namespace's last-good fragment because the one on
+    /// disk fails to load (see `GET /status`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+}
+
+impl RuleInfo {
+    /// The `GET /rules` entry for `route` with `counters`.
+    pub fn new(route: &CompiledRoute, counters: &RouteCounters) -> Self {
+        RuleInfo {
+            namespace: route.namespace.clone(),
+            name: route.name.clone(),
+            match_pattern: route.match_pattern.clone(),
+            path: route.path_prefix.clone(),
+            target: route.target_template.clone(),
+            headers: route.header_templates.clone(),
+            hits: counters.hits,
+            errors: counters.errors,
+            last_hit_ms: counters.last_hit_ms,
+            last_status: counters.last_status,
+            priority: route.priority,
+            stale: false,
+        }
+    }
+}
+
+fn is_zero(n: &i32) -> bool {
+    *n == 0
+}
+
+/// Why an admin API call failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminError {
+    /// Nothing is listening: `runtime.json` is stale.
+    Unreachable(String),
+    /// The proxy answered with an error (its `error` message).
+    Api(String),
+    /// A conditional write found the namespace changed (`412`).
+    Changed(String),
+}
+
+impl std::fmt::Display for AdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminError::Unreachable(e) => write!(f, "admin API unreachable: {}", e),
+            AdminError::Api(e) | AdminError::Changed(e) => write!(f, "admin API: {}", e),
+        }
+    }
+}
+
+/// Client for a running proxy's loopback admin API.
+pub struct AdminClient {
+    base: String,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl AdminClient {
+    pub fn new(admin_port: u16) -> Self {
+        AdminClient {
+            base: format!("http://127.0.0.1:{}", admin_port),
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    /// `GET /rules` — the full merged rule table.
+    pub async fn list_rules(&self) -> Result<Vec<RuleInfo>, AdminError> {
+        self.call(Method::GET, "/rules", None).await
+    }
+
+    /// `GET /status` — how each conf.d namespace loaded.
+    pub async fn status(&self) -> Result<Status, AdminError> {
+        self.call(Method::GET, "/status", None).await
+    }
+
+    /// `PUT /rules/{ns}` — reconcile a namespace to `file`. The write is
+    /// conditional on the namespace still being as [`namespace_etag`]
+    /// (`Self::namespace_etag`) just found it, so two `up`s racing on
+    /// one namespace can't silently overwrite each other: the loser gets
+    /// an error.
+    pub async fn apply_rules(&self, ns: &str, file: &RoutesFile) -> Result<Vec<RuleInfo>, AdminError> {
+        self.put_rules(ns, file, "").await
+    }
+
+    /// Create namespace `ns` as `file` under a lease: the proxy removes
+    /// it unless [`renew_lease`](Self::renew_lease) is called within
+    /// `ttl` (e.g. `30s`). Only creates — an existing namespace, leased or
+    /// not, is someone else's and fails with [`AdminError::Changed`].
+    /// Returns the new namespace's ETag, for
+    /// [`remove_own_rules`](Self::remove_own_rules).
+    pub async fn lease_rules(&self, ns: &str, file: &RoutesFile, ttl: &str) -> Result<Option<String>, AdminError> {
+        let body = serde_yaml::to_string(file).map_err(|e| AdminError::Api(format!("serialize: {}", e)))?;
+        let path = format!("/rules/{}?ttl={}", ns, ttl);
+        let (status, headers, bytes) = self.send(Method::PUT, &path, Some(body), &[("if-none-match", "*")]).await?;
+        match status {
+            s if s.is_success() => Ok(headers.get(hyper::header::ETAG).and_then(|v| v.to_str().ok()).map(String::from)),
+            hyper::StatusCode::PRECONDITION_FAILED => Err(AdminError::Changed(format!("namespace '{}' already exists", ns))),
+            s => Err(api_error(s, &bytes)),
+        }
+    }
+
+    /// `DELETE /rules/{ns}?keep_nested=1` — remove `ns`'s own fragment,
+    /// not the namespaces nested below it, if it is still as `etag` (from
+    /// [`lease_rules`](Self::lease_rules)); a namespace changed since
+    /// fails with [`AdminError::Changed`]. Returns whether it existed.
+    pub async fn remove_own_rules(&self, ns: &str, etag: Option<&str>) -> Result<bool, AdminError> {
+        #[derive(Deserialize)]
+        struct Removed {
+            removed: bool,
+        }
+        let precondition: Vec<(&str, &str)> = etag.map(|t| ("if-match", t)).into_iter().collect();
+        let path = format!("/rules/{}?keep_nested=1", ns);
+        let r: Removed = self.call_with(Method::DELETE, &path, None, &precondition).await?;
+        Ok(r.removed)
+    }
+
+    /// `POST /leases/{ns}` — heartbeat. `false` if the lease already
+    /// lapsed and the namespace is gone.
+    pub async fn renew_lease(&self, ns: &str) -> Result<bool, AdminError> {
+        let (status, _, bytes) = self.send(Method::POST, &format!("/leases/{}", ns), None, &[]).await?;
+        match status {
+            s if s.is_success() => Ok(true),
+            hyper::StatusCode::NOT_FOUND => Ok(false),
+            s => Err(api_error(s, &bytes)),
+        }
+    }
+
+    async fn put_rules(&self, ns: &str, file: &RoutesFile, query: &str) -> Result<Vec<RuleInfo>, AdminError> {
+        let body = serde_yaml::to_string(file).map_err(|e| AdminError::Api(format!("serialize: {}", e)))?;
+        let precondition = match self.namespace_etag(ns).await? {
+            Some(etag) => ("if-match", etag),
+            None => ("if-none-match", "*".to_string()),
+        };
+        let path = format!("/rules/{}{}", ns, query);
+        match self.call_with(Method::PUT, &path, Some(body), &[(precondition.0, &precondition.1)]).await {
+            Err(AdminError::Changed(e)) => Err(AdminError::Changed(format!(
+                "{} (namespace '{}' changed while applying; re-run to apply over it)",
+                e, ns
+            ))),
+            other => other,
+        }
+    }
+
+    /// `GET /rules/{ns}`'s ETag: the namespace's fragment as it is now,
+    /// `None` if it has none.
+    pub async fn namespace_etag(&self, ns: &str) -> Result<Option<String>, AdminError> {
+        let (status, headers, bytes) = self.send(Method::GET, &format!("/rules/{}", ns), None, &[]).await?;
+        // A fragment that doesn't load is still tagged.
+        if let Some(etag) = headers.get(hyper::header::ETAG).and_then(|v| v.to_str().ok()) {
+            return Ok(Some(etag.to_string()));
+        }
+        if status == hyper::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Err(api_error(status, &bytes))
+    }
+
+    /// `DELETE /rules/{ns}` — remove a namespace's fragment. Returns
+    /// whether it existed.
+    pub async fn delete_rules(&self, ns: &str) -> Result<bool, AdminError> {
+        #[derive(Deserialize)]
+        struct Removed {
+            removed: bool,
+        }
+        let r: Removed = self.call(Method::DELETE, &format!("/rules/{}", ns), None).await?;
+        Ok(r.removed)
+    }
+
+    /// `GET /lint?namespace=` — findings for the live rule set.
+    pub async fn lint(&self, ns: &str) -> Result<Vec<Lint>, AdminError> {
+        self.call(Method::GET, &format!("/lint?namespace={}", ns), None).await
+    }
+
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<T, AdminError> {
+        self.call_with(method, path, body, &[]).await
+    }
+
+    /// [`call`](Self::call) with extra request headers.
+    async fn call_with<T: serde::de::DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+        headers: &[(&str, &str)],
+    ) -> Result<T, AdminError> {
+        let (status, _, bytes) = self.send(method, path, body, headers).await?;
+        if !status.is_success() {
+            return Err(api_error(status, &bytes));
+        }
+        serde_json::from_slice(&bytes).map_err(|e| AdminError::Api(format!("bad response: {}", e)))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+        headers: &[(&str, &str)],
+    ) -> Result<(hyper::StatusCode, hyper::HeaderMap, Bytes), AdminError> {
+        let mut req = Request::builder().method(method).uri(format!("{}{}", self.base, path));
+        if body.is_some() {
+            req = req.header("content-type", "application/yaml");
+        }
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req
+            .body(Full::new(Bytes::from(body.unwrap_or_default())))
+            .map_err(|e| AdminError::Api(e.to_string()))?;
+        let res = match tokio::time::timeout(ADMIN_TIMEOUT, self.client.request(req)).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(e)) if e.is_connect() => return Err(AdminError::Unreachable(e.to_string())),
+            Ok(Err(e)) => return Err(AdminError::Api(e.to_string())),
+            Err(_) => return Err(AdminError::Api(format!("no answer within {}s", ADMIN_TIMEOUT.as_secs()))),
+        };
+        let (status, headers) = (res.status(), res.headers().clone());
+        let bytes = res.into_body().collect().await.map_err(|e| AdminError::Api(e.to_string()))?.to_bytes();
+        Ok((status, headers, bytes))
+    }
+}
+
+/// An admin API error answer, with its `error` message.
+fn api_error(status: hyper::StatusCode, body: &[u8]) -> AdminError {
+    let msg = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(String::from))
+        .unwrap_or_else(|| status.to_string());
+    match status {
+        hyper::StatusCode::PRECONDITION_FAILED => AdminError::Changed(msg),
+        _ => AdminError::Api(msg),
+    }
+}
+
+/// `12s ago (200)`, or `never` for a rule that has not been hit.
+pub fn format_last_hit(last_hit_ms: Option<u64>, last_status: Option<u16>, now_ms: u64) -> String {
+    let Some(last) = last_hit_ms else { return "never".to_string() };
+    let secs = (now_ms.saturating_sub(last) + 500) / 1000;
+    let ago = match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    };
+    match last_status {
+        Some(status) => format!("{} ago ({})", ago, status),
+        None => format!("{} ago", ago),
+    }
+}
+
+/// The `ps` table: one row per rule, columns padded to fit.
+pub fn rules_table(rules: &[RuleInfo]) -> String {
+    if rules.is_empty() {
+        return "(no rules)\n".to_string();
+    }
+    const COLS: [&str; 8] = ["NAMESPACE", "NAME", "MATCH", "PATH", "TARGET", "HITS", "ERRORS", "LAST HIT"];
+    let now = unix_ms();
+    let rows: Vec<[String; 8]> = rules
+        .iter()
+        .map(|r| {
+            [
+                if r.stale { format!("{} (stale)", r.namespace) } else { r.namespace.clone() },
+                r.name.clone(),
+                r.match_pattern.clone(),
+                r.path.clone().unwrap_or_else(|| "*".to_string()),
+                r.target.clone(),
+                r.hits.to_string(),
+                r.errors.to_string(),
+                format_last_hit(r.last_hit_ms, r.last_status, now),
+            ]
+        })
+        .collect();
+    let widths: Vec<usize> = (0..COLS.len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).fold(COLS[i].len(), usize::max))
+        .collect();
+    let line = |cells: &[&str]| {
+        let padded: Vec<String> = cells.iter().zip(&widths).map(|(c, w)| format!("{:<w$}", c, w = *w)).collect();
+        format!("{}\n", padded.join("  ").trim_end())
+    };
+    let mut out = line(&COLS);
+    for row in &rows {
+        out.push_str(&line(&row.iter().map(String::as_str).collect::<Vec<_>>()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose_file_becomes_a_fragment_and_names_its_namespace() {
+        let dir = std::env::temp_dir().join(format!("fbi-compose-{}", std::process::id())).join("my-app");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(DEFAULT_FILE);
+        std::fs::write(
+            &file,
+            "routes:\n  - name: web\n    match: fbi.com\n    path: /\n    target: localhost:3000\ntests:\n  - host: fbi.com\n    expect: { route: web }\n",
+        )
+        .unwrap();
+        let compose = load_compose(&file).unwrap();
+        assert_eq!(resolve_namespace(None, compose.name.as_deref(), &file), "my-app");
+        assert_eq!(resolve_namespace(Some("x"), Some("y"), &file), "x");
+        assert_eq!(resolve_namespace(None, Some("y"), &file), "y");
+        let frag = compose.to_routes_file();
+        assert_eq!((frag.routes.len(), frag.tests.len()), (1, 1));
+        assert!(routes::parse_yaml(&serde_yaml::to_string(&frag).unwrap()).is_ok());
+
+        // The proxy doesn't expand `${NAME}` from its own environment for
+        // admin writes, so `up` sends the values along.
+        std::fs::write(&file, "vars: { port: \"3000\" }\nroutes:\n  - name: web\n    match: \"${CARGO_PKG_NAME}.{domain}\"\n    path: /${base:-api}\n    target: localhost:${port}\n").unwrap();
+        let vars = load_compose(&file).unwrap().to_routes_file().vars;
+        assert_eq!(vars.get("CARGO_PKG_NAME").map(String::as_str), Some("fbi-proxy"));
+        assert_eq!(vars.get("base").map(String::as_str), Some("api"));
+        assert_eq!(vars.get("port").map(String::as_str), Some("3000"));
+
+        std::fs::write(&file, "name: bad\nroutes:\n  - name: web\n    match: \"{x:nope}\"\n    target: a\n").unwrap();
+        assert!(load_compose(&file).unwrap_err().contains("unknown placeholder kind"));
+        assert!(load_compose(&dir.join("missing.yaml")).unwrap_err().contains("not found"));
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn runtime_json_round_trips_and_is_optional() {
+        let root = std::env::temp_dir().join(format!("fbi-runtime-{}", std::process::id()));
+        let conf_dir = root.join("conf.d");
+        assert_eq!(read_runtime(&conf_dir), Ok(None));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            runtime_json_path(&conf_dir),
+            r#"{ "adminPort": 2498, "proxyPort": 2432, "pid": 7, "confDir": "/x/conf.d" }"#,
+        )
+        .unwrap();
+        let info = read_runtime(&conf_dir).unwrap().unwrap();
+        assert_eq!((info.admin_port, info.proxy_port, info.pid), (2498, 2432, 7));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn last_hit_and_table_formatting() {
+        assert_eq!(format_last_hit(None, None, 5_000), "never");
+        assert_eq!(format_last_hit(Some(0), Some(200), 12_000), "12s ago (200)");
+        assert_eq!(format_last_hit(Some(0), None, 125_000), "2m ago");
+        assert_eq!(format_last_hit(Some(0), None, 3 * 86_400_000), "3d ago");
+
+        let rule = RuleInfo {
+            namespace: "app".into(),
+            name: "web".into(),
+            match_pattern: "fbi.com".into(),
+            path: None,
+            target: "localhost:3000".into(),
+            headers: HashMap::new(),
+            hits: 3,
+            errors: 0,
+            last_hit_ms: None,
+            last_status: None,
+            priority: 0,
+            stale: false,
+        };
+        let stale = RuleInfo { namespace: "old".into(), stale: true, ..rule.clone() };
+        assert_eq!(rules_table(&[stale]).lines().nth(1).unwrap().split("  ").next(), Some("old (stale)"));
+        let table = rules_table(&[rule]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "NAMESPACE  NAME  MATCH    PATH  TARGET          HITS  ERRORS  LAST HIT");
+        assert_eq!(lines[1], "app        web   fbi.com  *     localhost:3000  3     0       never");
+        assert_eq!(rules_table(&[]), "(no rules)\n");
+    }
+}
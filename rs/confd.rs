@@ -0,0 +1,620 @@
+//! Fault-isolated loading of the conf.d directory.
+//!
+//! Every `*.{yaml,yml,json,toml}` fragment under conf.d is one namespace
+//! — its path below conf.d without the extension, so
+//! `conf.d/team-a/api.yaml` is `team-a/api` and two files differing only
+//! in extension are an error for that namespace. Hidden files and
+//! directories are skipped. Fragments are merged after the
+//! bundled defaults in namespace order and then put in evaluation order by
+//! rule `priority` (see [`routes::sort_by_priority`]). A fragment's
+//! `disable_defaults` switches bundled rules off while it is loaded. [`Loader`] loads each fragment on
+//! its own, so one namespace's typo never freezes or drops the others: a
+//! fragment that fails to parse, compile or pass its route tests keeps
+//! serving its last-good rules (or is skipped, if it never loaded) and
+//! the error is recorded in [`Status`] against that namespace.
+//!
+//! Route tests run against the merged set, so a failure can be caused by
+//! a namespace other than the one owning the test. The loader blames the
+//! namespaces that changed since the last load — an owner that changed
+//! first, otherwise every changed namespace — and only falls back to the
+//! test's owner when nothing changed (e.g. at startup). Blamed namespaces
+//! revert to their last-good version, then are skipped, until the tests
+//! pass.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::include;
+use crate::routes::{self, CompiledRoute, RoutesFile};
+use crate::schema::{self, Format};
+use crate::unix_ms;
+
+/// One fragment as read from disk.
+#[derive(Debug, Clone)]
+pub struct FragmentFile {
+    /// The path below conf.d without its extension (see [`namespace_of`]).
+    pub namespace: String,
+    pub path: PathBuf,
+    /// The parsed file, or why it could not be read or parsed.
+    pub parsed: Result<RoutesFile, String>,
+    /// Schema findings that didn't stop it loading (unknown keys outside
+    /// strict mode, deprecated versions), as `message at line N column M`.
+    pub warnings: Vec<String>,
+}
+
+/// Every fragment path under `conf_dir` (YAML, JSON or TOML by
+/// extension), recursively, sorted by namespace so ordering is
+/// deterministic. A missing directory has no fragments.
+pub fn fragment_paths(conf_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    if conf_dir.is_dir() {
+        collect_fragments(conf_dir, &mut paths)?;
+    }
+    paths.sort_by_cached_key(|p| (namespace_of(conf_dir, p), p.clone()));
+    Ok(paths)
+}
+
+fn collect_fragments(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("read {}: {}", dir.display(), e))?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        if entry.file_name().to_str().is_none_or(|n| n.starts_with('.')) {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            collect_fragments(&path, out)?;
+        } else if Format::from_path(&path).is_some() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A fragment's namespace: its path below `conf_dir` without the
+/// extension, `/`-separated.
+pub fn namespace_of(conf_dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(conf_dir).unwrap_or(path).with_extension("");
+    let segments: Vec<&str> = rel.iter().filter_map(|s| s.to_str()).collect();
+    segments.join("/")
+}
+
+/// Whether `ns` is `prefix` or nested below it.
+pub fn in_subtree(ns: &str, prefix: &str) -> bool {
+    ns.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Where namespace `ns` is written in `format`.
+pub fn fragment_path(conf_dir: &Path, ns: &str, format: Format) -> PathBuf {
+    let mut path: PathBuf = conf_dir.to_path_buf();
+    path.extend(ns.split('/'));
+    path.with_extension(format.extension())
+}
+
+/// Remove the fragment at `path`, then any directories below `conf_dir`
+/// that leaves empty.
+pub fn remove_fragment(conf_dir: &Path, path: &Path) -> Result<(), String> {
+    std::fs::remove_file(path).map_err(|e| format!("remove {}: {}", path.display(), e))?;
+    // Only empty directories can be removed, so this stops at the first
+    // one still holding something.
+    let mut dir = path.parent();
+    while let Some(d) = dir.filter(|d| *d != conf_dir && d.starts_with(conf_dir)) {
+        if std::fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+    Ok(())
+}
+
+/// Every namespace with a fragment under `conf_dir`, in load order.
+pub fn namespaces(conf_dir: &Path) -> Result<Vec<String>, String> {
+    let mut out: Vec<String> = fragment_paths(conf_dir)?.iter().map(|p| namespace_of(conf_dir, p)).collect();
+    out.dedup();
+    Ok(out)
+}
+
+/// A strong HTTP entity tag (quotes included) for `bytes`.
+pub fn etag(bytes: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, bytes);
+    let hex: String = digest.as_ref()[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// The ETag of namespace `ns` as it is on disk — of its fragment's
+/// bytes, so any edit changes it, wherever it came from. `None` if the
+/// namespace has no fragment.
+pub fn namespace_etag(conf_dir: &Path, ns: &str) -> Result<Option<String>, String> {
+    let mut bytes = Vec::new();
+    let paths: Vec<PathBuf> = fragment_paths(conf_dir)?.into_iter().filter(|p| namespace_of(conf_dir, p) == ns).collect();
+    for path in &paths {
+        bytes.extend(std::fs::read(path).map_err(|e| format!("read {}: {}", path.display(), e))?);
+    }
+    Ok((!paths.is_empty()).then(|| etag(&bytes)))
+}
+
+/// Namespace `ns` as written — `include:` unresolved, so it can be
+/// edited and written back — with its format. `None` if it has no
+/// fragment.
+pub fn read_namespace(conf_dir: &Path, ns: &str, strict: bool) -> Result<Option<(RoutesFile, Format)>, String> {
+    let paths: Vec<PathBuf> = fragment_paths(conf_dir)?.into_iter().filter(|p| namespace_of(conf_dir, p) == ns).collect();
+    let path = match paths.as_slice() {
+        [] => return Ok(None),
+        [path] => path,
+        [a, b, ..] => return Err(format!("both {} and {} define this namespace; remove one", a.display(), b.display())),
+    };
+    let format = Format::from_path(path).unwrap_or(Format::Yaml);
+    let src = std::fs::read_to_string(path).map_err(|e| format!("read: {}", e))?;
+    let (file, _) = schema::load(&src, format, strict).map_err(|e| format!("parse: {}", e))?;
+    Ok(Some((file, format)))
+}
+
+/// Read and parse every fragment in `conf_dir`. Only a directory that
+/// can't be listed is an error; a bad file is reported in its entry. In
+/// `strict` mode an unknown key makes the file bad.
+pub fn read_dir(conf_dir: &Path, strict: bool) -> Result<Vec<FragmentFile>, String> {
+    let mut out: Vec<FragmentFile> = Vec::new();
+    for path in fragment_paths(conf_dir)? {
+        let namespace = namespace_of(conf_dir, &path);
+        if let Some(first) = out.iter_mut().find(|f| f.namespace == namespace) {
+            let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            first.parsed = Err(format!("both {} and {} define this namespace; remove one", name(&first.path), name(&path)));
+            continue;
+        }
+        let format = Format::from_path(&path).unwrap_or(Format::Yaml);
+        let mut warnings = Vec::new();
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|e| format!("read: {}", e))
+            .and_then(|src| schema::load(&src, format, strict).map_err(|e| format!("parse: {}", e)))
+            .and_then(|(file, findings)| {
+                warnings = findings.iter().map(ToString::to_string).collect();
+                include::resolve(file, &path, strict)
+            });
+        out.push(FragmentFile { namespace, path, parsed, warnings });
+    }
+    Ok(out)
+}
+
+/// How a namespace is being served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NamespaceState {
+    /// The fragment on disk is live.
+    Ok,
+    /// The fragment on disk failed; its last-good rules are live.
+    LastGood,
+    /// The fragment on disk failed and there is nothing to fall back to.
+    Skipped,
+}
+
+impl NamespaceState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NamespaceState::Ok => "ok",
+            NamespaceState::LastGood => "last_good",
+            NamespaceState::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceStatus {
+    pub namespace: String,
+    pub file: String,
+    pub state: NamespaceState,
+    /// Rules live for this namespace.
+    pub rules: usize,
+    pub error: Option<String>,
+    /// Unix epoch ms when the current error was first seen.
+    pub failing_since_ms: Option<u64>,
+}
+
+/// Outcome of the last load, per namespace (in merge order).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    /// Unix epoch ms of the load.
+    pub loaded_at_ms: u64,
+    /// Live rules, bundled defaults included.
+    pub rules: usize,
+    pub namespaces: Vec<NamespaceStatus>,
+}
+
+impl Status {
+    /// Namespaces whose fragment on disk is not what is being served.
+    pub fn failing(&self) -> impl Iterator<Item = &NamespaceStatus> {
+        self.namespaces.iter().filter(|n| n.state != NamespaceState::Ok)
+    }
+
+    pub fn get(&self, namespace: &str) -> Option<&NamespaceStatus> {
+        self.namespaces.iter().find(|n| n.namespace == namespace)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Fresh,
+    LastGood,
+    Skip,
+}
+
+/// One namespace while resolving a load.
+struct Slot<'a> {
+    frag: &'a FragmentFile,
+    /// The file on disk, if it parsed and compiled.
+    fresh: Option<&'a RoutesFile>,
+    choice: Choice,
+    error: Option<String>,
+}
+
+/// Loads conf.d fragments independently, remembering each namespace's
+/// last-good version across loads.
+pub struct Loader {
+    bundled: Vec<CompiledRoute>,
+    last_good: HashMap<String, RoutesFile>,
+    failing_since: HashMap<String, u64>,
+    status: Status,
+}
+
+impl Loader {
+    /// A loader on top of the bundled defaults (namespace `"default"`).
+    pub fn new(bundled_yaml: &str) -> Result<Self, String> {
+        let parsed = routes::parse_yaml(bundled_yaml).map_err(|e| format!("parse bundled routes: {}", e))?;
+        let bundled =
+            parsed.rules().and_then(|r| routes::compile_in_namespace(r, "default")).map_err(|e| format!("compile bundled routes: {}", e))?;
+        Ok(Loader { bundled, last_good: HashMap::new(), failing_since: HashMap::new(), status: Status::default() })
+    }
+
+    /// Status of the last [`load`](Self::load).
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Merge the bundled defaults with `fragments`, isolating failures
+    /// per namespace, and remember the result. Never fails: at worst
+    /// every namespace is skipped.
+    pub fn load(&mut self, fragments: &[FragmentFile], domain_filter: Option<&str>) -> Vec<CompiledRoute> {
+        let (merged, slots) = self.resolve(fragments, domain_filter);
+        let now = unix_ms();
+        let namespaces = slots
+            .iter()
+            .map(|s| {
+                let ns = &s.frag.namespace;
+                let failing_since_ms = match &s.error {
+                    Some(_) => Some(*self.failing_since.entry(ns.clone()).or_insert(now)),
+                    None => {
+                        self.failing_since.remove(ns);
+                        None
+                    }
+                };
+                NamespaceStatus {
+                    namespace: ns.clone(),
+                    file: s.frag.path.display().to_string(),
+                    state: match s.choice {
+                        Choice::Fresh => NamespaceState::Ok,
+                        Choice::LastGood => NamespaceState::LastGood,
+                        Choice::Skip => NamespaceState::Skipped,
+                    },
+                    rules: self.in_use(s).map_or(0, |f| f.routes.len()),
+                    error: s.error.clone(),
+                    failing_since_ms,
+                }
+            })
+            .collect();
+        for s in &slots {
+            if let (Choice::Fresh, Some(fresh)) = (s.choice, s.fresh) {
+                self.last_good.insert(s.frag.namespace.clone(), fresh.clone());
+            }
+        }
+        // A removed fragment was removed on purpose; forget it.
+        let present: Vec<&str> = fragments.iter().map(|f| f.namespace.as_str()).collect();
+        self.last_good.retain(|ns, _| present.contains(&ns.as_str()));
+        self.failing_since.retain(|ns, _| present.contains(&ns.as_str()));
+        self.status = Status { loaded_at_ms: now, rules: merged.len(), namespaces };
+        merged
+    }
+
+    /// What [`load`](Self::load) would serve for `fragments`, without
+    /// remembering anything. Fails if namespace `changed` would not load
+    /// cleanly, or if a namespace that loads cleanly now would stop
+    /// doing so — so an admin-API write can't break anyone else.
+    pub fn stage(
+        &self,
+        fragments: &[FragmentFile],
+        changed: &str,
+        domain_filter: Option<&str>,
+    ) -> Result<Vec<CompiledRoute>, String> {
+        let (merged, slots) = self.resolve(fragments, domain_filter);
+        for s in &slots {
+            let ns = s.frag.namespace.as_str();
+            let was_ok = self.status.get(ns).is_some_and(|n| n.state == NamespaceState::Ok);
+            if let Some(error) = s.error.as_ref().filter(|_| ns == changed || was_ok) {
+                return Err(if ns == changed { error.clone() } else { format!("would break namespace '{}': {}", ns, error) });
+            }
+        }
+        Ok(merged)
+    }
+
+    fn resolve<'a>(&self, fragments: &'a [FragmentFile], domain_filter: Option<&str>) -> (Vec<CompiledRoute>, Vec<Slot<'a>>) {
+        let mut slots: Vec<Slot<'a>> = fragments
+            .iter()
+            .map(|frag| {
+                let fresh = frag.parsed.as_ref().map_err(String::clone).and_then(|file| {
+                    self.check_disabled(file)?;
+                    file.rules()
+                        .and_then(|r| routes::compile_in_namespace(r, &frag.namespace))
+                        .map(|_| file)
+                        .map_err(|e| format!("compile: {}", e))
+                });
+                let fallback = if self.last_good.contains_key(&frag.namespace) { Choice::LastGood } else { Choice::Skip };
+                match fresh {
+                    Ok(file) => Slot { frag, fresh: Some(file), choice: Choice::Fresh, error: None },
+                    Err(e) => Slot { frag, fresh: None, choice: fallback, error: Some(e) },
+                }
+            })
+            .collect();
+
+        loop {
+            let mut merged = self.bundled.clone();
+            let disabled: Vec<&str> = slots
+                .iter()
+                .filter_map(|s| self.in_use(s))
+                .flat_map(|f| f.disable_defaults.iter().map(String::as_str))
+                .collect();
+            routes::disable_defaults(&mut merged, &disabled);
+            for s in &slots {
+                if let Some(file) = self.in_use(s) {
+                    // Both versions compiled when they were accepted.
+                    let compiled = file.rules().and_then(|r| routes::compile_in_namespace(r, &s.frag.namespace));
+                    merged.extend(compiled.unwrap_or_default());
+                }
+            }
+            routes::sort_by_priority(&mut merged);
+            let mut failed: Vec<(usize, String)> = Vec::new();
+            for (i, s) in slots.iter().enumerate() {
+                let Some(file) = self.in_use(s) else { continue };
+                let label = s.frag.path.display().to_string();
+                let tests = file.tests.iter().enumerate().map(|(j, t)| (label.as_str(), j, t));
+                let failures: Vec<String> =
+                    routes::run_tests(&merged, tests, domain_filter).iter().map(ToString::to_string).collect();
+                if !failures.is_empty() {
+                    failed.push((i, failures.join("; ")));
+                }
+            }
+            if failed.is_empty() {
+                return (merged, slots);
+            }
+
+            let changed: Vec<usize> = (0..slots.len())
+                .filter(|&i| slots[i].choice == Choice::Fresh && self.last_good.get(&slots[i].frag.namespace) != slots[i].fresh)
+                .collect();
+            let owners: Vec<usize> = failed.iter().map(|(i, _)| *i).collect();
+            let both: Vec<usize> = owners.iter().copied().filter(|i| changed.contains(i)).collect();
+            let blame = if !both.is_empty() {
+                both
+            } else if !changed.is_empty() {
+                changed.clone()
+            } else {
+                owners
+            };
+            let all = failed.iter().map(|(_, f)| f.as_str()).collect::<Vec<_>>().join("; ");
+            for i in blame {
+                let own = failed.iter().find(|(j, _)| *j == i).map(|(_, f)| f.as_str());
+                let s = &mut slots[i];
+                s.error = Some(match own {
+                    Some(f) => format!("route tests failed: {}", f),
+                    None => format!("breaks other namespaces' route tests: {}", all),
+                });
+                s.choice = match s.choice {
+                    Choice::Fresh if changed.contains(&i) && self.last_good.contains_key(&s.frag.namespace) => Choice::LastGood,
+                    _ => Choice::Skip,
+                };
+            }
+        }
+    }
+
+    /// `disable_defaults` may only name bundled rules.
+    fn check_disabled(&self, file: &RoutesFile) -> Result<(), String> {
+        let names: Vec<&str> = file.disable_defaults.iter().map(String::as_str).collect();
+        match routes::disable_defaults(&mut self.bundled.clone(), &names)[..] {
+            [] => Ok(()),
+            ref unknown => Err(format!("disable_defaults: no bundled rule named {}", unknown.join(", "))),
+        }
+    }
+
+    /// The version of a namespace being served, if any.
+    fn in_use<'a>(&'a self, s: &Slot<'a>) -> Option<&'a RoutesFile> {
+        match s.choice {
+            Choice::Fresh => s.fresh,
+            Choice::LastGood => self.last_good.get(&s.frag.namespace),
+            Choice::Skip => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLED: &str = include_str!("../routes.yaml");
+
+    fn frag(ns: &str, yaml: &str) -> FragmentFile {
+        FragmentFile {
+            namespace: ns.into(),
+            path: PathBuf::from(format!("{}.yaml", ns)),
+            parsed: routes::parse_yaml(yaml).map_err(|e| format!("parse: {}", e)),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn web(ns: &str, path: &str, port: u16) -> FragmentFile {
+        frag(
+            ns,
+            &format!("routes:\n  - name: web\n    match: fbi.com\n    path: {}\n    target: localhost:{}\n", path, port),
+        )
+    }
+
+    fn served(routes: &[CompiledRoute], ns: &str) -> Vec<String> {
+        routes.iter().filter(|r| r.namespace == ns).map(|r| r.target_template.clone()).collect()
+    }
+
+    #[test]
+    fn a_bad_fragment_is_isolated_and_keeps_its_last_good_rules() {
+        let mut loader = Loader::new(BUNDLED).unwrap();
+        let routes = loader.load(&[web("a", "/a", 3001), web("b", "/b", 3002)], Some("fbi.com"));
+        assert_eq!((served(&routes, "a"), served(&routes, "b")), (vec!["localhost:3001".into()], vec!["localhost:3002".into()]));
+        assert_eq!(loader.status().failing().count(), 0);
+
+        // b gets a typo; a's edit still goes live.
+        let broken = frag("b", "routes:\n  - name: web\n    match: \"{x:nope}.{domain}\"\n    target: y\n");
+        let routes = loader.load(&[web("a", "/a", 4001), broken.clone()], Some("fbi.com"));
+        assert_eq!(served(&routes, "a"), vec!["localhost:4001".to_string()]);
+        assert_eq!(served(&routes, "b"), vec!["localhost:3002".to_string()]);
+        let b = loader.status().get("b").unwrap().clone();
+        assert_eq!(b.state, NamespaceState::LastGood);
+        assert!(b.error.as_deref().unwrap().starts_with("compile: route 'web': unknown placeholder kind"));
+        let since = b.failing_since_ms.unwrap();
+
+        // Still broken on the next load: the failure keeps its start time.
+        loader.load(&[web("a", "/a", 4001), broken], Some("fbi.com"));
+        assert_eq!(loader.status().get("b").unwrap().failing_since_ms, Some(since));
+
+        // A fresh loader (startup) has nothing to fall back to.
+        let mut fresh = Loader::new(BUNDLED).unwrap();
+        let routes = fresh.load(&[web("a", "/a", 3001), frag("b", "routes: [")], Some("fbi.com"));
+        assert_eq!(served(&routes, "a"), vec!["localhost:3001".to_string()]);
+        let b = fresh.status().get("b").unwrap();
+        assert_eq!((b.state, b.rules), (NamespaceState::Skipped, 0));
+        assert!(b.error.as_deref().unwrap().starts_with("parse: "));
+    }
+
+    #[test]
+    fn test_failures_are_blamed_on_the_namespace_that_changed() {
+        let with_test = |port| {
+            frag(
+                "a",
+                &format!(
+                    "routes:\n  - name: web\n    match: fbi.com\n    path: /a\n    target: localhost:{}\ntests:\n  - host: fbi.com\n    path: /a/x\n    expect: {{ route: a/web }}\n",
+                    port
+                ),
+            )
+        };
+        let mut loader = Loader::new(BUNDLED).unwrap();
+        loader.load(&[with_test(3001)], Some("fbi.com"));
+
+        // b claims a longer prefix and steals a's test; b is new, so b
+        // is skipped and a keeps serving.
+        let thief = web("b", "/a/x", 9999);
+        let fragments = [with_test(3001), thief];
+        let err = loader.stage(&fragments, "b", Some("fbi.com")).unwrap_err();
+        assert!(err.starts_with("breaks other namespaces' route tests: a.yaml test #1"), "{err}");
+        let routes = loader.load(&fragments, Some("fbi.com"));
+        assert_eq!(served(&routes, "b"), Vec::<String>::new());
+        assert_eq!(loader.status().get("a").unwrap().state, NamespaceState::Ok);
+        assert_eq!(loader.status().get("b").unwrap().state, NamespaceState::Skipped);
+
+        // At startup nothing changed, so the test's owner is skipped.
+        let mut fresh = Loader::new(BUNDLED).unwrap();
+        let routes = fresh.load(&fragments, Some("fbi.com"));
+        assert_eq!(served(&routes, "b"), vec!["localhost:9999".to_string()]);
+        let a = fresh.status().get("a").unwrap();
+        assert_eq!(a.state, NamespaceState::Skipped);
+        assert!(a.error.as_deref().unwrap().starts_with("route tests failed: a.yaml test #1"));
+    }
+
+    #[test]
+    fn fragments_disable_defaults_and_outrank_them_by_priority() {
+        let mut loader = Loader::new(BUNDLED).unwrap();
+        let app = frag("app", "priority: 1\ndisable_defaults: [direct-forward-bare]\nroutes:\n  - name: web\n    match: \"{host}.{domain}\"\n    target: localhost:3000\n");
+        let routes = loader.load(&[app.clone(), frag("typo", "disable_defaults: [nope]\nroutes: []\n")], Some("fbi.com"));
+        assert_eq!(routes[0].namespace, "app");
+        assert!(!routes.iter().any(|r| r.name == "direct-forward-bare"));
+        let typo = loader.status().get("typo").unwrap();
+        assert_eq!(typo.error.as_deref(), Some("disable_defaults: no bundled rule named nope"));
+
+        // Removing the fragment brings the bundled rule back.
+        let routes = loader.load(&[], Some("fbi.com"));
+        assert!(routes.iter().any(|r| r.name == "direct-forward-bare"));
+    }
+
+    #[test]
+    fn json_and_toml_fragments_load_and_a_namespace_has_one_file() {
+        let dir = std::env::temp_dir().join(format!("fbi-confd-formats-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str| std::fs::write(dir.join(name), text).unwrap();
+        write("api.json", r#"{"routes": [{"name": "api", "match": {"host": "api.{domain}"}, "action": {"proxy": "localhost:3000"}}]}"#);
+        write("web.toml", "[[routes]]\nname = \"web\"\nmatch = { host = \"web.{domain}\" }\naction = { proxy = \"localhost:3001\" }\n");
+        write("dup.yaml", "routes: []\n");
+        write("dup.toml", "routes = []\n");
+        write("notes.txt", "not a fragment\n");
+
+        let files = read_dir(&dir, false).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.namespace.as_str()).collect();
+        assert_eq!(names, vec!["api", "dup", "web"]);
+        assert_eq!(files[0].parsed.as_ref().unwrap().routes[0].target, "localhost:3000");
+        assert_eq!(files[2].parsed.as_ref().unwrap().routes[0].target, "localhost:3001");
+        assert_eq!(files[1].parsed.as_ref().unwrap_err(), "both dup.toml and dup.yaml define this namespace; remove one");
+        assert_eq!(fragment_path(&dir, "web", Format::Toml), dir.join("web.toml"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn subdirectories_are_nested_namespaces() {
+        let dir = std::env::temp_dir().join(format!("fbi-confd-nested-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |name: &str, text: &str| {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write("team-a/api.yaml", "priority: 1\nroutes:\n  - name: api\n    match: { host: \"api.a.{domain}\" }\n    action: { proxy: localhost:3000 }\ntests:\n  - host: api.a.fbi.com\n    expect: { route: team-a/api/api }\n");
+        write("team-a/web/site.json", r#"{"routes": []}"#);
+        write("team-a.yaml", "routes: []\n");
+        write("team-ab.yaml", "routes: []\n");
+        write(".git/config.yaml", "not: [a fragment\n");
+        write("team-a/.draft.yaml", "routes: [\n");
+
+        let files = read_dir(&dir, false).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.namespace.as_str()).collect();
+        assert_eq!(names, vec!["team-a", "team-a/api", "team-a/web/site", "team-ab"]);
+        assert!(files.iter().all(|f| f.parsed.is_ok()), "{files:?}");
+        let mut loader = Loader::new(BUNDLED).unwrap();
+        let routes = loader.load(&files, Some("fbi.com"));
+        assert_eq!(loader.status().get("team-a/api").map(|n| (n.state, n.error.clone())), Some((NamespaceState::Ok, None)));
+        assert_eq!(served(&routes, "team-a/api"), vec!["localhost:3000"]);
+
+        assert_eq!(fragment_path(&dir, "team-a/web/site", Format::Json), dir.join("team-a").join("web").join("site.json"));
+        let subtree: Vec<&str> = names.iter().copied().filter(|ns| in_subtree(ns, "team-a")).collect();
+        assert_eq!(subtree, vec!["team-a", "team-a/api", "team-a/web/site"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn namespace_etags_follow_the_bytes_on_disk() {
+        let dir = std::env::temp_dir().join(format!("fbi-confd-etag-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("team")).unwrap();
+        assert_eq!(namespace_etag(&dir, "team/app").unwrap(), None);
+        std::fs::write(dir.join("team/app.yaml"), "include: [../base.yaml]\nroutes: []\n").unwrap();
+        let first = namespace_etag(&dir, "team/app").unwrap().unwrap();
+        assert!(first.starts_with('"') && first.len() == 18, "{first}");
+        std::fs::write(dir.join("team/app.yaml"), "include: [../base.yaml]\nroutes: [] # edited\n").unwrap();
+        assert_ne!(namespace_etag(&dir, "team/app").unwrap().unwrap(), first);
+
+        let (file, format) = read_namespace(&dir, "team/app", false).unwrap().unwrap();
+        assert_eq!((file.include, format), (vec!["../base.yaml".to_string()], Format::Yaml));
+        assert_eq!(namespaces(&dir).unwrap(), vec!["team/app"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stage_refuses_to_break_a_healthy_namespace_but_tolerates_broken_ones() {
+        let mut loader = Loader::new(BUNDLED).unwrap();
+        loader.load(&[web("a", "/a", 3001), frag("broken", "routes: [")], None);
+        // An unrelated broken fragment doesn't block writes.
+        assert!(loader.stage(&[web("a", "/a", 3001), frag("broken", "routes: ["), web("c", "/c", 3003)], "c", None).is_ok());
+        let err = loader.stage(&[frag("c", "routes: [")], "c", None).unwrap_err();
+        assert!(err.starts_with("parse: "), "{err}");
+    }
+}
@@ -1,10 +1,27 @@
 use clap::{Arg, Command};
-use fbi_proxy::metrics::Metrics;
-use fbi_proxy::routes::{self, CompiledRoute, RouteHit};
+use fbi_proxy::access_log::{self, AccessLog};
+use fbi_proxy::check;
+use fbi_proxy::compose::{self, RuleInfo};
+use fbi_proxy::confd;
+use fbi_proxy::history::{self, History};
+use fbi_proxy::include;
+use fbi_proxy::launch::{self, Launch, Launcher};
+use fbi_proxy::lease::{self, Leases};
+use fbi_proxy::inspector::{self, ErrorClass, Inspector, RequestEvent, RequestFilter, StatusFilter};
+use fbi_proxy::lint;
+use fbi_proxy::metrics::{self, CountingBody, Metrics};
+use fbi_proxy::otel::{self, SpanKind, Tracer};
+use fbi_proxy::route_stats::RouteStats;
+use fbi_proxy::schema::{self, Format};
+use fbi_proxy::run;
+use fbi_proxy::routes::{self, parse_target_scheme, AccessLogPolicy, CompiledRoute, Decision, RouteHit};
+use fbi_proxy::trace::{self, TraceContext};
+use fbi_proxy::untrusted;
+use fbi_proxy::unix_ms;
 use futures_util::{SinkExt, StreamExt};
 use http_body_util::{BodyExt, Full};
 use hyper::body::{Bytes, Incoming};
-use hyper::header::{HeaderValue, HOST};
+use hyper::header::{HeaderName, HeaderValue, HOST};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Method, Request, Response, StatusCode, Uri};
@@ -18,8 +35,8 @@ use log::{error, info, warn};
 use regex::Regex;
 use std::convert::Infallible;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time::timeout;
 use tokio::io::copy_bidirectional;
@@ -32,6 +49,10 @@ type BoxBody = http_body_util::combinators::BoxBody<Bytes, hyper::Error>;
 /// behavior. Loaded at compile-time so the binary works out-of-the-box.
 const BUNDLED_ROUTES_YAML: &str = include_str!("../routes.yaml");
 
+/// Admin dashboard page served at `GET /` on the admin listener. Static
+/// HTML/JS that drives the JSON admin endpoints from the browser.
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
 pub struct FBIProxy {
     client: Client<HttpsConnector<HttpConnector>, BoxBody>,
     number_regex: Regex,
@@ -41,6 +62,18 @@ pub struct FBIProxy {
     /// `.load()` and never block; writes are atomic Arc swaps.
     compiled_routes: Arc<ArcSwap<Vec<CompiledRoute>>>,
     metrics: Arc<Metrics>,
+    /// Recent-request ring + live stream for the admin inspector.
+    inspector: Arc<Inspector>,
+    /// Per-rule hit counters for `GET /rules`.
+    route_stats: Arc<RouteStats>,
+    /// Structured access log, when enabled with `--access-log`.
+    access_log: Option<Arc<AccessLog>>,
+    /// OTLP span exporter, when enabled with `--otel-endpoint`.
+    tracer: Option<Arc<Tracer>>,
+    /// conf.d loader, so the landing page can flag failing namespaces.
+    conf: Option<Arc<ConfD>>,
+    /// Processes of `launch:` rules, started on demand.
+    launcher: Arc<Launcher>,
 }
 
 /*
@@ -73,7 +106,19 @@ the landing page.
 /// Outcome of routing a request through the rule engine.
 enum RouteDecision {
     /// Forward to `target` (upstream authority) with this outgoing `Host`.
-    Hit { target: String, host: String },
+    /// `route` / `namespace` identify the matched rule for reporting.
+    Hit {
+        target: String,
+        host: String,
+        /// The rule's other `headers:`, secrets filled in and marked
+        /// sensitive.
+        headers: Vec<(HeaderName, HeaderValue)>,
+        route: String,
+        namespace: String,
+        access_log: AccessLogPolicy,
+        /// The rule's on-demand process, started before forwarding.
+        launch: Option<Arc<Launch>>,
+    },
     /// Serve the built-in landing page (apex domain, no matching rule).
     Landing,
     /// Reject with 502 (host not allowed / no matching rule).
@@ -104,9 +149,33 @@ impl FBIProxy {
             domain_filter,
             compiled_routes: Arc::new(ArcSwap::from_pointee(compiled_routes)),
             metrics: Metrics::new(),
+            inspector: Arc::new(Inspector::default()),
+            route_stats: Arc::new(RouteStats::new()),
+            access_log: None,
+            tracer: None,
+            conf: None,
+            launcher: Arc::new(Launcher::default()),
         }
     }
 
+    /// Enable the structured access log.
+    pub fn with_access_log(mut self, log: Option<Arc<AccessLog>>) -> Self {
+        self.access_log = log;
+        self
+    }
+
+    /// Enable OTLP trace export.
+    pub fn with_tracer(mut self, tracer: Option<Arc<Tracer>>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Report conf.d namespaces that failed to load on the landing page.
+    fn with_conf(mut self, conf: Option<Arc<ConfD>>) -> Self {
+        self.conf = conf;
+        self
+    }
+
     /// Return a handle to the live routes Arc so callers (e.g. the
     /// file watcher) can swap them at runtime without re-creating the
     /// proxy.
@@ -120,7 +189,27 @@ impl FBIProxy {
         Arc::clone(&self.metrics)
     }
 
-    fn landing_page_html() -> String {
+    /// Return a handle to the request inspector so the admin server can
+    /// serve `/requests` and `/requests/stream`.
+    pub fn inspector_handle(&self) -> Arc<Inspector> {
+        Arc::clone(&self.inspector)
+    }
+
+    /// Per-rule hit counters, for the admin server.
+    pub fn route_stats_handle(&self) -> Arc<RouteStats> {
+        Arc::clone(&self.route_stats)
+    }
+
+    /// The on-demand processes, for the reloader and admin server.
+    pub fn launcher_handle(&self) -> Arc<Launcher> {
+        Arc::clone(&self.launcher)
+    }
+
+    fn landing_page_html(&self) -> String {
+        let failing = match &self.conf {
+            Some(conf) => conf_failures_html(&conf.status()),
+            None => String::new(),
+        };
         r#"<!DOCTYPE html>
 <html lang="en">
 <head>
@@ -173,6 +262,7 @@ impl FBIProxy {
 <body>
     <h1>🔀 FBI-Proxy</h1>
     <p>A reverse proxy with intelligent host header routing.</p>
+<!-- conf.d status -->
 
     <h2>How It Works</h2>
     <p>FBI-Proxy routes requests based on the <code>Host</code> header:</p>
@@ -207,19 +297,7 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
 
     <p><a href="https://github.com/snomiao/fbi-proxy">GitHub</a> · <a href="https://www.npmjs.com/package/fbi-proxy">npm</a> · <a href="https://crates.io/crates/fbi-proxy">crates.io</a></p>
 </body>
-</html>"#.to_string()
-    }
-
-    /// Extract the hostname portion (before the first `:`) from a target
-    /// string like `"127.0.0.1:3000"` or `"https://api.github.com:443"`.
-    /// This is the default `Host` header value used when a matched rule
-    /// doesn't specify an explicit `headers.Host` rewrite.
-    fn host_from_target(target: &str) -> String {
-        let authority = parse_target_scheme(target).1;
-        match authority.find(':') {
-            Some(i) => authority[..i].to_string(),
-            None => authority.to_string(),
-        }
+</html>"#.replace("<!-- conf.d status -->\n", &failing)
     }
 
     /// Returns Some((target, new_host_header)) if the routing engine
@@ -233,53 +311,32 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
     /// Returns None if the host is rejected (filter mismatch or no
     /// matching rule).
     fn route(&self, host_header: &str, req_path: &str) -> RouteDecision {
-        // Drop port if present.
-        let host_without_port = match host_header.find(':') {
-            Some(i) => &host_header[..i],
-            None => host_header,
-        };
-
-        // CONNECT and some clients carry an empty path — treat it as "/"
-        // so host-level rules (path "/" or path-less) still match. Path
-        // routing only applies to L7 requests we terminate ourselves.
-        let req_path = if req_path.is_empty() { "/" } else { req_path };
-
-        // Is this the exact apex host (e.g. `fbi.com` itself, not a
-        // subdomain)? The apex is reserved for the landing page unless a
-        // rule *explicitly* claims it with a `path` — otherwise a bundled
-        // placeholder rule like `{host}.{domain}` would swallow
-        // `https://fbi.com/` and break `fbi-proxy setup`'s verification.
-        let is_apex = match &self.domain_filter {
-            Some(d) => !d.is_empty() && host_without_port.eq_ignore_ascii_case(d),
-            None => false,
-        };
-
         // Lock-free read of the live routes (may have been swapped by the
         // watcher/admin API mid-flight). `.load()` returns an Arc held for
-        // the duration of the match. At the apex, only explicit-path rules
-        // are eligible; elsewhere, normal longest-prefix matching applies.
+        // the duration of the match.
         let routes_guard = self.compiled_routes.load();
-        if let Some(hit) = routes::match_request_opts(
-            routes_guard.as_ref(),
-            host_header,
-            req_path,
-            self.domain_filter.as_deref(),
-            is_apex,
-        ) {
-            let RouteHit { target, host_header: rewrite, .. } = hit;
-            let new_host = rewrite.unwrap_or_else(|| Self::host_from_target(&target));
-            return RouteDecision::Hit { target, host: new_host };
-        }
-
-        // No rule matched. Apex with a domain filter → built-in landing
-        // page; anything else → reject.
-        if is_apex {
-            return RouteDecision::Landing;
+        match routes::decide(routes_guard.as_ref(), host_header, req_path, self.domain_filter.as_deref()) {
+            Decision::Hit(hit) => {
+                let new_host = hit.upstream_host();
+                let headers = upstream_headers(&hit);
+                let RouteHit { target, route_name, namespace, access_log, launch, .. } = hit;
+                RouteDecision::Hit { target, host: new_host, headers, route: route_name, namespace, access_log, launch }
+            }
+            Decision::Landing => RouteDecision::Landing,
+            Decision::Reject => RouteDecision::Reject,
         }
-        RouteDecision::Reject
     }
 
-    pub async fn handle_request(&self, req: Request<Incoming>) -> Result<Response<BoxBody>, BoxError> {
+    /// Handle one request end-to-end and report it to the inspector and
+    /// access log once the response (or error) is known.
+    pub async fn handle_request(
+        &self,
+        req: Request<Incoming>,
+        remote: SocketAddr,
+    ) -> Result<Response<BoxBody>, BoxError> {
+        let started = Instant::now();
+        let received_at = SystemTime::now();
+        let inflight = self.metrics.track(|m| &m.inflight_requests);
         // Extract host for routing. HTTP/1.1 sends it in the Host header;
         // HTTP/2 sends it in the :authority pseudo-header (which hyper exposes
         // as the request URI's authority, NOT a Host header). Fall back to the
@@ -293,28 +350,173 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
             .map(|s| s.to_string())
             .or_else(|| req.uri().authority().map(|a| a.as_str().to_string()))
             .unwrap_or_else(|| "localhost".to_string());
+        let method = req.method().to_string();
+        let path = req.uri().path().to_string();
+        let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+        let mut trace = TraceContext::from_headers(header(trace::TRACEPARENT_HEADER), header(trace::TRACESTATE_HEADER));
+        if let Some(tracer) = &self.tracer {
+            tracer.sample(&mut trace);
+        }
+        let request_id = trace::request_id(header(trace::REQUEST_ID_HEADER), || trace.trace_id_hex());
+        // Captured up front: `forward` consumes the request.
+        let access = self.access_log.as_ref().map(|log| {
+            let headers = log.capture_headers(|name| req.headers().get(name).and_then(|v| v.to_str().ok()));
+            let uri = req.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/").to_string();
+            (headers, uri, format!("{:?}", req.version()))
+        });
+
+        let mut rec = RequestRecord::default();
+        let mut result = self.forward(req, &host_header, &trace, &request_id, &mut rec).await;
+        if let Err(e) = &result {
+            error!("[{}] {} {}{} 500 ({})", request_id, method, host_header, path, e);
+            rec.error.get_or_insert(ErrorClass::Internal);
+            result = error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("500 Internal Server Error: {}", e), &request_id);
+        }
+        if let Ok(resp) = &mut result
+            && let Ok(v) = HeaderValue::from_str(&request_id)
+        {
+            resp.headers_mut().insert(trace::REQUEST_ID_HEADER, v);
+        }
+
+        let (status, bytes) = match &result {
+            Ok(resp) => (
+                resp.status().as_u16(),
+                resp.headers()
+                    .get(hyper::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok()),
+            ),
+            Err(_) => (500, None),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+        if let (Some(log), Some((headers, uri, proto))) = (&self.access_log, access) {
+            let policy = rec.access_log.unwrap_or_default();
+            if policy.enabled && log.sampled(policy.sample) {
+                log.log(&access_log::Entry {
+                    ts: Some(received_at),
+                    request_id: Some(request_id.clone()),
+                    trace_id: Some(trace.trace_id_hex()),
+                    remote: Some(remote.ip().to_string()),
+                    method: method.clone(),
+                    host: host_header.clone(),
+                    uri,
+                    proto,
+                    status,
+                    bytes,
+                    latency_ms,
+                    route: rec.route.clone(),
+                    namespace: rec.namespace.clone(),
+                    target: rec.target.clone(),
+                    error: rec.error.map(|e| e.as_str().to_string()),
+                    headers,
+                });
+            }
+        }
+        let ns_label = rec.namespace.clone().unwrap_or_default();
+        let route_label = rec.route.clone().unwrap_or_default();
+        if let (Some(ns), Some(route)) = (&rec.namespace, &rec.route) {
+            self.route_stats.record(ns, route, status, rec.error.is_some(), unix_ms());
+        }
+        self.metrics.route_requests.inc(
+            &[&ns_label, &route_label, metrics::method_label(&method), metrics::status_class(status)],
+            1,
+        );
+        // Response bytes, upstream duration and the in-flight gauge are
+        // only known once the body has finished streaming to the client.
+        let upstream_sent = rec.upstream_sent;
+        let launched = rec.launched.take();
+        let metrics = Arc::clone(&self.metrics);
+        let result = result.map(|resp| {
+            resp.map(|body| {
+                CountingBody::new(body, move |n| {
+                    drop(inflight);
+                    drop(launched);
+                    metrics.route_bytes.inc(&[&ns_label, &route_label, "response"], n);
+                    if let Some(sent) = upstream_sent {
+                        metrics.upstream_duration.observe(&[&ns_label, &route_label], sent.elapsed());
+                    }
+                })
+                .boxed()
+            })
+        });
+        let event = RequestEvent {
+            id: 0,
+            request_id,
+            trace_id: trace.trace_id_hex(),
+            ts_ms: unix_ms(),
+            method,
+            host: host_header,
+            path,
+            route: rec.route.take(),
+            namespace: rec.namespace.take(),
+            target: rec.target.take(),
+            status,
+            latency_ms,
+            bytes,
+            error: rec.error,
+        };
+        if let Some(tracer) = &self.tracer
+            && trace.sampled()
+        {
+            record_spans(tracer, &trace, &rec.attempts, &event, received_at, remote);
+        }
+        self.inspector.publish(event);
+        result
+    }
 
+    /// Route and forward one request, filling `rec` with what was
+    /// decided along the way (matched rule, target, failure class).
+    async fn forward(
+        &self,
+        req: Request<Incoming>,
+        host_header: &str,
+        trace: &TraceContext,
+        rid: &str,
+        rec: &mut RequestRecord,
+    ) -> Result<Response<BoxBody>, BoxError> {
         // Route the host + path via the rule engine.
         let req_path = req.uri().path().to_string();
-        let (target_host, new_host) = match self.route(&host_header, &req_path) {
-            RouteDecision::Hit { target, host } => (target, host),
+        let (target_host, new_host, rule_headers) = match self.route(host_header, &req_path) {
+            RouteDecision::Hit { target, host, headers, route, namespace, access_log, launch } => {
+                rec.route = Some(route.clone());
+                rec.namespace = Some(namespace.clone());
+                rec.access_log = Some(access_log);
+                rec.target = Some(target.clone());
+                // Held until the process is up; a start that fails is
+                // the rule's answer.
+                if let Some(launch) = launch {
+                    match self.launcher.ensure_running(&namespace, &route, &launch).await {
+                        Ok(busy) => rec.launched = Some(busy),
+                        Err(e) => {
+                            error!("[{}] {} {}@{} 502 (launch failed: {})", rid, req.method(), host_header, target, e);
+                            rec.error = Some(ErrorClass::Launch);
+                            self.metrics.record_status(502);
+                            return error_response(
+                                StatusCode::BAD_GATEWAY,
+                                format!("502 Bad Gateway: could not start {}/{}: {}", namespace, route, e),
+                                rid,
+                            );
+                        }
+                    }
+                }
+                (target, host, headers)
+            }
             RouteDecision::Landing => {
-                info!("GET {} => LANDING 200", host_header);
+                info!("[{}] GET {} => LANDING 200", rid, host_header);
                 self.metrics.record_status(200);
                 return Ok(Response::builder()
                     .status(StatusCode::OK)
                     .header("Content-Type", "text/html; charset=utf-8")
-                    .body(Full::new(Bytes::from(Self::landing_page_html())).map_err(|e| match e {}).boxed())?);
+                    .body(Full::new(Bytes::from(self.landing_page_html())).map_err(|e| match e {}).boxed())?);
             }
             RouteDecision::Reject => {
                 let method = req.method();
                 let uri = req.uri();
-                info!("{} {} => REJECTED{} 502", method, host_header, uri);
+                info!("[{}] {} {} => REJECTED{} 502", rid, method, host_header, uri);
+                rec.error = Some(ErrorClass::Rejected);
                 self.metrics.host_rejected_total.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                 self.metrics.record_status(502);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(Full::new(Bytes::from("Bad Gateway: Host not allowed")).map_err(|e| match e {}).boxed())?);
+                return error_response(StatusCode::BAD_GATEWAY, "Bad Gateway: Host not allowed", rid);
             }
         };
 
@@ -334,17 +536,18 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
 
             // Apply domain filtering to CONNECT target
             let connect_host = connect_target.split(':').next().unwrap_or(&connect_target);
-            if let Some(ref domain) = self.domain_filter {
-                if !domain.is_empty() && !connect_host.ends_with(domain) {
-                    info!(
-                        "CONNECT {} => REJECTED{} 502",
-                        host_header,
-                        original_uri
-                    );
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_GATEWAY)
-                        .body(Full::new(Bytes::from("Bad Gateway: Host not allowed")).map_err(|e| match e {}).boxed())?);
-                }
+            if let Some(ref domain) = self.domain_filter
+                && !domain.is_empty()
+                && !connect_host.ends_with(domain)
+            {
+                info!(
+                    "[{}] CONNECT {} => REJECTED{} 502",
+                    rid,
+                    host_header,
+                    original_uri
+                );
+                rec.error = Some(ErrorClass::Rejected);
+                return error_response(StatusCode::BAD_GATEWAY, "Bad Gateway: Host not allowed", rid);
             }
 
             // Parse the connect target for routing
@@ -376,36 +579,54 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
             };
 
             info!(
-                "CONNECT {}@{}{} tunneling",
+                "[{}] CONNECT {}@{}{} tunneling",
+                rid,
                 host_header,
                 tunnel_target,
                 original_uri
             );
+            rec.target = Some(tunnel_target.clone());
 
             // Connect to upstream with timeout
+            rec.begin_attempt("tunnel");
             let connect_result = timeout(
                 Duration::from_secs(3),
                 TcpStream::connect(&tunnel_target)
             ).await;
+            rec.end_attempt(
+                None,
+                match &connect_result {
+                    Ok(Ok(_)) => None,
+                    Ok(Err(e)) => Some(e.to_string()),
+                    Err(_) => Some("connection timeout".to_string()),
+                },
+            );
 
             match connect_result {
                 Ok(Ok(upstream)) => {
                     // Spawn a task to handle the tunnel
+                    let rid = rid.to_string();
+                    let metrics = Arc::clone(&self.metrics);
                     tokio::spawn(async move {
                         // The upgrade happens after we return the response
                         // We need to use hyper's upgrade mechanism
                         match hyper::upgrade::on(req).await {
                             Ok(upgraded) => {
+                                let _active = metrics.track(|m| &m.active_tunnels);
                                 let mut upgraded = TokioIo::new(upgraded);
                                 let mut upstream = upstream;
 
                                 // Bidirectional copy
-                                if let Err(e) = copy_bidirectional(&mut upgraded, &mut upstream).await {
-                                    error!("Tunnel error: {}", e);
+                                match copy_bidirectional(&mut upgraded, &mut upstream).await {
+                                    Ok((to_upstream, to_client)) => {
+                                        metrics.relayed_bytes.inc(&["client_to_upstream"], to_upstream);
+                                        metrics.relayed_bytes.inc(&["upstream_to_client"], to_client);
+                                    }
+                                    Err(e) => error!("[{}] Tunnel error: {}", rid, e),
                                 }
                             }
                             Err(e) => {
-                                error!("Upgrade error: {}", e);
+                                error!("[{}] Upgrade error: {}", rid, e);
                             }
                         }
                     });
@@ -417,28 +638,26 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
                 }
                 Ok(Err(e)) => {
                     error!(
-                        "CONNECT {}@{}{} 502 ({})",
+                        "[{}] CONNECT {}@{}{} 502 ({})",
+                        rid,
                         host_header,
                         tunnel_target,
                         original_uri,
                         e
                     );
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_GATEWAY)
-                        .header("Content-Type", "text/plain")
-                        .body(Full::new(Bytes::from(format!("502 Bad Gateway: failed to connect to {}: {}", tunnel_target, e))).map_err(|e| match e {}).boxed())?);
+                    rec.error = Some(ErrorClass::Connect);
+                    return error_response(StatusCode::BAD_GATEWAY, format!("502 Bad Gateway: failed to connect to {}: {}", tunnel_target, e), rid);
                 }
                 Err(_) => {
                     error!(
-                        "CONNECT {}@{}{} 502 (connection timeout)",
+                        "[{}] CONNECT {}@{}{} 502 (connection timeout)",
+                        rid,
                         host_header,
                         tunnel_target,
                         original_uri
                     );
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_GATEWAY)
-                        .header("Content-Type", "text/plain")
-                        .body(Full::new(Bytes::from(format!("502 Bad Gateway: connection to {} timed out", tunnel_target))).map_err(|e| match e {}).boxed())?);
+                    rec.error = Some(ErrorClass::Timeout);
+                    return error_response(StatusCode::BAD_GATEWAY, format!("502 Bad Gateway: connection to {} timed out", tunnel_target), rid);
                 }
             }
         }
@@ -446,9 +665,13 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
         // Handle WebSocket upgrade requests
         if hyper_tungstenite::is_upgrade_request(&req) {
             self.metrics.websocket_upgrades_total.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            return self
-                .handle_websocket_upgrade(req, &target_host, &new_host)
+            let resp = self
+                .handle_websocket_upgrade(req, &target_host, &rule_headers, trace, rid, rec)
                 .await;
+            if matches!(&resp, Ok(r) if r.status() == StatusCode::BAD_GATEWAY) {
+                rec.error = Some(ErrorClass::Upgrade);
+            }
+            return resp;
         }
 
         // Build target URL for HTTP requests. parse_target_scheme handles
@@ -467,7 +690,14 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
 
         // Convert incoming body to a format the client can use
         let (mut parts, incoming_body) = req.into_parts();
-        let body = incoming_body.map_err(|e| e).boxed();
+        let ns_label = rec.namespace.clone().unwrap_or_default();
+        let route_label = rec.route.clone().unwrap_or_default();
+        let metrics = Arc::clone(&self.metrics);
+        let labels = (ns_label.clone(), route_label.clone());
+        let body = CountingBody::new(incoming_body, move |n| {
+            metrics.route_bytes.inc(&[&labels.0, &labels.1, "request"], n);
+        })
+        .boxed();
 
         // Update request URI and headers
         parts.uri = target_uri;
@@ -475,24 +705,41 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
         // version so the forwarded request is well-formed h1 regardless of how
         // the client connected.
         parts.version = hyper::Version::HTTP_11;
+        for (name, value) in rule_headers {
+            parts.headers.insert(name, value);
+        }
         parts.headers.insert(HOST, HeaderValue::from_str(&new_host)?);
+        let attempt_span = rec.begin_attempt("http");
+        set_trace_headers(&mut parts.headers, trace, &attempt_span, rid);
         // Preserve content-encoding header to maintain compression
 
         // Rebuild the request with the converted body
         let new_req = Request::from_parts(parts, body);
 
         // Forward the request with timeout
+        let sent = Instant::now();
         let request_result = timeout(
             Duration::from_secs(3),
             self.client.request(new_req)
         ).await;
+        rec.end_attempt(
+            request_result.as_ref().ok().and_then(|r| r.as_ref().ok()).map(|r| r.status().as_u16()),
+            match &request_result {
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(_) => Some("request timeout".to_string()),
+            },
+        );
 
         match request_result {
             Ok(Ok(response)) => {
+                self.metrics.upstream_ttfb.observe(&[&ns_label, &route_label], sent.elapsed());
+                rec.upstream_sent = Some(sent);
                 // Preserve content-encoding header in response to maintain compression
                 let status = response.status();
                 info!(
-                    "{} {}@{}{} {}",
+                    "[{}] {} {}@{}{} {}",
+                    rid,
                     method,
                     host_header,
                     target_host,
@@ -507,34 +754,32 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
             }
             Ok(Err(e)) => {
                 error!(
-                    "{} {}@{}{} 502 ({})",
+                    "[{}] {} {}@{}{} 502 ({})",
+                    rid,
                     method,
                     host_header,
                     target_host,
                     original_uri,
                     e
                 );
+                rec.error = Some(ErrorClass::Connect);
                 self.metrics.upstream_connect_failures_total.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                 self.metrics.record_status(502);
-                Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .header("Content-Type", "text/plain")
-                    .body(Full::new(Bytes::from(format!("502 Bad Gateway: failed to connect to {}: {}", target_host, e))).map_err(|e| match e {}).boxed())?)
+                error_response(StatusCode::BAD_GATEWAY, format!("502 Bad Gateway: failed to connect to {}: {}", target_host, e), rid)
             }
             Err(_) => {
                 error!(
-                    "{} {}@{}{} 502 (request timeout)",
+                    "[{}] {} {}@{}{} 502 (request timeout)",
+                    rid,
                     method,
                     host_header,
                     target_host,
                     original_uri
                 );
+                rec.error = Some(ErrorClass::Timeout);
                 self.metrics.upstream_timeouts_total.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                 self.metrics.record_status(502);
-                Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .header("Content-Type", "text/plain")
-                    .body(Full::new(Bytes::from(format!("502 Bad Gateway: request to {} timed out", target_host))).map_err(|e| match e {}).boxed())?)
+                error_response(StatusCode::BAD_GATEWAY, format!("502 Bad Gateway: request to {} timed out", target_host), rid)
             }
         }
     }
@@ -543,7 +788,10 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
         &self,
         req: Request<Incoming>,
         target_host: &str,
-        _new_host: &str, // Currently not used for WebSocket connections, but kept for consistency
+        rule_headers: &[(HeaderName, HeaderValue)],
+        trace: &TraceContext,
+        rid: &str,
+        rec: &mut RequestRecord,
     ) -> Result<Response<BoxBody>, BoxError> {
         let uri = req.uri().clone();
         let (scheme, authority) = parse_target_scheme(target_host);
@@ -572,10 +820,8 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
         let mut upstream_req = match ws_url.as_str().into_client_request() {
             Ok(r) => r,
             Err(e) => {
-                error!("WS :ws:{} => invalid upstream request {}: {}", target_host, uri, e);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .body(Full::new(Bytes::from(format!("502 Bad Gateway: invalid WebSocket target: {}", e))).map_err(|e| match e {}).boxed())?);
+                error!("[{}] WS :ws:{} => invalid upstream request {}: {}", rid, target_host, uri, e);
+                return error_response(StatusCode::BAD_GATEWAY, format!("502 Bad Gateway: invalid WebSocket target: {}", e), rid);
             }
         };
         // Forward the subprotocol, but deliberately NOT
@@ -597,17 +843,24 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
         if let Ok(v) = HeaderValue::from_str(&upstream_origin) {
             upstream_req.headers_mut().insert("origin", v);
         }
+        for (name, value) in rule_headers {
+            upstream_req.headers_mut().insert(name.clone(), value.clone());
+        }
+        let attempt_span = rec.begin_attempt("websocket");
+        set_trace_headers(upstream_req.headers_mut(), trace, &attempt_span, rid);
 
         // Step 1: Connect to upstream WebSocket FIRST before upgrading client
         // This ensures we can return proper errors if upstream is unavailable
-        let (upstream_ws, _) = match connect_async(upstream_req).await {
+        let connected = connect_async(upstream_req).await;
+        rec.end_attempt(
+            connected.as_ref().ok().map(|(_, resp)| resp.status().as_u16()),
+            connected.as_ref().err().map(|e| e.to_string()),
+        );
+        let (upstream_ws, _) = match connected {
             Ok(ws) => ws,
             Err(e) => {
-                error!("WS :ws:{} => :ws:{}{} 502 (upstream connection failed: {})", target_host, target_host, uri, e);
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_GATEWAY)
-                    .header("Content-Type", "text/plain")
-                    .body(Full::new(Bytes::from(format!("502 Bad Gateway: WebSocket upstream {} unavailable: {}", target_host, e))).map_err(|e| match e {}).boxed())?);
+                error!("[{}] WS :ws:{} => :ws:{}{} 502 (upstream connection failed: {})", rid, target_host, target_host, uri, e);
+                return error_response(StatusCode::BAD_GATEWAY, format!("502 Bad Gateway: WebSocket upstream {} unavailable: {}", target_host, e), rid);
             }
         };
 
@@ -615,39 +868,186 @@ npx fbi-proxy -d fbi.example.com  # Only accept *.fbi.example.com</pre>
         // Only do this after confirming upstream is available
         let (response, websocket) = hyper_tungstenite::upgrade(req, None)?;
 
-        // Step 3: Spawn task to handle WebSocket forwarding
+        // Step 3: Spawn task to handle WebSocket forwarding. When tracing,
+        // an INTERNAL span covers the socket's whole lifetime.
+        let task_rid = rid.to_string();
+        let metrics = Arc::clone(&self.metrics);
+        let ws_span = self.tracer.clone().filter(|_| trace.sampled()).map(|tracer| {
+            let mut span = otel::Span::new(
+                trace,
+                trace::new_span_id(),
+                Some(trace.span_id),
+                format!("websocket {}", target_host),
+                SpanKind::Internal,
+                SystemTime::now(),
+            );
+            span.attr("fbi.target", target_host).attr_opt("fbi.route", rec.route.clone());
+            (tracer, span)
+        });
+        let launched = rec.launched.take();
         tokio::spawn(async move {
-            if let Err(e) = handle_websocket_forwarding(websocket, upstream_ws).await {
-                error!("WebSocket forwarding error: {}", e);
+            let _active = metrics.track(|m| &m.active_websockets);
+            let _launched = launched;
+            let result = handle_websocket_forwarding(websocket, upstream_ws, &metrics, &task_rid).await;
+            if let Err(e) = &result {
+                error!("[{}] WebSocket forwarding error: {}", task_rid, e);
+            }
+            if let Some((tracer, mut span)) = ws_span {
+                span.end = SystemTime::now();
+                let lifetime = span.end.duration_since(span.start).unwrap_or_default();
+                span.attr("fbi.websocket.duration_ms", lifetime.as_millis() as u64);
+                match result {
+                    Ok((to_upstream, to_client)) => {
+                        span.attr("fbi.websocket.messages_to_upstream", to_upstream)
+                            .attr("fbi.websocket.messages_to_client", to_client);
+                    }
+                    Err(e) => span.error = Some(e.to_string()),
+                }
+                tracer.record(span);
             }
         });
 
-        info!("WS :ws:{} => :ws:{}{} 101", target_host, target_host, uri);
+        info!("[{}] WS :ws:{} => :ws:{}{} 101", rid, target_host, target_host, uri);
         let (parts, body) = response.into_parts();
         let boxed_body = body.map_err(|_: std::convert::Infallible| unreachable!()).boxed();
         Ok(Response::from_parts(parts, boxed_body))
     }
 }
 
-/// Parse a route target into (scheme, authority). Supports an optional
-/// `http://` or `https://` prefix; defaults to `http` so existing
-/// `host:port`-style targets keep working unchanged. Used by both the
-/// HTTP forwarder (chooses URL scheme) and the WebSocket upgrade path
-/// (chooses `ws` vs `wss`).
-fn parse_target_scheme(target: &str) -> (&'static str, &str) {
-    if let Some(rest) = target.strip_prefix("https://") {
-        ("https", rest)
-    } else if let Some(rest) = target.strip_prefix("http://") {
-        ("http", rest)
-    } else {
-        ("http", target)
+/// Stamp the request ID and trace context onto an upstream request, with
+/// the attempt's client span as the parent. `tracestate` is forwarded
+/// only if it survived validation alongside a valid `traceparent`.
+fn set_trace_headers(headers: &mut hyper::HeaderMap, trace: &TraceContext, parent: &[u8; 8], rid: &str) {
+    if let Ok(v) = HeaderValue::from_str(rid) {
+        headers.insert(trace::REQUEST_ID_HEADER, v);
+    }
+    if let Ok(v) = HeaderValue::from_str(&trace.traceparent_for(parent)) {
+        headers.insert(trace::TRACEPARENT_HEADER, v);
+    }
+    match trace.tracestate.as_deref().map(HeaderValue::from_str) {
+        Some(Ok(v)) => {
+            headers.insert(trace::TRACESTATE_HEADER, v);
+        }
+        _ => {
+            headers.remove(trace::TRACESTATE_HEADER);
+        }
+    }
+}
+
+/// Plain-text error page from the proxy itself. The request ID is part of
+/// the body so a pasted error page leads to the matching log line.
+fn error_response(status: StatusCode, msg: impl std::fmt::Display, rid: &str) -> Result<Response<BoxBody>, BoxError> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Full::new(Bytes::from(format!("{}\nRequest ID: {}\n", msg, rid))).map_err(|e| match e {}).boxed())?)
+}
+
+/// What `FBIProxy::forward` learned about a request, reported to the
+/// inspector by `handle_request` together with status and latency.
+#[derive(Default)]
+struct RequestRecord {
+    route: Option<String>,
+    namespace: Option<String>,
+    target: Option<String>,
+    error: Option<ErrorClass>,
+    /// Matched rule's access-log policy (`None` → log unconditionally).
+    access_log: Option<AccessLogPolicy>,
+    /// Upstream attempts, in order (exported as CLIENT spans).
+    attempts: Vec<UpstreamAttempt>,
+    /// When the HTTP request that produced the response was sent upstream.
+    upstream_sent: Option<Instant>,
+    /// Keeps the rule's on-demand process from idling out while the
+    /// response (or WebSocket) is still in use.
+    launched: Option<launch::Busy>,
+}
+
+/// One upstream connect/request attempt.
+struct UpstreamAttempt {
+    span_id: [u8; 8],
+    /// `http`, `websocket` or `tunnel`.
+    kind: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+impl RequestRecord {
+    /// Start timing an attempt; returns its span ID for `traceparent`.
+    fn begin_attempt(&mut self, kind: &'static str) -> [u8; 8] {
+        let now = SystemTime::now();
+        let span_id = trace::new_span_id();
+        self.attempts.push(UpstreamAttempt { span_id, kind, start: now, end: now, status: None, error: None });
+        span_id
+    }
+
+    fn end_attempt(&mut self, status: Option<u16>, error: Option<String>) {
+        if let Some(a) = self.attempts.last_mut() {
+            a.end = SystemTime::now();
+            a.status = status;
+            a.error = error;
+        }
+    }
+}
+
+/// Export the request's SERVER span and one CLIENT span per upstream
+/// attempt.
+fn record_spans(
+    tracer: &Tracer,
+    trace: &TraceContext,
+    attempts: &[UpstreamAttempt],
+    ev: &RequestEvent,
+    start: SystemTime,
+    remote: SocketAddr,
+) {
+    let name = match &ev.route {
+        Some(route) => format!("{} {}", ev.method, route),
+        None => ev.method.clone(),
+    };
+    let mut server = otel::Span::new(trace, trace.span_id, trace.parent_id, name, SpanKind::Server, start);
+    server.end = start + Duration::from_millis(ev.latency_ms);
+    server
+        .attr("http.request.method", ev.method.as_str())
+        .attr("url.path", ev.path.as_str())
+        .attr("server.address", ev.host.as_str())
+        .attr("client.address", remote.ip().to_string())
+        .attr("http.response.status_code", ev.status)
+        .attr("fbi.request_id", ev.request_id.as_str())
+        .attr_opt("fbi.route", ev.route.clone())
+        .attr_opt("fbi.namespace", ev.namespace.clone())
+        .attr_opt("fbi.target", ev.target.clone())
+        .attr("fbi.retries", attempts.len().saturating_sub(1) as u64)
+        .attr_opt("error.type", ev.error.map(|e| e.as_str()));
+    if ev.error.is_some() || ev.status >= 500 {
+        server.error = Some(ev.error.map(|e| e.as_str()).unwrap_or("server error").to_string());
+    }
+    tracer.record(server);
+
+    for (i, a) in attempts.iter().enumerate() {
+        let mut client = otel::Span::new(trace, a.span_id, Some(trace.span_id), ev.method.clone(), SpanKind::Client, a.start);
+        client.end = a.end;
+        client
+            .attr("fbi.upstream.kind", a.kind)
+            .attr_opt("server.address", ev.target.clone())
+            .attr_opt("http.response.status_code", a.status)
+            .attr_opt("error.type", a.error.clone());
+        if i > 0 {
+            client.attr("http.request.resend_count", i as u64);
+        }
+        client.error = a.error.clone();
+        tracer.record(client);
     }
 }
 
+/// Pump frames both ways until either side closes. Returns the number
+/// of messages sent (to upstream, to client). `rid` tags its log lines.
 async fn handle_websocket_forwarding(
     websocket: HyperWebsocket,
     upstream_ws: WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-) -> Result<(), BoxError> {
+    metrics: &Metrics,
+    rid: &str,
+) -> Result<(u64, u64), BoxError> {
     // Get the client WebSocket stream
     let client_ws = websocket.await?;
 
@@ -655,13 +1055,18 @@ async fn handle_websocket_forwarding(
     let (mut upstream_sink, mut upstream_stream) = upstream_ws.split();
 
     // Forward messages from client to upstream
+    let mut to_upstream = 0u64;
+    let mut to_client = 0u64;
     let client_to_upstream = async {
         while let Some(msg) = client_stream.next().await {
             match msg {
                 Ok(msg) => {
+                    let len = msg.len() as u64;
                     if upstream_sink.send(msg).await.is_err() {
                         break;
                     }
+                    to_upstream += 1;
+                    metrics.relayed_bytes.inc(&["client_to_upstream"], len);
                 }
                 Err(_) => break,
             }
@@ -676,12 +1081,15 @@ async fn handle_websocket_forwarding(
         while let Some(msg) = upstream_stream.next().await {
             match msg {
                 Ok(msg) => {
+                    let len = msg.len() as u64;
                     if client_sink.send(msg).await.is_err() {
                         break;
                     }
+                    to_client += 1;
+                    metrics.relayed_bytes.inc(&["upstream_to_client"], len);
                 }
                 Err(e) => {
-                    warn!("[ws] upstream recv error: {}", e);
+                    warn!("[{}] [ws] upstream recv error: {}", rid, e);
                     break;
                 }
             }
@@ -693,14 +1101,15 @@ async fn handle_websocket_forwarding(
         _ = client_to_upstream => {},
         _ = upstream_to_client => {},
     }
-    Ok(())
+    Ok((to_upstream, to_client))
 }
 
 async fn handle_connection(
     req: Request<Incoming>,
     proxy: Arc<FBIProxy>,
+    remote: SocketAddr,
 ) -> Result<Response<BoxBody>, Infallible> {
-    match proxy.handle_request(req).await {
+    match proxy.handle_request(req, remote).await {
         Ok(response) => Ok(response),
         Err(e) => {
             error!("Request handling error: {}", e);
@@ -721,19 +1130,49 @@ fn load_routes(yaml_src: &str, source_label: &str) -> Vec<CompiledRoute> {
         Ok(p) => p,
         Err(e) => panic!("failed to parse {}: {}", source_label, e),
     };
-    match routes::compile(parsed.routes) {
-        Ok(c) => c,
+    match parsed.rules().and_then(routes::compile) {
+        Ok(mut c) => {
+            routes::sort_by_priority(&mut c);
+            c
+        }
         Err(e) => panic!("failed to compile {}: {}", source_label, e),
     }
 }
 
+/// The headers a hit adds to the upstream request. Values holding a
+/// secret are marked sensitive, so `Debug` hides them and HTTP/2 never
+/// indexes them; names or values that aren't valid HTTP are dropped.
+fn upstream_headers(hit: &RouteHit) -> Vec<(HeaderName, HeaderValue)> {
+    hit.upstream_headers()
+        .filter_map(|(k, v)| {
+            let name = HeaderName::from_bytes(k.as_bytes()).ok()?;
+            let mut value = HeaderValue::from_str(v).ok()?;
+            value.set_sensitive(hit.secret_headers.iter().any(|(name, _)| name == k));
+            Some((name, value))
+        })
+        .collect()
+}
+
 /// Shared state for the loopback admin/control server.
 struct AdminState {
     metrics: Arc<Metrics>,
+    inspector: Arc<Inspector>,
+    route_stats: Arc<RouteStats>,
     routes_handle: Arc<ArcSwap<Vec<CompiledRoute>>>,
-    /// conf.d directory. `Some` enables the mutating `/rules` endpoints;
-    /// `None` (legacy `--routes` single-file mode) makes them 409.
-    conf_dir: Option<std::path::PathBuf>,
+    /// conf.d loader. `Some` enables the mutating `/rules` endpoints and
+    /// `/status`; `None` (legacy `--routes` single-file mode) makes them
+    /// 409.
+    conf: Option<Arc<ConfD>>,
+    /// Reloads the rules (`POST /reload`) and tracks the config
+    /// generation; `None` when there is no routes source to reload.
+    reloader: Option<Arc<Reloader>>,
+    /// TLS settings when `--tls` is on; `GET /tls` reports the cert.
+    tls: Option<TlsOptions>,
+    /// The proxy's `--domain` filter, so `GET /explain` routes exactly
+    /// like the proxy does.
+    domain_filter: Option<String>,
+    /// On-demand processes of `launch:` rules (`/processes`).
+    launcher: Arc<Launcher>,
 }
 
 fn admin_text(status: StatusCode, content_type: &str, body: String) -> Response<BoxBody> {
@@ -752,29 +1191,240 @@ fn admin_err(status: StatusCode, msg: &str) -> Response<BoxBody> {
     admin_json(status, serde_json::json!({ "error": msg }).to_string())
 }
 
-/// A namespace must be a safe filename stem (it becomes `<ns>.yaml`).
+/// Decode `%XX` escapes and `+` (as space) in a query component.
+/// Malformed escapes are kept verbatim.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Parse the request's query string into a map (last value wins).
+fn query_params(uri: &Uri) -> std::collections::HashMap<String, String> {
+    uri.query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.split_once('=') {
+            Some((k, v)) => (percent_decode(k), percent_decode(v)),
+            None => (percent_decode(kv), String::new()),
+        })
+        .collect()
+}
+
+/// Build an inspector filter from `?host=&route=&status=&request_id=`.
+fn request_filter(params: &std::collections::HashMap<String, String>) -> Result<RequestFilter, String> {
+    let non_empty = |k: &str| params.get(k).filter(|v| !v.is_empty()).cloned();
+    let status = match non_empty("status") {
+        Some(s) => Some(
+            StatusFilter::parse(&s)
+                .ok_or_else(|| format!("invalid status filter '{}' (expected e.g. 502 or 5xx)", s))?,
+        ),
+        None => None,
+    };
+    Ok(RequestFilter {
+        host: non_empty("host"),
+        route: non_empty("route"),
+        status,
+        request_id: non_empty("request_id"),
+    })
+}
+
+/// `GET /requests/stream` — Server-Sent Events, one `request` event per
+/// proxied request matching the filter. A comment line is sent every
+/// 15s so idle streams notice disconnected clients.
+fn requests_stream(inspector: &Inspector, filter: RequestFilter) -> Response<BoxBody> {
+    use hyper::body::Frame;
+    use tokio::sync::broadcast::error::RecvError;
+
+    const KEEPALIVE: Duration = Duration::from_secs(15);
+    let rx = inspector.subscribe();
+    let stream = futures_util::stream::unfold((rx, filter), |(mut rx, filter)| async move {
+        loop {
+            let chunk = match timeout(KEEPALIVE, rx.recv()).await {
+                Err(_) => ": keepalive\n\n".to_string(),
+                Ok(Ok(ev)) => {
+                    if !filter.matches(&ev) {
+                        continue;
+                    }
+                    let data = serde_json::to_string(&ev).unwrap_or_default();
+                    format!("id: {}\nevent: request\ndata: {}\n\n", ev.id, data)
+                }
+                Ok(Err(RecvError::Lagged(n))) => format!(": dropped {} event(s)\n\n", n),
+                Ok(Err(RecvError::Closed)) => return None,
+            };
+            return Some((Ok::<_, hyper::Error>(Frame::data(Bytes::from(chunk))), (rx, filter)));
+        }
+    });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(BodyExt::boxed(http_body_util::StreamBody::new(stream)))
+        .unwrap()
+}
+
+/// A namespace must be a safe relative path (it becomes
+/// `<conf.d>/<ns>.yaml`, `.json` or `.toml`): `/`-separated segments of
+/// 1-64 of `A-Za-z0-9_-`, so no `.`, `..` or empty segment can escape
+/// conf.d or name a hidden file.
 fn is_valid_namespace(ns: &str) -> bool {
-    !ns.is_empty()
-        && ns.len() <= 64
-        && ns.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    ns.len() <= 255
+        && ns.split('/').all(|seg| {
+            !seg.is_empty() && seg.len() <= 64 && seg.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
 }
 
-/// Serialize the live compiled routes to a JSON array for `GET /rules`.
-fn rules_to_json(routes: &[CompiledRoute]) -> String {
-    let arr: Vec<serde_json::Value> = routes
+/// The `GET /rules` entries for `routes`. Rules of a namespace served
+/// from its last-good fragment are `stale`.
+fn rule_infos(routes: &[CompiledRoute], stats: &RouteStats, conf: Option<&ConfD>) -> Vec<RuleInfo> {
+    let status = conf.map(ConfD::status).unwrap_or_default();
+    let stale = |ns: &str| status.get(ns).is_some_and(|n| n.state == confd::NamespaceState::LastGood);
+    routes
         .iter()
-        .map(|r| {
-            serde_json::json!({
-                "namespace": r.namespace,
-                "name": r.name,
-                "match": r.match_pattern,
-                "path": r.path_prefix,
-                "target": r.target_template,
-                "headers": r.header_templates,
-            })
-        })
-        .collect();
-    serde_json::to_string(&arr).unwrap_or_else(|_| "[]".to_string())
+        .zip(stats.snapshot(routes))
+        .map(|(r, c)| RuleInfo { stale: stale(&r.namespace), ..RuleInfo::new(r, &c) })
+        .collect()
+}
+
+/// [`rule_infos`] without the hit counters: just the rules' config.
+fn rule_configs(routes: &[CompiledRoute], conf: Option<&ConfD>) -> Vec<RuleInfo> {
+    let status = conf.map(ConfD::status).unwrap_or_default();
+    let stale = |ns: &str| status.get(ns).is_some_and(|n| n.state == confd::NamespaceState::LastGood);
+    routes.iter().map(|r| RuleInfo { stale: stale(&r.namespace), ..RuleInfo::new(r, &Default::default()) }).collect()
+}
+
+/// The merged set's ETag: of the live rules' config, so hit counters
+/// don't change it.
+fn merged_etag(routes: &[CompiledRoute], conf: Option<&ConfD>) -> String {
+    confd::etag(serde_json::to_string(&rule_configs(routes, conf)).unwrap_or_default().as_bytes())
+}
+
+/// The live compiled routes as a list for `GET /rules`: JSON, or YAML
+/// if `accept` prefers it.
+fn rules_response(accept: &str, routes: &[CompiledRoute], stats: &RouteStats, conf: Option<&ConfD>) -> Response<BoxBody> {
+    let arr = rule_infos(routes, stats, conf);
+    match Format::negotiate(accept, &[Format::Json, Format::Yaml]) {
+        Some(Format::Yaml) => admin_text(StatusCode::OK, Format::Yaml.media_type(), Format::Yaml.serialize(&arr).unwrap_or_default()),
+        _ => admin_json(StatusCode::OK, serde_json::to_string(&arr).unwrap_or_else(|_| "[]".to_string())),
+    }
+}
+
+/// A request header as text; missing or non-UTF-8 is empty.
+fn header_str<'a>(req: &'a Request<Incoming>, name: &str) -> &'a str {
+    req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("")
+}
+
+/// How one request routes, plus the request-level context
+/// [`routes::Explanation`] leaves to the caller.
+struct RouteExplanation {
+    method: String,
+    apex: bool,
+    /// `hit`, `landing` or `reject`.
+    decision: &'static str,
+    explanation: routes::Explanation,
+}
+
+/// Explain `method host path` the way the proxy would route it. Rules
+/// never match on method; it only matters in that CONNECT carries no
+/// path.
+fn explain_route(routes: &[CompiledRoute], domain_filter: Option<&str>, host: &str, path: &str, method: &str) -> RouteExplanation {
+    let method = method.to_ascii_uppercase();
+    let path = if method == "CONNECT" { "" } else { path };
+    let (req_path, is_apex) = routes::routing_inputs(domain_filter, host, path);
+    let explanation = routes::explain_request(routes, host, req_path, domain_filter, is_apex);
+    let decision = match &explanation.hit {
+        Some(_) => "hit",
+        None if is_apex => "landing",
+        None => "reject",
+    };
+    RouteExplanation { method, apex: is_apex, decision, explanation }
+}
+
+impl RouteExplanation {
+    fn to_json(&self) -> Result<serde_json::Value, String> {
+        let upstream_host = self.explanation.hit.as_ref().map(RouteHit::upstream_host);
+        let mut out = serde_json::to_value(&self.explanation).map_err(|e| e.to_string())?;
+        if let Some(obj) = out.as_object_mut() {
+            obj.insert("method".into(), self.method.clone().into());
+            obj.insert("apex".into(), self.apex.into());
+            obj.insert("decision".into(), self.decision.into());
+            obj.insert("upstream_host".into(), upstream_host.into());
+        }
+        Ok(out)
+    }
+
+    /// Human-readable form, matching `fbi-proxy explain` in the CLI.
+    fn print(&self) {
+        let ex = &self.explanation;
+        println!("{} {}{}", self.method, ex.host, ex.path);
+        if !ex.domain_allowed {
+            println!("  rejected: host is not under the domain '{}'", ex.domain.as_deref().unwrap_or(""));
+            return;
+        }
+        if self.apex {
+            println!("  apex host: only rules with an explicit path are eligible");
+        }
+        for c in &ex.candidates {
+            let mark = if c.winner {
+                "=>"
+            } else if matches!(c.verdict, routes::Verdict::Matched { .. }) {
+                " ~"
+            } else {
+                "  "
+            };
+            let path = c.path.as_deref().map(|p| format!(" {}", p)).unwrap_or_default();
+            println!("{} {}/{}  {}{}", mark, c.namespace, c.name, c.match_pattern, path);
+            println!("     {}", c.reason);
+        }
+        match &ex.hit {
+            Some(hit) => {
+                println!("\nwinner: {}/{} -> {} (Host: {})", hit.namespace, hit.route_name, hit.target, hit.upstream_host());
+                for (k, v) in &ex.captures {
+                    println!("  {{{}}} = {}", k, v);
+                }
+                let mut headers: Vec<_> = hit.other_headers.iter().collect();
+                headers.sort();
+                for (k, v) in headers {
+                    println!("  {}: {}", k, v);
+                }
+            }
+            None if self.decision == "landing" => println!("\nno rule matched: serves the built-in landing page"),
+            None => println!("\nno rule matched: rejected with 502"),
+        }
+    }
+}
+
+/// `GET /explain?host=&path=&method=` — how the live rules route one
+/// request: every rule's verdict, the winner, and the expanded target,
+/// outgoing `Host` and headers (see [`explain_route`]).
+fn explain_to_json(
+    routes: &[CompiledRoute],
+    domain_filter: Option<&str>,
+    params: &std::collections::HashMap<String, String>,
+) -> Result<String, String> {
+    let host = params.get("host").filter(|h| !h.is_empty()).ok_or("missing ?host=")?;
+    let method = params.get("method").map(String::as_str).filter(|m| !m.is_empty()).unwrap_or("GET");
+    let path = params.get("path").map_or("/", String::as_str);
+    Ok(explain_route(routes, domain_filter, host, path, method).to_json()?.to_string())
 }
 
 async fn handle_admin(req: Request<Incoming>, state: Arc<AdminState>) -> Response<BoxBody> {
@@ -782,35 +1432,114 @@ async fn handle_admin(req: Request<Incoming>, state: Arc<AdminState>) -> Respons
     let path = req.uri().path().to_string();
 
     match (&method, path.as_str()) {
+        (&Method::GET, "/") | (&Method::GET, "/dashboard") => {
+            admin_text(StatusCode::OK, "text/html; charset=utf-8", DASHBOARD_HTML.to_string())
+        }
+        (&Method::GET, "/tls") => match &state.tls {
+            Some(opts) => {
+                let info = fbi_proxy::tls::cert_info(&opts.domain, &opts.cert_dir);
+                admin_json(StatusCode::OK, serde_json::to_string(&info).unwrap_or_default())
+            }
+            None => admin_err(StatusCode::NOT_FOUND, "TLS termination is not enabled"),
+        },
         (&Method::GET, "/metrics") => {
+            if let Some(conf) = &state.conf {
+                record_conf_status(&state.metrics, &conf.status());
+            }
             admin_text(StatusCode::OK, "text/plain; version=0.0.4", state.metrics.render_prometheus())
         }
         (&Method::GET, "/rules") => {
             let routes = state.routes_handle.load();
-            admin_json(StatusCode::OK, rules_to_json(routes.as_ref()))
+            let etag = merged_etag(routes.as_ref(), state.conf.as_deref());
+            if header_str(&req, "if-none-match").split(',').any(|t| t.trim().trim_start_matches("W/") == etag) {
+                let not_modified = admin_text(StatusCode::NOT_MODIFIED, "application/json", String::new());
+                return with_etag(not_modified, Some(etag));
+            }
+            let resp = rules_response(header_str(&req, "accept"), routes.as_ref(), &state.route_stats, state.conf.as_deref());
+            with_etag(resp, Some(etag))
+        }
+        (&Method::GET, "/schema") => admin_json(StatusCode::OK, schema::json_schema().to_string()),
+        (&Method::GET, "/status") => {
+            let body = StatusBody {
+                reload: state.reloader.as_ref().map(|r| r.status()),
+                conf: state.conf.as_ref().map(|c| c.status()),
+            };
+            admin_json(StatusCode::OK, serde_json::to_string(&body).unwrap_or_default())
+        }
+        (&Method::POST, "/reload") => match &state.reloader {
+            Some(reloader) => {
+                let outcome = reloader.reload("api");
+                let status = if outcome.failed().is_some() { StatusCode::CONFLICT } else { StatusCode::OK };
+                admin_json(status, serde_json::to_string(&outcome).unwrap_or_default())
+            }
+            None => admin_err(StatusCode::CONFLICT, "no routes source to reload"),
+        },
+        (&Method::GET, "/lint") => {
+            let routes = state.routes_handle.load();
+            let params = query_params(req.uri());
+            let findings: Vec<lint::Lint> = lint::lint(routes.as_ref())
+                .into_iter()
+                .filter(|l| params.get("namespace").is_none_or(|ns| ns.is_empty() || *ns == l.namespace))
+                .collect();
+            admin_json(StatusCode::OK, serde_json::to_string(&findings).unwrap_or_else(|_| "[]".to_string()))
+        }
+        (&Method::GET, "/explain") => {
+            let routes = state.routes_handle.load();
+            match explain_to_json(routes.as_ref(), state.domain_filter.as_deref(), &query_params(req.uri())) {
+                Ok(body) => admin_json(StatusCode::OK, body),
+                Err(e) => admin_err(StatusCode::BAD_REQUEST, &e),
+            }
+        }
+        (&Method::GET, "/requests") => {
+            let params = query_params(req.uri());
+            let filter = match request_filter(&params) {
+                Ok(f) => f,
+                Err(e) => return admin_err(StatusCode::BAD_REQUEST, &e),
+            };
+            let limit = params
+                .get("limit")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(inspector::DEFAULT_CAPACITY);
+            let events = state.inspector.recent(&filter, limit);
+            admin_json(StatusCode::OK, serde_json::to_string(&events).unwrap_or_else(|_| "[]".to_string()))
+        }
+        (&Method::GET, "/requests/stream") => match request_filter(&query_params(req.uri())) {
+            Ok(filter) => requests_stream(&state.inspector, filter),
+            Err(e) => admin_err(StatusCode::BAD_REQUEST, &e),
+        },
+        // Nested namespaces are `/`-separated; a client may also send
+        // them as one `%2F`-escaped segment.
+        (_, p) if p.starts_with("/rules/") => {
+            let target = percent_decode(p.trim_start_matches("/rules/"));
+            handle_rules_path(req, state, &target).await
         }
-        (&Method::PUT, p) if p.starts_with("/rules/") => {
-            let ns = p.trim_start_matches("/rules/").to_string();
-            handle_put_rules(req, state, ns).await
+        (&Method::GET, p) if p == "/history" || p.starts_with("/history/") => handle_history(&req, &state, p),
+        (&Method::POST, p) if p.starts_with("/rollback/") => rollback(&state, p.trim_start_matches("/rollback/")),
+        (_, p) if p == "/leases" || p.starts_with("/leases/") => {
+            let ns = percent_decode(p.trim_start_matches("/leases").trim_start_matches('/'));
+            handle_leases(&req, &state, &ns)
         }
-        (&Method::DELETE, p) if p.starts_with("/rules/") => {
-            let ns = p.trim_start_matches("/rules/").to_string();
-            handle_delete_rules(state, ns).await
+        (_, p) if p == "/processes" || p.starts_with("/processes/") => {
+            let key = percent_decode(p.trim_start_matches("/processes").trim_start_matches('/'));
+            handle_processes(&req, &state, &key).await
         }
         _ => admin_err(StatusCode::NOT_FOUND, "not found"),
     }
 }
 
-/// Reconcile namespace `ns` to the rules in the request body: validate +
-/// compile, write `<conf_dir>/<ns>.yaml`, then rebuild + atomically swap
-/// the live route set. Returns the new merged rule list on success.
-async fn handle_put_rules(
-    req: Request<Incoming>,
-    state: Arc<AdminState>,
-    ns: String,
-) -> Response<BoxBody> {
-    let conf_dir = match &state.conf_dir {
-        Some(d) => d.clone(),
+/// `/rules/{ns}` and `/rules/{ns}/{route}` (conf.d mode): one
+/// namespace's fragment, or one rule in it. Namespaces nest, so which a
+/// path names depends on what exists: `GET` and `DELETE` take it as a
+/// namespace if there is one by that name (or below it, for `DELETE`),
+/// else as `{ns}/{route}`. `PUT` always names a namespace; `POST` and
+/// `PATCH` always a rule.
+///
+/// Every write honours `If-Match` / `If-None-Match` against the
+/// namespace's ETag (or the merged set's, from `GET /rules`), and with
+/// `?dry_run=1` only reports what it would do.
+async fn handle_rules_path(req: Request<Incoming>, state: Arc<AdminState>, path: &str) -> Response<BoxBody> {
+    let conf = match &state.conf {
+        Some(c) => Arc::clone(c),
         None => {
             return admin_err(
                 StatusCode::CONFLICT,
@@ -818,113 +1547,684 @@ async fn handle_put_rules(
             )
         }
     };
-    if !is_valid_namespace(&ns) {
-        return admin_err(
-            StatusCode::BAD_REQUEST,
-            "invalid namespace (allowed: A-Za-z0-9_-, max 64 chars)",
-        );
-    }
-
-    let body_bytes = match req.into_body().collect().await {
-        Ok(b) => b.to_bytes(),
-        Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("read body: {}", e)),
+    let namespaces = match confd::namespaces(&conf.dir) {
+        Ok(n) => n,
+        Err(e) => return admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
     };
-    let src = String::from_utf8_lossy(&body_bytes);
-    let parsed = match routes::parse_yaml(&src) {
-        Ok(p) => p,
-        Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("parse: {}", e)),
+    let exists = |ns: &str| namespaces.iter().any(|n| n == ns);
+    let as_rule = path.rsplit_once('/').filter(|(ns, _)| exists(ns));
+    let method = req.method().clone();
+    let target = match (&method, as_rule) {
+        (&Method::GET, Some((ns, route))) if !exists(path) => RulesTarget::Rule(ns, route),
+        (&Method::DELETE, Some((ns, route))) if !namespaces.iter().any(|n| confd::in_subtree(n, path)) => {
+            RulesTarget::Rule(ns, route)
+        }
+        (&Method::POST | &Method::PATCH, _) => match path.rsplit_once('/') {
+            Some((ns, route)) => RulesTarget::Rule(ns, route),
+            None => return admin_err(StatusCode::BAD_REQUEST, "expected /rules/{namespace}/{route}"),
+        },
+        _ => RulesTarget::Namespace(path),
     };
-    // Validate by compiling under this namespace *before* touching disk.
-    if let Err(e) = routes::compile_in_namespace(parsed.routes.clone(), &ns) {
-        return admin_err(StatusCode::BAD_REQUEST, &format!("compile: {}", e));
-    }
-    let yaml = match serde_yaml::to_string(&parsed) {
-        Ok(y) => y,
-        Err(e) => return admin_err(StatusCode::INTERNAL_SERVER_ERROR, &format!("serialize: {}", e)),
+    let ns = match target {
+        RulesTarget::Namespace(ns) | RulesTarget::Rule(ns, _) => ns,
     };
-    let frag_path = conf_dir.join(format!("{}.yaml", ns));
-    if let Err(e) = std::fs::write(&frag_path, yaml) {
+    if !is_valid_namespace(ns) {
         return admin_err(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            &format!("write {}: {}", frag_path.display(), e),
+            StatusCode::BAD_REQUEST,
+            "invalid namespace (allowed: /-separated segments of A-Za-z0-9_-, max 64 chars each)",
         );
     }
-    match rebuild_routes(&conf_dir, BUNDLED_ROUTES_YAML) {
-        Ok(merged) => state.routes_handle.store(Arc::new(merged)),
-        Err(e) => {
-            return admin_err(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("rebuild after write: {}", e),
-            )
+    let accept = header_str(&req, "accept").to_string();
+    match (method, target) {
+        (Method::GET, target) => get_rules_target(&conf, target, &accept),
+        (Method::PUT, RulesTarget::Namespace(ns)) => handle_put_rules(req, state, &conf, ns).await,
+        (Method::DELETE, RulesTarget::Namespace(ns)) => {
+            let opts = WriteOptions::of(&req);
+            let nested = !query_params(req.uri()).get("keep_nested").is_some_and(|v| matches!(v.as_str(), "1" | "true"));
+            commit_write(&state, &conf, ns, &opts, || Ok(NamespaceWrite::Remove { nested }))
         }
+        (method, RulesTarget::Rule(ns, route)) => handle_rule_edit(req, state, &conf, method, ns, route).await,
+        _ => admin_err(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
     }
-    info!("[admin] applied {} rule(s) to namespace '{}'", parsed.routes.len(), ns);
-    let routes = state.routes_handle.load();
-    admin_json(StatusCode::OK, rules_to_json(routes.as_ref()))
 }
 
-/// Remove namespace `ns`: delete its fragment, rebuild + swap.
-async fn handle_delete_rules(state: Arc<AdminState>, ns: String) -> Response<BoxBody> {
-    let conf_dir = match &state.conf_dir {
-        Some(d) => d.clone(),
-        None => {
-            return admin_err(
-                StatusCode::CONFLICT,
-                "rule mutation requires conf.d mode (started with --routes single-file mode)",
-            )
-        }
+/// What a `/rules/...` path names.
+#[derive(Clone, Copy)]
+enum RulesTarget<'a> {
+    Namespace(&'a str),
+    Rule(&'a str, &'a str),
+}
+
+/// `GET /rules/{ns}` — the fragment as written (`include:` kept), in the
+/// format `Accept` asks for — or `GET /rules/{ns}/{route}`, one rule of
+/// it. Both carry the namespace's ETag.
+fn get_rules_target(conf: &ConfD, target: RulesTarget, accept: &str) -> Response<BoxBody> {
+    let ns = match target {
+        RulesTarget::Namespace(ns) | RulesTarget::Rule(ns, _) => ns,
     };
-    if !is_valid_namespace(&ns) {
-        return admin_err(
-            StatusCode::BAD_REQUEST,
-            "invalid namespace (allowed: A-Za-z0-9_-, max 64 chars)",
-        );
-    }
-    let frag_path = conf_dir.join(format!("{}.yaml", ns));
-    let existed = frag_path.exists();
-    if existed {
-        if let Err(e) = std::fs::remove_file(&frag_path) {
-            return admin_err(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("remove {}: {}", frag_path.display(), e),
-            );
-        }
-    }
-    match rebuild_routes(&conf_dir, BUNDLED_ROUTES_YAML) {
-        Ok(merged) => state.routes_handle.store(Arc::new(merged)),
+    let (file, _) = match confd::read_namespace(&conf.dir, ns, conf.strict) {
+        Ok(Some(found)) => found,
+        Ok(None) => return admin_err(StatusCode::NOT_FOUND, &format!("no namespace '{}'", ns)),
         Err(e) => {
-            return admin_err(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                &format!("rebuild after delete: {}", e),
-            )
+            // Still tagged, so a client can overwrite the broken file.
+            let resp = admin_err(StatusCode::CONFLICT, &format!("namespace '{}' does not load: {}", ns, e));
+            return with_etag(resp, namespace_etag(conf, ns));
         }
+    };
+    let format = Format::negotiate(accept, &[Format::Json, Format::Yaml, Format::Toml]).unwrap_or(Format::Json);
+    let body = match target {
+        RulesTarget::Namespace(_) => format.serialize(&file),
+        RulesTarget::Rule(_, name) => match file.routes.iter().find(|r| r.name == name) {
+            Some(route) => format.serialize(&schema::RouteV2::from(route.clone())),
+            None => return admin_err(StatusCode::NOT_FOUND, &format!("no rule '{}' in namespace '{}'", name, ns)),
+        },
+    };
+    match body {
+        Ok(body) => with_etag(admin_text(StatusCode::OK, format.media_type(), body), namespace_etag(conf, ns)),
+        Err(e) => admin_err(StatusCode::INTERNAL_SERVER_ERROR, &format!("serialize: {}", e)),
     }
-    info!("[admin] removed namespace '{}' (existed: {})", ns, existed);
-    admin_json(StatusCode::OK, serde_json::json!({ "ok": true, "removed": existed }).to_string())
 }
 
-/// Run the loopback admin/control server on an already-bound listener.
-/// Serves `GET /metrics`, `GET /rules`, `PUT /rules/{ns}`,
-/// `DELETE /rules/{ns}`. Binds loopback-only so it is never reachable
-/// from the user-facing proxy port.
-async fn serve_admin(state: Arc<AdminState>, listener: TcpListener) -> Result<(), BoxError> {
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let state = Arc::clone(&state);
-        let io = TokioIo::new(stream);
-        tokio::spawn(async move {
-            let service = service_fn(move |req: Request<Incoming>| {
-                let state = Arc::clone(&state);
-                async move { Ok::<_, Infallible>(handle_admin(req, state).await) }
-            });
-            if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
-                error!("[admin] connection error: {}", e);
+/// Reconcile namespace `ns` to the rules in the request body (YAML, JSON
+/// or TOML per its `Content-Type`; `415` for anything else): validate +
+/// compile, write
+/// `<conf_dir>/<ns>.<ext>` in that format, then rebuild + atomically swap
+/// the live route set. Returns the new merged rule list on success, in
+/// the format `Accept` asks for.
+async fn handle_put_rules(req: Request<Incoming>, state: Arc<AdminState>, conf: &ConfD, ns: &str) -> Response<BoxBody> {
+    let Some(format) = body_format(&req) else { return unsupported_media_type() };
+    let mut opts = WriteOptions::of(&req);
+    opts.lease = match query_params(req.uri()).get("ttl").map(|t| lease::parse_ttl(t)) {
+        Some(Ok(ttl_ms)) => LeaseChange::Grant(ttl_ms),
+        Some(Err(e)) => return admin_err(StatusCode::BAD_REQUEST, &format!("ttl: {}", e)),
+        None => LeaseChange::Release,
+    };
+    let body_bytes = match req.into_body().collect().await {
+        Ok(b) => b.to_bytes(),
+        Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("read body: {}", e)),
+    };
+    let src = String::from_utf8_lossy(&body_bytes);
+    let parsed = match schema::load(&src, format, conf.strict) {
+        Ok((p, findings)) => {
+            for f in findings {
+                warn!("[admin] namespace '{}': {}", ns, f);
             }
-        });
-    }
+            p
+        }
+        Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("parse: {}", e)),
+    };
+    commit_write(&state, conf, ns, &opts, || Ok(NamespaceWrite::Put(parsed, format)))
 }
 
-/// Publish `runtime.json` (next to conf.d) so `fbi-proxy up/down/ps` can
+/// `POST` (create), `PATCH` (JSON merge patch of its version 2 form) or
+/// `DELETE /rules/{ns}/{route}`: edit one rule and write the namespace
+/// back in its own format. `POST` creates the namespace if needed, in
+/// the body's format.
+async fn handle_rule_edit(
+    req: Request<Incoming>,
+    state: Arc<AdminState>,
+    conf: &ConfD,
+    method: Method,
+    ns: &str,
+    name: &str,
+) -> Response<BoxBody> {
+    let body_format = match body_format(&req) {
+        Some(f) => f,
+        None if method == Method::DELETE => Format::Yaml,
+        None => return unsupported_media_type(),
+    };
+    let opts = WriteOptions::of(&req);
+    let body = if method == Method::DELETE {
+        serde_json::Value::Null
+    } else {
+        let bytes = match req.into_body().collect().await {
+            Ok(b) => b.to_bytes(),
+            Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("read body: {}", e)),
+        };
+        match schema::parse_value(&String::from_utf8_lossy(&bytes), body_format) {
+            Ok(v) => v,
+            Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("parse: {}", e)),
+        }
+    };
+    let created = method == Method::POST;
+    let resp = commit_write(&state, conf, ns, &opts, || {
+        let (mut file, format) = match confd::read_namespace(&conf.dir, ns, conf.strict) {
+            Ok(Some(found)) => found,
+            Ok(None) if created => (empty_routes_file(), body_format),
+            Ok(None) => return Err((StatusCode::NOT_FOUND, format!("no namespace '{}'", ns))),
+            Err(e) => return Err((StatusCode::CONFLICT, format!("namespace '{}' does not load: {}", ns, e))),
+        };
+        let index = file.routes.iter().position(|r| r.name == name);
+        let taken = |file: &routes::RoutesFile, other: &str| other != name && file.routes.iter().any(|r| r.name == other);
+        match (&method, index) {
+            (&Method::POST, Some(_)) => {
+                return Err((StatusCode::CONFLICT, format!("rule '{}' already exists in namespace '{}'", name, ns)));
+            }
+            (&Method::POST, None) => {
+                let mut value = body;
+                match value.get("name").and_then(|n| n.as_str()) {
+                    Some(n) if n != name => {
+                        return Err((StatusCode::BAD_REQUEST, format!("body names rule '{}' but the path names '{}'", n, name)));
+                    }
+                    _ => schema::merge_patch(&mut value, &serde_json::json!({ "name": name })),
+                }
+                let route = schema::route_from_value(value).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+                file.routes.push(route);
+            }
+            (_, None) => {
+                return Err((StatusCode::NOT_FOUND, format!("no rule '{}' in namespace '{}'", name, ns)));
+            }
+            (&Method::PATCH, Some(i)) => {
+                let mut value = schema::route_to_value(&file.routes[i]);
+                schema::merge_patch(&mut value, &body);
+                let route = schema::route_from_value(value).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+                if taken(&file, &route.name) {
+                    return Err((StatusCode::CONFLICT, format!("rule '{}' already exists in namespace '{}'", route.name, ns)));
+                }
+                file.routes[i] = route;
+            }
+            (_, Some(i)) => {
+                file.routes.remove(i);
+            }
+        }
+        Ok(NamespaceWrite::Put(file, format))
+    });
+    if created && resp.status() == StatusCode::OK && !opts.dry_run {
+        let mut resp = resp;
+        *resp.status_mut() = StatusCode::CREATED;
+        return resp;
+    }
+    resp
+}
+
+/// The routes file format a write's `Content-Type` names. Nothing else
+/// is read — in particular not the form and plain-text types a web page
+/// can send cross-origin without a preflight.
+fn body_format(req: &Request<Incoming>) -> Option<Format> {
+    Format::from_media_type(header_str(req, "content-type"))
+}
+
+fn unsupported_media_type() -> Response<BoxBody> {
+    admin_err(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Content-Type must be a routes file format: application/yaml, application/json or application/toml",
+    )
+}
+
+fn empty_routes_file() -> routes::RoutesFile {
+    routes::RoutesFile {
+        version: schema::CURRENT_VERSION,
+        priority: None,
+        disable_defaults: Vec::new(),
+        vars: Default::default(),
+        include: Vec::new(),
+        routes: Vec::new(),
+        tests: Vec::new(),
+    }
+}
+
+/// A write to one namespace, ready to validate and apply.
+enum NamespaceWrite {
+    /// Set its fragment to this file (includes unresolved), in this format.
+    Put(routes::RoutesFile, Format),
+    /// Remove it, and with `nested` every namespace nested below it
+    /// (`?keep_nested=1` leaves those, like a lapsed lease).
+    Remove { nested: bool },
+}
+
+/// How a rules write was asked for.
+struct WriteOptions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    accept: String,
+    /// `?dry_run=1`: validate and report, but touch nothing.
+    dry_run: bool,
+    /// What the write does to the namespace's lease.
+    lease: LeaseChange,
+}
+
+/// A write's effect on the lease of the namespace it writes.
+#[derive(Clone, Copy)]
+enum LeaseChange {
+    /// Leave it (single-rule edits).
+    Keep,
+    /// `PUT ...?ttl=`: lease it for this many ms.
+    Grant(u64),
+    /// A `PUT` without `?ttl=`: the namespace is permanent from now on.
+    Release,
+}
+
+impl WriteOptions {
+    fn of(req: &Request<Incoming>) -> Self {
+        let header = |name| Some(header_str(req, name).to_string()).filter(|v| !v.is_empty());
+        let dry_run = query_params(req.uri()).get("dry_run").is_some_and(|v| matches!(v.as_str(), "1" | "true"));
+        WriteOptions {
+            if_match: header("if-match"),
+            if_none_match: header("if-none-match"),
+            accept: header_str(req, "accept").to_string(),
+            dry_run,
+            lease: LeaseChange::Keep,
+        }
+    }
+}
+
+/// The would-be result of a `?dry_run=1` write.
+#[derive(serde::Serialize)]
+struct DryRun {
+    dry_run: bool,
+    /// The merged rule set the write would serve.
+    rules: Vec<RuleInfo>,
+    /// Lint findings (shadowing, duplicates) involving the namespace.
+    conflicts: Vec<lint::Lint>,
+}
+
+/// Apply a write to namespace `ns`: check its preconditions against the
+/// ETags, build it (with `build`, which sees the namespace as it is
+/// once no other write can interleave), validate it against every
+/// namespace's route tests, then write the fragment and reload. Writes
+/// are serialized, so two clients sending the same `If-Match` can't both
+/// succeed.
+fn commit_write(
+    state: &AdminState,
+    conf: &ConfD,
+    ns: &str,
+    opts: &WriteOptions,
+    build: impl FnOnce() -> Result<NamespaceWrite, (StatusCode, String)>,
+) -> Response<BoxBody> {
+    let _writing = conf.writes.lock().unwrap_or_else(|p| p.into_inner());
+    let current = namespace_etag(conf, ns);
+    let merged = merged_etag(state.routes_handle.load().as_ref(), Some(conf));
+    if let Err(e) = check_preconditions(opts, current.as_deref(), &merged) {
+        return with_etag(admin_err(StatusCode::PRECONDITION_FAILED, &format!("namespace '{}': {}", ns, e)), current);
+    }
+    let write = match build() {
+        Ok(w) => w,
+        Err((status, e)) => return admin_err(status, &e),
+    };
+    let staged = match &write {
+        NamespaceWrite::Put(file, format) => {
+            // Only what the operator already wrote may read outside the
+            // request (see `untrusted`).
+            let old = confd::read_namespace(&conf.dir, ns, conf.strict).ok().flatten();
+            if let Err(e) = untrusted::check_includes(old.as_ref().map(|(f, _)| f), file) {
+                return admin_err(StatusCode::FORBIDDEN, &e);
+            }
+            // Validate by compiling under this namespace *before* touching
+            // disk. `include:` resolves relative to where the fragment
+            // will live.
+            let resolved = match include::resolve(file.clone(), &confd::fragment_path(&conf.dir, ns, *format), conf.strict) {
+                Ok(f) => f,
+                Err(e) => return admin_err(StatusCode::BAD_REQUEST, &e),
+            };
+            // Rules compared as they compile, includes and all.
+            let old = old.and_then(|(f, format)| include::resolve(f, &confd::fragment_path(&conf.dir, ns, format), conf.strict).ok());
+            if let Err(e) = untrusted::check_rules(old.as_ref(), &resolved) {
+                return admin_err(StatusCode::FORBIDDEN, &e);
+            }
+            if let Err(e) = resolved.rules().and_then(|r| routes::compile_in_namespace(r, ns)) {
+                return admin_err(StatusCode::BAD_REQUEST, &format!("compile: {}", e));
+            }
+            conf.stage(ns, Some((&resolved, *format)), false)
+        }
+        NamespaceWrite::Remove { nested } => conf.stage(ns, None, *nested),
+    };
+    let staged = match staged {
+        Ok(routes) => routes,
+        Err((status, e)) => return admin_err(status, &e),
+    };
+    if opts.dry_run {
+        let in_ns = |rule: &str| rule.rsplit_once('/').is_some_and(|(n, _)| n == ns);
+        let conflicts = lint::lint(&staged)
+            .into_iter()
+            .filter(|l| l.namespace == ns || l.other.as_deref().is_some_and(in_ns))
+            .collect();
+        let preview = DryRun { dry_run: true, rules: rule_configs(&staged, Some(conf)), conflicts };
+        return match Format::negotiate(&opts.accept, &[Format::Json, Format::Yaml]) {
+            Some(Format::Yaml) => admin_text(StatusCode::OK, Format::Yaml.media_type(), Format::Yaml.serialize(&preview).unwrap_or_default()),
+            _ => admin_json(StatusCode::OK, serde_json::to_string(&preview).unwrap_or_default()),
+        };
+    }
+    let written = match &write {
+        NamespaceWrite::Put(file, format) => write_namespace(&conf.dir, ns, Some((file, *format)), false),
+        NamespaceWrite::Remove { nested } => write_namespace(&conf.dir, ns, None, *nested),
+    };
+    let removed = match written {
+        Ok(removed) => removed,
+        Err(e) => return admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    };
+    // The lease changes with the fragment, before anyone else can write
+    // or the reaper can look at it.
+    let leased = match (&write, opts.lease) {
+        (NamespaceWrite::Put(..), LeaseChange::Grant(ttl_ms)) => conf.leases.grant(ns, ttl_ms, unix_ms()).map(Some),
+        (NamespaceWrite::Put(..), LeaseChange::Keep) => Ok(None),
+        (NamespaceWrite::Put(..), LeaseChange::Release) => conf.leases.release(ns).map(|_| None),
+        (NamespaceWrite::Remove { .. }, _) => {
+            removed.iter().chain([&ns.to_string()]).try_for_each(|n| conf.leases.release(n).map(|_| ())).map(|_| None)
+        }
+    };
+    let leased = match leased {
+        Ok(l) => l,
+        Err(e) => return admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    };
+    if let Some(reloader) = &state.reloader {
+        let outcome = reloader.reload("admin");
+        if let Some(e) = outcome.failed() {
+            return admin_err(StatusCode::INTERNAL_SERVER_ERROR, e);
+        }
+    }
+    match write {
+        NamespaceWrite::Put(file, _) => {
+            info!("[admin] applied {} rule(s) to namespace '{}'", file.routes.len(), ns);
+            let routes = state.routes_handle.load();
+            let resp = with_etag(rules_response(&opts.accept, routes.as_ref(), &state.route_stats, Some(conf)), namespace_etag(conf, ns));
+            with_lease(resp, leased.as_ref())
+        }
+        NamespaceWrite::Remove { .. } => {
+            info!("[admin] removed namespace '{}' ({} fragment(s): {:?})", ns, removed.len(), removed);
+            let body = serde_json::json!({ "ok": true, "removed": !removed.is_empty(), "namespaces": removed });
+            admin_json(StatusCode::OK, body.to_string())
+        }
+    }
+}
+
+/// Namespace `ns`'s ETag, `None` if it has no fragment (or can't be read).
+fn namespace_etag(conf: &ConfD, ns: &str) -> Option<String> {
+    confd::namespace_etag(&conf.dir, ns).ok().flatten()
+}
+
+/// `If-Match` passes if it is `*` and the namespace exists, or lists its
+/// ETag or the merged set's; `If-None-Match` fails if it is `*` and the
+/// namespace exists (create-only), or lists its ETag.
+fn check_preconditions(opts: &WriteOptions, current: Option<&str>, merged: &str) -> Result<(), String> {
+    let lists = |header: &str, tag: &str| header.split(',').map(|t| t.trim().trim_start_matches("W/")).any(|t| t == tag);
+    if let Some(want) = &opts.if_match {
+        let ok = match current {
+            _ if want.trim() == "*" => current.is_some(),
+            Some(tag) => lists(want, tag) || lists(want, merged),
+            None => lists(want, merged),
+        };
+        if !ok {
+            return Err(match current {
+                Some(tag) => format!("If-Match {} does not match ETag {}", want, tag),
+                None => format!("If-Match {} but the namespace does not exist", want),
+            });
+        }
+    }
+    if let (Some(want), Some(tag)) = (&opts.if_none_match, current)
+        && (want.trim() == "*" || lists(want, tag))
+    {
+        return Err(format!("If-None-Match {} but the namespace exists (ETag {})", want, tag));
+    }
+    Ok(())
+}
+
+/// Add a `Lease-Expires` header (Unix epoch ms) for a leased write.
+fn with_lease(mut resp: Response<BoxBody>, lease: Option<&lease::Lease>) -> Response<BoxBody> {
+    if let Some(lease) = lease {
+        resp.headers_mut().insert("lease-expires", HeaderValue::from(lease.expires_ms));
+    }
+    resp
+}
+
+/// Add an `ETag` header, if there is one.
+fn with_etag(mut resp: Response<BoxBody>, etag: Option<String>) -> Response<BoxBody> {
+    if let Some(value) = etag.and_then(|t| HeaderValue::from_str(&t).ok()) {
+        resp.headers_mut().insert(hyper::header::ETAG, value);
+    }
+    resp
+}
+
+/// Write `<conf_dir>/<ns>.<ext>` in `format`, removing the namespace's
+/// fragments in any other format; or, for `None`, remove the namespace
+/// (and, if `nested`, every namespace nested below it), then any
+/// directories that leaves empty. Returns the namespaces that had a
+/// fragment before.
+fn write_namespace(
+    conf_dir: &std::path::Path,
+    ns: &str,
+    file: Option<(&routes::RoutesFile, Format)>,
+    nested: bool,
+) -> Result<Vec<String>, String> {
+    let existing: Vec<std::path::PathBuf> = confd::fragment_paths(conf_dir)?
+        .into_iter()
+        .filter(|p| {
+            let found = confd::namespace_of(conf_dir, p);
+            if file.is_none() && nested { confd::in_subtree(&found, ns) } else { found == ns }
+        })
+        .collect();
+    let keep = match file {
+        Some((file, format)) => {
+            let frag_path = confd::fragment_path(conf_dir, ns, format);
+            let text = format.serialize(file).map_err(|e| format!("serialize: {}", e))?;
+            let dir = frag_path.parent().unwrap_or(conf_dir);
+            std::fs::create_dir_all(dir).map_err(|e| format!("create {}: {}", dir.display(), e))?;
+            std::fs::write(&frag_path, text).map_err(|e| format!("write {}: {}", frag_path.display(), e))?;
+            Some(frag_path)
+        }
+        None => None,
+    };
+    for old in existing.iter().filter(|p| Some(*p) != keep.as_ref()) {
+        confd::remove_fragment(conf_dir, old)?;
+    }
+    let mut namespaces: Vec<String> = existing.iter().map(|p| confd::namespace_of(conf_dir, p)).collect();
+    namespaces.dedup();
+    Ok(namespaces)
+}
+
+/// `GET /leases`, `POST /leases/{ns}` (heartbeat: renew for its TTL, or
+/// `?ttl=`, which becomes its TTL) and `DELETE /leases/{ns}` (keep the
+/// namespace for good). A namespace whose lease lapsed answers 404, so
+/// its owner knows to `PUT` it again.
+fn handle_leases(req: &Request<Incoming>, state: &AdminState, ns: &str) -> Response<BoxBody> {
+    let Some(conf) = state.conf.as_deref() else {
+        return admin_err(StatusCode::CONFLICT, "leases require conf.d mode (started with --routes single-file mode)");
+    };
+    let lease_json = |lease: &lease::Lease| admin_json(StatusCode::OK, serde_json::to_string(lease).unwrap_or_default());
+    let no_lease = || admin_err(StatusCode::NOT_FOUND, &format!("namespace '{}' has no lease (never leased, released, or lapsed)", ns));
+    match (req.method(), ns) {
+        (&Method::GET, "") => admin_json(StatusCode::OK, serde_json::to_string(&conf.leases.list()).unwrap_or_else(|_| "[]".to_string())),
+        (_, "") => admin_err(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
+        (_, ns) if !is_valid_namespace(ns) => admin_err(
+            StatusCode::BAD_REQUEST,
+            "invalid namespace (allowed: /-separated segments of A-Za-z0-9_-, max 64 chars each)",
+        ),
+        (&Method::POST, ns) => {
+            let ttl_ms = match query_params(req.uri()).get("ttl").map(|t| lease::parse_ttl(t)).transpose() {
+                Ok(t) => t,
+                Err(e) => return admin_err(StatusCode::BAD_REQUEST, &format!("ttl: {}", e)),
+            };
+            match conf.leases.renew(ns, ttl_ms, unix_ms()) {
+                Ok(Some(lease)) => lease_json(&lease),
+                Ok(None) => no_lease(),
+                Err(e) => admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
+            }
+        }
+        (&Method::DELETE, ns) => match conf.leases.release(ns) {
+            Ok(Some(lease)) => {
+                info!("[lease] namespace '{}' released; keeping its fragment", ns);
+                lease_json(&lease)
+            }
+            Ok(None) => no_lease(),
+            Err(e) => admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        },
+        _ => admin_err(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
+    }
+}
+
+/// `GET /processes` (every `launch:` rule's process), `GET
+/// /processes/{ns}/{route}`, `GET .../logs?tail=` (recent output, oldest
+/// first) and `POST .../start` / `POST .../stop`. A start answers once
+/// the process is ready, like the request that would have started it.
+async fn handle_processes(req: &Request<Incoming>, state: &AdminState, key: &str) -> Response<BoxBody> {
+    let launcher = &state.launcher;
+    let (key, action) = match key.rsplit_once('/') {
+        Some((k, a @ ("logs" | "start" | "stop"))) => (k, a),
+        _ => (key, ""),
+    };
+    let not_found = || admin_err(StatusCode::NOT_FOUND, &format!("no launched rule '{}' (expected {{ns}}/{{route}})", key));
+    let info_json = |info: &launch::ProcessInfo| admin_json(StatusCode::OK, serde_json::to_string(info).unwrap_or_default());
+    match (req.method(), key, action) {
+        (&Method::GET, "", "") => admin_json(StatusCode::OK, serde_json::to_string(&launcher.list()).unwrap_or_else(|_| "[]".to_string())),
+        (&Method::GET, key, "") => launcher.info(key).map_or_else(not_found, |info| info_json(&info)),
+        (&Method::GET, key, "logs") => {
+            let tail = match query_params(req.uri()).get("tail").map(|t| t.parse::<usize>()).transpose() {
+                Ok(tail) => tail.unwrap_or(launch::LOG_LINES),
+                Err(_) => return admin_err(StatusCode::BAD_REQUEST, "tail: expected a number of lines"),
+            };
+            match launcher.logs(key, tail) {
+                Some(lines) => admin_json(StatusCode::OK, serde_json::to_string(&lines).unwrap_or_else(|_| "[]".to_string())),
+                None => not_found(),
+            }
+        }
+        (&Method::POST, key, "start") => match launcher.start(key).await {
+            Some(Ok(info)) => info_json(&info),
+            Some(Err(e)) => admin_err(StatusCode::BAD_GATEWAY, &format!("could not start {}: {}", key, e)),
+            None => not_found(),
+        },
+        (&Method::POST, key, "stop") => launcher.stop(key).map_or_else(not_found, |info| info_json(&info)),
+        _ => admin_err(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
+    }
+}
+
+/// The snapshot store, or the 409 for when there is none.
+fn history_of(state: &AdminState) -> Result<(&ConfD, &History), (StatusCode, String)> {
+    let conf = state.conf.as_deref().ok_or_else(|| {
+        (StatusCode::CONFLICT, "config history requires conf.d mode (started with --routes single-file mode)".to_string())
+    })?;
+    let history = conf
+        .history
+        .as_ref()
+        .ok_or_else(|| (StatusCode::CONFLICT, "config history is off (--history-limit 0)".to_string()))?;
+    Ok((conf, history))
+}
+
+/// Snapshot `id` (a path segment), or the 404 for when it isn't kept.
+fn snapshot_of(history: &History, id: &str) -> Result<history::Snapshot, (StatusCode, String)> {
+    let id: u64 = id.parse().map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid snapshot id '{}'", id)))?;
+    match history.get(id) {
+        Ok(Some(snapshot)) => Ok(snapshot),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("no snapshot {} (never taken, or pruned)", id))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+/// `GET /history` (newest first), `GET /history/{id}` and `GET
+/// /history/{id}/diff` — against the snapshot before it, or
+/// `?against={id}`.
+fn handle_history(req: &Request<Incoming>, state: &AdminState, path: &str) -> Response<BoxBody> {
+    let history = match history_of(state) {
+        Ok((_, h)) => h,
+        Err((status, e)) => return admin_err(status, &e),
+    };
+    let rest = path.trim_start_matches("/history").trim_start_matches('/');
+    let (id, diff) = match rest.strip_suffix("/diff") {
+        Some(id) => (id, true),
+        None => (rest, false),
+    };
+    if id.is_empty() {
+        return match history.list() {
+            Ok(list) => admin_json(StatusCode::OK, serde_json::to_string(&list).unwrap_or_else(|_| "[]".to_string())),
+            Err(e) => admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        };
+    }
+    let snapshot = match snapshot_of(history, id) {
+        Ok(s) => s,
+        Err((status, e)) => return admin_err(status, &e),
+    };
+    if !diff {
+        return admin_json(StatusCode::OK, serde_json::to_string(&snapshot).unwrap_or_default());
+    }
+    let base = match query_params(req.uri()).get("against") {
+        Some(against) => snapshot_of(history, against).map(Some),
+        None => history.previous(snapshot.id).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+    match base {
+        Ok(base) => admin_text(StatusCode::OK, "text/x-diff; charset=utf-8", history::diff(base.as_ref(), &snapshot)),
+        Err((status, e)) => admin_err(status, &e),
+    }
+}
+
+/// `POST /rollback/{id}`: put conf.d back the way snapshot `id` saw it
+/// and reload, which records the result as a new snapshot. Serialized
+/// with the `/rules` writes.
+fn rollback(state: &AdminState, id: &str) -> Response<BoxBody> {
+    let found = history_of(state).and_then(|(conf, history)| Ok((conf, snapshot_of(history, id)?)));
+    let (conf, snapshot) = match found {
+        Ok(found) => found,
+        Err((status, e)) => return admin_err(status, &e),
+    };
+    let Some(reloader) = &state.reloader else {
+        return admin_err(StatusCode::CONFLICT, "no routes source to reload");
+    };
+    let _writing = conf.writes.lock().unwrap_or_else(|p| p.into_inner());
+    let changed = match history::restore(&conf.dir, &snapshot) {
+        Ok(changed) => changed,
+        Err(e) => return admin_err(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    };
+    let outcome = reloader.reload("rollback");
+    if let Some(e) = outcome.failed() {
+        return admin_err(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    info!("[admin] rolled back to snapshot {} ({} namespace(s) changed: {:?})", snapshot.id, changed.len(), changed);
+    let body = serde_json::json!({
+        "ok": true,
+        "snapshot": snapshot.id,
+        "namespaces": changed,
+        "generation": outcome.generation,
+        "last_result": outcome.last_result,
+    });
+    admin_json(StatusCode::OK, body.to_string())
+}
+
+/// Run the loopback admin/control server on an already-bound listener:
+/// the endpoints [`handle_admin`] routes, listed in `docs/api.md`, and
+/// the HTML dashboard at `GET /`. Binds loopback-only so it is never
+/// reachable from the user-facing proxy port.
+///
+/// Requests must name the admin server itself in `Host` and, when they
+/// send one, `Origin` (see [`is_admin_authority`]), so a web page — even
+/// one whose name was rebound to 127.0.0.1 — can't drive the API.
+async fn serve_admin(state: Arc<AdminState>, listener: TcpListener) -> Result<(), BoxError> {
+    let port = listener.local_addr()?.port();
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        let io = TokioIo::new(stream);
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<Incoming>| {
+                let state = Arc::clone(&state);
+                async move {
+                    let host = header_str(&req, "host");
+                    let origin = header_str(&req, "origin");
+                    if !host.is_empty() && !is_admin_authority(host, port) {
+                        return Ok::<_, Infallible>(admin_err(StatusCode::FORBIDDEN, "Host is not the admin address"));
+                    }
+                    if !origin.is_empty() && !origin.strip_prefix("http://").is_some_and(|a| is_admin_authority(a, port)) {
+                        return Ok(admin_err(StatusCode::FORBIDDEN, "cross-origin admin requests are not allowed"));
+                    }
+                    Ok(handle_admin(req, state).await)
+                }
+            });
+            if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
+                error!("[admin] connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Whether `authority` (a `Host` header, or an `Origin` without its
+/// scheme) names the loopback admin server on `port`: `127.0.0.1`,
+/// `localhost` or `[::1]`, with that port or none.
+fn is_admin_authority(authority: &str, port: u16) -> bool {
+    let (name, p) = match authority.rsplit_once(':') {
+        Some((name, p)) if !p.contains(']') => (name, Some(p)),
+        _ => (authority, None),
+    };
+    ["127.0.0.1", "localhost", "[::1]"].iter().any(|n| name.eq_ignore_ascii_case(n))
+        && p.is_none_or(|p| p.parse() == Ok(port))
+}
+
+/// Publish `runtime.json` (next to conf.d) so `fbi-proxy up/down/ps` can
 /// discover the running daemon's admin port. Single-instance model:
 /// last writer wins.
 fn write_runtime_json(conf_dir: &std::path::Path, admin_port: u16, proxy_port: u16) {
@@ -946,14 +2246,48 @@ fn write_runtime_json(conf_dir: &std::path::Path, admin_port: u16, proxy_port: u
 
 /// Parse + compile a routes file without panicking. Returns Err with a
 /// human-readable message on any failure. Used by the hot-reload path
-/// where we want to log + keep current rules rather than crash.
-fn try_reload_routes(path: &str) -> Result<Vec<CompiledRoute>, String> {
-    let yaml = std::fs::read_to_string(path)
+/// where we want to log + keep current rules rather than crash. Schema
+/// findings are logged; with `strict`, unknown keys fail the load.
+fn try_reload_routes(path: &str, domain_filter: Option<&str>, strict: bool) -> Result<Vec<CompiledRoute>, String> {
+    let src = std::fs::read_to_string(path)
         .map_err(|e| format!("read {}: {}", path, e))?;
-    let parsed = routes::parse_yaml(&yaml)
+    let format = Format::from_path(std::path::Path::new(path)).unwrap_or(Format::Yaml);
+    let (parsed, findings) = schema::load(&src, format, strict)
         .map_err(|e| format!("parse {}: {}", path, e))?;
-    routes::compile(parsed.routes)
-        .map_err(|e| format!("compile {}: {}", path, e))
+    for f in findings {
+        warn!("[routes] {}: {}", path, f);
+    }
+    let parsed = include::resolve(parsed, std::path::Path::new(path), strict)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let mut compiled = parsed.rules().and_then(routes::compile)
+        .map_err(|e| format!("compile {}: {}", path, e))?;
+    routes::sort_by_priority(&mut compiled);
+    let tests = parsed.tests.iter().enumerate().map(|(i, t)| (path, i, t));
+    check_route_tests(compiled, tests, domain_filter)
+}
+
+/// Run embedded route tests against a merged rule set; the set is only
+/// returned (and so only goes live) if every test passes.
+fn check_route_tests<'a>(
+    routes: Vec<CompiledRoute>,
+    tests: impl IntoIterator<Item = (&'a str, usize, &'a routes::RouteTest)>,
+    domain_filter: Option<&str>,
+) -> Result<Vec<CompiledRoute>, String> {
+    let failures = routes::run_tests(&routes, tests, domain_filter);
+    if failures.is_empty() {
+        Ok(routes)
+    } else {
+        let lines: Vec<String> = failures.iter().map(ToString::to_string).collect();
+        Err(format!("{} route test(s) failed:\n  {}", failures.len(), lines.join("\n  ")))
+    }
+}
+
+/// Log every lint finding for a freshly loaded rule set. Findings never
+/// block a reload; `fbi-proxy check` is the strict gate.
+fn warn_lints(routes: &[CompiledRoute]) {
+    for l in lint::lint(routes) {
+        warn!("[routes lint] {}", l);
+    }
 }
 
 /// Watch a routes file and atomically swap in new rules on change.
@@ -962,10 +2296,7 @@ fn try_reload_routes(path: &str) -> Result<Vec<CompiledRoute>, String> {
 /// compile failure, log a warning and leave the existing rules in
 /// place — the running proxy continues to work with whatever last
 /// loaded successfully.
-fn spawn_routes_watcher(
-    path: String,
-    handle: Arc<ArcSwap<Vec<CompiledRoute>>>,
-) {
+fn spawn_routes_watcher(path: String, reloader: Arc<Reloader>) {
     use notify::{RecursiveMode, Watcher};
     use std::sync::mpsc;
 
@@ -1000,6 +2331,8 @@ fn spawn_routes_watcher(
         loop {
             // Block for the next event.
             match rx.recv() {
+                // Reads (including our own reload's) aren't edits.
+                Ok(Ok(event)) if is_read_event(&event) => continue,
                 Ok(Ok(_event)) => {}
                 Ok(Err(e)) => {
                     warn!("[routes hot-reload] watcher error: {}", e);
@@ -1010,30 +2343,23 @@ fn spawn_routes_watcher(
             // Drain any additional events that arrive during the debounce
             // window, so a single save that fires 3 events triggers
             // exactly one reload.
-            loop {
-                match rx.recv_timeout(DEBOUNCE) {
-                    Ok(_) => continue,
-                    Err(_) => break,
-                }
-            }
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
 
-            match try_reload_routes(&path) {
-                Ok(new_routes) => {
-                    let n = new_routes.len();
-                    handle.store(Arc::new(new_routes));
-                    info!("[routes hot-reload] reloaded {} rule(s) from {}", n, path);
-                }
-                Err(reason) => {
-                    warn!(
-                        "[routes hot-reload] reload failed, keeping previous rules: {}",
-                        reason
-                    );
-                }
-            }
+            reloader.reload("watch");
         }
     });
 }
 
+/// Open/close-without-write events: reading the watched files,
+/// which every reload does itself.
+fn is_read_event(event: &notify::Event) -> bool {
+    matches!(
+        event.kind,
+        notify::EventKind::Access(notify::event::AccessKind::Open(_) | notify::event::AccessKind::Read)
+            | notify::EventKind::Access(notify::event::AccessKind::Close(notify::event::AccessMode::Read))
+    )
+}
+
 /// Resolve the user's home directory cross-platform: `HOME` on Unix,
 /// `USERPROFILE` on Windows (mirrors Node's `os.homedir()`, so the conf
 /// dir / runtime.json the proxy writes match where the TS CLI looks).
@@ -1048,154 +2374,523 @@ fn home_dir() -> std::path::PathBuf {
 /// `FBI_PROXY_CONF_DIR` overrides; default `<home>/.config/fbi-proxy/conf.d`
 /// on every platform (so it lines up with the TS CLI's `os.homedir()`).
 fn default_conf_dir() -> std::path::PathBuf {
-    if let Ok(d) = std::env::var("FBI_PROXY_CONF_DIR") {
-        if !d.is_empty() {
-            return std::path::PathBuf::from(d);
-        }
+    if let Ok(d) = std::env::var("FBI_PROXY_CONF_DIR")
+        && !d.is_empty()
+    {
+        return std::path::PathBuf::from(d);
     }
     home_dir().join(".config").join("fbi-proxy").join("conf.d")
 }
 
-/// Rebuild the merged compiled route set: bundled defaults (namespace
-/// `"default"`) followed by every `<conf_dir>/*.yaml` fragment (namespace
-/// = file stem), sorted by filename so ordering is deterministic.
-/// Returns Err with a human-readable message on any parse/compile error
-/// so callers can log + keep the previous rules instead of crashing.
-fn rebuild_routes(
-    conf_dir: &std::path::Path,
-    bundled_yaml: &str,
-) -> Result<Vec<CompiledRoute>, String> {
-    let parsed = routes::parse_yaml(bundled_yaml)
-        .map_err(|e| format!("parse bundled routes: {}", e))?;
-    let mut merged = routes::compile_in_namespace(parsed.routes, "default")
-        .map_err(|e| format!("compile bundled routes: {}", e))?;
-
-    if conf_dir.is_dir() {
-        let mut paths: Vec<std::path::PathBuf> = std::fs::read_dir(conf_dir)
-            .map_err(|e| format!("read {}: {}", conf_dir.display(), e))?
-            .filter_map(|e| e.ok().map(|e| e.path()))
-            .filter(|p| {
-                matches!(
-                    p.extension().and_then(|x| x.to_str()),
-                    Some("yaml") | Some("yml")
-                )
-            })
-            .collect();
-        paths.sort();
-        for path in paths {
-            let ns = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_string();
-            let src = std::fs::read_to_string(&path)
-                .map_err(|e| format!("read {}: {}", path.display(), e))?;
-            let parsed = routes::parse_yaml(&src)
-                .map_err(|e| format!("parse {}: {}", path.display(), e))?;
-            let compiled = routes::compile_in_namespace(parsed.routes, &ns)
-                .map_err(|e| format!("compile {}: {}", path.display(), e))?;
-            merged.extend(compiled);
-        }
-    }
-    Ok(merged)
-}
-
-/// Watch the conf.d directory and atomically swap in the merged rule set
-/// on any change. Same debounce + fail-soft policy as the single-file
-/// watcher: a bad fragment logs a warning and leaves current rules in
-/// place. External edits and admin-API writes both converge here because
-/// disk is the source of truth.
-fn spawn_conf_dir_watcher(
-    conf_dir: std::path::PathBuf,
-    bundled_yaml: &'static str,
-    handle: Arc<ArcSwap<Vec<CompiledRoute>>>,
-) {
-    use notify::{RecursiveMode, Watcher};
-    use std::sync::mpsc;
+/// conf.d mode: the fragment [`confd::Loader`] plus everything needed to
+/// re-run it. Shared by the watcher, the admin API and the landing page;
+/// disk stays the source of truth, so every change is a re-read of the
+/// directory.
+pub struct ConfD {
+    dir: std::path::PathBuf,
+    domain_filter: Option<String>,
+    /// `--strict`: fragments with unknown keys fail to load.
+    strict: bool,
+    loader: Mutex<confd::Loader>,
+    /// Held across an admin write's precondition check, validation and
+    /// write, so concurrent writers see each other's ETags.
+    writes: Mutex<()>,
+    /// Snapshots of every change that went live; `None` with
+    /// `--history-limit 0`.
+    history: Option<History>,
+    /// Namespaces written with a TTL, kept in `leases.json` next to conf.d.
+    leases: Leases,
+}
 
-    std::thread::spawn(move || {
-        let (tx, rx) = mpsc::channel();
-        let mut watcher = match notify::recommended_watcher(move |res| {
-            let _ = tx.send(res);
-        }) {
-            Ok(w) => w,
-            Err(e) => {
-                error!("[routes hot-reload] failed to create watcher: {}", e);
-                return;
-            }
-        };
+impl ConfD {
+    fn new(dir: std::path::PathBuf, domain_filter: Option<String>, strict: bool) -> Self {
+        let loader = confd::Loader::new(BUNDLED_ROUTES_YAML).unwrap_or_else(|e| panic!("{}", e));
+        let leases = Leases::load(dir.parent().unwrap_or_else(|| std::path::Path::new(".")).join("leases.json"), unix_ms());
+        ConfD { dir, domain_filter, strict, loader: Mutex::new(loader), writes: Mutex::new(()), history: None, leases }
+    }
 
-        if let Err(e) = watcher.watch(&conf_dir, RecursiveMode::NonRecursive) {
-            error!(
-                "[routes hot-reload] failed to watch {}: {}",
-                conf_dir.display(),
-                e
-            );
-            return;
+    /// Keep the newest `keep` snapshots in `history/` next to conf.d; 0
+    /// keeps none.
+    fn with_history(mut self, keep: usize) -> Self {
+        let dir = self.dir.parent().unwrap_or_else(|| std::path::Path::new(".")).join("history");
+        self.history = (keep > 0).then(|| History::new(dir, keep));
+        self
+    }
+
+    /// Snapshot conf.d and the `routes` it produced as generation
+    /// `generation`, if history is on and something changed.
+    fn record(&self, generation: u64, trigger: &str, routes: &[CompiledRoute]) {
+        let Some(history) = &self.history else { return };
+        match history.record(&self.dir, generation, trigger, rule_configs(routes, Some(self))) {
+            Ok(Some(id)) => info!("[history] snapshot {} (generation {}, {})", id, generation, trigger),
+            Ok(None) => {}
+            Err(e) => warn!("[history] could not record a snapshot: {}", e),
         }
-        info!("[routes hot-reload] watching {}", conf_dir.display());
+    }
 
-        const DEBOUNCE: Duration = Duration::from_millis(150);
-        loop {
-            match rx.recv() {
-                Ok(Ok(_event)) => {}
-                Ok(Err(e)) => {
-                    warn!("[routes hot-reload] watcher error: {}", e);
-                    continue;
-                }
-                Err(_) => break,
-            }
-            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    fn loader(&self) -> std::sync::MutexGuard<'_, confd::Loader> {
+        self.loader.lock().unwrap_or_else(|p| p.into_inner())
+    }
 
-            match rebuild_routes(&conf_dir, bundled_yaml) {
-                Ok(new_routes) => {
-                    let n = new_routes.len();
-                    handle.store(Arc::new(new_routes));
-                    info!("[routes hot-reload] reloaded {} rule(s) from {}", n, conf_dir.display());
-                }
-                Err(reason) => {
-                    warn!(
-                        "[routes hot-reload] reload failed, keeping previous rules: {}",
-                        reason
-                    );
-                }
+    /// Re-read every fragment and merge them after the bundled defaults.
+    /// A fragment that fails keeps its last-good rules or is skipped (see
+    /// [`confd::Loader::load`]); only an unreadable directory is an error.
+    fn load(&self) -> Result<Vec<CompiledRoute>, String> {
+        let fragments = confd::read_dir(&self.dir, self.strict)?;
+        for f in &fragments {
+            for w in &f.warnings {
+                warn!("[conf.d] {}: {}", f.path.display(), w);
             }
         }
-    });
+        let mut loader = self.loader();
+        let merged = loader.load(&fragments, self.domain_filter.as_deref());
+        for ns in loader.status().failing() {
+            warn!(
+                "[conf.d] namespace '{}' ({}): {}",
+                ns.namespace,
+                ns.state.as_str(),
+                ns.error.as_deref().unwrap_or("")
+            );
+        }
+        Ok(merged)
+    }
+
+    /// Outcome of the last [`load`](Self::load).
+    fn status(&self) -> confd::Status {
+        self.loader().status().clone()
+    }
+
+    /// Check that setting namespace `ns` to `file` in its format (includes
+    /// already resolved; or removing it, and with `nested` every namespace
+    /// nested below it, for `None`) loads cleanly and breaks no namespace
+    /// that loads cleanly now. Nothing is written; the error carries the
+    /// status the admin API answers with.
+    fn stage(
+        &self,
+        ns: &str,
+        file: Option<(&routes::RoutesFile, Format)>,
+        nested: bool,
+    ) -> Result<Vec<CompiledRoute>, (StatusCode, String)> {
+        let mut fragments = confd::read_dir(&self.dir, self.strict).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        match file {
+            None if nested => fragments.retain(|f| !confd::in_subtree(&f.namespace, ns)),
+            _ => fragments.retain(|f| f.namespace != ns),
+        }
+        if let Some((file, format)) = file {
+            let path = confd::fragment_path(&self.dir, ns, format);
+            let parsed = Ok(file.clone());
+            fragments.push(confd::FragmentFile { namespace: ns.to_string(), path, parsed, warnings: Vec::new() });
+            fragments.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        }
+        self.loader()
+            .stage(&fragments, ns, self.domain_filter.as_deref())
+            .map_err(|e| (StatusCode::CONFLICT, e))
+    }
 }
 
-pub struct TlsOptions {
-    /// Apex domain used for SAN entries on the self-signed cert.
-    /// Empty string falls back to `localhost` + `127.0.0.1`.
-    pub domain: String,
-    /// Directory the generated cert+key are persisted to. The same
-    /// fingerprint is reused across boots so browsers can remember
-    /// "trust this exception" once.
-    pub cert_dir: std::path::PathBuf,
+/// Where the live rules are loaded from, for [`Reloader`].
+enum RulesSource {
+    /// `--routes <file>`: the file replaces the bundled defaults.
+    File { path: String, strict: bool },
+    /// conf.d mode: bundled defaults plus every fragment.
+    ConfD(Arc<ConfD>),
 }
 
-pub async fn start_proxy_server(
-    host: Option<&str>,
-    port: u16,
-    domain_filter: Option<String>,
-    compiled_routes: Vec<CompiledRoute>,
-    watch_path: Option<String>,
-    conf_dir: Option<std::path::PathBuf>,
-    admin_port: Option<u16>,
-    tls: Option<TlsOptions>,
-) -> Result<(), BoxError> {
-    let host = host.unwrap_or("127.0.0.1");
-    let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
-    let proxy = Arc::new(FBIProxy::new(domain_filter.clone(), compiled_routes));
+/// Outcome of the most recent rules (re)load.
+#[derive(Debug, Clone, serde::Serialize)]
+struct ReloadStatus {
+    /// Bumped every time a new rule set goes live; 1 after startup.
+    generation: u64,
+    /// Unix epoch ms of the last reload attempt.
+    last_reload_ms: u64,
+    /// `ok`; `degraded` when conf.d namespaces failed (see `/status`) but
+    /// the rest went live; `failed` when the previous rules were kept.
+    last_result: &'static str,
+    last_error: Option<String>,
+    /// `startup`, `watch`, `admin` (a `/rules` write), `api`
+    /// (`POST /reload`), `rollback` (`POST /rollback/{id}`), `lease` (a
+    /// lapsed lease removed its namespace) or `sighup`.
+    last_trigger: &'static str,
+}
 
-    // Hot-reload. In conf.d mode (the default) we watch the directory and
-    // re-merge bundled + all fragments on change. In legacy single-file
+impl ReloadStatus {
+    /// The error, if the previous rules were kept.
+    fn failed(&self) -> Option<&str> {
+        self.last_error.as_deref().filter(|_| self.last_result == "failed")
+    }
+}
+
+/// `GET /status`: reload tracking plus, in conf.d mode, each namespace.
+#[derive(serde::Serialize)]
+struct StatusBody {
+    #[serde(flatten)]
+    reload: Option<ReloadStatus>,
+    #[serde(flatten)]
+    conf: Option<confd::Status>,
+}
+
+/// Reloads the live rules from their source and keeps the config
+/// generation. Every path that swaps rules — the watchers, SIGHUP,
+/// `POST /reload` and the `/rules` writes — goes through [`reload`], so
+/// tooling can tell from the generation whether an edit took effect.
+///
+/// [`reload`]: Reloader::reload
+struct Reloader {
+    source: RulesSource,
+    domain_filter: Option<String>,
+    routes: Arc<ArcSwap<Vec<CompiledRoute>>>,
+    metrics: Arc<Metrics>,
+    /// Follows each swap, so processes of removed or changed `launch:`
+    /// rules stop.
+    launcher: Arc<Launcher>,
+    /// Also serializes reloads, so generations go live in order.
+    status: Mutex<ReloadStatus>,
+}
+
+impl Reloader {
+    /// Track reloads of rules already loaded from `source` at startup.
+    fn new(
+        source: RulesSource,
+        domain_filter: Option<String>,
+        routes: Arc<ArcSwap<Vec<CompiledRoute>>>,
+        metrics: Arc<Metrics>,
+        launcher: Arc<Launcher>,
+    ) -> Self {
+        let degraded = match &source {
+            RulesSource::ConfD(conf) => {
+                conf.record(1, "startup", routes.load().as_ref());
+                conf_failures(&conf.status())
+            }
+            RulesSource::File { .. } => None,
+        };
+        metrics.config_generation.set(1);
+        let status = ReloadStatus {
+            generation: 1,
+            last_reload_ms: unix_ms(),
+            last_result: if degraded.is_some() { "degraded" } else { "ok" },
+            last_error: degraded,
+            last_trigger: "startup",
+        };
+        Reloader { source, domain_filter, routes, metrics, launcher, status: Mutex::new(status) }
+    }
+
+    fn status(&self) -> ReloadStatus {
+        self.status.lock().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// Reload from the source and swap the result in; a failure keeps
+    /// the current rules. Returns the new status.
+    fn reload(&self, trigger: &'static str) -> ReloadStatus {
+        let mut status = self.status.lock().unwrap_or_else(|p| p.into_inner());
+        let loaded = match &self.source {
+            RulesSource::File { path, strict } => {
+                try_reload_routes(path, self.domain_filter.as_deref(), *strict).map(|r| (r, None))
+            }
+            RulesSource::ConfD(conf) => conf.load().map(|r| (r, conf_failures(&conf.status()))),
+        };
+        status.last_reload_ms = unix_ms();
+        status.last_trigger = trigger;
+        match loaded {
+            Ok((new_routes, degraded)) => {
+                let n = new_routes.len();
+                warn_lints(&new_routes);
+                status.generation += 1;
+                if let RulesSource::ConfD(conf) = &self.source {
+                    conf.record(status.generation, trigger, &new_routes);
+                }
+                self.launcher.sync(&new_routes);
+                self.metrics.prune_routes(&new_routes);
+                self.routes.store(Arc::new(new_routes));
+                status.last_result = if degraded.is_some() { "degraded" } else { "ok" };
+                status.last_error = degraded;
+                self.metrics.config_generation.set(status.generation as i64);
+                info!("[routes reload] generation {} ({}): {} rule(s) live", status.generation, trigger, n);
+            }
+            Err(reason) => {
+                warn!("[routes reload] reload failed ({}), keeping previous rules: {}", trigger, reason);
+                status.last_result = "failed";
+                status.last_error = Some(reason);
+            }
+        }
+        if status.last_result != "ok" {
+            self.metrics.config_reload_failures_total.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+        status.clone()
+    }
+}
+
+/// Remove the fragments of namespaces whose lease lapsed, checking once
+/// a second.
+fn spawn_lease_reaper(conf: Arc<ConfD>, reloader: Arc<Reloader>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            let (conf, reloader) = (Arc::clone(&conf), Arc::clone(&reloader));
+            let _ = tokio::task::spawn_blocking(move || reap_leases(&conf, &reloader)).await;
+        }
+    });
+}
+
+/// Like an admin `DELETE` of each lapsed namespace (but not the ones
+/// nested below it), serialized with the `/rules` writes so a `PUT`
+/// renewing the lease can't be undone by it.
+fn reap_leases(conf: &ConfD, reloader: &Reloader) {
+    let _writing = conf.writes.lock().unwrap_or_else(|p| p.into_inner());
+    let expired = match conf.leases.take_expired(unix_ms()) {
+        Ok(expired) => expired,
+        Err(e) => {
+            warn!("[lease] could not update leases: {}", e);
+            return;
+        }
+    };
+    if expired.is_empty() {
+        return;
+    }
+    for lease in &expired {
+        match lease::remove_namespace(&conf.dir, &lease.namespace) {
+            Ok(true) => info!("[lease] namespace '{}' lease lapsed ({} ms TTL); removed it", lease.namespace, lease.ttl_ms),
+            Ok(false) => {}
+            Err(e) => warn!("[lease] namespace '{}' lease lapsed but it could not be removed: {}", lease.namespace, e),
+        }
+    }
+    reloader.reload("lease");
+}
+
+/// Stop `launch:` processes that sat idle for their `idle_timeout`,
+/// checking once a second.
+fn spawn_launch_reaper(launcher: Arc<Launcher>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            launcher.reap_idle(unix_ms());
+        }
+    });
+}
+
+/// Stop `launch:` processes before exiting on Ctrl-C or SIGTERM: each
+/// runs in a process group of its own, out of reach of the terminal's
+/// Ctrl-C.
+fn spawn_launch_shutdown(launcher: Arc<Launcher>) {
+    tokio::spawn(async move {
+        let code = shutdown_signal().await;
+        if launcher.list().iter().any(|p| p.pid.is_some()) {
+            eprintln!("[launch] stopping launched processes...");
+            launcher.stop_all().await;
+        }
+        std::process::exit(code);
+    });
+}
+
+/// Wait for Ctrl-C (or SIGTERM on Unix); returns the exit code for it.
+async fn shutdown_signal() -> i32 {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            return tokio::select! {
+                _ = tokio::signal::ctrl_c() => 128 + libc::SIGINT,
+                _ = term.recv() => 128 + libc::SIGTERM,
+            };
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    130
+}
+
+/// Reload on SIGHUP, like most daemons. No-op off Unix.
+fn spawn_sighup_reloader(reloader: Arc<Reloader>) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("[routes reload] could not install SIGHUP handler: {}", e);
+                return;
+            }
+        };
+        while hup.recv().await.is_some() {
+            let reloader = Arc::clone(&reloader);
+            let _ = tokio::task::spawn_blocking(move || reloader.reload("sighup")).await;
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = reloader;
+}
+
+/// Failing conf.d namespaces as one message, or `None` if all loaded.
+fn conf_failures(status: &confd::Status) -> Option<String> {
+    let failing: Vec<String> = status
+        .failing()
+        .map(|n| format!("{}: {}", n.namespace, n.error.as_deref().unwrap_or("")))
+        .collect();
+    (!failing.is_empty()).then(|| failing.join("; "))
+}
+
+/// Publish the conf.d load state to the `/metrics` gauges.
+fn record_conf_status(metrics: &Metrics, status: &confd::Status) {
+    metrics
+        .conf_namespaces
+        .replace(status.namespaces.iter().map(|n| (vec![n.namespace.as_str(), n.state.as_str()], 1)));
+    metrics.conf_namespaces_failing.set(status.failing().count() as i64);
+}
+
+/// Landing-page warning listing conf.d namespaces that failed to load;
+/// empty when every namespace is healthy.
+fn conf_failures_html(status: &confd::Status) -> String {
+    let items: Vec<String> = status
+        .failing()
+        .map(|n| {
+            let served = match n.state {
+                confd::NamespaceState::LastGood => "serving last-good rules",
+                _ => "skipped",
+            };
+            format!(
+                "<li><code>{}</code> ({}): {}</li>",
+                html_escape(&n.namespace),
+                served,
+                html_escape(n.error.as_deref().unwrap_or(""))
+            )
+        })
+        .collect();
+    if items.is_empty() {
+        return String::new();
+    }
+    format!(
+        "    <div class=\"warning\">\n        ⚠️ <strong>conf.d:</strong> {} namespace(s) failed to load.\n        <ul>{}</ul>\n    </div>\n",
+        items.len(),
+        items.join("")
+    )
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Watch the conf.d directory tree (nested namespaces included) and
+/// atomically swap in the merged rule set on any change. Same debounce as the single-file watcher; a bad
+/// fragment only affects its own namespace (see [`ConfD::load`]).
+/// External edits and admin-API writes both converge here because disk
+/// is the source of truth.
+fn spawn_conf_dir_watcher(conf_dir: std::path::PathBuf, reloader: Arc<Reloader>) {
+    use notify::{RecursiveMode, Watcher};
+    use std::sync::mpsc;
+
+    std::thread::spawn(move || {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        }) {
+            Ok(w) => w,
+            Err(e) => {
+                error!("[routes hot-reload] failed to create watcher: {}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = watcher.watch(&conf_dir, RecursiveMode::Recursive) {
+            error!(
+                "[routes hot-reload] failed to watch {}: {}",
+                conf_dir.display(),
+                e
+            );
+            return;
+        }
+        info!("[routes hot-reload] watching {}", conf_dir.display());
+
+        const DEBOUNCE: Duration = Duration::from_millis(150);
+        loop {
+            match rx.recv() {
+                Ok(Ok(event)) if is_read_event(&event) => continue,
+                Ok(Ok(_event)) => {}
+                Ok(Err(e)) => {
+                    warn!("[routes hot-reload] watcher error: {}", e);
+                    continue;
+                }
+                Err(_) => break,
+            }
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+            reloader.reload("watch");
+        }
+    });
+}
+
+#[derive(Clone)]
+pub struct TlsOptions {
+    /// Apex domain used for SAN entries on the self-signed cert.
+    /// Empty string falls back to `localhost` + `127.0.0.1`.
+    pub domain: String,
+    /// Directory the generated cert+key are persisted to. The same
+    /// fingerprint is reused across boots so browsers can remember
+    /// "trust this exception" once.
+    pub cert_dir: std::path::PathBuf,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn start_proxy_server(
+    host: Option<&str>,
+    port: u16,
+    domain_filter: Option<String>,
+    compiled_routes: Vec<CompiledRoute>,
+    watch_path: Option<String>,
+    strict: bool,
+    conf: Option<Arc<ConfD>>,
+    admin_port: Option<u16>,
+    tls: Option<TlsOptions>,
+    access_log: Option<Arc<AccessLog>>,
+    tracer: Option<Arc<Tracer>>,
+) -> Result<(), BoxError> {
+    let host = host.unwrap_or("127.0.0.1");
+    let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
+    // With the access log on stdout, keep the human-readable banner on
+    // stderr so stdout stays one access-log line per request.
+    let banner_to_stderr = access_log.as_ref().is_some_and(|l| l.writes_to_stdout());
+    macro_rules! banner {
+        ($($arg:tt)*) => {
+            if banner_to_stderr { eprintln!($($arg)*) } else { println!($($arg)*) }
+        };
+    }
+    let proxy = Arc::new(
+        FBIProxy::new(domain_filter.clone(), compiled_routes)
+            .with_access_log(access_log)
+            .with_tracer(tracer)
+            .with_conf(conf.clone()),
+    );
+
+    // Hot-reload. In conf.d mode (the default) we watch the directory and
+    // re-merge bundled + all fragments on change. In legacy single-file
     // mode (--routes <file>) we watch just that file. Failures leave the
-    // current rules in place — never crash on a typo in YAML.
-    if let Some(dir) = conf_dir.clone() {
-        spawn_conf_dir_watcher(dir, BUNDLED_ROUTES_YAML, proxy.routes_handle());
-    } else if let Some(path) = watch_path {
-        spawn_routes_watcher(path, proxy.routes_handle());
+    // current rules in place — never crash on a typo in YAML. SIGHUP and
+    // `POST /reload` force the same reload.
+    let source = match (&conf, watch_path) {
+        (Some(conf), _) => Some(RulesSource::ConfD(Arc::clone(conf))),
+        (None, Some(path)) => Some(RulesSource::File { path, strict }),
+        (None, None) => None,
+    };
+    let reloader = source.map(|source| {
+        Arc::new(Reloader::new(
+            source,
+            domain_filter.clone(),
+            proxy.routes_handle(),
+            proxy.metrics_handle(),
+            proxy.launcher_handle(),
+        ))
+    });
+    proxy.launcher.sync(proxy.compiled_routes.load().as_ref());
+    spawn_launch_reaper(proxy.launcher_handle());
+    spawn_launch_shutdown(proxy.launcher_handle());
+    if let Some(reloader) = &reloader {
+        match &reloader.source {
+            RulesSource::ConfD(conf) => {
+                spawn_conf_dir_watcher(conf.dir.clone(), Arc::clone(reloader));
+                spawn_lease_reaper(Arc::clone(conf), Arc::clone(reloader));
+            }
+            RulesSource::File { path, .. } => spawn_routes_watcher(path.clone(), Arc::clone(reloader)),
+        }
+        spawn_sighup_reloader(Arc::clone(reloader));
     }
 
     // Admin/control server: always on, loopback-only. Serves /metrics and
@@ -1217,14 +2912,20 @@ pub async fn start_proxy_server(
                     .map(|a| a.port())
                     .unwrap_or_else(|_| pinned.unwrap_or(0));
                 info!("[admin] listening on http://127.0.0.1:{}", bound);
-                println!("[admin] control API on http://127.0.0.1:{}/ (/metrics, /rules)", bound);
-                if let Some(dir) = &conf_dir {
-                    write_runtime_json(dir, bound, port);
+                banner!("[admin] dashboard + control API on http://127.0.0.1:{}/ (/metrics, /rules, /requests)", bound);
+                if let Some(conf) = &conf {
+                    write_runtime_json(&conf.dir, bound, port);
                 }
                 let state = Arc::new(AdminState {
                     metrics: proxy.metrics_handle(),
+                    inspector: proxy.inspector_handle(),
+                    route_stats: proxy.route_stats_handle(),
                     routes_handle: proxy.routes_handle(),
-                    conf_dir: conf_dir.clone(),
+                    conf: conf.clone(),
+                    reloader: reloader.clone(),
+                    tls: tls.clone(),
+                    domain_filter: domain_filter.clone(),
+                    launcher: proxy.launcher_handle(),
                 });
                 tokio::spawn(async move {
                     if let Err(e) = serve_admin(state, listener).await {
@@ -1246,7 +2947,7 @@ pub async fn start_proxy_server(
             // "browser warnings forever" mode.
             let cert_path = fbi_proxy::tls::cert_pem_path(&opts.domain, &opts.cert_dir);
             match fbi_proxy::tls::install_to_system_trust(&cert_path) {
-                Ok(true) => println!(
+                Ok(true) => banner!(
                     "TLS: cert installed to system trust store ({})",
                     cert_path.display()
                 ),
@@ -1267,50 +2968,52 @@ pub async fn start_proxy_server(
 
     let scheme = if acceptor.is_some() { "https" } else { "http" };
     info!("FBI Proxy server running on {}://{}", scheme, addr);
-    println!("FBI Proxy listening on: {}://{}", scheme, addr);
+    banner!("FBI Proxy listening on: {}://{}", scheme, addr);
     if let Some(opts) = &tls {
-        println!(
+        banner!(
             "TLS: self-signed cert at {}/{}.pem (browser warning expected — Phase 1)",
             opts.cert_dir.display(),
             if opts.domain.is_empty() { "localhost" } else { &opts.domain }
         );
     }
-    if let Some(ref domain) = domain_filter {
-        if !domain.is_empty() {
-            println!("Domain filter: Only accepting requests for *.{}", domain);
-        }
-    }
-    println!();
-    println!("== HOW IT WORKS ==");
-    println!("Routes requests based on Host header (configurable via routes.yaml):");
-    println!("  3000         -> localhost:3000  (port as host)");
-    println!("  api--8080    -> api:8080        (host--port syntax)");
-    println!("  3000.fbi.com -> localhost:3000  (subdomain as port)");
-    println!("  app.server   -> server:80       (subdomain hoisting)");
-    println!();
-    println!("== CADDY SETUP ==");
-    println!("# Caddyfile - expose *.fbi.example.com to local ports");
-    println!("*.fbi.example.com {{");
-    println!("  tls {{ dns cloudflare {{env.CF_API_TOKEN}} }}");
-    println!("  reverse_proxy localhost:2432");
-    println!("}}");
-    println!();
-    println!("Then: fbi-proxy -d fbi.example.com");
-    println!("  https://3000.fbi.example.com -> localhost:3000");
-    println!("  https://8080.fbi.example.com -> localhost:8080");
-    println!();
-    println!("⚠️ FBI-Proxy WARNING: ENSURE YOU KNOW WHAT YOU'RE DOING and be sure to set up an auth gateway before exposing to the internet");
-    println!("   This proxy is production ready but requires proper security measures.");
+    if let Some(ref domain) = domain_filter
+        && !domain.is_empty()
+    {
+        banner!("Domain filter: Only accepting requests for *.{}", domain);
+    }
+    banner!("");
+    banner!("== HOW IT WORKS ==");
+    banner!("Routes requests based on Host header (configurable via routes.yaml):");
+    banner!("  3000         -> localhost:3000  (port as host)");
+    banner!("  api--8080    -> api:8080        (host--port syntax)");
+    banner!("  3000.fbi.com -> localhost:3000  (subdomain as port)");
+    banner!("  app.server   -> server:80       (subdomain hoisting)");
+    banner!("");
+    banner!("== CADDY SETUP ==");
+    banner!("# Caddyfile - expose *.fbi.example.com to local ports");
+    banner!("*.fbi.example.com {{");
+    banner!("  tls {{ dns cloudflare {{env.CF_API_TOKEN}} }}");
+    banner!("  reverse_proxy localhost:2432");
+    banner!("}}");
+    banner!("");
+    banner!("Then: fbi-proxy -d fbi.example.com");
+    banner!("  https://3000.fbi.example.com -> localhost:3000");
+    banner!("  https://8080.fbi.example.com -> localhost:8080");
+    banner!("");
+    banner!("⚠️ FBI-Proxy WARNING: ENSURE YOU KNOW WHAT YOU'RE DOING and be sure to set up an auth gateway before exposing to the internet");
+    banner!("   This proxy is production ready but requires proper security measures.");
 
     info!("Features: HTTP proxying + WebSocket forwarding + Port encoding + Domain filtering");
 
     loop {
-        let (stream, _) = listener.accept().await?;
+        let (stream, remote) = listener.accept().await?;
         let proxy = proxy.clone();
         let acceptor = acceptor.clone();
+        let connection = proxy.metrics.track(|m| &m.open_connections);
 
         tokio::task::spawn(async move {
-            let service = service_fn(move |req| handle_connection(req, proxy.clone()));
+            let _connection = connection;
+            let service = service_fn(move |req| handle_connection(req, proxy.clone(), remote));
 
             // auto::Builder serves HTTP/2 or HTTP/1.1 depending on what TLS ALPN
             // negotiated (h2 multiplexes many requests over one socket — far
@@ -1355,6 +3058,8 @@ fn main() {
 
     let matches = Command::new(env!("CARGO_CRATE_NAME"))
         .version("0.1.1")
+        // `-h` is --host, so help is long-only.
+        .disable_help_flag(true)
         .about("A fast and flexible proxy server with smart host header parsing and WebSocket support")
         .long_about(
 "FBI Proxy - A any-host-port reverse-proxy server with intelligent host header parsing
@@ -1421,6 +3126,7 @@ TRY RUN:
                 .help("Domain filter - only accept requests for *.domain (env: FBI_PROXY_DOMAIN)")
                 .env("FBI_PROXY_DOMAIN")
                 .default_value("")
+                .global(true)
         )
         .arg(
             Arg::new("routes")
@@ -1430,6 +3136,7 @@ TRY RUN:
                 .help("Path to a custom routes.yaml (env: FBI_PROXY_ROUTES, default: bundled)")
                 .env("FBI_PROXY_ROUTES")
                 .default_value("")
+                .global(true)
         )
         .arg(
             Arg::new("tls")
@@ -1454,6 +3161,7 @@ TRY RUN:
                 .help("conf.d directory of per-namespace route fragments (env: FBI_PROXY_CONF_DIR, default: ~/.config/fbi-proxy/conf.d). Ignored when --routes is set.")
                 .env("FBI_PROXY_CONF_DIR")
                 .default_value("")
+                .global(true)
         )
         .arg(
             Arg::new("admin-port")
@@ -1463,8 +3171,215 @@ TRY RUN:
                 .env("FBI_PROXY_ADMIN_PORT")
                 .default_value("")
         )
+        .arg(
+            Arg::new("history-limit")
+                .long("history-limit")
+                .value_name("N")
+                .help("conf.d snapshots to keep in history/ next to conf.d for GET /history and POST /rollback; 0 turns history off (env: FBI_PROXY_HISTORY_LIMIT, default: 50)")
+                .env("FBI_PROXY_HISTORY_LIMIT")
+                .default_value("50")
+        )
+        .arg(
+            Arg::new("access-log")
+                .long("access-log")
+                .value_name("stdout|stderr|PATH")
+                .help("Write one access-log line per request to stdout, stderr or a file (env: FBI_PROXY_ACCESS_LOG, default: off)")
+                .env("FBI_PROXY_ACCESS_LOG")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("access-log-format")
+                .long("access-log-format")
+                .value_name("FORMAT")
+                .help("json, common, combined, or a template like '{remote} {method} {uri} {status} {latency_ms}' (env: FBI_PROXY_ACCESS_LOG_FORMAT, default: json)")
+                .env("FBI_PROXY_ACCESS_LOG_FORMAT")
+                .default_value("json")
+        )
+        .arg(
+            Arg::new("access-log-headers")
+                .long("access-log-headers")
+                .value_name("NAMES")
+                .help("Comma-separated request headers to capture (env: FBI_PROXY_ACCESS_LOG_HEADERS)")
+                .env("FBI_PROXY_ACCESS_LOG_HEADERS")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("access-log-redact")
+                .long("access-log-redact")
+                .value_name("NAMES")
+                .help("Extra comma-separated headers to redact; authorization, cookie and friends are always redacted (env: FBI_PROXY_ACCESS_LOG_REDACT)")
+                .env("FBI_PROXY_ACCESS_LOG_REDACT")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("access-log-max-size")
+                .long("access-log-max-size")
+                .value_name("SIZE")
+                .help("Rotate the log file past this size, e.g. 10M (env: FBI_PROXY_ACCESS_LOG_MAX_SIZE)")
+                .env("FBI_PROXY_ACCESS_LOG_MAX_SIZE")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("access-log-rotate")
+                .long("access-log-rotate")
+                .value_name("hourly|daily")
+                .help("Rotate the log file on a schedule (env: FBI_PROXY_ACCESS_LOG_ROTATE)")
+                .env("FBI_PROXY_ACCESS_LOG_ROTATE")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("access-log-keep")
+                .long("access-log-keep")
+                .value_name("N")
+                .help("Rotated files to keep (env: FBI_PROXY_ACCESS_LOG_KEEP, default: 7)")
+                .env("FBI_PROXY_ACCESS_LOG_KEEP")
+                .default_value("7")
+        )
+        .arg(
+            Arg::new("access-log-compress")
+                .long("access-log-compress")
+                .help("Gzip rotated log files (env: FBI_PROXY_ACCESS_LOG_COMPRESS)")
+                .env("FBI_PROXY_ACCESS_LOG_COMPRESS")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("otel-endpoint")
+                .long("otel-endpoint")
+                .value_name("URL")
+                .help("Export OpenTelemetry spans to this OTLP collector, e.g. http://127.0.0.1:4318 (env: FBI_PROXY_OTEL_ENDPOINT or OTEL_EXPORTER_OTLP_ENDPOINT, default: off)")
+                .env("FBI_PROXY_OTEL_ENDPOINT")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("otel-protocol")
+                .long("otel-protocol")
+                .value_name("PROTOCOL")
+                .help("http/json, http/protobuf or grpc (env: FBI_PROXY_OTEL_PROTOCOL or OTEL_EXPORTER_OTLP_PROTOCOL, default: http/json)")
+                .env("FBI_PROXY_OTEL_PROTOCOL")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("otel-sample")
+                .long("otel-sample")
+                .value_name("RATIO")
+                .help("Fraction of new traces to export, 0..1; requests with a traceparent follow its sampled flag (env: FBI_PROXY_OTEL_SAMPLE, default: 1)")
+                .env("FBI_PROXY_OTEL_SAMPLE")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("otel-service-name")
+                .long("otel-service-name")
+                .value_name("NAME")
+                .help("service.name resource attribute (env: FBI_PROXY_OTEL_SERVICE_NAME or OTEL_SERVICE_NAME, default: fbi-proxy)")
+                .env("FBI_PROXY_OTEL_SERVICE_NAME")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("otel-headers")
+                .long("otel-headers")
+                .value_name("K=V,...")
+                .help("Extra headers sent to the collector (env: FBI_PROXY_OTEL_HEADERS or OTEL_EXPORTER_OTLP_HEADERS)")
+                .env("FBI_PROXY_OTEL_HEADERS")
+                .default_value("")
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Refuse routes files with unknown keys instead of warning about them (env: FBI_PROXY_STRICT)")
+                .env("FBI_PROXY_STRICT")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            Arg::new("help")
+                .long("help")
+                .help("Print help")
+                .action(clap::ArgAction::Help)
+                .global(true)
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Validate routes files without starting the proxy: report every parse/compile error, failing route test and lint finding as FILE:LINE:COL; exits 1 if there are any")
+                .arg(
+                    Arg::new("paths")
+                        .value_name("FILE|DIR")
+                        .num_args(0..)
+                        .help("Fragments and conf.d directories to check on top of the bundled rules (default: the rules the proxy would load: --routes, else bundled + --conf-dir)")
+                )
+        )
+        .subcommand(
+            Command::new("route")
+                .about("Show how the rules the proxy would load (bundled + --conf-dir, or --routes) route one request, rule by rule; exits 1 if it is rejected")
+                .arg(Arg::new("host").value_name("HOST").required(true))
+                .arg(Arg::new("path").value_name("PATH").default_value("/"))
+                .arg(
+                    Arg::new("method")
+                        .short('X')
+                        .long("method")
+                        .value_name("METHOD")
+                        .default_value("GET")
+                        .help("Request method (only CONNECT changes routing)")
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Print the decision as JSON (same shape as GET /explain)")
+                )
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print the JSON Schema for routes files, for editor autocompletion")
+                .arg(
+                    Arg::new("migrate")
+                        .long("migrate")
+                        .value_name("FILE")
+                        .help("Instead, print FILE rewritten in the current schema version (comments are not kept)")
+                )
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Run COMMAND with a free port in $PORT and route NAME.<domain> to it through the running proxy until it exits")
+                .arg(Arg::new("name").value_name("NAME").required(true).help("Namespace and host label, e.g. web for web.<domain>"))
+                .arg(
+                    Arg::new("command")
+                        .value_name("COMMAND")
+                        .num_args(1..)
+                        .last(true)
+                        .required(true)
+                        .help("The command to run, after --")
+                )
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .value_name("PORT")
+                        .help("Use this port instead of picking a free one")
+                )
+                .arg(
+                    Arg::new("ttl")
+                        .long("ttl")
+                        .value_name("TTL")
+                        .default_value(run::DEFAULT_TTL)
+                        .help("Lease on the route, renewed while COMMAND runs, so it lapses if this wrapper is killed")
+                )
+        )
+        .subcommand(compose_command("up", "Apply this project's fbi-proxy.yaml to the running proxy (with none running, write it to conf.d)"))
+        .subcommand(compose_command("down", "Remove this project's rules from the running proxy (with none running, from conf.d)"))
+        .subcommand(compose_command("ps", "List active rules across all namespaces"))
+        .subcommand(compose_command("config", "Print the merged resolved routing table"))
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("check", sub)) => std::process::exit(run_check(sub)),
+        Some(("route", sub)) => std::process::exit(run_route(sub)),
+        Some(("schema", sub)) => std::process::exit(run_schema(sub)),
+        Some(("run", sub)) => std::process::exit(run_wrapped(sub)),
+        Some((cmd @ ("up" | "down" | "ps" | "config"), sub)) => std::process::exit(run_compose(cmd, sub)),
+        _ => {}
+    }
+
     let tls_enabled = matches.get_flag("tls");
 
     // Default port jumps to 443 when --tls is set unless the user explicitly
@@ -1492,6 +3407,7 @@ TRY RUN:
     let host = matches.get_one::<String>("host").unwrap();
     let domain = matches.get_one::<String>("domain").unwrap();
     let routes_path = matches.get_one::<String>("routes").unwrap();
+    let strict = matches.get_flag("strict");
 
     let domain_filter = if domain.is_empty() {
         None
@@ -1503,43 +3419,51 @@ TRY RUN:
     //   * --routes <file>  → legacy single-file mode (file fully replaces
     //     the bundled defaults; hot-reload watches that one file).
     //   * otherwise        → conf.d mode (default): merge bundled defaults
-    //     with every <conf_dir>/*.yaml fragment; the admin API + CLI
+    //     with every <conf_dir> fragment (YAML, JSON or TOML); the admin API + CLI
     //     manage fragments at runtime, and the dir is hot-reloaded.
-    let (compiled_routes, watch_path, conf_dir) = if !routes_path.is_empty() {
-        let src = match std::fs::read_to_string(routes_path) {
-            Ok(s) => s,
+    let (compiled_routes, watch_path, conf) = if !routes_path.is_empty() {
+        match try_reload_routes(routes_path, domain_filter.as_deref(), strict) {
+            Ok(compiled) => (compiled, Some(routes_path.clone()), None),
             Err(e) => {
-                eprintln!("error: failed to read --routes file '{}': {}", routes_path, e);
+                eprintln!("error: failed to load --routes file: {}", e);
                 std::process::exit(2);
             }
-        };
-        (
-            load_routes(&src, &format!("routes file '{}'", routes_path)),
-            Some(routes_path.clone()),
-            None,
-        )
+        }
     } else {
-        let cli_conf_dir = matches.get_one::<String>("conf-dir").map(String::as_str).unwrap_or("");
-        let dir = if cli_conf_dir.is_empty() {
-            default_conf_dir()
-        } else {
-            std::path::PathBuf::from(cli_conf_dir)
-        };
+        let dir = conf_dir_arg(&matches);
         if let Err(e) = std::fs::create_dir_all(&dir) {
             eprintln!("warning: could not create conf dir '{}': {}", dir.display(), e);
         }
-        let compiled = match rebuild_routes(&dir, BUNDLED_ROUTES_YAML) {
+        // Failing fragments are skipped (and reported on /status); only an
+        // unreadable directory falls back to the bundled defaults alone.
+        let history_limit = matches.get_one::<String>("history-limit").map(|s| s.trim()).unwrap_or("");
+        let Ok(history_limit) = history_limit.parse::<usize>() else {
+            eprintln!("error: --history-limit: invalid count '{}'", history_limit);
+            std::process::exit(2);
+        };
+        let conf = Arc::new(ConfD::new(dir, domain_filter.clone(), strict).with_history(history_limit));
+        let compiled = match conf.load() {
             Ok(c) => c,
             Err(reason) => {
                 eprintln!(
-                    "warning: failed to load conf.d ({}); falling back to bundled defaults",
+                    "warning: failed to read conf.d ({}); falling back to bundled defaults",
                     reason
                 );
                 load_routes(BUNDLED_ROUTES_YAML, "bundled routes.yaml")
             }
         };
-        (compiled, None, Some(dir))
+        for ns in conf.status().failing() {
+            eprintln!(
+                "warning: conf.d namespace '{}' skipped: {}",
+                ns.namespace,
+                ns.error.as_deref().unwrap_or("")
+            );
+        }
+        (compiled, None, Some(conf))
     };
+    for l in lint::lint(&compiled_routes) {
+        eprintln!("warning: {}", l);
+    }
 
     let admin_port = matches
         .get_one::<String>("admin-port")
@@ -1561,8 +3485,39 @@ TRY RUN:
         None
     };
 
+    let access_log = match access_log_config(&matches) {
+        Ok(None) => None,
+        Ok(Some(cfg)) => match AccessLog::open(cfg) {
+            Ok(log) => Some(Arc::new(log)),
+            Err(e) => {
+                eprintln!("error: failed to open access log: {}", e);
+                std::process::exit(2);
+            }
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let otel_cfg = match otel_config(&matches) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(async {
+        // The exporter task needs the runtime, so start it in here.
+        let tracer = match otel_cfg.map(Tracer::start).transpose() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(2);
+            }
+        };
         info!(
             "Starting FBI-Proxy on {}:{} with domain filter: {:?}, tls: {}",
             host, port, domain_filter, tls_enabled
@@ -1573,9 +3528,12 @@ TRY RUN:
             domain_filter,
             compiled_routes,
             watch_path,
-            conf_dir,
+            strict,
+            conf,
             admin_port,
             tls_opts,
+            access_log,
+            tracer,
         )
         .await
         {
@@ -1584,9 +3542,967 @@ TRY RUN:
     });
 }
 
+/// `--conf-dir`, falling back to [`default_conf_dir`].
+fn conf_dir_arg(matches: &clap::ArgMatches) -> std::path::PathBuf {
+    match matches.get_one::<String>("conf-dir").map(String::as_str).unwrap_or("") {
+        "" => default_conf_dir(),
+        dir => std::path::PathBuf::from(dir),
+    }
+}
+
+/// Sources for the offline subcommands. With no `paths`, exactly what
+/// the server loads: the `--routes` file, else the bundled rules plus
+/// every `--conf-dir` fragment. Otherwise each path (a fragment, or a
+/// conf.d directory) is layered on the bundled rules — or on `--routes`
+/// when set — in the order given. Also returns a one-line description.
+fn offline_sources(matches: &clap::ArgMatches, paths: &[String]) -> Result<(Vec<check::Source>, String), String> {
+    let read = |path: &std::path::Path| {
+        std::fs::read_to_string(path).map_err(|e| format!("read {}: {}", path.display(), e))
+    };
+    let routes_path = matches.get_one::<String>("routes").map(String::as_str).unwrap_or("");
+    let mut sources = vec![if routes_path.is_empty() {
+        check::Source {
+            path: "<bundled routes.yaml>".to_string(),
+            namespace: "default".to_string(),
+            text: BUNDLED_ROUTES_YAML.to_string(),
+        }
+    } else {
+        check::Source {
+            path: routes_path.to_string(),
+            namespace: "default".to_string(),
+            text: read(std::path::Path::new(routes_path))?,
+        }
+    }];
+    let mut described = vec![if routes_path.is_empty() { "bundled".to_string() } else { routes_path.to_string() }];
+    // Like the server, a missing default conf.d is just empty.
+    let default_dir = paths.is_empty() && routes_path.is_empty();
+    let paths: Vec<std::path::PathBuf> = match paths {
+        [] if default_dir => vec![conf_dir_arg(matches)],
+        [] => vec![],
+        given => given.iter().map(std::path::PathBuf::from).collect(),
+    };
+    for path in paths {
+        described.push(path.display().to_string());
+        // A directory's fragments are namespaced below it; a lone file by
+        // its name.
+        let (root, files) = if default_dir || path.is_dir() {
+            (path.clone(), confd::fragment_paths(&path)?)
+        } else {
+            (path.parent().unwrap_or(std::path::Path::new("")).to_path_buf(), vec![path])
+        };
+        for file in files {
+            sources.push(check::Source {
+                path: file.display().to_string(),
+                namespace: confd::namespace_of(&root, &file),
+                text: read(&file)?,
+            });
+        }
+    }
+    Ok((sources, described.join(" + ")))
+}
+
+/// `fbi-proxy check [FILE|DIR]...`: parse, compile, test and lint the
+/// rules offline and print every problem as `FILE:LINE:COL`. Returns the
+/// process exit code.
+fn run_check(matches: &clap::ArgMatches) -> i32 {
+    let paths: Vec<String> = matches.get_many::<String>("paths").map(|v| v.cloned().collect()).unwrap_or_default();
+    let domain = matches.get_one::<String>("domain").map(String::as_str).filter(|d| !d.is_empty());
+    let (sources, described) = match offline_sources(matches, &paths) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("error: {}", e);
+            return 1;
+        }
+    };
+    let report = check::check(&sources, domain, matches.get_flag("strict"));
+    for d in &report.diagnostics {
+        println!("{}", d);
+    }
+    let errors = report.errors();
+    let warnings = report.diagnostics.len() - errors;
+    if report.diagnostics.is_empty() {
+        println!("ok: {} rule(s) from {}", report.routes.len(), described);
+        0
+    } else {
+        println!("{} error(s), {} warning(s) in {}", errors, warnings, described);
+        1
+    }
+}
+
+/// `fbi-proxy route <host> [path]`: explain one routing decision against
+/// the rules the proxy would load. Exits 1 if the request is rejected, 2
+/// if the rules don't load.
+fn run_route(matches: &clap::ArgMatches) -> i32 {
+    let domain = matches.get_one::<String>("domain").map(String::as_str).filter(|d| !d.is_empty());
+    let report = match offline_sources(matches, &[]) {
+        Ok((sources, _)) => check::check(&sources, domain, matches.get_flag("strict")),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+    if report.errors() > 0 {
+        for d in report.diagnostics.iter().filter(|d| d.severity == check::Severity::Error) {
+            eprintln!("{}", d);
+        }
+        eprintln!("the proxy would refuse these rules; fix them first (fbi-proxy check)");
+        return 2;
+    }
+    let get = |name: &str| matches.get_one::<String>(name).map(String::as_str).unwrap_or("");
+    let ex = explain_route(&report.routes, domain, get("host"), get("path"), get("method"));
+    if matches.get_flag("json") {
+        match ex.to_json().and_then(|v| serde_json::to_string_pretty(&v).map_err(|e| e.to_string())) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return 2;
+            }
+        }
+    } else {
+        ex.print();
+    }
+    if ex.decision == "reject" { 1 } else { 0 }
+}
+
+/// `fbi-proxy schema [--migrate FILE]`: print the routes file JSON
+/// Schema, or FILE in the current schema version (in FILE's format). Unknown keys can't be
+/// carried over; they are reported on stderr. Returns the process exit
+/// code.
+fn run_schema(matches: &clap::ArgMatches) -> i32 {
+    let Some(path) = matches.get_one::<String>("migrate") else {
+        println!("{:#}", schema::json_schema());
+        return 0;
+    };
+    let format = Format::from_path(std::path::Path::new(path)).unwrap_or(Format::Yaml);
+    let migrated = std::fs::read_to_string(path)
+        .map_err(|e| format!("read {}: {}", path, e))
+        .and_then(|src| schema::parse(&src, format).map_err(|e| format!("parse {}: {}", path, e)))
+        .and_then(|parsed| {
+            for f in parsed.findings.iter().filter(|f| f.kind == schema::FindingKind::UnknownKey) {
+                eprintln!("warning: {}: {} (dropped)", path, f);
+            }
+            format.serialize(&parsed.file).map_err(|e| format!("serialize: {}", e))
+        });
+    match migrated {
+        Ok(text) => {
+            print!("{}", text);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+/// A compose-style subcommand; they all share the same options.
+fn compose_command(name: &'static str, about: &'static str) -> Command {
+    Command::new(name)
+        .about(about)
+        .arg(
+            Arg::new("file")
+                .short('f')
+                .long("file")
+                .value_name("FILE")
+                .default_value(compose::DEFAULT_FILE)
+                .help("Path to the compose file")
+        )
+        .arg(
+            Arg::new("project")
+                .short('p')
+                .long("project")
+                .value_name("NAME")
+                .help("Override the namespace (defaults to compose `name` or dir name)")
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FORMAT")
+                .value_parser(["table", "json", "yaml"])
+                .default_value("table")
+                .help("Output format for ps/config")
+        )
+}
+
+/// `fbi-proxy up|down|ps|config`: compose-style rule management (see
+/// [`compose`]). Talks to the proxy found through `runtime.json`; with
+/// none running, edits or reads the conf.d fragments directly — with the
+/// same validation as the admin API — so changes apply when it next
+/// starts. Returns the process exit code.
+fn run_compose(cmd: &str, matches: &clap::ArgMatches) -> i32 {
+    let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("[fbi-proxy] could not start runtime: {}", e);
+            return 1;
+        }
+    };
+    match rt.block_on(compose_cmd(cmd, matches)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("[fbi-proxy] {}", e);
+            1
+        }
+    }
+}
+
+/// `fbi-proxy run NAME -- COMMAND`: see [`run`]. Exits with the
+/// child's exit code (128 + signal if a signal ended it).
+fn run_wrapped(matches: &clap::ArgMatches) -> i32 {
+    let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("[fbi-proxy] could not start runtime: {}", e);
+            return 1;
+        }
+    };
+    match rt.block_on(run_cmd(matches)) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("[fbi-proxy] run: {}", e);
+            1
+        }
+    }
+}
+
+async fn run_cmd(matches: &clap::ArgMatches) -> Result<i32, String> {
+    let get = |name: &str| matches.get_one::<String>(name).map(String::as_str).filter(|s| !s.is_empty());
+    let name = get("name").unwrap_or_default();
+    if !run::is_valid_name(name) {
+        return Err(format!("invalid name '{}' (allowed: 1-63 chars of A-Za-z0-9-, not starting or ending with -)", name));
+    }
+    let ttl = get("ttl").unwrap_or(run::DEFAULT_TTL);
+    let ttl_ms = lease::parse_ttl(ttl).map_err(|e| format!("--ttl: {}", e))?;
+    let command: Vec<&String> = matches.get_many::<String>("command").map(|c| c.collect()).unwrap_or_default();
+    let port = match get("port") {
+        Some(p) => p.parse::<u16>().map_err(|_| format!("--port: invalid port '{}'", p))?,
+        None => run::free_port()?,
+    };
+    let conf_dir = conf_dir_arg(matches);
+    let no_proxy = || format!("no running proxy found through {}; start one first", compose::runtime_json_path(&conf_dir).display());
+    let info = compose::read_runtime(&conf_dir)?.ok_or_else(no_proxy)?;
+    let mut client = compose::AdminClient::new(info.admin_port);
+    let mut admin_port = info.admin_port;
+    let file = run::routes_file(name, port)?;
+    let mut etag = client.lease_rules(name, &file, ttl).await.map_err(|e| match e {
+        compose::AdminError::Unreachable(_) => no_proxy(),
+        compose::AdminError::Changed(e) => format!("{} (pick another --name, or remove it first)", e),
+        e => e.to_string(),
+    })?;
+    let host = match get("domain") {
+        Some(domain) => format!("{}.{}", name, domain),
+        None => format!("{}.<domain>", name),
+    };
+    eprintln!("[fbi-proxy] run: {} -> localhost:{} (PORT={})", host, port, port);
+
+    let mut child = match tokio::process::Command::new(command[0]).args(&command[1..]).env("PORT", port.to_string()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = client.remove_own_rules(name, etag.as_deref()).await;
+            return Err(format!("could not start {}: {}", command[0], e));
+        }
+    };
+
+    // Renew well inside the TTL, so one missed heartbeat doesn't drop the route.
+    let mut heartbeat = tokio::time::interval(Duration::from_millis((ttl_ms / 3).max(1_000)));
+    heartbeat.tick().await;
+    let mut signals = ForwardedSignals::new()?;
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status.map_err(|e| format!("wait for {}: {}", command[0], e))?,
+            Some(sig) = signals.recv() => forward_signal(&mut child, sig),
+            _ = heartbeat.tick() => {
+                // The proxy may have restarted on another admin port.
+                if let Ok(Some(info)) = compose::read_runtime(&conf_dir)
+                    && info.admin_port != admin_port
+                {
+                    (client, admin_port) = (compose::AdminClient::new(info.admin_port), info.admin_port);
+                }
+                let renewed = match client.renew_lease(name).await {
+                    Ok(true) => Ok(()),
+                    // Lapsed (e.g. the proxy was down for longer than the TTL): register again,
+                    // unless someone else has the namespace by now.
+                    Ok(false) => client.lease_rules(name, &file, ttl).await.map(|t| etag = t),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = renewed {
+                    eprintln!("[fbi-proxy] run: could not renew the route for {}: {}", host, e);
+                }
+            }
+        }
+    };
+    // Only our own fragment, as we left it: not namespaces nested below
+    // it, nor one someone else has written over.
+    match client.remove_own_rules(name, etag.as_deref()).await {
+        Ok(_) => eprintln!("[fbi-proxy] run: {} exited ({}); removed {}", command[0], status, host),
+        Err(compose::AdminError::Changed(_)) => {
+            eprintln!("[fbi-proxy] run: {} exited ({}); left {}, which changed since it was registered", command[0], status, host)
+        }
+        Err(e) => eprintln!("[fbi-proxy] run: could not remove {} ({}); its lease lapses within {}", host, e, ttl),
+    }
+    Ok(exit_code(status))
+}
+
+/// The signals `run` passes on to its child instead of dying of them.
+#[cfg(unix)]
+struct ForwardedSignals(Vec<(i32, tokio::signal::unix::Signal)>);
+
+#[cfg(unix)]
+impl ForwardedSignals {
+    fn new() -> Result<Self, String> {
+        use tokio::signal::unix::{signal, SignalKind};
+        [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT]
+            .into_iter()
+            .map(|sig| signal(SignalKind::from_raw(sig)).map(|s| (sig, s)).map_err(|e| format!("install signal handler: {}", e)))
+            .collect::<Result<_, _>>()
+            .map(ForwardedSignals)
+    }
+
+    async fn recv(&mut self) -> Option<i32> {
+        let pending = self.0.iter_mut().map(|(sig, s)| Box::pin(async move { s.recv().await.map(|_| *sig) }));
+        futures_util::future::select_all(pending).await.0
+    }
+}
+
+#[cfg(unix)]
+fn forward_signal(child: &mut tokio::process::Child, sig: i32) {
+    // The terminal sends Ctrl-C and Ctrl-\ to its whole foreground
+    // process group, child included; passing them on would make two.
+    // (The child stays in that group so it can still read the terminal.)
+    if matches!(sig, libc::SIGINT | libc::SIGQUIT) && in_terminal_foreground() {
+        return;
+    }
+    if let Some(pid) = child.id() {
+        // SAFETY: kill(2) on our own child's pid has no memory-safety
+        // preconditions.
+        unsafe { libc::kill(pid as i32, sig) };
+    }
+}
+
+/// Whether `run` is in the foreground process group of the terminal on
+/// its stdin, stdout or stderr.
+#[cfg(unix)]
+fn in_terminal_foreground() -> bool {
+    // SAFETY: getpgrp(2) and tcgetpgrp(3) only read process state; the
+    // latter returns -1 for a descriptor that isn't a terminal.
+    let group = unsafe { libc::getpgrp() };
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO].into_iter().any(|fd| unsafe { libc::tcgetpgrp(fd) } == group)
+}
+
+/// Off Unix only Ctrl-C is caught, and it stops the child.
+#[cfg(not(unix))]
+struct ForwardedSignals;
+
+#[cfg(not(unix))]
+impl ForwardedSignals {
+    fn new() -> Result<Self, String> {
+        Ok(ForwardedSignals)
+    }
+
+    async fn recv(&mut self) -> Option<i32> {
+        tokio::signal::ctrl_c().await.ok().map(|_| 0)
+    }
+}
+
+#[cfg(not(unix))]
+fn forward_signal(child: &mut tokio::process::Child, _sig: i32) {
+    let _ = child.start_kill();
+}
+
+/// A child's exit status as the wrapper's exit code.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(sig) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + sig;
+    }
+    status.code().unwrap_or(1)
+}
+
+/// `Ok(None)` when the proxy named by `runtime.json` doesn't answer: the
+/// file is stale, so there is no running proxy and conf.d is used.
+fn reachable<T>(res: Result<T, compose::AdminError>, conf_dir: &std::path::Path) -> Result<Option<T>, String> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(compose::AdminError::Unreachable(e)) => {
+            eprintln!("[fbi-proxy] no proxy answering on its admin port ({}); using {}", e, conf_dir.display());
+            Ok(None)
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn compose_cmd(cmd: &str, matches: &clap::ArgMatches) -> Result<(), String> {
+    let get = |name: &str| matches.get_one::<String>(name).map(String::as_str).filter(|s| !s.is_empty());
+    let conf_dir = conf_dir_arg(matches);
+    let domain = get("domain");
+    let strict = matches.get_flag("strict");
+    let file = std::path::PathBuf::from(get("file").unwrap_or(compose::DEFAULT_FILE));
+    let client = compose::read_runtime(&conf_dir)?.map(|info| compose::AdminClient::new(info.admin_port));
+    // Without a proxy, judge changes against conf.d as it loads now.
+    let offline_conf = || -> Result<ConfD, String> {
+        let conf = ConfD::new(conf_dir.clone(), domain.map(str::to_string), strict);
+        conf.load()?;
+        Ok(conf)
+    };
+
+    match cmd {
+        "up" => {
+            let compose = compose::load_compose(&file)?;
+            let ns = compose::resolve_namespace(get("project"), compose.name.as_deref(), &file);
+            let frag = compose.to_routes_file();
+            let applied = match &client {
+                Some(c) => reachable(c.apply_rules(&ns, &frag).await, &conf_dir)?,
+                None => None,
+            };
+            let (rules, lints) = match (applied, &client) {
+                (Some(rules), Some(c)) => (rules, c.lint(&ns).await.map_err(|e| e.to_string())?),
+                _ => {
+                    if !is_valid_namespace(&ns) {
+                        return Err(format!("invalid namespace '{}' (allowed: /-separated segments of A-Za-z0-9_-, max 64 chars each)", ns));
+                    }
+                    let merged = offline_conf()?.stage(&ns, Some((&frag, Format::Yaml)), false).map_err(|(_, e)| e)?;
+                    write_namespace(&conf_dir, &ns, Some((&frag, Format::Yaml)), false)?;
+                    println!(
+                        "[fbi-proxy] no running proxy: wrote {} (applies when it starts)",
+                        confd::fragment_path(&conf_dir, &ns, Format::Yaml).display()
+                    );
+                    let rules = merged.iter().map(|r| RuleInfo::new(r, &Default::default())).collect();
+                    (rules, lint::lint(&merged).into_iter().filter(|l| l.namespace == ns).collect())
+                }
+            };
+            println!("[fbi-proxy] up: namespace '{}' ({} rule(s))", ns, frag.routes.len());
+            let ours: Vec<RuleInfo> = rules.into_iter().filter(|r| r.namespace == ns).collect();
+            print!("{}", compose::rules_table(&ours));
+            for l in lints {
+                eprintln!("[fbi-proxy] warning: {}/{}: {}", l.namespace, l.route, l.message);
+            }
+        }
+        "down" => {
+            // Namespace can come from -p, or the compose file if present.
+            let ns = match get("project") {
+                Some(p) => p.to_string(),
+                None => {
+                    let name = if file.exists() { compose::load_compose(&file)?.name } else { None };
+                    compose::resolve_namespace(None, name.as_deref(), &file)
+                }
+            };
+            let removed = match &client {
+                Some(c) => reachable(c.delete_rules(&ns).await, &conf_dir)?,
+                None => None,
+            };
+            let removed = match removed {
+                Some(removed) => removed,
+                None => {
+                    if !is_valid_namespace(&ns) {
+                        return Err(format!("invalid namespace '{}' (allowed: /-separated segments of A-Za-z0-9_-, max 64 chars each)", ns));
+                    }
+                    offline_conf()?.stage(&ns, None, true).map_err(|(_, e)| e)?;
+                    !write_namespace(&conf_dir, &ns, None, true)?.is_empty()
+                }
+            };
+            if removed {
+                println!("[fbi-proxy] down: removed namespace '{}'", ns);
+            } else {
+                println!("[fbi-proxy] down: namespace '{}' was not present", ns);
+            }
+        }
+        _ => {
+            let listed = match &client {
+                Some(c) => match reachable(c.list_rules().await, &conf_dir)? {
+                    Some(rules) => Some((rules, c.status().await.map_err(|e| e.to_string())?)),
+                    None => None,
+                },
+                None => None,
+            };
+            let (rules, status) = match listed {
+                Some(listed) => listed,
+                None => {
+                    if client.is_none() {
+                        eprintln!("[fbi-proxy] no running proxy: showing the rules in {}", conf_dir.display());
+                    }
+                    let conf = ConfD::new(conf_dir.clone(), domain.map(str::to_string), strict);
+                    let routes = conf.load()?;
+                    let status = conf.status();
+                    let stale = |ns: &str| status.get(ns).is_some_and(|n| n.state == confd::NamespaceState::LastGood);
+                    let rules = routes
+                        .iter()
+                        .map(|r| RuleInfo { stale: stale(&r.namespace), ..RuleInfo::new(r, &Default::default()) })
+                        .collect();
+                    (rules, status)
+                }
+            };
+            match get("output") {
+                Some("json") => println!("{}", serde_json::to_string_pretty(&rules).map_err(|e| e.to_string())?),
+                Some("yaml") => print!("{}", serde_yaml::to_string(&rules).map_err(|e| e.to_string())?),
+                _ => print!("{}", compose::rules_table(&rules)),
+            }
+            for ns in status.failing() {
+                eprintln!(
+                    "[fbi-proxy] warning: namespace '{}' failed to load ({}): {}",
+                    ns.namespace,
+                    ns.state.as_str(),
+                    ns.error.as_deref().unwrap_or("")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Assemble the access-log config from the `--access-log*` flags;
+/// `Ok(None)` when `--access-log` is unset.
+fn access_log_config(matches: &clap::ArgMatches) -> Result<Option<access_log::Config>, String> {
+    let get = |name: &str| matches.get_one::<String>(name).map(|s| s.trim()).unwrap_or("");
+    let output = get("access-log");
+    if output.is_empty() {
+        return Ok(None);
+    }
+    let list = |name: &str| -> Vec<String> {
+        get(name).split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+    };
+    let max_bytes = match get("access-log-max-size") {
+        "" => None,
+        s => Some(access_log::parse_size(s).map_err(|e| format!("--access-log-max-size: {}", e))?),
+    };
+    let every = access_log::RotateEvery::parse(get("access-log-rotate"))
+        .map_err(|e| format!("--access-log-rotate: {}", e))?;
+    let keep = get("access-log-keep")
+        .parse::<usize>()
+        .map_err(|_| format!("--access-log-keep: invalid count '{}'", get("access-log-keep")))?;
+    Ok(Some(access_log::Config {
+        format: access_log::Format::parse(get("access-log-format")),
+        output: access_log::Output::parse(output),
+        headers: list("access-log-headers"),
+        redact: list("access-log-redact"),
+        rotation: access_log::Rotation {
+            max_bytes,
+            every,
+            keep,
+            compress: matches.get_flag("access-log-compress"),
+        },
+    }))
+}
+
+/// Assemble the OTLP exporter config from the `--otel-*` flags, falling
+/// back to the standard `OTEL_*` environment variables; `Ok(None)` when
+/// no endpoint is configured.
+fn otel_config(matches: &clap::ArgMatches) -> Result<Option<otel::Config>, String> {
+    let get = |name: &str, std_env: &str| {
+        let v = matches.get_one::<String>(name).map(|s| s.trim().to_string()).unwrap_or_default();
+        if v.is_empty() { std::env::var(std_env).unwrap_or_default().trim().to_string() } else { v }
+    };
+    let endpoint = match get("otel-endpoint", "OTEL_EXPORTER_OTLP_ENDPOINT") {
+        e if e.is_empty() => return Ok(None),
+        e => e,
+    };
+    let protocol = match get("otel-protocol", "OTEL_EXPORTER_OTLP_PROTOCOL") {
+        p if p.is_empty() => otel::Protocol::HttpJson,
+        p => otel::Protocol::parse(&p).map_err(|e| format!("--otel-protocol: {}", e))?,
+    };
+    let mut cfg = otel::Config::new(endpoint, protocol);
+    let sample = match get("otel-sample", "OTEL_TRACES_SAMPLER_ARG") {
+        s if s.is_empty() => "1".to_string(),
+        s => s,
+    };
+    cfg.sample_ratio = sample
+        .parse::<f64>()
+        .ok()
+        .filter(|r| (0.0..=1.0).contains(r))
+        .ok_or_else(|| format!("--otel-sample: expected a ratio within 0..=1, got '{}'", sample))?;
+    let service = get("otel-service-name", "OTEL_SERVICE_NAME");
+    if !service.is_empty() {
+        cfg.service_name = service;
+    }
+    cfg.headers = otel::parse_headers(&get("otel-headers", "OTEL_EXPORTER_OTLP_HEADERS"))
+        .map_err(|e| format!("--otel-headers: {}", e))?;
+    Ok(Some(cfg))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::parse_target_scheme;
+    use super::{
+        AdminState, ArcSwap, BUNDLED_ROUTES_YAML, ConfD, FBIProxy, Inspector, LeaseChange, Launcher, Metrics, Reloader, RouteStats,
+        RulesSource, WriteOptions, check_preconditions, explain_to_json, is_admin_authority, is_valid_namespace, load_routes,
+        handle_connection, parse_target_scheme, query_params, serve_admin,
+    };
+    use fbi_proxy::compose::{AdminClient, AdminError};
+    use fbi_proxy::run;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// An admin server over an empty conf.d in a fresh temp directory,
+    /// wired like `start_proxy_server` wires it.
+    struct Admin {
+        port: u16,
+        root: PathBuf,
+        state: Arc<AdminState>,
+    }
+
+    impl Admin {
+        async fn start(name: &str) -> Admin {
+            let root = std::env::temp_dir().join(format!("fbi-admin-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(root.join("conf.d")).unwrap();
+            let conf = Arc::new(ConfD::new(root.join("conf.d"), None, false).with_history(10));
+            let routes = Arc::new(ArcSwap::from_pointee(conf.load().unwrap()));
+            let (metrics, launcher) = (Metrics::new(), Arc::new(Launcher::default()));
+            let source = RulesSource::ConfD(Arc::clone(&conf));
+            let reloader = Reloader::new(source, None, Arc::clone(&routes), Arc::clone(&metrics), Arc::clone(&launcher));
+            let state = Arc::new(AdminState {
+                metrics,
+                inspector: Arc::new(Inspector::default()),
+                route_stats: Arc::new(RouteStats::new()),
+                routes_handle: routes,
+                conf: Some(conf),
+                reloader: Some(Arc::new(reloader)),
+                tls: None,
+                domain_filter: None,
+                launcher,
+            });
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(serve_admin(Arc::clone(&state), listener));
+            Admin { port, root, state }
+        }
+
+        fn conf_d(&self) -> &Path {
+            &self.state.conf.as_ref().unwrap().dir
+        }
+
+        /// Send one request; returns the status, the headers (lowercased
+        /// names) and the body.
+        async fn send(&self, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> (u16, String, String) {
+            let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", self.port)).await.unwrap();
+            let mut head = format!(
+                "{} {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\nContent-Length: {}\r\n",
+                method,
+                path,
+                self.port,
+                body.len()
+            );
+            for (name, value) in headers {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            stream.write_all(format!("{}\r\n{}", head, body).as_bytes()).await.unwrap();
+            let mut raw = String::new();
+            stream.read_to_string(&mut raw).await.unwrap();
+            let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+            let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+            (status, head.to_ascii_lowercase(), body.to_string())
+        }
+
+        async fn put_yaml(&self, path: &str, body: &str) -> (u16, String) {
+            let (status, _, body) = self.send("PUT", path, &[("Content-Type", "application/yaml")], body).await;
+            (status, body)
+        }
+    }
+
+    impl Drop for Admin {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[tokio::test]
+    async fn admin_writes_cant_read_outside_conf_d_or_the_environment() {
+        let admin = Admin::start("untrusted").await;
+        std::fs::write(admin.root.join("secret.yaml"), "root:x:0:0:root:/root:/bin/bash\n").unwrap();
+        for include in ["/etc/passwd", "../secret.yaml"] {
+            let (status, body) = admin.put_yaml("/rules/app", &format!("include: [\"{}\"]\nroutes: []\n", include)).await;
+            assert_eq!(status, 403, "{body}");
+            assert!(!body.contains("root:x"), "{body}");
+        }
+        let leak = "routes:\n  - name: web\n    match: web.fbi.com\n    target: \"${CARGO_PKG_NAME}.example:80\"\n";
+        let (status, body) = admin.put_yaml("/rules/app", leak).await;
+        assert_eq!(status, 403, "{body}");
+        assert!(body.contains("${CARGO_PKG_NAME} in target is not in `vars:`"), "{body}");
+        assert!(!admin.conf_d().join("app.yaml").exists());
+
+        // An operator's fragment keeps its environment lookups through
+        // an edit of another rule.
+        std::fs::write(admin.conf_d().join("ops.yaml"), leak).unwrap();
+        let rule = r#"{"match": {"host": "api.fbi.com"}, "action": {"proxy": "localhost:3001"}}"#;
+        let (status, _, body) = admin.send("POST", "/rules/ops/api", &[("Content-Type", "application/json")], rule).await;
+        assert_eq!(status, 201, "{body}");
+    }
+
+    /// The value of header `name` in a lowercased response head.
+    fn header_of<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+        head.lines().find_map(|l| l.strip_prefix(name)?.strip_prefix(':')).map(str::trim)
+    }
+
+    const WEB: &str = "routes:\n  - name: web\n    match: web.fbi.com\n    target: localhost:3000\n";
+
+    #[tokio::test]
+    async fn namespace_writes_are_conditional_on_the_etag() {
+        let admin = Admin::start("etag").await;
+        let (status, head, body) = admin.send("PUT", "/rules/app", &[("Content-Type", "application/yaml")], WEB).await;
+        assert_eq!(status, 200, "{body}");
+        let etag = header_of(&head, "etag").unwrap().to_string();
+        let (status, head, body) = admin.send("GET", "/rules/app", &[], "").await;
+        assert_eq!((status, header_of(&head, "etag")), (200, Some(etag.as_str())));
+        assert!(body.contains("web.fbi.com"), "{body}");
+
+        let api = WEB.replace("web", "api");
+        let stale = [("Content-Type", "application/yaml"), ("If-Match", "\"0000\"")];
+        assert_eq!(admin.send("PUT", "/rules/app", &stale, &api).await.0, 412);
+        let exists = [("Content-Type", "application/yaml"), ("If-None-Match", "*")];
+        assert_eq!(admin.send("PUT", "/rules/app", &exists, &api).await.0, 412);
+        let written = std::fs::read_to_string(admin.conf_d().join("app.yaml")).unwrap();
+        assert!(written.contains("web.fbi.com"), "{written}");
+
+        let fresh = [("Content-Type", "application/yaml"), ("If-Match", etag.as_str())];
+        let (status, head, body) = admin.send("PUT", "/rules/app", &fresh, &api).await;
+        assert_eq!(status, 200, "{body}");
+        assert_ne!(header_of(&head, "etag"), Some(etag.as_str()));
+        assert_eq!(admin.send("PUT", "/rules/app", &fresh, WEB).await.0, 412);
+    }
+
+    #[tokio::test]
+    async fn single_rules_are_created_patched_and_deleted() {
+        let admin = Admin::start("rule-edit").await;
+        let json = [("Content-Type", "application/json")];
+        let rule = r#"{"match": {"host": "api.fbi.com"}, "action": {"proxy": "localhost:3001"}}"#;
+        assert_eq!(admin.send("POST", "/rules/app/api", &json, rule).await.0, 201);
+        assert_eq!(admin.send("POST", "/rules/app/api", &json, rule).await.0, 409);
+        let web = r#"{"match": {"host": "web.fbi.com"}, "action": {"proxy": "localhost:3000"}}"#;
+        assert_eq!(admin.send("POST", "/rules/app/web", &json, web).await.0, 201);
+
+        let (status, _, body) = admin.send("PATCH", "/rules/app/api", &json, r#"{"action": {"proxy": "localhost:4000"}}"#).await;
+        assert_eq!(status, 200, "{body}");
+        let (_, _, body) = admin.send("GET", "/rules/app/api", &[], "").await;
+        assert!(body.contains("localhost:4000") && body.contains("api.fbi.com"), "{body}");
+
+        let (status, _, body) = admin.send("PATCH", "/rules/app/api", &json, r#"{"name": "web"}"#).await;
+        assert_eq!(status, 409, "{body}");
+        assert!(body.contains("rule 'web' already exists"), "{body}");
+        assert_eq!(admin.send("PATCH", "/rules/app/nope", &json, "{}").await.0, 404);
+
+        assert_eq!(admin.send("DELETE", "/rules/app/api", &[], "").await.0, 200);
+        assert_eq!(admin.send("GET", "/rules/app/api", &[], "").await.0, 404);
+        let (status, _, body) = admin.send("GET", "/rules/app/web", &[], "").await;
+        assert_eq!(status, 200, "{body}");
+    }
+
+    #[tokio::test]
+    async fn dry_runs_touch_neither_conf_d_nor_the_counters() {
+        let admin = Admin::start("dry-run").await;
+        assert_eq!(admin.put_yaml("/rules/app", WEB).await.0, 200);
+        admin.state.route_stats.record("app", "web", 200, false, 1);
+        let written = std::fs::read_to_string(admin.conf_d().join("app.yaml")).unwrap();
+
+        let yaml = [("Content-Type", "application/yaml")];
+        let (status, _, body) = admin.send("PUT", "/rules/app?dry_run=1", &yaml, &WEB.replace("web", "api")).await;
+        assert_eq!(status, 200, "{body}");
+        assert!(body.contains("\"dry_run\":true") && body.contains("\"api\""), "{body}");
+        let (status, _, body) = admin.send("DELETE", "/rules/app?dry_run=1", &[], "").await;
+        assert_eq!(status, 200, "{body}");
+
+        assert_eq!(std::fs::read_to_string(admin.conf_d().join("app.yaml")).unwrap(), written);
+        assert_eq!(admin.state.route_stats.get("app", "web").hits, 1);
+    }
+
+    #[tokio::test]
+    async fn rules_paths_name_a_namespace_before_a_rule() {
+        let admin = Admin::start("paths").await;
+        let two = "routes:\n  - name: web\n    match: web.fbi.com\n    target: localhost:3000\n  - name: x\n    match: x.fbi.com\n    target: localhost:3000\n";
+        assert_eq!(admin.put_yaml("/rules/team", two).await.0, 200);
+        assert_eq!(admin.put_yaml("/rules/team/web", &WEB.replace("web.", "nested.")).await.0, 200);
+
+        // `team/web` is both a namespace and a rule of `team`.
+        let (status, _, body) = admin.send("GET", "/rules/team/web", &[], "").await;
+        assert_eq!(status, 200, "{body}");
+        assert!(body.contains("nested.fbi.com"), "{body}");
+        let (status, _, body) = admin.send("GET", "/rules/team/x", &[], "").await;
+        assert_eq!(status, 200, "{body}");
+        assert!(body.contains("x.fbi.com") && !body.contains("web.fbi.com"), "{body}");
+
+        assert_eq!(admin.send("DELETE", "/rules/team/x", &[], "").await.0, 200);
+        let team = std::fs::read_to_string(admin.conf_d().join("team.yaml")).unwrap();
+        assert!(!team.contains("x.fbi.com") && team.contains("web.fbi.com"), "{team}");
+        assert_eq!(admin.send("DELETE", "/rules/team/web", &[], "").await.0, 200);
+        assert!(!admin.conf_d().join("team/web.yaml").exists());
+        assert!(admin.conf_d().join("team.yaml").exists());
+
+        assert_eq!(admin.put_yaml("/rules/team/web", WEB).await.0, 200);
+        assert_eq!(admin.send("DELETE", "/rules/team", &[], "").await.0, 200);
+        assert!(!admin.conf_d().join("team.yaml").exists() && !admin.conf_d().join("team/web.yaml").exists());
+    }
+
+    #[tokio::test]
+    async fn run_only_creates_and_removes_its_own_namespace() {
+        let admin = Admin::start("run").await;
+        let client = AdminClient::new(admin.port);
+        let file = run::routes_file("api", 3001).unwrap();
+
+        // Someone else's namespace is neither taken over nor removed.
+        assert_eq!(admin.put_yaml("/rules/api", WEB).await.0, 200);
+        let err = client.lease_rules("api", &file, "30s").await.unwrap_err();
+        assert!(matches!(&err, AdminError::Changed(e) if e == "namespace 'api' already exists"), "{err}");
+        assert!(std::fs::read_to_string(admin.conf_d().join("api.yaml")).unwrap().contains("web.fbi.com"));
+        assert!(admin.state.conf.as_ref().unwrap().leases.list().is_empty());
+
+        // Exiting removes its fragment, but not a namespace nested below it.
+        let etag = client.lease_rules("dev", &file, "30s").await.unwrap();
+        assert!(etag.is_some());
+        assert_eq!(admin.put_yaml("/rules/dev/web", WEB).await.0, 200);
+        assert!(client.remove_own_rules("dev", etag.as_deref()).await.unwrap());
+        assert!(!admin.conf_d().join("dev.yaml").exists());
+        assert!(admin.conf_d().join("dev/web.yaml").exists());
+        assert!(admin.state.conf.as_ref().unwrap().leases.list().is_empty());
+
+        // Nor once someone has written over it.
+        let etag = client.lease_rules("tmp", &file, "30s").await.unwrap();
+        assert_eq!(admin.put_yaml("/rules/tmp", WEB).await.0, 200);
+        assert!(matches!(client.remove_own_rules("tmp", etag.as_deref()).await, Err(AdminError::Changed(_))));
+        assert!(admin.conf_d().join("tmp.yaml").exists());
+    }
+
+    /// A request for a `launch:` rule starts the command, and is answered
+    /// by it once it listens on `$PORT`.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn proxied_requests_start_the_launched_server() {
+        if !std::process::Command::new("python3").arg("--version").output().is_ok_and(|o| o.status.success()) {
+            eprintln!("skipped: needs python3 for the launched server");
+            return;
+        }
+        let root = std::env::temp_dir().join(format!("fbi-launch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("hello.txt"), "served by the launched command\n").unwrap();
+        let port = run::free_port().unwrap();
+        let yaml = format!(
+            "routes:\n  - name: wiki\n    match: wiki.fbi.com\n    target: 127.0.0.1:{}\n    launch:\n      command: exec python3 -m http.server $PORT --bind 127.0.0.1\n      cwd: {}\n      ready: {{ timeout: 20s }}\n",
+            port,
+            root.display()
+        );
+        let proxy = Arc::new(FBIProxy::new(None, load_routes(&yaml, "launch test")));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_port = listener.local_addr().unwrap().port();
+        let served = Arc::clone(&proxy);
+        tokio::spawn(async move {
+            let (stream, remote) = listener.accept().await.unwrap();
+            let service = hyper::service::service_fn(move |req| handle_connection(req, Arc::clone(&served), remote));
+            let _ = hyper::server::conn::http1::Builder::new().serve_connection(hyper_util::rt::TokioIo::new(stream), service).await;
+        });
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", proxy_port)).await.unwrap();
+        stream.write_all(b"GET /hello.txt HTTP/1.1\r\nHost: wiki.fbi.com\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+
+        let info = proxy.launcher.info("default/wiki").unwrap();
+        proxy.launcher.stop_all().await;
+        let _ = std::fs::remove_dir_all(&root);
+        let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.0 200") || head.starts_with("HTTP/1.1 200"), "{raw}");
+        assert!(head.to_ascii_lowercase().contains("server: simplehttp"), "{raw}");
+        assert_eq!(body, "served by the launched command\n");
+        assert_eq!((info.starts, info.pid.is_some()), (1, true));
+    }
+
+    #[tokio::test]
+    async fn admin_writes_cant_launch_commands_or_come_from_forms() {
+        let admin = Admin::start("launch").await;
+        let launch = "routes:\n  - name: wiki\n    match: wiki.fbi.com\n    target: localhost:4100\n    launch:\n      command: touch /tmp/pwned\n";
+        let (status, body) = admin.put_yaml("/rules/app", launch).await;
+        assert_eq!(status, 403, "{body}");
+        assert!(body.contains("`launch:` can only be set in conf.d files"), "{body}");
+        let rule = r#"{"match": {"host": "x.fbi.com"}, "action": {"proxy": "localhost:4100", "launch": {"command": "touch /tmp/pwned"}}}"#;
+        let (status, _, body) = admin.send("POST", "/rules/app/x", &[("Content-Type", "application/json")], rule).await;
+        assert_eq!(status, 403, "{body}");
+
+        // Nor change an included rule's command through `vars:`.
+        let tools = "vars: { tools: /usr/bin }\nroutes:\n  - name: wiki\n    match: wiki.fbi.com\n    target: localhost:4100\n    launch:\n      command: \"${tools}/sleep 30\"\n";
+        std::fs::write(admin.root.join("tools.yaml"), tools).unwrap();
+        let ops = "include: [../tools.yaml]\nroutes: []\n";
+        std::fs::write(admin.conf_d().join("ops.yaml"), ops).unwrap();
+        let (status, body) = admin.put_yaml("/rules/ops", &format!("{ops}vars: {{ tools: /tmp }}\n")).await;
+        assert_eq!(status, 403, "{body}");
+        assert!(body.contains("`launch:` can only be set in conf.d files"), "{body}");
+        assert_eq!(std::fs::read_to_string(admin.conf_d().join("ops.yaml")).unwrap(), ops);
+
+        // What a page can send cross-origin without a preflight.
+        for media_type in ["application/x-www-form-urlencoded", "text/plain", "multipart/form-data; boundary=x"] {
+            let (status, _, body) = admin.send("PUT", "/rules/app", &[("Content-Type", media_type)], WEB).await;
+            assert_eq!(status, 415, "{media_type}: {body}");
+            let (status, _, _) = admin.send("POST", "/rules/app/x", &[("Content-Type", media_type)], "{}").await;
+            assert_eq!(status, 415, "{media_type}");
+        }
+        assert_eq!(admin.send("PUT", "/rules/app", &[], WEB).await.0, 415);
+        assert!(!admin.conf_d().join("app.yaml").exists());
+    }
+
+    #[tokio::test]
+    async fn admin_writes_cant_add_secret_references() {
+        let admin = Admin::start("secrets").await;
+        for value in ["Bearer ${env:CARGO_PKG_NAME}", "${file:/etc/passwd}"] {
+            let body = format!(
+                "routes:\n  - name: web\n    match: web.fbi.com\n    target: localhost:3000\n    headers:\n      X-Leak: \"{}\"\n",
+                value
+            );
+            let (status, body) = admin.put_yaml("/rules/app", &body).await;
+            assert_eq!(status, 403, "{body}");
+            assert!(body.contains("header 'X-Leak': secret references"), "{body}");
+            assert!(!admin.conf_d().join("app.yaml").exists());
+        }
+
+        // Nor send an included rule's secret elsewhere through `vars:`.
+        let shared = "vars: { api: localhost }\nroutes:\n  - name: api\n    match: api.fbi.com\n    target: \"${api}:3001\"\n    headers:\n      Authorization: \"Bearer ${env:CARGO_PKG_NAME}\"\n";
+        std::fs::write(admin.root.join("shared.yaml"), shared).unwrap();
+        let ops = "include: [../shared.yaml]\nroutes: []\n";
+        std::fs::write(admin.conf_d().join("ops.yaml"), ops).unwrap();
+        let (status, body) = admin.put_yaml("/rules/ops", &format!("{ops}vars: {{ api: evil.example }}\n")).await;
+        assert_eq!(status, 403, "{body}");
+        assert!(body.contains("header 'Authorization' sends a secret, so its target"), "{body}");
+        let (status, body) = admin.put_yaml("/rules/ops", &format!("{ops}vars: {{ api: localhost }}\n")).await;
+        assert_eq!(status, 200, "{body}");
+    }
+
+    #[test]
+    fn admin_requests_must_name_the_admin_server() {
+        for ok in ["127.0.0.1:2019", "localhost:2019", "LOCALHOST", "[::1]:2019", "[::1]"] {
+            assert!(is_admin_authority(ok, 2019), "{ok}");
+        }
+        for bad in ["127.0.0.1:3000", "evil.example:2019", "evil.example", "localhost.evil.example:2019", "127.0.0.1:x", ""] {
+            assert!(!is_admin_authority(bad, 2019), "{bad}");
+        }
+    }
+
+    #[test]
+    fn preconditions_check_the_namespace_or_merged_etag() {
+        let opts = |if_match: Option<&str>, if_none_match: Option<&str>| WriteOptions {
+            if_match: if_match.map(String::from),
+            if_none_match: if_none_match.map(String::from),
+            accept: String::new(),
+            dry_run: false,
+            lease: LeaseChange::Keep,
+        };
+        let (ns, merged) = (Some("\"aaaa\""), "\"mmmm\"");
+        assert!(check_preconditions(&opts(None, None), ns, merged).is_ok());
+        assert!(check_preconditions(&opts(Some("\"bbbb\", \"aaaa\""), None), ns, merged).is_ok());
+        assert!(check_preconditions(&opts(Some("W/\"mmmm\""), None), None, merged).is_ok());
+        assert_eq!(
+            check_preconditions(&opts(Some("\"bbbb\""), None), ns, merged).unwrap_err(),
+            "If-Match \"bbbb\" does not match ETag \"aaaa\""
+        );
+        assert!(check_preconditions(&opts(Some("*"), None), None, merged).is_err());
+        assert!(check_preconditions(&opts(None, Some("*")), None, merged).is_ok());
+        assert!(check_preconditions(&opts(None, Some("*")), ns, merged).is_err());
+    }
+
+    #[test]
+    fn namespaces_are_safe_relative_paths() {
+        for ok in ["app", "team-a/api", "a_b/c-d/e1"] {
+            assert!(is_valid_namespace(ok), "{ok}");
+        }
+        for bad in ["", "/app", "app/", "team-a//api", "../x", "a/./b", ".hidden", "a b", &"x".repeat(65)] {
+            assert!(!is_valid_namespace(bad), "{bad}");
+        }
+    }
 
     #[test]
     fn parse_target_scheme_defaults_to_http_with_no_prefix() {
@@ -1611,4 +4527,42 @@ mod tests {
             ("https", "example.dev"),
         );
     }
+
+    #[test]
+    fn query_params_decodes_components() {
+        let uri: hyper::Uri = "/requests?host=3000.fbi.com&status=5xx&path=%2Fapi%20v1&flag".parse().unwrap();
+        let q = query_params(&uri);
+        assert_eq!(q["host"], "3000.fbi.com");
+        assert_eq!(q["status"], "5xx");
+        assert_eq!(q["path"], "/api v1");
+        assert_eq!(q["flag"], "");
+        assert!(query_params(&"/requests".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn explain_reports_decision_like_route() {
+        let routes = load_routes(BUNDLED_ROUTES_YAML, "bundled routes.yaml");
+        let explain = |query: &str| -> serde_json::Value {
+            let params = query_params(&format!("/explain?{}", query).parse().unwrap());
+            serde_json::from_str(&explain_to_json(&routes, Some("fbi.com"), &params).unwrap()).unwrap()
+        };
+
+        let v = explain("host=admin.app.fbi.com");
+        assert_eq!(v["decision"], "hit");
+        assert_eq!(v["hit"]["route_name"], "subdomain-hoisting");
+        assert_eq!(v["upstream_host"], "admin");
+        assert_eq!(v["method"], "GET");
+
+        let v = explain("host=fbi.com&path=/x");
+        assert_eq!(v["decision"], "landing");
+        assert_eq!(v["apex"], true);
+        assert!(v["candidates"].as_array().unwrap().iter().any(|c| c["result"] == "needs_explicit_path"));
+
+        let v = explain("host=example.org");
+        assert_eq!(v["decision"], "reject");
+        assert_eq!(v["domain_allowed"], false);
+
+        let params = query_params(&"/explain".parse().unwrap());
+        assert!(explain_to_json(&routes, None, &params).is_err());
+    }
 }
@@ -0,0 +1,409 @@
+//! Config history for conf.d.
+//!
+//! Every time a conf.d change goes live — an admin-API write, a watched
+//! edit, a reload or a rollback — a [`Snapshot`] records the source of
+//! every fragment plus the merged rule set it produced. Snapshots are
+//! JSON files named `<id>.json` in the history directory (next to conf.d,
+//! outside the watched tree), ids only ever grow, and the oldest are
+//! pruned past the retention limit. A snapshot is skipped when nothing
+//! changed since the latest one, so a write followed by the watcher
+//! noticing it is recorded once.
+//!
+//! [`restore`] puts conf.d back the way a snapshot saw it, and [`diff`]
+//! shows what changed between two snapshots as a unified diff.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compose::RuleInfo;
+use crate::confd;
+use crate::unix_ms;
+
+/// One fragment's source as it was on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fragment {
+    pub namespace: String,
+    /// Path below conf.d, `/`-separated.
+    pub path: String,
+    pub source: String,
+}
+
+/// conf.d at one point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: u64,
+    /// Unix epoch ms when it was recorded.
+    pub at_ms: u64,
+    /// The config generation it went live as.
+    pub generation: u64,
+    /// What caused the reload (`startup`, `watch`, `admin`, `rollback`, ...).
+    pub trigger: String,
+    /// Every fragment, in load order.
+    pub fragments: Vec<Fragment>,
+    /// The merged rule set that was served, bundled defaults included.
+    pub rules: Vec<RuleInfo>,
+}
+
+/// A snapshot without its contents, for `GET /history`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub id: u64,
+    pub at_ms: u64,
+    pub generation: u64,
+    pub trigger: String,
+    pub namespaces: Vec<String>,
+    /// Namespaces added, removed or edited since the previous snapshot
+    /// kept (all of them, for the oldest).
+    pub changed: Vec<String>,
+    pub rules: usize,
+}
+
+/// The snapshot store.
+pub struct History {
+    dir: PathBuf,
+    keep: usize,
+    /// Serializes recording, so ids are handed out once.
+    writes: Mutex<()>,
+}
+
+impl History {
+    /// A store in `dir` keeping the newest `keep` snapshots. The
+    /// directory is created on the first snapshot.
+    pub fn new(dir: PathBuf, keep: usize) -> Self {
+        History { dir, keep: keep.max(1), writes: Mutex::new(()) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Record conf.d as it is now and the `rules` it produced, unless
+    /// neither changed since the latest snapshot. Returns the new id.
+    pub fn record(&self, conf_dir: &Path, generation: u64, trigger: &str, rules: Vec<RuleInfo>) -> Result<Option<u64>, String> {
+        let _writing = self.writes.lock().unwrap_or_else(|p| p.into_inner());
+        let fragments = capture(conf_dir)?;
+        let ids = self.ids()?;
+        if let Some(latest) = ids.last().map(|&id| self.get(id)).transpose()?.flatten()
+            && latest.fragments == fragments
+            && latest.rules == rules
+        {
+            return Ok(None);
+        }
+        let id = ids.last().map_or(1, |last| last + 1);
+        let snapshot = Snapshot { id, at_ms: unix_ms(), generation, trigger: trigger.to_string(), fragments, rules };
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("create {}: {}", self.dir.display(), e))?;
+        let body = serde_json::to_string(&snapshot).map_err(|e| format!("serialize snapshot: {}", e))?;
+        let tmp = self.dir.join(format!(".{}.json.tmp", id));
+        std::fs::write(&tmp, body).map_err(|e| format!("write {}: {}", tmp.display(), e))?;
+        std::fs::rename(&tmp, self.path(id)).map_err(|e| format!("write {}: {}", self.path(id).display(), e))?;
+        let kept = ids.len() + 1;
+        for old in ids.iter().take(kept.saturating_sub(self.keep)) {
+            std::fs::remove_file(self.path(*old)).map_err(|e| format!("remove {}: {}", self.path(*old).display(), e))?;
+        }
+        Ok(Some(id))
+    }
+
+    /// Ids of the snapshots kept, oldest first.
+    pub fn ids(&self) -> Result<Vec<u64>, String> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("read {}: {}", self.dir.display(), e)),
+        };
+        let mut ids: Vec<u64> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str()?.strip_suffix(".json")?.parse().ok())
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Snapshot `id`, `None` if it was never taken or was pruned.
+    pub fn get(&self, id: u64) -> Result<Option<Snapshot>, String> {
+        let path = self.path(id);
+        let src = match std::fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("read {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&src).map(Some).map_err(|e| format!("parse {}: {}", path.display(), e))
+    }
+
+    /// The snapshot kept just before `id`, if any.
+    pub fn previous(&self, id: u64) -> Result<Option<Snapshot>, String> {
+        match self.ids()?.into_iter().rev().find(|&i| i < id) {
+            Some(prev) => self.get(prev),
+            None => Ok(None),
+        }
+    }
+
+    /// Every snapshot kept, newest first.
+    pub fn list(&self) -> Result<Vec<Summary>, String> {
+        let mut out = Vec::new();
+        let mut prev: Option<Snapshot> = None;
+        for id in self.ids()? {
+            let Some(snap) = self.get(id)? else { continue };
+            out.push(Summary {
+                id: snap.id,
+                at_ms: snap.at_ms,
+                generation: snap.generation,
+                trigger: snap.trigger.clone(),
+                namespaces: snap.fragments.iter().map(|f| f.namespace.clone()).collect(),
+                changed: changed_namespaces(prev.as_ref(), &snap),
+                rules: snap.rules.len(),
+            });
+            prev = Some(snap);
+        }
+        out.reverse();
+        Ok(out)
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+/// Every fragment under `conf_dir` as it is on disk, in load order.
+pub fn capture(conf_dir: &Path) -> Result<Vec<Fragment>, String> {
+    confd::fragment_paths(conf_dir)?
+        .into_iter()
+        .map(|path| {
+            let source = std::fs::read_to_string(&path).map_err(|e| format!("read {}: {}", path.display(), e))?;
+            let rel = path.strip_prefix(conf_dir).unwrap_or(&path);
+            let rel: Vec<&str> = rel.iter().filter_map(|s| s.to_str()).collect();
+            Ok(Fragment { namespace: confd::namespace_of(conf_dir, &path), path: rel.join("/"), source })
+        })
+        .collect()
+}
+
+/// Namespaces whose fragments differ between `old` (nothing, if `None`)
+/// and `new`, in order.
+pub fn changed_namespaces(old: Option<&Snapshot>, new: &Snapshot) -> Vec<String> {
+    let before = old.map(|s| s.fragments.as_slice()).unwrap_or_default();
+    let mut out: Vec<String> = before
+        .iter()
+        .chain(&new.fragments)
+        .filter(|f| !before.contains(f) || !new.fragments.contains(f))
+        .map(|f| f.namespace.clone())
+        .collect();
+    out.sort();
+    out.dedup();
+    out
+}
+
+/// Put conf.d back the way `snapshot` saw it: rewrite every fragment
+/// whose source differs and remove fragments it didn't have (pruning
+/// directories that leaves empty). Returns the namespaces that changed.
+pub fn restore(conf_dir: &Path, snapshot: &Snapshot) -> Result<Vec<String>, String> {
+    let current = capture(conf_dir)?;
+    for f in &snapshot.fragments {
+        if current.iter().any(|c| c.path == f.path && c.source == f.source) {
+            continue;
+        }
+        let rel = Path::new(&f.path);
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("snapshot {}: fragment path '{}' escapes conf.d", snapshot.id, f.path));
+        }
+        let path = conf_dir.join(rel);
+        let dir = path.parent().unwrap_or(conf_dir);
+        std::fs::create_dir_all(dir).map_err(|e| format!("create {}: {}", dir.display(), e))?;
+        std::fs::write(&path, &f.source).map_err(|e| format!("write {}: {}", path.display(), e))?;
+    }
+    for c in current.iter().filter(|c| !snapshot.fragments.iter().any(|f| f.path == c.path)) {
+        confd::remove_fragment(conf_dir, &conf_dir.join(&c.path))?;
+    }
+    let now = Snapshot { fragments: current, ..snapshot.clone() };
+    Ok(changed_namespaces(Some(&now), snapshot))
+}
+
+/// A unified diff (three lines of context) of the fragments in `old`
+/// (nothing, if `None`) against those in `new`, file by file in path
+/// order. Empty if they have the same fragments.
+pub fn diff(old: Option<&Snapshot>, new: &Snapshot) -> String {
+    let before = old.map(|s| s.fragments.as_slice()).unwrap_or_default();
+    let mut paths: Vec<&str> = before.iter().chain(&new.fragments).map(|f| f.path.as_str()).collect();
+    paths.sort();
+    paths.dedup();
+    let source = |frags: &[Fragment], path: &str| frags.iter().find(|f| f.path == path).map(|f| f.source.clone());
+    let mut out = String::new();
+    for path in paths {
+        let (a, b) = (source(before, path), source(&new.fragments, path));
+        if a == b {
+            continue;
+        }
+        let label = |side: &str, present: bool| if present { format!("{}/{}", side, path) } else { "/dev/null".to_string() };
+        out.push_str(&format!("--- {}\n+++ {}\n", label("a", a.is_some()), label("b", b.is_some())));
+        match unified(a.as_deref().unwrap_or(""), b.as_deref().unwrap_or("")) {
+            Some(hunks) => out.push_str(&hunks),
+            None => out.push_str("(files differ; too many changed lines to diff)\n"),
+        }
+    }
+    out
+}
+
+/// At most this many cells (changed lines of `a` times changed lines
+/// of `b`) in the table [`unified`] builds; bigger changes are only
+/// reported as differing.
+const MAX_DIFF_CELLS: usize = 1 << 20;
+
+/// Hunks of a line diff from `a` to `b`, or `None` if too many lines
+/// changed to diff them (see [`MAX_DIFF_CELLS`]).
+fn unified(a: &str, b: &str) -> Option<String> {
+    const CONTEXT: usize = 3;
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+    // Lines shared at both ends need no table.
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (am, bm) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if am.len().saturating_mul(bm.len()) > MAX_DIFF_CELLS {
+        return None;
+    }
+    // Longest common subsequence table of the middles, from the end.
+    let mut lcs = vec![vec![0usize; bm.len() + 1]; am.len() + 1];
+    for i in (0..am.len()).rev() {
+        for j in (0..bm.len()).rev() {
+            lcs[i][j] = if am[i] == bm[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    // Edit script: (' ' | '-' | '+', line index in a, line index in b).
+    let mut ops: Vec<(char, usize, usize)> = (0..prefix).map(|i| (' ', i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < am.len() || j < bm.len() {
+        if i < am.len() && j < bm.len() && am[i] == bm[j] {
+            ops.push((' ', prefix + i, prefix + j));
+            (i, j) = (i + 1, j + 1);
+        } else if i < am.len() && (j == bm.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', prefix + i, prefix + j));
+            i += 1;
+        } else {
+            ops.push(('+', prefix + i, prefix + j));
+            j += 1;
+        }
+    }
+    ops.extend((0..suffix).map(|k| (' ', prefix + am.len() + k, prefix + bm.len() + k)));
+    let mut out = String::new();
+    let mut k = 0;
+    while let Some(first) = ops[k..].iter().position(|op| op.0 != ' ').map(|p| p + k) {
+        let start = first.saturating_sub(CONTEXT);
+        // Extend the hunk while the next change is within reach of its context.
+        let mut end = first;
+        while let Some(next) = ops[end + 1..].iter().position(|op| op.0 != ' ').map(|p| p + end + 1) {
+            if next - end - 1 > 2 * CONTEXT {
+                break;
+            }
+            end = next;
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+        let count = |side: char| hunk.iter().filter(|op| op.0 == ' ' || op.0 == side).count();
+        let (a_len, b_len) = (count('-'), count('+'));
+        let a_start = if a_len == 0 { hunk[0].1 } else { hunk[0].1 + 1 };
+        let b_start = if b_len == 0 { hunk[0].2 } else { hunk[0].2 + 1 };
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", a_start, a_len, b_start, b_len));
+        for &(op, i, j) in hunk {
+            let line = if op == '+' { b[j] } else { a[i] };
+            out.push_str(&format!("{}{}\n", op, line));
+        }
+        k = end;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dirs(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("fbi-history-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("conf.d")).unwrap();
+        (root.join("conf.d"), root.join("history"))
+    }
+
+    fn write(conf_dir: &Path, rel: &str, text: &str) {
+        let path = conf_dir.join(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn snapshots_record_changes_and_restore_them() {
+        let (conf, dir) = temp_dirs("restore");
+        let history = History::new(dir, 3);
+        write(&conf, "app.yaml", "routes: []\n");
+        assert_eq!(history.record(&conf, 1, "startup", Vec::new()).unwrap(), Some(1));
+        // Nothing changed: the watcher seeing an admin write is not a new snapshot.
+        assert_eq!(history.record(&conf, 2, "watch", Vec::new()).unwrap(), None);
+
+        write(&conf, "team/api.yaml", "routes: []\n");
+        write(&conf, "app.yaml", "routes: [] # edited\n");
+        assert_eq!(history.record(&conf, 2, "admin", Vec::new()).unwrap(), Some(2));
+        let list = history.list().unwrap();
+        assert_eq!(list.iter().map(|s| s.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(list[0].changed, vec!["app", "team/api"]);
+        assert_eq!(list[0].trigger, "admin");
+
+        let first = history.get(1).unwrap().unwrap();
+        assert_eq!(restore(&conf, &first).unwrap(), vec!["app", "team/api"]);
+        assert_eq!(std::fs::read_to_string(conf.join("app.yaml")).unwrap(), "routes: []\n");
+        assert!(!conf.join("team").exists());
+        assert_eq!(capture(&conf).unwrap(), first.fragments);
+
+        // Retention drops the oldest.
+        for n in 0..3 {
+            write(&conf, "app.yaml", &format!("routes: [] # {}\n", n));
+            history.record(&conf, 3 + n, "watch", Vec::new()).unwrap();
+        }
+        assert_eq!(history.ids().unwrap(), vec![3, 4, 5]);
+        assert_eq!(history.previous(3).unwrap(), None);
+        assert_eq!(history.previous(5).unwrap().map(|s| s.id), Some(4));
+        let _ = std::fs::remove_dir_all(conf.parent().unwrap());
+    }
+
+    #[test]
+    fn diffs_are_unified_per_fragment() {
+        let snap = |fragments: &[(&str, &str)]| Snapshot {
+            id: 1,
+            at_ms: 0,
+            generation: 1,
+            trigger: "watch".into(),
+            fragments: fragments
+                .iter()
+                .map(|(path, source)| Fragment {
+                    namespace: path.trim_end_matches(".yaml").into(),
+                    path: path.to_string(),
+                    source: source.to_string(),
+                })
+                .collect(),
+            rules: Vec::new(),
+        };
+        let long: String = (1..=10).map(|n| format!("l{}\n", n)).collect();
+        let old = snap(&[("app.yaml", &long), ("gone.yaml", "routes: []\n")]);
+        let new = snap(&[("app.yaml", &long.replace("l2\n", "l2b\n").replace("l9\n", "")), ("new.yaml", "routes: []\n")]);
+        assert_eq!(
+            diff(Some(&old), &new),
+            "--- a/app.yaml\n+++ b/app.yaml\n@@ -1,10 +1,9 @@\n l1\n-l2\n+l2b\n l3\n l4\n l5\n l6\n l7\n l8\n-l9\n l10\n\
+             --- a/gone.yaml\n+++ /dev/null\n@@ -1,1 +0,0 @@\n-routes: []\n\
+             --- /dev/null\n+++ b/new.yaml\n@@ -0,0 +1,1 @@\n+routes: []\n"
+        );
+        // Far-apart changes get their own hunks.
+        let far = snap(&[("app.yaml", &long.replace("l1\n", "l0\n").replace("l10\n", "l11\n"))]);
+        let d = diff(Some(&snap(&[("app.yaml", &long)])), &far);
+        assert_eq!(d.matches("@@ -").collect::<Vec<_>>().len(), 2, "{d}");
+        assert!(d.contains("@@ -1,4 +1,4 @@\n-l1\n+l0\n l2\n"), "{d}");
+        assert!(d.contains("@@ -7,4 +7,4 @@\n l7\n l8\n l9\n-l10\n+l11\n"), "{d}");
+        assert_eq!(diff(Some(&new), &new), "");
+
+        // Big fragments: one changed line in the middle is still a hunk,
+        // a rewrite of every line only says they differ.
+        let big: String = (0..5000).map(|n| format!("r{}\n", n)).collect();
+        let d = diff(Some(&snap(&[("app.yaml", &big)])), &snap(&[("app.yaml", &big.replace("r2500\n", "x\n"))]));
+        assert!(d.ends_with("@@ -2498,7 +2498,7 @@\n r2497\n r2498\n r2499\n-r2500\n+x\n r2501\n r2502\n r2503\n"), "{d}");
+        let rewritten = big.replace('r', "w");
+        let d = diff(Some(&snap(&[("app.yaml", &big)])), &snap(&[("app.yaml", &rewritten)]));
+        assert_eq!(d, "--- a/app.yaml\n+++ b/app.yaml\n(files differ; too many changed lines to diff)\n");
+    }
+}
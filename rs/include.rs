@@ -0,0 +1,204 @@
+//! `include:` in routes files.
+//!
+//! [`resolve`] flattens a parsed routes file and everything it includes
+//! into one [`RoutesFile`], so the loaders downstream never see an
+//! `include:`. Each entry is a path, or a glob with `*` / `?` in its file
+//! name, relative to the file that names it (YAML, or JSON / TOML by
+//! extension); a glob matches in sorted
+//! order and may match nothing, a plain path must exist. Included files
+//! may include others; a cycle is an error. In strict mode an unknown
+//! key in an included file fails the include (see [`crate::schema`]).
+//!
+//! The merge puts included rules and tests ahead of the file's own, in
+//! include order, with each included file's `priority` already applied
+//! to its rules. `vars` merge the other way round — the including file
+//! wins — so a shared base can reference `${api_port}` and each machine's
+//! file sets it. `disable_defaults` is the union.
+
+use std::mem::take;
+use std::path::{Path, PathBuf};
+
+use crate::routes::{RouteConfig, RoutesFile};
+use crate::schema::{self, Format};
+
+/// Merge everything `file` (read from `path`) includes into it.
+pub fn resolve(file: RoutesFile, path: &Path, strict: bool) -> Result<RoutesFile, String> {
+    let mut stack = vec![canonical(path)];
+    expand(file, path, &mut Includer { stack: &mut stack, strict })
+}
+
+struct Includer<'a> {
+    /// The files being expanded, outermost first.
+    stack: &'a mut Vec<PathBuf>,
+    strict: bool,
+}
+
+fn expand(mut file: RoutesFile, path: &Path, inc: &mut Includer) -> Result<RoutesFile, String> {
+    if file.include.is_empty() {
+        return Ok(file);
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let include = take(&mut file.include);
+    let (routes, tests) = (take(&mut file.routes), take(&mut file.tests));
+    let (vars, disabled) = (take(&mut file.vars), take(&mut file.disable_defaults));
+    for pattern in include {
+        for p in matches(dir, &pattern).map_err(|e| format!("include {}: {}", pattern, e))? {
+            let included = include_one(&p, inc).map_err(|e| format!("include {}: {}", p.display(), e))?;
+            // The included file's own `priority` doesn't survive the merge.
+            let priority = included.priority;
+            file.routes.extend(included.routes.into_iter().map(|r| RouteConfig { priority: r.priority.or(priority), ..r }));
+            file.tests.extend(included.tests);
+            file.vars.extend(included.vars);
+            file.disable_defaults.extend(included.disable_defaults);
+        }
+    }
+    file.routes.extend(routes);
+    file.tests.extend(tests);
+    file.vars.extend(vars);
+    for name in disabled {
+        if !file.disable_defaults.contains(&name) {
+            file.disable_defaults.push(name);
+        }
+    }
+    Ok(file)
+}
+
+fn include_one(path: &Path, inc: &mut Includer) -> Result<RoutesFile, String> {
+    let canon = canonical(path);
+    if inc.stack.contains(&canon) {
+        return Err("include cycle".to_string());
+    }
+    let src = std::fs::read_to_string(path).map_err(|e| format!("read: {}", e))?;
+    let format = Format::from_path(path).unwrap_or(Format::Yaml);
+    // Only the position: parser messages quote the file's contents, and
+    // whoever sees this error may not be allowed to read it.
+    if let Err(e) = schema::parse(&src, format) {
+        return Err(match e.at {
+            Some((line, col)) => format!("parse: not a routes file (line {} column {})", line, col),
+            None => "parse: not a routes file".to_string(),
+        });
+    }
+    // It parses, so this can only fail on `strict`'s unknown keys.
+    let (file, _) = schema::load(&src, format, inc.strict).map_err(|e| format!("parse: {}", e))?;
+    inc.stack.push(canon);
+    let out = expand(file, path, inc);
+    inc.stack.pop();
+    out
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The files `pattern` names relative to `dir`.
+fn matches(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let full = dir.join(pattern);
+    let is_glob = |s: &str| s.contains(['*', '?']);
+    let parent = full.parent().unwrap_or(Path::new(""));
+    if parent.to_str().is_some_and(is_glob) {
+        return Err("wildcards are only supported in the file name".to_string());
+    }
+    let Some(name) = full.file_name().and_then(|n| n.to_str()).filter(|n| is_glob(n)) else {
+        return Ok(vec![full]);
+    };
+    let listed = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+    if !listed.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut out: Vec<PathBuf> = std::fs::read_dir(listed)
+        .map_err(|e| format!("read {}: {}", listed.display(), e))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|e| e.file_name().to_str().is_some_and(|n| (!n.starts_with('.') || name.starts_with('.')) && wildcard(name, n)))
+        .map(|e| parent.join(e.file_name()))
+        .collect();
+    out.sort();
+    Ok(out)
+}
+
+/// Shell-style match of `*` (any run) and `?` (one character).
+fn wildcard(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ni));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == n[ni] => {
+                pi += 1;
+                ni += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    pi = sp + 1;
+                    ni = sn + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes;
+
+    fn write(dir: &Path, name: &str, text: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn includes_merge_ahead_of_the_file_and_its_vars_win() {
+        let dir = std::env::temp_dir().join(format!("fbi-include-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        write(&dir, "shared/base.yaml", "priority: 3\nvars:\n  api_port: \"3000\"\n  api_host: localhost\nroutes:\n  - name: api\n    match: \"api.{domain}\"\n    target: \"${api_host}:${api_port}\"\n");
+        write(&dir, "shared/teams/a.yaml", "disable_defaults: [direct-forward]\nroutes:\n  - name: team-a\n    match: \"a.{domain}\"\n    target: localhost:5001\n");
+        write(&dir, "shared/teams/b.yaml", "routes:\n  - name: team-b\n    match: \"b.{domain}\"\n    target: localhost:5002\n");
+        let main = write(&dir, "machine.yaml", "include: [shared/base.yaml, \"shared/teams/*.yaml\", \"none/*.yaml\"]\nvars:\n  api_port: \"4000\"\nroutes:\n  - name: own\n    match: \"own.{domain}\"\n    target: localhost:6000\n");
+
+        let file = routes::parse_yaml(&std::fs::read_to_string(&main).unwrap()).unwrap();
+        let merged = resolve(file, &main, false).unwrap();
+        assert!(merged.include.is_empty());
+        let names: Vec<&str> = merged.routes.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["api", "team-a", "team-b", "own"]);
+        let rules = merged.rules().unwrap();
+        assert_eq!(rules[0].target, "localhost:4000");
+        assert_eq!((rules[0].priority, rules[3].priority), (Some(3), None));
+        assert_eq!(merged.disable_defaults, vec!["direct-forward"]);
+
+        write(&dir, "loop-a.yaml", "include: [loop-b.yaml]\nroutes: []\n");
+        let b = write(&dir, "loop-b.yaml", "include: [loop-a.yaml]\nroutes: []\n");
+        let err = resolve(routes::parse_yaml("include: [loop-a.yaml]\nroutes: []\n").unwrap(), &b, false).unwrap_err();
+        assert!(err.ends_with("loop-b.yaml: include cycle"), "{err}");
+        let err = resolve(routes::parse_yaml("include: [missing.yaml]\nroutes: []\n").unwrap(), &main, false).unwrap_err();
+        assert!(err.starts_with("include ") && err.contains("missing.yaml: read: "), "{err}");
+        write(&dir, "passwd", "root:x:0:0:root:/root:/bin/bash\n");
+        let err = resolve(routes::parse_yaml("include: [passwd]\nroutes: []\n").unwrap(), &main, false).unwrap_err();
+        assert!(err.ends_with("passwd: parse: not a routes file (line 1 column 1)"), "{err}");
+        assert!(!err.contains("root:"), "{err}");
+
+        write(&dir, "typo.yaml", "routes: []\nvar:\n  a: b\n");
+        let file = routes::parse_yaml("include: [typo.yaml]\nroutes: []\n").unwrap();
+        assert!(resolve(file.clone(), &main, false).is_ok());
+        let err = resolve(file, &main, true).unwrap_err();
+        assert!(err.ends_with("typo.yaml: parse: unknown key `var` at line 2 column 1"), "{err}");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn wildcard_matches_like_a_shell() {
+        assert!(wildcard("*.yaml", "a.yaml"));
+        assert!(wildcard("team-?.y*l", "team-a.yaml"));
+        assert!(!wildcard("*.yaml", "a.yml"));
+        assert!(wildcard("*", ""));
+    }
+}
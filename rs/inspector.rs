@@ -0,0 +1,303 @@
+//! Live request inspector for the admin server.
+//!
+//! Every proxied request produces one [`RequestEvent`]. Events are kept
+//! in a bounded ring buffer (served by `GET /requests`) and fanned out
+//! over a broadcast channel to any `GET /requests/stream` subscribers —
+//! an ngrok-style view of local traffic without grepping `RUST_LOG`.
+//!
+//! Publishing never blocks the request path: the ring is a short
+//! `Mutex<VecDeque>` critical section, and a broadcast send with no
+//! subscribers (or lagging ones) is a cheap no-op.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::broadcast;
+
+/// Default ring capacity — enough for a few minutes of dev traffic.
+pub const DEFAULT_CAPACITY: usize = 500;
+
+/// Coarse failure classification for an event. `None` on the event
+/// means the upstream answered (whatever its status).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// Host rejected by the domain filter or no matching rule.
+    Rejected,
+    /// TCP/TLS connect to the upstream failed.
+    Connect,
+    /// Upstream did not answer within the request timeout.
+    Timeout,
+    /// WebSocket upstream handshake failed.
+    Upgrade,
+    /// The rule's on-demand process failed to start or become ready.
+    Launch,
+    /// Anything else that turned into a 5xx inside the proxy.
+    Internal,
+}
+
+impl ErrorClass {
+    /// Same spelling as the serialized form.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::Rejected => "rejected",
+            ErrorClass::Connect => "connect",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Upgrade => "upgrade",
+            ErrorClass::Launch => "launch",
+            ErrorClass::Internal => "internal",
+        }
+    }
+}
+
+/// One proxied request, as reported by the inspector.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestEvent {
+    /// Monotonic sequence number assigned on publish.
+    pub id: u64,
+    /// `X-Request-Id` returned to the client.
+    pub request_id: String,
+    /// W3C trace ID (32 hex chars) of the proxy span.
+    pub trace_id: String,
+    /// Unix epoch milliseconds when the request arrived.
+    pub ts_ms: u64,
+    pub method: String,
+    pub host: String,
+    pub path: String,
+    /// Matched rule name; `None` for rejected / landing requests.
+    pub route: Option<String>,
+    /// Namespace of the matched rule.
+    pub namespace: Option<String>,
+    /// Expanded upstream target.
+    pub target: Option<String>,
+    pub status: u16,
+    /// Time until response headers were ready, in milliseconds.
+    pub latency_ms: u64,
+    /// Response body size, when announced via `Content-Length`.
+    pub bytes: Option<u64>,
+    pub error: Option<ErrorClass>,
+}
+
+/// Query filter for `GET /requests` and `GET /requests/stream`. Empty
+/// fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct RequestFilter {
+    /// Case-insensitive substring of the request host.
+    pub host: Option<String>,
+    /// Exact rule name.
+    pub route: Option<String>,
+    pub status: Option<StatusFilter>,
+    /// Exact request ID or trace ID.
+    pub request_id: Option<String>,
+}
+
+/// `status=502` (exact) or `status=5xx` (class).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFilter {
+    Exact(u16),
+    Class(u16),
+}
+
+impl StatusFilter {
+    /// Parse `"404"` or `"4xx"` (case-insensitive `x`).
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let lower = s.to_ascii_lowercase();
+        if let Some(d) = lower.strip_suffix("xx") {
+            return match d.parse::<u16>() {
+                Ok(c @ 1..=5) if d.len() == 1 => Some(StatusFilter::Class(c)),
+                _ => None,
+            };
+        }
+        s.parse::<u16>().ok().map(StatusFilter::Exact)
+    }
+
+    fn matches(self, status: u16) -> bool {
+        match self {
+            StatusFilter::Exact(s) => s == status,
+            StatusFilter::Class(c) => status / 100 == c,
+        }
+    }
+}
+
+impl RequestFilter {
+    pub fn matches(&self, ev: &RequestEvent) -> bool {
+        if let Some(h) = &self.host
+            && !ev.host.to_ascii_lowercase().contains(&h.to_ascii_lowercase())
+        {
+            return false;
+        }
+        if let Some(r) = &self.route
+            && ev.route.as_deref() != Some(r.as_str())
+        {
+            return false;
+        }
+        if let Some(s) = self.status
+            && !s.matches(ev.status)
+        {
+            return false;
+        }
+        if let Some(id) = &self.request_id
+            && ev.request_id != *id
+            && ev.trace_id != *id
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Ring buffer + broadcast fan-out of recent requests.
+pub struct Inspector {
+    ring: Mutex<VecDeque<RequestEvent>>,
+    capacity: usize,
+    next_id: AtomicU64,
+    tx: broadcast::Sender<RequestEvent>,
+}
+
+impl Inspector {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (tx, _) = broadcast::channel(capacity);
+        Self {
+            ring: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            next_id: AtomicU64::new(1),
+            tx,
+        }
+    }
+
+    /// Assign the event an id, store it in the ring (evicting the oldest
+    /// entry when full), and broadcast it to live subscribers.
+    pub fn publish(&self, mut ev: RequestEvent) {
+        ev.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut ring = self.ring.lock().unwrap_or_else(|p| p.into_inner());
+            if ring.len() == self.capacity {
+                ring.pop_front();
+            }
+            ring.push_back(ev.clone());
+        }
+        // Err only means "no subscribers" — fine.
+        let _ = self.tx.send(ev);
+    }
+
+    /// Most recent events (oldest first) that pass `filter`, at most
+    /// `limit` of them.
+    pub fn recent(&self, filter: &RequestFilter, limit: usize) -> Vec<RequestEvent> {
+        let ring = self.ring.lock().unwrap_or_else(|p| p.into_inner());
+        let mut out: Vec<RequestEvent> = ring
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .take(limit)
+            .cloned()
+            .collect();
+        out.reverse();
+        out
+    }
+
+    /// Subscribe to events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<RequestEvent> {
+        self.tx.subscribe()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ev(host: &str, route: Option<&str>, status: u16) -> RequestEvent {
+        RequestEvent {
+            id: 0,
+            request_id: format!("req-{host}"),
+            trace_id: String::new(),
+            ts_ms: 0,
+            method: "GET".into(),
+            host: host.into(),
+            path: "/".into(),
+            route: route.map(String::from),
+            namespace: route.map(|_| "default".into()),
+            target: None,
+            status,
+            latency_ms: 1,
+            bytes: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn ring_evicts_oldest_and_assigns_ids() {
+        let insp = Inspector::new(2);
+        insp.publish(ev("a.fbi.com", None, 200));
+        insp.publish(ev("b.fbi.com", None, 200));
+        insp.publish(ev("c.fbi.com", None, 200));
+        let all = insp.recent(&RequestFilter::default(), 10);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].host, "b.fbi.com");
+        assert_eq!(all[1].host, "c.fbi.com");
+        assert_eq!(all[1].id, 3);
+    }
+
+    #[test]
+    fn filters_by_host_route_and_status() {
+        let insp = Inspector::new(10);
+        insp.publish(ev("3000.fbi.com", Some("port-as-host"), 200));
+        insp.publish(ev("api--8080.fbi.com", Some("host-double-dash-port"), 502));
+        insp.publish(ev("evil.com", None, 502));
+
+        let f = RequestFilter { host: Some("FBI.com".into()), ..Default::default() };
+        assert_eq!(insp.recent(&f, 10).len(), 2);
+
+        let f = RequestFilter { route: Some("port-as-host".into()), ..Default::default() };
+        assert_eq!(insp.recent(&f, 10).len(), 1);
+
+        let f = RequestFilter { status: StatusFilter::parse("5xx"), ..Default::default() };
+        assert_eq!(insp.recent(&f, 10).len(), 2);
+
+        let f = RequestFilter { status: StatusFilter::parse("200"), ..Default::default() };
+        assert_eq!(insp.recent(&f, 10)[0].host, "3000.fbi.com");
+
+        let f = RequestFilter { request_id: Some("req-evil.com".into()), ..Default::default() };
+        assert_eq!(insp.recent(&f, 10)[0].host, "evil.com");
+    }
+
+    #[test]
+    fn recent_limit_keeps_newest() {
+        let insp = Inspector::new(10);
+        for i in 0..5 {
+            insp.publish(ev(&format!("{i}.fbi.com"), None, 200));
+        }
+        let last = insp.recent(&RequestFilter::default(), 2);
+        assert_eq!(last.iter().map(|e| e.host.as_str()).collect::<Vec<_>>(), ["3.fbi.com", "4.fbi.com"]);
+    }
+
+    #[test]
+    fn status_filter_parse() {
+        assert_eq!(StatusFilter::parse("4xx"), Some(StatusFilter::Class(4)));
+        assert_eq!(StatusFilter::parse("5XX"), Some(StatusFilter::Class(5)));
+        assert_eq!(StatusFilter::parse("404"), Some(StatusFilter::Exact(404)));
+        assert_eq!(StatusFilter::parse("9xx"), None);
+        assert_eq!(StatusFilter::parse("nope"), None);
+    }
+
+    #[test]
+    fn subscribers_receive_published_events() {
+        let insp = Inspector::new(4);
+        let mut rx = insp.subscribe();
+        insp.publish(ev("a.fbi.com", None, 200));
+        let got = rx.try_recv().expect("event broadcast");
+        assert_eq!(got.host, "a.fbi.com");
+    }
+}
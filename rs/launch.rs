@@ -0,0 +1,830 @@
+//! On-demand processes for routes (`launch:` on a rule).
+//!
+//! A rule with a `launch` block names the command that serves its
+//! target. Nothing runs until a request for the rule arrives: the proxy
+//! then starts the command, holds the request until the target accepts
+//! connections (or answers the `ready` probe) and forwards it. Once no
+//! request has been in flight for `idle_timeout`, the process is stopped
+//! again, so dozens of occasionally used tools cost nothing while unused.
+//!
+//! Each process gets the target's port as `PORT` and, on Unix, a process
+//! group of its own, so stopping it (SIGTERM, then SIGKILL after
+//! [`STOP_GRACE`]) also stops whatever its shell command started. Its
+//! recent output is kept for the admin API.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::Child;
+use tokio::sync::{Notify, watch};
+use tokio::time::{Instant, timeout};
+
+use crate::lease;
+use crate::routes::{CompiledRoute, parse_target_scheme};
+use crate::unix_ms;
+
+/// How long a process may take to become ready unless `ready.timeout`
+/// says otherwise.
+pub const DEFAULT_READY_TIMEOUT: &str = "30s";
+/// How long a process may sit idle unless `idle_timeout` says otherwise.
+pub const DEFAULT_IDLE_TIMEOUT: &str = "10m";
+/// Output lines kept per process.
+pub const LOG_LINES: usize = 500;
+/// How long a stopped process gets to exit before it is killed.
+pub const STOP_GRACE: Duration = Duration::from_secs(5);
+
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A rule's `launch:` block, as written.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LaunchConfig {
+    /// A command line (run with `sh -c`), or an argv list run as is.
+    pub command: CommandLine,
+    /// Working directory; a leading `~/` is the home directory. Defaults
+    /// to the proxy's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Environment on top of the proxy's and `PORT`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// When a starting process counts as up.
+    #[serde(default, skip_serializing_if = "ReadyProbe::is_default")]
+    pub ready: ReadyProbe,
+    /// Stop the process once no request has been in flight for this
+    /// long, e.g. `15m` (default [`DEFAULT_IDLE_TIMEOUT`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<String>,
+}
+
+/// `command:` — a string for the shell, or a list.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CommandLine {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandLine::Shell(line) => f.write_str(line),
+            CommandLine::Argv(argv) => f.write_str(&argv.join(" ")),
+        }
+    }
+}
+
+/// `ready:` — by default a process is up once its target accepts a TCP
+/// connection.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ReadyProbe {
+    /// Path to `GET` instead; any status below 500 means ready.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+    /// Give up on a start after this long (default
+    /// [`DEFAULT_READY_TIMEOUT`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+impl ReadyProbe {
+    fn is_default(&self) -> bool {
+        *self == ReadyProbe::default()
+    }
+}
+
+/// A validated `launch:` block, with the address its rule proxies to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launch {
+    pub config: LaunchConfig,
+    /// `host:port` the process is expected to listen on.
+    pub addr: String,
+    /// The port, handed to the process as `PORT`.
+    pub port: u16,
+    pub ready_timeout_ms: u64,
+    pub idle_timeout_ms: u64,
+}
+
+impl Launch {
+    /// Check `config` for a rule whose target is `target`. Errors name
+    /// the field at fault, e.g. `launch.idle_timeout`.
+    pub fn new(config: LaunchConfig, target: &str) -> Result<Launch, (&'static str, String)> {
+        let blank = match &config.command {
+            CommandLine::Shell(line) => line.trim().is_empty(),
+            CommandLine::Argv(argv) => argv.first().is_none_or(|a| a.is_empty()),
+        };
+        if blank {
+            return Err(("launch.command", "is empty".to_string()));
+        }
+        if target.contains('{') {
+            return Err(("target", "a launched rule's target can't use placeholders: it is where its one process listens".to_string()));
+        }
+        if let Some(key) = config.env.keys().find(|k| k.is_empty() || k.contains('=')) {
+            return Err(("launch.env", format!("invalid variable name '{}'", key)));
+        }
+        if config.ready.http.as_deref().is_some_and(|p| !p.starts_with('/')) {
+            return Err(("launch.ready.http", "must be a path starting with '/'".to_string()));
+        }
+        let (scheme, authority) = parse_target_scheme(target);
+        let authority = authority.split('/').next().unwrap_or(authority);
+        let port = match authority.rsplit_once(':') {
+            Some((_, port)) if !authority.ends_with(']') => {
+                port.parse().map_err(|_| ("target", format!("invalid port '{}'", port)))?
+            }
+            _ if scheme == "https" => 443,
+            _ => 80,
+        };
+        let ready_timeout = config.ready.timeout.as_deref().unwrap_or(DEFAULT_READY_TIMEOUT);
+        let ready_timeout_ms = lease::parse_ttl(ready_timeout).map_err(|e| ("launch.ready.timeout", e))?;
+        let idle_timeout = config.idle_timeout.as_deref().unwrap_or(DEFAULT_IDLE_TIMEOUT);
+        let idle_timeout_ms = lease::parse_ttl(idle_timeout).map_err(|e| ("launch.idle_timeout", e))?;
+        let addr = match authority.rsplit_once(':') {
+            Some(_) if !authority.ends_with(']') => authority.to_string(),
+            _ => format!("{}:{}", authority, port),
+        };
+        Ok(Launch { config, addr, port, ready_timeout_ms, idle_timeout_ms })
+    }
+
+    fn command(&self) -> tokio::process::Command {
+        let mut cmd = match &self.config.command {
+            CommandLine::Shell(line) => {
+                let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+                let mut cmd = tokio::process::Command::new(shell);
+                cmd.arg(flag).arg(line);
+                cmd
+            }
+            CommandLine::Argv(argv) => {
+                let mut cmd = tokio::process::Command::new(&argv[0]);
+                cmd.args(&argv[1..]);
+                cmd
+            }
+        };
+        if let Some(cwd) = &self.config.cwd {
+            cmd.current_dir(expand_home(cwd));
+        }
+        cmd.env("PORT", self.port.to_string())
+            .envs(&self.config.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        cmd.process_group(0);
+        cmd
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Where a launched process is in its life.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Not running; the next request starts it.
+    #[default]
+    Stopped,
+    /// Started, not ready yet; requests are held.
+    Starting,
+    Running,
+    /// Asked to stop, not exited yet.
+    Stopping,
+    /// The last start failed (see `last_error`); the next request tries
+    /// again.
+    Failed,
+}
+
+/// A launched rule's process, as `GET /processes` reports it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessInfo {
+    pub namespace: String,
+    pub route: String,
+    pub state: Phase,
+    pub command: String,
+    /// Address it is expected to listen on.
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ready_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_request_ms: Option<u64>,
+    /// Requests (and WebSockets) currently using it.
+    pub in_flight: usize,
+    pub idle_timeout_ms: u64,
+    /// Starts attempted so far.
+    pub starts: u64,
+    /// How the last run ended, e.g. `exited with status 1`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_exit: Option<String>,
+    /// Why the last start failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// One line of a process's output: `stream` is `stdout`, `stderr`, or
+/// `proxy` for the proxy's own notes (started, stopping, exited).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogLine {
+    pub ts_ms: u64,
+    pub stream: &'static str,
+    pub line: String,
+}
+
+/// Every launched rule's process, keyed by `namespace/route`.
+#[derive(Default)]
+pub struct Launcher {
+    processes: Mutex<BTreeMap<String, Arc<Process>>>,
+}
+
+/// The key of namespace `ns`'s rule `route`.
+pub fn key(ns: &str, route: &str) -> String {
+    format!("{}/{}", ns, route)
+}
+
+impl Launcher {
+    /// Follow the live rules: list every launched rule, and stop the
+    /// processes of rules that went away or whose `launch`/target
+    /// changed (the next request starts the new one).
+    pub fn sync(&self, routes: &[CompiledRoute]) {
+        let wanted: BTreeMap<String, &CompiledRoute> =
+            routes.iter().filter(|r| r.launch.is_some()).map(|r| (key(&r.namespace, &r.name), r)).collect();
+        let mut processes = self.lock();
+        processes.retain(|k, p| {
+            let keep = wanted.contains_key(k);
+            if !keep {
+                p.stop("its rule was removed");
+            }
+            keep
+        });
+        for (k, route) in wanted {
+            let Some(launch) = &route.launch else { continue };
+            match processes.get(&k) {
+                Some(p) => p.reconfigure(launch),
+                None => {
+                    processes.insert(k, Process::new(&route.namespace, &route.name, launch));
+                }
+            }
+        }
+    }
+
+    /// Start namespace `ns`'s rule `route` if it isn't running, waiting
+    /// until it is ready. The returned guard counts a request in flight
+    /// until dropped.
+    pub async fn ensure_running(&self, ns: &str, route: &str, launch: &Arc<Launch>) -> Result<Busy, String> {
+        let process = {
+            let mut processes = self.lock();
+            let process = processes.entry(key(ns, route)).or_insert_with(|| Process::new(ns, route, launch));
+            process.reconfigure(launch);
+            Arc::clone(process)
+        };
+        let busy = Busy::new(&process);
+        process.ensure_running().await?;
+        Ok(busy)
+    }
+
+    /// Start the process at `key` (see [`key`]) without a request for it.
+    /// `None` if no launched rule has that key.
+    pub async fn start(&self, key: &str) -> Option<Result<ProcessInfo, String>> {
+        let process = self.get(key)?;
+        Some(process.ensure_running().await.map(|_| process.info()))
+    }
+
+    /// Ask the process at `key` to stop; the next request starts it
+    /// again.
+    pub fn stop(&self, key: &str) -> Option<ProcessInfo> {
+        let process = self.get(key)?;
+        process.stop("stopped through the admin API");
+        Some(process.info())
+    }
+
+    /// Stop the processes idle for their `idle_timeout` by `now_ms`.
+    /// Returns their keys.
+    pub fn reap_idle(&self, now_ms: u64) -> Vec<String> {
+        let processes: Vec<(String, Arc<Process>)> = self.lock().iter().map(|(k, p)| (k.clone(), Arc::clone(p))).collect();
+        processes
+            .into_iter()
+            .filter(|(_, p)| {
+                let idle_timeout_ms = p.launch().idle_timeout_ms;
+                let idle_since = {
+                    let state = p.state();
+                    if state.phase != Phase::Running || p.in_flight.load(Ordering::SeqCst) > 0 {
+                        return false;
+                    }
+                    state.last_request_ms.max(state.ready_ms).unwrap_or(now_ms)
+                };
+                now_ms >= idle_since + idle_timeout_ms
+                    && p.stop(&format!("idle for {}", describe_ms(now_ms - idle_since)))
+            })
+            .map(|(k, _)| k)
+            .collect()
+    }
+
+    /// Stop every process and wait for them to exit (e.g. on shutdown).
+    pub async fn stop_all(&self) {
+        let processes: Vec<Arc<Process>> = self.lock().values().cloned().collect();
+        let mut exits = Vec::new();
+        for p in &processes {
+            p.stop("the proxy is shutting down");
+            if let Some(run) = &p.state().run {
+                exits.push(run.exited.clone());
+            }
+        }
+        let all_exited = futures_util::future::join_all(exits.iter_mut().map(|e| async move {
+            let _ = e.wait_for(|done| *done).await;
+        }));
+        let _ = timeout(STOP_GRACE + Duration::from_secs(1), all_exited).await;
+    }
+
+    /// Every launched rule's process, by key.
+    pub fn list(&self) -> Vec<ProcessInfo> {
+        self.lock().values().map(|p| p.info()).collect()
+    }
+
+    /// The process at `key`.
+    pub fn info(&self, key: &str) -> Option<ProcessInfo> {
+        self.get(key).map(|p| p.info())
+    }
+
+    /// The last `tail` lines the process at `key` wrote, oldest first.
+    pub fn logs(&self, key: &str, tail: usize) -> Option<Vec<LogLine>> {
+        let process = self.get(key)?;
+        let logs = process.logs.lock().unwrap_or_else(|p| p.into_inner());
+        Some(logs.iter().skip(logs.len().saturating_sub(tail)).cloned().collect())
+    }
+
+    fn get(&self, key: &str) -> Option<Arc<Process>> {
+        self.lock().get(key).cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Arc<Process>>> {
+        self.processes.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+/// A request in flight to a launched process. A process isn't idle
+/// while any exist; its idle time counts from when the last one ended.
+pub struct Busy(Arc<Process>);
+
+impl Busy {
+    fn new(process: &Arc<Process>) -> Self {
+        process.in_flight.fetch_add(1, Ordering::SeqCst);
+        process.state().last_request_ms = Some(unix_ms());
+        Busy(Arc::clone(process))
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.state().last_request_ms = Some(unix_ms());
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct Process {
+    namespace: String,
+    route: String,
+    launch: Mutex<Arc<Launch>>,
+    state: Mutex<State>,
+    in_flight: AtomicUsize,
+    /// Serializes starts, so concurrent first requests share one.
+    starting: tokio::sync::Mutex<()>,
+    logs: Mutex<VecDeque<LogLine>>,
+}
+
+#[derive(Default)]
+struct State {
+    phase: Phase,
+    pid: Option<u32>,
+    started_ms: Option<u64>,
+    ready_ms: Option<u64>,
+    last_request_ms: Option<u64>,
+    starts: u64,
+    last_exit: Option<String>,
+    last_error: Option<String>,
+    run: Option<Run>,
+}
+
+/// The latest run: how to ask it to stop, and whether it has exited.
+struct Run {
+    id: u64,
+    stop: Arc<Notify>,
+    exited: watch::Receiver<bool>,
+}
+
+impl Process {
+    fn new(ns: &str, route: &str, launch: &Arc<Launch>) -> Arc<Self> {
+        Arc::new(Process {
+            namespace: ns.to_string(),
+            route: route.to_string(),
+            launch: Mutex::new(Arc::clone(launch)),
+            state: Mutex::new(State::default()),
+            in_flight: AtomicUsize::new(0),
+            starting: tokio::sync::Mutex::new(()),
+            logs: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn launch(&self) -> Arc<Launch> {
+        Arc::clone(&self.launch.lock().unwrap_or_else(|p| p.into_inner()))
+    }
+
+    /// Take on `launch`; a running process started with a different one
+    /// is stopped.
+    fn reconfigure(&self, launch: &Arc<Launch>) {
+        let mut current = self.launch.lock().unwrap_or_else(|p| p.into_inner());
+        if Arc::ptr_eq(&current, launch) || **current == **launch {
+            return;
+        }
+        *current = Arc::clone(launch);
+        drop(current);
+        self.stop("its launch settings changed");
+    }
+
+    async fn ensure_running(self: &Arc<Self>) -> Result<(), String> {
+        let seen = {
+            let state = self.state();
+            if state.phase == Phase::Running {
+                return Ok(());
+            }
+            state.starts
+        };
+        let _starting = self.starting.lock().await;
+        {
+            // Another request may have started it (or failed to) while
+            // this one waited.
+            let state = self.state();
+            match state.phase {
+                Phase::Running => return Ok(()),
+                Phase::Failed if state.starts != seen => {
+                    return Err(state.last_error.clone().unwrap_or_else(|| "failed to start".to_string()));
+                }
+                _ => {}
+            }
+        }
+        self.start().await
+    }
+
+    async fn start(self: &Arc<Self>) -> Result<(), String> {
+        // The previous run may still be shutting down, holding the port.
+        let previous = self.state().run.as_ref().map(|r| r.exited.clone());
+        if let Some(mut exited) = previous {
+            let _ = timeout(STOP_GRACE + Duration::from_secs(1), exited.wait_for(|done| *done)).await;
+        }
+
+        let launch = self.launch();
+        let (stop, mut exited) = self.spawn(&launch)?;
+        let deadline = Instant::now() + Duration::from_millis(launch.ready_timeout_ms);
+        loop {
+            if *exited.borrow() {
+                let why = self.state().last_exit.clone().unwrap_or_default();
+                return Err(self.fail(&format!("exited before it was ready ({})", why)));
+            }
+            if probe(&launch).await {
+                return self.ready();
+            }
+            if Instant::now() >= deadline {
+                let error = self.fail(&format!("not ready after {}", describe_ms(launch.ready_timeout_ms)));
+                stop.notify_one();
+                return Err(error);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(PROBE_INTERVAL) => {}
+                _ = exited.changed() => {}
+            }
+        }
+    }
+
+    /// Spawn a new run and the tasks that watch it. Returns how to stop
+    /// it and whether it has exited.
+    fn spawn(self: &Arc<Self>, launch: &Launch) -> Result<(Arc<Notify>, watch::Receiver<bool>), String> {
+        let spawned = launch.command().spawn();
+        let mut state = self.state();
+        state.starts += 1;
+        let id = state.starts;
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let error = format!("could not start `{}`: {}", launch.config.command, e);
+                state.phase = Phase::Failed;
+                state.last_error = Some(error.clone());
+                drop(state);
+                self.note(&error);
+                return Err(error);
+            }
+        };
+        let stop = Arc::new(Notify::new());
+        let (exited_tx, exited) = watch::channel(false);
+        state.phase = Phase::Starting;
+        state.pid = child.id();
+        state.started_ms = Some(unix_ms());
+        state.ready_ms = None;
+        state.last_error = None;
+        state.run = Some(Run { id, stop: Arc::clone(&stop), exited: exited.clone() });
+        drop(state);
+
+        log::info!("[launch] {}/{}: started `{}` (pid {:?}, PORT={})", self.namespace, self.route, launch.config.command, child.id(), launch.port);
+        self.note(&format!("started `{}` (PORT={})", launch.config.command, launch.port));
+        if let Some(out) = child.stdout.take() {
+            tokio::spawn(Arc::clone(self).capture(out, "stdout"));
+        }
+        if let Some(err) = child.stderr.take() {
+            tokio::spawn(Arc::clone(self).capture(err, "stderr"));
+        }
+        tokio::spawn(Arc::clone(self).supervise(id, child, Arc::clone(&stop), exited_tx));
+        Ok((stop, exited))
+    }
+
+    /// Record a start that became ready, unless it was stopped meanwhile.
+    fn ready(&self) -> Result<(), String> {
+        let mut state = self.state();
+        if state.phase != Phase::Starting {
+            return Err("stopped before it was ready".to_string());
+        }
+        state.phase = Phase::Running;
+        state.ready_ms = Some(unix_ms());
+        drop(state);
+        self.note("ready");
+        Ok(())
+    }
+
+    /// Record a failed start, unless it was stopped meanwhile. Returns
+    /// the error for the requests waiting on it.
+    fn fail(&self, error: &str) -> String {
+        let mut state = self.state();
+        if state.phase != Phase::Starting {
+            return "stopped before it was ready".to_string();
+        }
+        state.phase = Phase::Failed;
+        state.last_error = Some(error.to_string());
+        drop(state);
+        log::warn!("[launch] {}/{}: {}", self.namespace, self.route, error);
+        self.note(error);
+        error.to_string()
+    }
+
+    /// Ask the current run to stop. Returns whether there was one.
+    fn stop(&self, why: &str) -> bool {
+        let mut state = self.state();
+        if !matches!(state.phase, Phase::Starting | Phase::Running) {
+            return false;
+        }
+        state.phase = Phase::Stopping;
+        if let Some(run) = &state.run {
+            run.stop.notify_one();
+        }
+        drop(state);
+        log::info!("[launch] {}/{}: stopping ({})", self.namespace, self.route, why);
+        self.note(&format!("stopping: {}", why));
+        true
+    }
+
+    /// Wait for run `id` to exit, or to be asked to stop, and record how
+    /// it ended.
+    async fn supervise(self: Arc<Self>, id: u64, mut child: Child, stop: Arc<Notify>, exited: watch::Sender<bool>) {
+        let pid = child.id();
+        let status = tokio::select! {
+            status = child.wait() => status,
+            _ = stop.notified() => terminate(&mut child).await,
+        };
+        // Whatever the command left running in its group goes with it.
+        #[cfg(unix)]
+        if let Some(pid) = pid {
+            signal_group(pid, libc::SIGTERM);
+        }
+        #[cfg(not(unix))]
+        let _ = pid;
+        let how = match status {
+            Ok(status) => describe_exit(status),
+            Err(e) => format!("wait failed: {}", e),
+        };
+        {
+            let mut state = self.state();
+            if state.run.as_ref().is_some_and(|r| r.id == id) {
+                // A start still waiting for readiness reports the exit
+                // itself.
+                if matches!(state.phase, Phase::Running | Phase::Stopping) {
+                    state.phase = Phase::Stopped;
+                }
+                state.pid = None;
+                state.last_exit = Some(how.clone());
+            }
+        }
+        log::info!("[launch] {}/{}: {}", self.namespace, self.route, how);
+        self.note(&how);
+        let _ = exited.send(true);
+    }
+
+    async fn capture(self: Arc<Self>, out: impl AsyncRead + Unpin, stream: &'static str) {
+        let mut reader = BufReader::new(out);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf).trim_end_matches(['\r', '\n']).to_string();
+                    self.log(stream, line);
+                }
+            }
+        }
+    }
+
+    fn note(&self, line: &str) {
+        self.log("proxy", line.to_string());
+    }
+
+    fn log(&self, stream: &'static str, line: String) {
+        let mut logs = self.logs.lock().unwrap_or_else(|p| p.into_inner());
+        if logs.len() == LOG_LINES {
+            logs.pop_front();
+        }
+        logs.push_back(LogLine { ts_ms: unix_ms(), stream, line });
+    }
+
+    fn info(&self) -> ProcessInfo {
+        let launch = self.launch();
+        let state = self.state();
+        ProcessInfo {
+            namespace: self.namespace.clone(),
+            route: self.route.clone(),
+            state: state.phase,
+            command: launch.config.command.to_string(),
+            target: launch.addr.clone(),
+            pid: state.pid,
+            started_ms: state.started_ms,
+            ready_ms: state.ready_ms,
+            last_request_ms: state.last_request_ms,
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            idle_timeout_ms: launch.idle_timeout_ms,
+            starts: state.starts,
+            last_exit: state.last_exit.clone(),
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+/// Whether the process answers: its address accepts a connection and,
+/// with `ready.http`, a `GET` of that path gets a status below 500.
+async fn probe(launch: &Launch) -> bool {
+    let Ok(Ok(mut stream)) = timeout(PROBE_TIMEOUT, TcpStream::connect(&launch.addr)).await else {
+        return false;
+    };
+    let Some(path) = &launch.config.ready.http else { return true };
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, launch.addr);
+    let status = async {
+        stream.write_all(request.as_bytes()).await.ok()?;
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).await.ok()?;
+        line.split_whitespace().nth(1)?.parse::<u16>().ok()
+    };
+    matches!(timeout(PROBE_TIMEOUT, status).await, Ok(Some(code)) if code < 500)
+}
+
+/// SIGTERM the process group, then SIGKILL it if it outlives
+/// [`STOP_GRACE`]. Off Unix the process is killed outright.
+async fn terminate(child: &mut Child) -> std::io::Result<ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        signal_group(pid, libc::SIGTERM);
+        if let Ok(status) = timeout(STOP_GRACE, child.wait()).await {
+            return status;
+        }
+        signal_group(pid, libc::SIGKILL);
+    }
+    let _ = child.start_kill();
+    child.wait().await
+}
+
+#[cfg(unix)]
+fn signal_group(pgid: u32, sig: i32) {
+    // SAFETY: kill(2) on a process group we created has no memory-safety
+    // preconditions.
+    unsafe { libc::kill(-(pgid as i32), sig) };
+}
+
+fn describe_exit(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    if let Some(sig) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return format!("killed by signal {}", sig);
+    }
+    match status.code() {
+        Some(code) => format!("exited with status {}", code),
+        None => "exited".to_string(),
+    }
+}
+
+fn describe_ms(ms: u64) -> String {
+    if ms >= 1_000 && ms.is_multiple_of(1_000) { format!("{}s", ms / 1_000) } else { format!("{}ms", ms) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> LaunchConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn launch_blocks_are_checked_against_their_target() {
+        let launch = Launch::new(config("command: npm run dev\nidle_timeout: 15m\n"), "localhost:4100").unwrap();
+        assert_eq!((launch.addr.as_str(), launch.port), ("localhost:4100", 4100));
+        assert_eq!((launch.ready_timeout_ms, launch.idle_timeout_ms), (30_000, 900_000));
+        let launch = Launch::new(config("command: [python3, -m, http.server]\n"), "http://127.0.0.1").unwrap();
+        assert_eq!((launch.addr.as_str(), launch.port), ("127.0.0.1:80", 80));
+        assert_eq!(launch.config.command.to_string(), "python3 -m http.server");
+
+        let err = |yaml: &str, target: &str| Launch::new(config(yaml), target).unwrap_err().0;
+        assert_eq!(err("command: ''\n", "localhost:1"), "launch.command");
+        assert_eq!(err("command: []\n", "localhost:1"), "launch.command");
+        assert_eq!(err("command: x\n", "localhost:{port}"), "target");
+        assert_eq!(err("command: x\n", "localhost:http"), "target");
+        assert_eq!(err("command: x\nidle_timeout: soon\n", "localhost:1"), "launch.idle_timeout");
+        assert_eq!(err("command: x\nready: { timeout: 0s }\n", "localhost:1"), "launch.ready.timeout");
+        assert_eq!(err("command: x\nready: { http: health }\n", "localhost:1"), "launch.ready.http");
+        assert_eq!(err("command: x\nenv: { 'A=B': c }\n", "localhost:1"), "launch.env");
+    }
+
+    #[cfg(unix)]
+    fn setup(command: &str, target: &str, ready_timeout: &str) -> (Launcher, Arc<Launch>) {
+        let cfg = config(&format!("command: {:?}\nready: {{ timeout: {} }}\nidle_timeout: 1s\n", command, ready_timeout));
+        (Launcher::default(), Arc::new(Launch::new(cfg, target).unwrap()))
+    }
+
+    /// Whether `line` shows up in the output of `key` within a second;
+    /// output is read as it comes, apart from the process's life.
+    #[cfg(unix)]
+    async fn logged(launcher: &Launcher, key: &str, stream: &str, line: &str) -> bool {
+        for _ in 0..100 {
+            if launcher.logs(key, LOG_LINES).unwrap().iter().any(|l| l.stream == stream && l.line == line) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn requests_start_the_process_and_idleness_stops_it() {
+        // The test listens on the port, standing in for the server the
+        // command would start.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let (launcher, launch) = setup("echo \"up on $PORT\"; exec sleep 30", &target, "5s");
+
+        let busy = launcher.ensure_running("tools", "wiki", &launch).await.unwrap();
+        let info = launcher.info("tools/wiki").unwrap();
+        assert_eq!((info.state, info.starts, info.in_flight), (Phase::Running, 1, 1));
+        // The listener was up before the command said so.
+        let port = listener.local_addr().unwrap().port();
+        assert!(logged(&launcher, "tools/wiki", "stdout", &format!("up on {}", port)).await);
+        // Busy or not yet idle for long enough: kept.
+        assert!(launcher.reap_idle(unix_ms() + 60_000).is_empty());
+        drop(busy);
+        assert!(launcher.reap_idle(unix_ms()).is_empty());
+        assert_eq!(launcher.reap_idle(unix_ms() + 1_000), vec!["tools/wiki"]);
+
+        let mut exited = launcher.get("tools/wiki").unwrap().state().run.as_ref().unwrap().exited.clone();
+        exited.wait_for(|done| *done).await.unwrap();
+        let info = launcher.info("tools/wiki").unwrap();
+        assert_eq!((info.state, info.pid, info.last_exit.as_deref()), (Phase::Stopped, None, Some("killed by signal 15")));
+        assert_eq!(launcher.logs("tools/wiki", 1).unwrap()[0].line, "killed by signal 15");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_starts_are_reported() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let (launcher, launch) = setup("echo boom >&2; exit 3", &closed, "5s");
+        let err = launcher.ensure_running("default", "broken", &launch).await.err().unwrap();
+        assert_eq!(err, "exited before it was ready (exited with status 3)");
+        let info = launcher.info("default/broken").unwrap();
+        assert_eq!((info.state, info.in_flight), (Phase::Failed, 0));
+        assert!(logged(&launcher, "default/broken", "stderr", "boom").await);
+
+        let (launcher, launch) = setup("exec sleep 30", &closed, "300ms");
+        let err = launcher.ensure_running("default", "slow", &launch).await.err().unwrap();
+        assert_eq!(err, "not ready after 300ms");
+        assert_eq!(launcher.info("default/slow").unwrap().state, Phase::Failed);
+    }
+}
@@ -0,0 +1,263 @@
+//! Namespace leases.
+//!
+//! A namespace written with a TTL (`PUT /rules/{ns}?ttl=30s`) only stays
+//! while its owner keeps renewing the lease; once it lapses the proxy
+//! removes the fragment, so a tool that registers routes for a dev
+//! server can't leave them behind by crashing. Leases are kept in
+//! `leases.json` next to conf.d, so they survive a proxy restart; on
+//! load each gets at least one full TTL from then, giving its owner the
+//! chance to reconnect and renew.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::confd;
+
+/// A namespace's lease.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lease {
+    pub namespace: String,
+    pub ttl_ms: u64,
+    /// Unix epoch ms after which the namespace is removed.
+    pub expires_ms: u64,
+}
+
+/// Every lease, persisted to a file on each change.
+pub struct Leases {
+    path: PathBuf,
+    leases: Mutex<BTreeMap<String, Lease>>,
+    /// Why the file couldn't be loaded. It's left for the operator to
+    /// fix rather than overwritten, so every change is refused.
+    unreadable: Option<String>,
+}
+
+impl Leases {
+    /// Leases stored at `path` (none if it doesn't exist), with at least
+    /// one TTL left from `now_ms`. A file that can't be read or parsed
+    /// is kept as it is and no lease can change until it's fixed.
+    pub fn load(path: PathBuf, now_ms: u64) -> Self {
+        let stored = match std::fs::read_to_string(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(format!("read {}: {}", path.display(), e)),
+            Ok(src) => serde_json::from_str::<Vec<Lease>>(&src).map_err(|e| format!("parse {}: {}", path.display(), e)),
+        };
+        let (stored, unreadable) = match stored {
+            Ok(stored) => (stored, None),
+            Err(e) => {
+                log::warn!("[lease] {}; leases can't change until it's fixed or removed", e);
+                (Vec::new(), Some(e))
+            }
+        };
+        let leases = stored
+            .into_iter()
+            .map(|l| {
+                let expires_ms = l.expires_ms.max(now_ms.saturating_add(l.ttl_ms));
+                (l.namespace.clone(), Lease { expires_ms, ..l })
+            })
+            .collect();
+        Leases { path, leases: Mutex::new(leases), unreadable }
+    }
+
+    /// Lease namespace `ns` for `ttl_ms` from `now_ms`, replacing any
+    /// lease it had.
+    pub fn grant(&self, ns: &str, ttl_ms: u64, now_ms: u64) -> Result<Lease, String> {
+        let lease = Lease { namespace: ns.to_string(), ttl_ms, expires_ms: now_ms.saturating_add(ttl_ms) };
+        self.update(|leases| {
+            leases.insert(ns.to_string(), lease.clone());
+        })?;
+        Ok(lease)
+    }
+
+    /// Extend namespace `ns`'s lease by its TTL (or by `ttl_ms`, which
+    /// then becomes its TTL) from `now_ms`. `None` if it has no lease —
+    /// it was never leased, was released, or lapsed and was removed.
+    pub fn renew(&self, ns: &str, ttl_ms: Option<u64>, now_ms: u64) -> Result<Option<Lease>, String> {
+        if !self.lock().contains_key(ns) {
+            return Ok(None);
+        }
+        self.update(|leases| {
+            let lease = leases.get_mut(ns)?;
+            lease.ttl_ms = ttl_ms.unwrap_or(lease.ttl_ms);
+            lease.expires_ms = now_ms.saturating_add(lease.ttl_ms);
+            Some(lease.clone())
+        })
+    }
+
+    /// Drop namespace `ns`'s lease, keeping its fragment. Returns the
+    /// lease it had.
+    pub fn release(&self, ns: &str) -> Result<Option<Lease>, String> {
+        if !self.lock().contains_key(ns) {
+            return Ok(None);
+        }
+        self.update(|leases| leases.remove(ns))
+    }
+
+    /// Drop and return the leases that lapsed by `now_ms`; their
+    /// namespaces are due for removal.
+    pub fn take_expired(&self, now_ms: u64) -> Result<Vec<Lease>, String> {
+        if !self.lock().values().any(|l| l.expires_ms <= now_ms) {
+            return Ok(Vec::new());
+        }
+        self.update(|leases| {
+            let expired: Vec<Lease> = leases.values().filter(|l| l.expires_ms <= now_ms).cloned().collect();
+            leases.retain(|_, l| l.expires_ms > now_ms);
+            expired
+        })
+    }
+
+    /// Every lease, by namespace.
+    pub fn list(&self) -> Vec<Lease> {
+        self.lock().values().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Lease>> {
+        self.leases.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Apply `change` and persist the result; if it can't be saved,
+    /// nothing changes.
+    fn update<T>(&self, change: impl FnOnce(&mut BTreeMap<String, Lease>) -> T) -> Result<T, String> {
+        if let Some(e) = &self.unreadable {
+            return Err(format!("{}; fix or remove it to change leases", e));
+        }
+        let mut leases = self.lock();
+        let mut next = leases.clone();
+        let out = change(&mut next);
+        self.save(&next)?;
+        *leases = next;
+        Ok(out)
+    }
+
+    fn save(&self, leases: &BTreeMap<String, Lease>) -> Result<(), String> {
+        let list: Vec<&Lease> = leases.values().collect();
+        let body = serde_json::to_string_pretty(&list).map_err(|e| format!("serialize leases: {}", e))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("create {}: {}", dir.display(), e))?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, body).map_err(|e| format!("write {}: {}", tmp.display(), e))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| format!("write {}: {}", self.path.display(), e))
+    }
+}
+
+/// The longest lease TTL: 30 days.
+pub const MAX_TTL_MS: u64 = 30 * 24 * 3_600_000;
+
+/// Parse a lease TTL like `30s`, `1500ms`, `5m` or `1h`; a bare number
+/// is seconds. Returns milliseconds, at most [`MAX_TTL_MS`].
+pub fn parse_ttl(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let split = t.find(|c: char| !c.is_ascii_digit()).unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let mult = match unit.trim() {
+        "ms" => 1,
+        "" | "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return Err(format!("invalid ttl '{}' (expected e.g. 30s, 5m)", t)),
+    };
+    match num.parse::<u64>().ok().and_then(|n| n.checked_mul(mult)) {
+        Some(ms) if ms > MAX_TTL_MS => Err(format!("ttl '{}' is longer than 30 days", t)),
+        Some(ms) if ms > 0 => Ok(ms),
+        _ => Err(format!("invalid ttl '{}' (expected e.g. 30s, 5m)", t)),
+    }
+}
+
+/// Remove namespace `ns`'s own fragment — not the namespaces nested
+/// below it, which may have leases of their own. Returns whether it had
+/// one.
+pub fn remove_namespace(conf_dir: &Path, ns: &str) -> Result<bool, String> {
+    let paths: Vec<PathBuf> =
+        confd::fragment_paths(conf_dir)?.into_iter().filter(|p| confd::namespace_of(conf_dir, p) == ns).collect();
+    for path in &paths {
+        confd::remove_fragment(conf_dir, path)?;
+    }
+    Ok(!paths.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttls_parse_with_units() {
+        assert_eq!(parse_ttl("30s"), Ok(30_000));
+        assert_eq!(parse_ttl("45"), Ok(45_000));
+        assert_eq!(parse_ttl("1500ms"), Ok(1_500));
+        assert_eq!(parse_ttl("5m"), Ok(300_000));
+        assert_eq!(parse_ttl(" 1h "), Ok(3_600_000));
+        assert_eq!(parse_ttl("720h"), Ok(MAX_TTL_MS));
+        for bad in ["", "0s", "s", "-1s", "2d", "1.5s", "18446744073709551615ms", "18446744073709551615"] {
+            assert!(parse_ttl(bad).is_err(), "{bad}");
+        }
+        assert_eq!(parse_ttl("721h").unwrap_err(), "ttl '721h' is longer than 30 days");
+    }
+
+    #[test]
+    fn leases_lapse_unless_renewed_and_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("fbi-lease-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("leases.json");
+        let leases = Leases::load(path.clone(), 0);
+        leases.grant("app", 1_000, 0).unwrap();
+        leases.grant("team/api", 1_000, 0).unwrap();
+        assert_eq!(leases.renew("app", None, 900).unwrap().map(|l| l.expires_ms), Some(1_900));
+        assert_eq!(leases.renew("nope", None, 900).unwrap(), None);
+
+        let expired = leases.take_expired(1_000).unwrap();
+        assert_eq!(expired.iter().map(|l| l.namespace.as_str()).collect::<Vec<_>>(), vec!["team/api"]);
+        assert_eq!(leases.renew("team/api", None, 1_001).unwrap(), None);
+
+        // Restarting long after: the owner gets one TTL to come back.
+        let reloaded = Leases::load(path, 10_000);
+        assert_eq!(reloaded.list(), vec![Lease { namespace: "app".into(), ttl_ms: 1_000, expires_ms: 11_000 }]);
+        assert_eq!(reloaded.release("app").unwrap().map(|l| l.ttl_ms), Some(1_000));
+        assert_eq!(reloaded.release("app").unwrap(), None);
+        assert!(reloaded.list().is_empty());
+
+        // Expiry saturates rather than wrapping around.
+        assert_eq!(reloaded.grant("far", u64::MAX, 1).unwrap().expires_ms, u64::MAX);
+        assert_eq!(reloaded.renew("far", None, 2).unwrap().map(|l| l.expires_ms), Some(u64::MAX));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn an_unreadable_leases_file_is_kept() {
+        let dir = std::env::temp_dir().join(format!("fbi-lease-bad-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("leases.json");
+        std::fs::write(&path, "[{\"namespace\": \"app\"").unwrap();
+        let leases = Leases::load(path.clone(), 0);
+        assert!(leases.list().is_empty());
+        let err = leases.grant("web", 1_000, 0).unwrap_err();
+        assert!(err.starts_with(&format!("parse {}: ", path.display())), "{err}");
+        assert!(err.ends_with("; fix or remove it to change leases"), "{err}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[{\"namespace\": \"app\"");
+
+        std::fs::remove_file(&path).unwrap();
+        let leases = Leases::load(path.clone(), 0);
+        leases.grant("web", 1_000, 0).unwrap();
+        assert_eq!(Leases::load(path, 0).list().len(), 1);
+        assert!(!dir.join("leases.json.tmp").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn lapsed_namespaces_keep_their_nested_ones() {
+        let dir = std::env::temp_dir().join(format!("fbi-lease-ns-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("team/web")).unwrap();
+        std::fs::write(dir.join("team.yaml"), "routes: []\n").unwrap();
+        std::fs::write(dir.join("team/web/site.yaml"), "routes: []\n").unwrap();
+        assert!(remove_namespace(&dir, "team").unwrap());
+        assert!(!remove_namespace(&dir, "team").unwrap());
+        assert_eq!(confd::namespaces(&dir).unwrap(), vec!["team/web/site"]);
+        assert!(remove_namespace(&dir, "team/web/site").unwrap());
+        assert!(!dir.join("team").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -3,6 +3,31 @@
 //! Exposes internal modules so they can be unit-tested via
 //! `cargo test --lib` and reused by the binary in `rs/fbi-proxy.rs`.
 
+pub mod access_log;
+pub mod check;
+pub mod compose;
+pub mod confd;
+pub mod history;
+pub mod include;
+pub mod launch;
+pub mod lease;
+pub mod inspector;
+pub mod lint;
 pub mod metrics;
+pub mod otel;
+pub mod route_stats;
 pub mod routes;
+pub mod run;
+pub mod schema;
+pub mod secrets;
 pub mod tls;
+pub mod trace;
+pub mod untrusted;
+
+/// Milliseconds since the Unix epoch (0 if the clock is before 1970).
+pub fn unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,518 @@
+//! Static checks over a merged route set.
+//!
+//! Rules are merged from the bundled defaults and every conf.d fragment,
+//! so one project's fragment can silently break another's. [`lint`]
+//! reports, for the live (or about-to-be-loaded) set:
+//!
+//! * **shadowed** rules — an earlier rule with the same path priority
+//!   matches every host this one matches, so it can never win;
+//! * **conflicts** — rules from different namespaces that overlap at the
+//!   same path priority, so evaluation order (rule `priority`, then
+//!   merge order) alone decides who wins;
+//! * **invalid targets** — target templates that can never expand to a
+//!   valid `host[:port]` authority;
+//! * **unused placeholders** — captured in `match` but used in neither
+//!   `target` nor `headers`. `{domain}` and names starting with `_` are
+//!   exempt, since they are routinely matched only to be discarded.
+//!
+//! Host patterns are compared label by label (dot-separated), with
+//! `{name:multi}` and `{domain}` spanning several labels. The analysis is
+//! conservative: overlaps it cannot prove are not reported, so a clean
+//! result is not a guarantee, but every finding is real.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::routes::{self, CompiledRoute, PlaceholderKind, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintKind {
+    Shadowed,
+    Conflict,
+    InvalidTarget,
+    UnusedPlaceholder,
+}
+
+impl LintKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LintKind::Shadowed => "shadowed",
+            LintKind::Conflict => "conflict",
+            LintKind::InvalidTarget => "invalid-target",
+            LintKind::UnusedPlaceholder => "unused-placeholder",
+        }
+    }
+}
+
+/// One finding, attached to the rule it concerns.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub namespace: String,
+    pub route: String,
+    /// The other rule involved (`namespace/name`), for shadowing and
+    /// conflicts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub other: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}: {}: {}", self.namespace, self.route, self.kind.as_str(), self.message)
+    }
+}
+
+/// Check `routes` (in evaluation order) and return every finding.
+pub fn lint(routes: &[CompiledRoute]) -> Vec<Lint> {
+    let labels: Vec<Option<Vec<Label>>> = routes.iter().map(|r| host_labels(&r.match_pattern)).collect();
+    let mut out = Vec::new();
+
+    for (j, b) in routes.iter().enumerate() {
+        let lint = |kind, other: Option<&CompiledRoute>, message: String| Lint {
+            kind,
+            namespace: b.namespace.clone(),
+            route: b.name.clone(),
+            other: other.map(|o| format!("{}/{}", o.namespace, o.name)),
+            message,
+        };
+
+        if let Some(lb) = &labels[j] {
+            let same_priority = |i: &usize| routes[*i].path_prefix == b.path_prefix;
+            let shadow = (0..j)
+                .filter(same_priority)
+                .find(|&i| labels[i].as_ref().is_some_and(|la| covers(la, lb)));
+            if let Some(i) = shadow {
+                let a = &routes[i];
+                out.push(lint(
+                    LintKind::Shadowed,
+                    Some(a),
+                    format!(
+                        "never matches: '{}/{}' ({}{}) comes earlier and matches every host '{}' does",
+                        a.namespace,
+                        a.name,
+                        a.match_pattern,
+                        a.path_prefix.as_deref().map(|p| format!(" {}", p)).unwrap_or_default(),
+                        b.match_pattern
+                    ),
+                ));
+            } else {
+                for i in (0..j).filter(same_priority) {
+                    let a = &routes[i];
+                    if a.namespace != b.namespace
+                        && labels[i].as_ref().is_some_and(|la| intersects(la, lb))
+                    {
+                        out.push(lint(
+                            LintKind::Conflict,
+                            Some(a),
+                            format!(
+                                "overlaps '{}/{}' ({}) at the same path priority; the earlier rule wins where both match (set `priority` to choose)",
+                                a.namespace, a.name, a.match_pattern
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(reason) = invalid_target(b) {
+            out.push(lint(LintKind::InvalidTarget, None, reason));
+        }
+
+        for p in &b.placeholders {
+            if p.name == "domain" || p.name.starts_with('_') || uses_placeholder(b, &p.name) {
+                continue;
+            }
+            out.push(lint(
+                LintKind::UnusedPlaceholder,
+                None,
+                format!(
+                    "{{{}}} is captured but never used in target or headers (rename it {{_{}}} if that is intended)",
+                    p.name, p.name
+                ),
+            ));
+        }
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Host pattern model
+// ---------------------------------------------------------------------------
+
+/// Character classes of the placeholder kinds, ordered by inclusion:
+/// `Int ⊂ Slug ⊂ AlnumHyphen ⊂ Any`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Class {
+    Int,
+    Slug,
+    /// `{domain}` labels: `[a-zA-Z0-9-]`.
+    AlnumHyphen,
+    /// Anything but a dot.
+    Any,
+}
+
+impl Class {
+    /// Smallest class containing every character of `lit`.
+    fn of_literal(lit: &str) -> Class {
+        if lit.bytes().all(|b| b.is_ascii_digit()) {
+            Class::Int
+        } else if lit.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-') {
+            Class::Slug
+        } else if lit.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            Class::AlnumHyphen
+        } else {
+            Class::Any
+        }
+    }
+
+    fn regex(self) -> &'static str {
+        match self {
+            Class::Int => r"\d+",
+            Class::Slug => "[a-z0-9-]+",
+            Class::AlnumHyphen => "[a-zA-Z0-9-]+",
+            Class::Any => "[^.]+",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Lit(String),
+    Ph(Class),
+}
+
+/// One dot-separated label of a host pattern, or a run of labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Label {
+    Seg(Vec<Piece>),
+    /// `{name:multi}` (at least 1 label, any chars) or `{domain}` (at
+    /// least 2 labels of `[a-zA-Z0-9-]`).
+    Multi { min: usize, class: Class },
+}
+
+impl Label {
+    /// Fewest labels this can produce.
+    fn min_labels(&self) -> usize {
+        match self {
+            Label::Seg(_) => 1,
+            Label::Multi { min, .. } => *min,
+        }
+    }
+
+    /// Every label this produces falls within `class`.
+    fn within(&self, class: Class) -> bool {
+        match self {
+            Label::Seg(pieces) => pieces.iter().all(|p| match p {
+                Piece::Lit(l) => Class::of_literal(l) <= class,
+                Piece::Ph(c) => *c <= class,
+            }),
+            Label::Multi { class: c, .. } => *c <= class,
+        }
+    }
+
+    /// At least one label this produces falls within `class`. Every
+    /// placeholder class admits digits, so only literals can rule it out.
+    fn can_be_within(&self, class: Class) -> bool {
+        match self {
+            Label::Seg(pieces) => pieces.iter().all(|p| match p {
+                Piece::Lit(l) => Class::of_literal(l) <= class,
+                Piece::Ph(_) => true,
+            }),
+            Label::Multi { .. } => true,
+        }
+    }
+}
+
+/// Split a `match` pattern into labels. `None` when a multi-label
+/// placeholder shares a label with other text — such rules are skipped
+/// rather than guessed at.
+fn host_labels(pattern: &str) -> Option<Vec<Label>> {
+    let tokens = routes::tokenize(pattern, "", "match pattern").ok()?;
+    let mut labels = Vec::new();
+    let mut current: Vec<Piece> = Vec::new();
+    // A multi-label placeholder was just pushed; the next thing must be a
+    // dot (or the end).
+    let mut after_multi = false;
+    for tok in tokens {
+        match tok {
+            Token::Literal(lit) => {
+                for (k, part) in lit.split('.').enumerate() {
+                    if k > 0 {
+                        if !after_multi {
+                            labels.push(Label::Seg(std::mem::take(&mut current)));
+                        }
+                        after_multi = false;
+                    }
+                    if !part.is_empty() {
+                        if after_multi {
+                            return None;
+                        }
+                        current.push(Piece::Lit(part.to_string()));
+                    }
+                }
+            }
+            Token::Placeholder { name, kind } => {
+                if after_multi {
+                    return None;
+                }
+                let parsed = routes::parse_kind("", &name, kind.as_deref()).ok()?;
+                let multi = match (parsed, kind.is_none() && name == "domain") {
+                    (_, true) => Some(Label::Multi { min: 2, class: Class::AlnumHyphen }),
+                    (PlaceholderKind::Multi, _) => Some(Label::Multi { min: 1, class: Class::Any }),
+                    _ => None,
+                };
+                match multi {
+                    Some(label) => {
+                        if !current.is_empty() {
+                            return None;
+                        }
+                        labels.push(label);
+                        after_multi = true;
+                    }
+                    None => current.push(Piece::Ph(match parsed {
+                        PlaceholderKind::Int => Class::Int,
+                        PlaceholderKind::Slug => Class::Slug,
+                        _ => Class::Any,
+                    })),
+                }
+            }
+        }
+    }
+    if !after_multi {
+        labels.push(Label::Seg(current));
+    }
+    Some(labels)
+}
+
+fn all_literal(pieces: &[Piece]) -> Option<String> {
+    pieces
+        .iter()
+        .map(|p| match p {
+            Piece::Lit(l) => Some(l.as_str()),
+            Piece::Ph(_) => None,
+        })
+        .collect()
+}
+
+fn seg_regex(pieces: &[Piece]) -> Option<Regex> {
+    let src: String = pieces
+        .iter()
+        .map(|p| match p {
+            Piece::Lit(l) => regex::escape(l),
+            Piece::Ph(c) => format!("(?:{})", c.regex()),
+        })
+        .collect();
+    Regex::new(&format!("^{}$", src)).ok()
+}
+
+/// Does label `a` match every string label `b` can?
+fn seg_covers(a: &[Piece], b: &[Piece]) -> bool {
+    if let [Piece::Ph(class)] = a {
+        return Label::Seg(b.to_vec()).within(*class);
+    }
+    match (all_literal(a), all_literal(b)) {
+        (Some(la), Some(lb)) => la == lb,
+        (None, Some(lb)) => seg_regex(a).is_some_and(|re| re.is_match(&lb)),
+        _ => a == b,
+    }
+}
+
+/// Can labels `a` and `b` match the same string?
+fn seg_intersects(a: &[Piece], b: &[Piece]) -> bool {
+    if let [Piece::Ph(class)] = a {
+        return Label::Seg(b.to_vec()).can_be_within(*class);
+    }
+    if let [Piece::Ph(class)] = b {
+        return Label::Seg(a.to_vec()).can_be_within(*class);
+    }
+    match (all_literal(a), all_literal(b)) {
+        (Some(la), Some(lb)) => la == lb,
+        (Some(l), None) => seg_regex(b).is_some_and(|re| re.is_match(&l)),
+        (None, Some(l)) => seg_regex(a).is_some_and(|re| re.is_match(&l)),
+        (None, None) => a == b,
+    }
+}
+
+/// Does pattern `a` match every host pattern `b` matches?
+fn covers(a: &[Label], b: &[Label]) -> bool {
+    match (a.split_first(), b.split_first()) {
+        (None, None) => true,
+        (None, _) | (_, None) => false,
+        (Some((Label::Multi { min, class }, rest_a)), _) => (1..=b.len()).any(|k| {
+            let (taken, rest_b) = b.split_at(k);
+            taken.iter().all(|l| l.within(*class))
+                && taken.iter().map(Label::min_labels).sum::<usize>() >= *min
+                && covers(rest_a, rest_b)
+        }),
+        (Some((Label::Seg(_), _)), Some((Label::Multi { .. }, _))) => false,
+        (Some((Label::Seg(sa), rest_a)), Some((Label::Seg(sb), rest_b))) => {
+            seg_covers(sa, sb) && covers(rest_a, rest_b)
+        }
+    }
+}
+
+/// Is there a host both `a` and `b` match?
+fn intersects(a: &[Label], b: &[Label]) -> bool {
+    // A multi-label piece on one side absorbs `k` labels of the other.
+    fn absorb(min: usize, class: Class, rest: &[Label], other: &[Label]) -> bool {
+        (1..=other.len()).any(|k| {
+            let (taken, rest_other) = other.split_at(k);
+            let unbounded = taken.iter().any(|l| matches!(l, Label::Multi { .. }));
+            taken.iter().all(|l| l.can_be_within(class))
+                && (unbounded || k >= min)
+                && intersects(rest, rest_other)
+        })
+    }
+    match (a.split_first(), b.split_first()) {
+        (None, None) => true,
+        (None, _) | (_, None) => false,
+        (Some((Label::Multi { min, class }, rest_a)), Some((lb, rest_b))) => {
+            absorb(*min, *class, rest_a, b)
+                || matches!(lb, Label::Multi { min: mb, class: cb } if absorb(*mb, *cb, rest_b, a))
+        }
+        (Some(_), Some((Label::Multi { min, class }, rest_b))) => absorb(*min, *class, rest_b, a),
+        (Some((Label::Seg(sa), rest_a)), Some((Label::Seg(sb), rest_b))) => {
+            seg_intersects(sa, sb) && intersects(rest_a, rest_b)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Targets and placeholders
+// ---------------------------------------------------------------------------
+
+/// Expand the target with the most permissive sample for each
+/// placeholder (digits parse as both a host label and a port) and check
+/// the result parses as an authority. `Some(reason)` when it cannot.
+fn invalid_target(route: &CompiledRoute) -> Option<String> {
+    let samples: HashMap<String, String> = route
+        .placeholders
+        .iter()
+        .map(|p| {
+            let sample = if p.kind == PlaceholderKind::Multi || p.name == "domain" { "a.b" } else { "1" };
+            (p.name.clone(), sample.to_string())
+        })
+        .collect();
+    let expanded = routes::expand(&route.target_template, &samples);
+    let authority = routes::parse_target_scheme(&expanded).1;
+    let reason = match hyper::http::uri::Authority::from_str(authority) {
+        Err(e) => e.to_string(),
+        Ok(a) if a.host().is_empty() => "empty host".to_string(),
+        // `Authority` accepts any port text; the connector does not.
+        Ok(a) => match a.as_str().strip_prefix(a.host()).and_then(|rest| rest.strip_prefix(':')) {
+            Some(port) if port.parse::<u16>().is_err() => format!("port '{}' is not a number in 0..=65535", port),
+            _ => return None,
+        },
+    };
+    Some(format!("target '{}' can never be a valid host[:port] ({})", route.target_template, reason))
+}
+
+fn uses_placeholder(route: &CompiledRoute, name: &str) -> bool {
+    let mentions = |template: &str| {
+        routes::tokenize(template, "", "template").is_ok_and(|toks| {
+            toks.iter().any(|t| matches!(t, Token::Placeholder { name: n, .. } if n == name))
+        })
+    };
+    mentions(&route.target_template) || route.header_templates.values().any(|v| mentions(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::{RouteConfig, compile_in_namespace, parse_yaml};
+
+    fn rule(name: &str, m: &str, path: Option<&str>, target: &str) -> RouteConfig {
+        RouteConfig {
+            name: name.into(),
+            r#match: m.into(),
+            path: path.map(String::from),
+            target: target.into(),
+            ..Default::default()
+        }
+    }
+
+    fn bundled() -> Vec<CompiledRoute> {
+        let parsed = parse_yaml(include_str!("../routes.yaml")).unwrap();
+        compile_in_namespace(parsed.routes, "default").unwrap()
+    }
+
+    fn kinds(lints: &[Lint]) -> Vec<(LintKind, &str)> {
+        lints.iter().map(|l| (l.kind, l.route.as_str())).collect()
+    }
+
+    #[test]
+    fn bundled_defaults_are_clean() {
+        assert_eq!(lint(&bundled()), vec![]);
+    }
+
+    #[test]
+    fn pattern_inclusion_and_overlap() {
+        let l = |p: &str| host_labels(p).unwrap();
+        assert!(covers(&l("{host}.{domain}"), &l("echo.{domain}")));
+        assert!(covers(&l("{host}.{domain}"), &l("{x:int}.fbi.com")));
+        assert!(covers(&l("{up:multi}.fbi.com"), &l("a.b.fbi.com")));
+        assert!(!covers(&l("{host}.{domain}"), &l("{up:multi}.fbi.com")));
+        assert!(!covers(&l("{port:int}.{domain}"), &l("{host}.{domain}")));
+        assert!(!covers(&l("{prefix}.{host}.{domain}"), &l("{host}.{domain}")));
+        assert!(covers(&l("{host}--{port:int}.{domain}"), &l("api--3000.fbi.com")));
+
+        assert!(intersects(&l("{prefix}.{host}.{domain}"), &l("echo.{domain}")));
+        assert!(intersects(&l("{x}.app.{domain}"), &l("admin.{y}.{domain}")));
+        assert!(!intersects(&l("{port:int}.{domain}"), &l("echo.{domain}")));
+        assert!(!intersects(&l("{prefix}.{host}"), &l("echo.{domain}")));
+        assert!(!intersects(&l("a.fbi.com"), &l("b.fbi.com")));
+    }
+
+    #[test]
+    fn reports_fragment_shadowed_by_bundled_defaults() {
+        let mut routes = bundled();
+        routes.extend(
+            compile_in_namespace(
+                vec![rule("web", "echo.{domain}", None, "localhost:3000"), rule("api", "echo.{domain}", Some("/api"), "localhost:3001")],
+                "app",
+            )
+            .unwrap(),
+        );
+        let lints = lint(&routes);
+        assert_eq!(kinds(&lints), [(LintKind::Shadowed, "web")]);
+        assert_eq!(lints[0].other.as_deref(), Some("default/direct-forward"));
+    }
+
+    #[test]
+    fn reports_cross_namespace_conflicts_only() {
+        let mut routes = compile_in_namespace(vec![rule("a", "{_x}.app.fbi.com", Some("/"), "localhost:1")], "one").unwrap();
+        routes.extend(compile_in_namespace(vec![rule("b", "admin.{y}.fbi.com", Some("/"), "{y}:2")], "two").unwrap());
+        routes.extend(compile_in_namespace(vec![rule("c", "admin.{y}.fbi.com", Some("/"), "{y}:3")], "one").unwrap());
+        let lints = lint(&routes);
+        assert_eq!(kinds(&lints), [(LintKind::Conflict, "b"), (LintKind::Shadowed, "c")]);
+        assert_eq!(lints[0].other.as_deref(), Some("one/a"));
+    }
+
+    #[test]
+    fn reports_invalid_targets_and_unused_placeholders() {
+        let routes = compile_in_namespace(
+            vec![
+                rule("bad-port", "{name}.bad.fbi.com", None, "localhost:http"),
+                rule("path", "p.fbi.com", None, "localhost:3000/app"),
+                rule("big-port", "b.fbi.com", None, "localhost:70000"),
+                rule("ok-scheme", "{_ignored}.{port:int}.fbi.com", None, "https://localhost:{port}"),
+            ],
+            "x",
+        )
+        .unwrap();
+        assert_eq!(
+            kinds(&lint(&routes)),
+            [
+                (LintKind::InvalidTarget, "bad-port"),
+                (LintKind::UnusedPlaceholder, "bad-port"),
+                (LintKind::InvalidTarget, "path"),
+                (LintKind::InvalidTarget, "big-port"),
+            ]
+        );
+    }
+}
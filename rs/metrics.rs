@@ -4,9 +4,26 @@
 //! enough. The counters are atomic so they can be incremented from any
 //! request task without locks; the renderer reads them with `Ordering::
 //! Relaxed` (monotonic counters, dirty reads are fine).
+//!
+//! Labeled series ([`CounterVec`], [`HistogramVec`]) keep a short-lived
+//! `Mutex<BTreeMap>` per metric. Label values come from the route table
+//! (namespace / rule name) plus small fixed sets (method, status class,
+//! direction), so cardinality stays bounded by the config; series of
+//! rules that are gone are dropped on reload ([`Metrics::prune_routes`]).
+
+use std::collections::{BTreeMap, HashSet};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+
+use crate::routes::CompiledRoute;
 
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+/// Upper bounds (seconds) for the latency histograms.
+pub const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
 
 #[derive(Default)]
 pub struct Metrics {
@@ -19,6 +36,37 @@ pub struct Metrics {
     pub upstream_timeouts_total: AtomicU64,
     pub websocket_upgrades_total: AtomicU64,
     pub host_rejected_total: AtomicU64,
+    /// Rule reloads that kept the previous rules or left conf.d
+    /// namespaces failing.
+    pub config_reload_failures_total: AtomicU64,
+
+    /// {namespace, route, method, status_class}. Unmatched requests have
+    /// empty `namespace` / `route`.
+    pub route_requests: CounterVec,
+    /// {namespace, route, direction="request"|"response"} body bytes.
+    pub route_bytes: CounterVec,
+    /// {namespace, route}: upstream request sent → response headers.
+    pub upstream_ttfb: HistogramVec,
+    /// {namespace, route}: upstream request sent → response body done.
+    pub upstream_duration: HistogramVec,
+    /// {direction="client_to_upstream"|"upstream_to_client"} bytes
+    /// relayed through CONNECT tunnels and WebSockets.
+    pub relayed_bytes: CounterVec,
+
+    /// Requests whose response has not finished streaming.
+    pub inflight_requests: Gauge,
+    /// Accepted client connections (h1 keep-alive / h2) still open.
+    pub open_connections: Gauge,
+    pub active_websockets: Gauge,
+    pub active_tunnels: Gauge,
+
+    /// {namespace, state="ok"|"last_good"|"skipped"}: 1 per conf.d
+    /// namespace, as of the last load.
+    pub conf_namespaces: GaugeVec,
+    /// conf.d namespaces whose fragment on disk failed to load.
+    pub conf_namespaces_failing: Gauge,
+    /// Bumped each time a new rule set goes live; 1 after startup.
+    pub config_generation: Gauge,
 }
 
 impl Metrics {
@@ -38,6 +86,20 @@ impl Metrics {
         bucket.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Drop the per-rule series of rules not in `routes` (the rule set
+    /// that just went live), keeping those of unmatched requests.
+    pub fn prune_routes(&self, routes: &[CompiledRoute]) {
+        let live: HashSet<(&str, &str)> = routes.iter().map(|r| (r.namespace.as_str(), r.name.as_str())).collect();
+        let keep = |labels: &[String]| match labels {
+            [ns, route, ..] => (ns.is_empty() && route.is_empty()) || live.contains(&(ns.as_str(), route.as_str())),
+            _ => true,
+        };
+        self.route_requests.retain(keep);
+        self.route_bytes.retain(keep);
+        self.upstream_ttfb.retain(keep);
+        self.upstream_duration.retain(keep);
+    }
+
     pub fn render_prometheus(&self) -> String {
         let mut out = String::with_capacity(1024);
         emit_counter(&mut out, "fbi_proxy_requests_total",
@@ -67,8 +129,307 @@ impl Metrics {
         emit_counter(&mut out, "fbi_proxy_host_rejected_total",
             "Requests rejected because the Host header didn't match the domain filter or any route.",
             self.host_rejected_total.load(Ordering::Relaxed));
+        emit_counter(&mut out, "fbi_proxy_config_reload_failures_total",
+            "Rule reloads that failed or left conf.d namespaces failing.",
+            self.config_reload_failures_total.load(Ordering::Relaxed));
+
+        self.route_requests.render(&mut out, "fbi_proxy_route_requests_total",
+            "Requests by matched rule, method and status class.",
+            &["namespace", "route", "method", "status_class"]);
+        self.route_bytes.render(&mut out, "fbi_proxy_route_bytes_total",
+            "HTTP body bytes by matched rule and direction.",
+            &["namespace", "route", "direction"]);
+        self.relayed_bytes.render(&mut out, "fbi_proxy_relayed_bytes_total",
+            "Bytes relayed through CONNECT tunnels and WebSockets.",
+            &["direction"]);
+        self.upstream_ttfb.render(&mut out, "fbi_proxy_upstream_ttfb_seconds",
+            "Time from sending the upstream request to receiving response headers.",
+            &["namespace", "route"]);
+        self.upstream_duration.render(&mut out, "fbi_proxy_upstream_duration_seconds",
+            "Time from sending the upstream request to the end of the response body.",
+            &["namespace", "route"]);
+
+        emit_gauge(&mut out, "fbi_proxy_inflight_requests",
+            "Requests whose response is still being produced or streamed.",
+            self.inflight_requests.get());
+        emit_gauge(&mut out, "fbi_proxy_open_connections",
+            "Open client connections.",
+            self.open_connections.get());
+        emit_gauge(&mut out, "fbi_proxy_active_websockets",
+            "WebSocket sessions currently being forwarded.",
+            self.active_websockets.get());
+        emit_gauge(&mut out, "fbi_proxy_active_tunnels",
+            "CONNECT tunnels currently open.",
+            self.active_tunnels.get());
+        self.conf_namespaces.render(&mut out, "fbi_proxy_conf_namespace",
+            "conf.d namespaces by load state (1 per namespace).",
+            &["namespace", "state"]);
+        emit_gauge(&mut out, "fbi_proxy_conf_namespaces_failing",
+            "conf.d namespaces whose fragment failed to load and are served last-good or skipped.",
+            self.conf_namespaces_failing.get());
+        emit_gauge(&mut out, "fbi_proxy_config_generation",
+            "Rule set generation; bumped on every reload that goes live.",
+            self.config_generation.get());
         out
     }
+
+    /// Increment the gauge picked by `pick` until the returned guard is
+    /// dropped. Owns an `Arc` so the guard can move into spawned tasks.
+    pub fn track(self: &Arc<Self>, pick: fn(&Metrics) -> &Gauge) -> GaugeGuard {
+        pick(self).inc();
+        GaugeGuard { metrics: Arc::clone(self), pick }
+    }
+}
+
+/// `2xx` etc. for the `status_class` label.
+pub fn status_class(status: u16) -> &'static str {
+    match status {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        500..=599 => "5xx",
+        _ => "other",
+    }
+}
+
+/// Standard methods pass through; anything else becomes `OTHER` so a
+/// client can't blow up label cardinality.
+pub fn method_label(method: &str) -> &str {
+    match method {
+        "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "CONNECT" | "OPTIONS" | "TRACE" | "PATCH" => method,
+        _ => "OTHER",
+    }
+}
+
+/// Counter with labels; label values in the metric's label-name order.
+#[derive(Default)]
+pub struct CounterVec {
+    series: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    pub fn inc(&self, labels: &[&str], by: u64) {
+        let mut series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        *series.entry(key(labels)).or_default() += by;
+    }
+
+    pub fn get(&self, labels: &[&str]) -> u64 {
+        let series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        series.get(&key(labels)).copied().unwrap_or(0)
+    }
+
+    /// Keep only the series whose label values pass `keep`.
+    pub fn retain(&self, keep: impl Fn(&[String]) -> bool) {
+        let mut series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        series.retain(|labels, _| keep(labels));
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, label_names: &[&str]) {
+        use std::fmt::Write;
+        let series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (values, v) in series.iter() {
+            let _ = writeln!(out, "{}{{{}}} {}", name, label_pairs(label_names, values), v);
+        }
+    }
+}
+
+struct Histogram {
+    /// Non-cumulative count per bucket in [`LATENCY_BUCKETS`], then +Inf.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Latency histogram with labels, bucketed by [`LATENCY_BUCKETS`].
+#[derive(Default)]
+pub struct HistogramVec {
+    series: Mutex<BTreeMap<Vec<String>, Histogram>>,
+}
+
+impl HistogramVec {
+    pub fn observe(&self, labels: &[&str], d: Duration) {
+        let secs = d.as_secs_f64();
+        let idx = LATENCY_BUCKETS.iter().position(|b| secs <= *b).unwrap_or(LATENCY_BUCKETS.len());
+        let mut series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        let h = series
+            .entry(key(labels))
+            .or_insert_with(|| Histogram { buckets: vec![0; LATENCY_BUCKETS.len() + 1], sum: 0.0, count: 0 });
+        h.buckets[idx] += 1;
+        h.sum += secs;
+        h.count += 1;
+    }
+
+    /// Observation count for one label set.
+    pub fn count(&self, labels: &[&str]) -> u64 {
+        let series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        series.get(&key(labels)).map(|h| h.count).unwrap_or(0)
+    }
+
+    /// Keep only the series whose label values pass `keep`.
+    pub fn retain(&self, keep: impl Fn(&[String]) -> bool) {
+        let mut series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        series.retain(|labels, _| keep(labels));
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, label_names: &[&str]) {
+        use std::fmt::Write;
+        let series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (values, h) in series.iter() {
+            let labels = label_pairs(label_names, values);
+            let mut cumulative = 0;
+            for (i, le) in LATENCY_BUCKETS.iter().map(|b| b.to_string()).chain(["+Inf".to_string()]).enumerate() {
+                cumulative += h.buckets[i];
+                let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, le, cumulative);
+            }
+            let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, h.sum);
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, h.count);
+        }
+    }
+}
+
+fn key(labels: &[&str]) -> Vec<String> {
+    labels.iter().map(|l| l.to_string()).collect()
+}
+
+fn label_pairs(names: &[&str], values: &[String]) -> String {
+    names
+        .iter()
+        .zip(values)
+        .map(|(n, v)| format!("{}=\"{}\"", n, escape_label(v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Up/down gauge.
+#[derive(Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, v: i64) {
+        self.0.store(v, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Labeled gauge whose series are replaced wholesale, for state that is
+/// recomputed rather than accumulated (e.g. per-namespace load state).
+#[derive(Default)]
+pub struct GaugeVec {
+    series: Mutex<BTreeMap<Vec<String>, i64>>,
+}
+
+impl GaugeVec {
+    /// Replace every series; label sets not in `series` disappear.
+    pub fn replace<'a>(&self, series: impl IntoIterator<Item = (Vec<&'a str>, i64)>) {
+        let fresh = series.into_iter().map(|(labels, v)| (key(&labels), v)).collect();
+        *self.series.lock().unwrap_or_else(|p| p.into_inner()) = fresh;
+    }
+
+    pub fn get(&self, labels: &[&str]) -> Option<i64> {
+        self.series.lock().unwrap_or_else(|p| p.into_inner()).get(&key(labels)).copied()
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, label_names: &[&str]) {
+        use std::fmt::Write;
+        let series = self.series.lock().unwrap_or_else(|p| p.into_inner());
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (values, v) in series.iter() {
+            let _ = writeln!(out, "{}{{{}}} {}", name, label_pairs(label_names, values), v);
+        }
+    }
+}
+
+/// Decrements its gauge on drop; see [`Metrics::track`].
+pub struct GaugeGuard {
+    metrics: Arc<Metrics>,
+    pick: fn(&Metrics) -> &Gauge,
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        (self.pick)(&self.metrics).dec();
+    }
+}
+
+/// Body wrapper that counts data bytes and calls `on_done` exactly once
+/// with the total — at end of stream, on error, or when dropped early
+/// (client went away).
+pub struct CountingBody<B> {
+    inner: B,
+    bytes: u64,
+    on_done: Option<Box<dyn FnOnce(u64) + Send + Sync>>,
+}
+
+impl<B> CountingBody<B> {
+    pub fn new(inner: B, on_done: impl FnOnce(u64) + Send + Sync + 'static) -> Self {
+        Self { inner, bytes: 0, on_done: Some(Box::new(on_done)) }
+    }
+
+    fn finish(&mut self) {
+        if let Some(f) = self.on_done.take() {
+            f(self.bytes);
+        }
+    }
+}
+
+impl<B> Body for CountingBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.bytes += data.len() as u64;
+                }
+                if self.inner.is_end_stream() {
+                    self.finish();
+                }
+            }
+            Poll::Ready(Some(Err(_))) | Poll::Ready(None) => self.finish(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for CountingBody<B> {
+    fn drop(&mut self) {
+        self.finish();
+    }
 }
 
 fn emit_counter(out: &mut String, name: &str, help: &str, value: u64) {
@@ -78,6 +439,13 @@ fn emit_counter(out: &mut String, name: &str, help: &str, value: u64) {
     let _ = writeln!(out, "{} {}", name, value);
 }
 
+fn emit_gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    use std::fmt::Write;
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,4 +476,92 @@ mod tests {
         assert!(out.contains("fbi_proxy_requests_total 1\n"));
         assert!(out.contains("fbi_proxy_status_2xx_total 1\n"));
     }
+
+    #[test]
+    fn labeled_counters_and_histograms_render() {
+        let m = Metrics::new();
+        m.route_requests.inc(&["default", "port-as-host", "GET", "2xx"], 1);
+        m.route_requests.inc(&["default", "port-as-host", "GET", "2xx"], 2);
+        m.route_requests.inc(&["dev", "a\"b", "POST", "5xx"], 1);
+        assert_eq!(m.route_requests.get(&["default", "port-as-host", "GET", "2xx"]), 3);
+        m.upstream_ttfb.observe(&["default", "port-as-host"], Duration::from_millis(20));
+        m.upstream_ttfb.observe(&["default", "port-as-host"], Duration::from_secs(60));
+        let out = m.render_prometheus();
+        assert!(out.contains(
+            "fbi_proxy_route_requests_total{namespace=\"default\",route=\"port-as-host\",method=\"GET\",status_class=\"2xx\"} 3\n"
+        ));
+        assert!(out.contains("route=\"a\\\"b\""));
+        assert!(out.contains("# TYPE fbi_proxy_upstream_ttfb_seconds histogram"));
+        assert!(out.contains("fbi_proxy_upstream_ttfb_seconds_bucket{namespace=\"default\",route=\"port-as-host\",le=\"0.01\"} 0\n"));
+        assert!(out.contains("fbi_proxy_upstream_ttfb_seconds_bucket{namespace=\"default\",route=\"port-as-host\",le=\"0.025\"} 1\n"));
+        assert!(out.contains("fbi_proxy_upstream_ttfb_seconds_bucket{namespace=\"default\",route=\"port-as-host\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("fbi_proxy_upstream_ttfb_seconds_count{namespace=\"default\",route=\"port-as-host\"} 2\n"));
+    }
+
+    #[test]
+    fn reloads_drop_series_of_removed_rules() {
+        let m = Metrics::new();
+        m.route_requests.inc(&["app", "web", "GET", "2xx"], 1);
+        m.route_requests.inc(&["app", "old", "GET", "2xx"], 1);
+        m.route_requests.inc(&["", "", "GET", "5xx"], 1);
+        m.route_bytes.inc(&["app", "old", "response"], 10);
+        m.upstream_ttfb.observe(&["app", "old"], Duration::from_millis(5));
+        m.upstream_duration.observe(&["app", "web"], Duration::from_millis(5));
+        m.relayed_bytes.inc(&["client_to_upstream"], 3);
+
+        let cfg = crate::routes::RouteConfig {
+            name: "web".into(),
+            r#match: "web.{domain}".into(),
+            target: "localhost:3000".into(),
+            ..Default::default()
+        };
+        m.prune_routes(&crate::routes::compile_in_namespace(vec![cfg], "app").unwrap());
+
+        assert_eq!(m.route_requests.get(&["app", "web", "GET", "2xx"]), 1);
+        assert_eq!(m.route_requests.get(&["app", "old", "GET", "2xx"]), 0);
+        assert_eq!(m.route_requests.get(&["", "", "GET", "5xx"]), 1);
+        assert_eq!(m.upstream_ttfb.count(&["app", "old"]), 0);
+        assert_eq!(m.upstream_duration.count(&["app", "web"]), 1);
+        assert_eq!(m.relayed_bytes.get(&["client_to_upstream"]), 3);
+        assert!(!m.render_prometheus().contains("route=\"old\""));
+    }
+
+    #[test]
+    fn gauge_guard_decrements_on_drop() {
+        let m = Metrics::new();
+        let a = m.track(|m| &m.active_websockets);
+        let b = m.track(|m| &m.active_websockets);
+        assert_eq!(m.active_websockets.get(), 2);
+        drop(a);
+        drop(b);
+        assert_eq!(m.active_websockets.get(), 0);
+        assert!(m.render_prometheus().contains("# TYPE fbi_proxy_active_websockets gauge\nfbi_proxy_active_websockets 0\n"));
+    }
+
+    #[test]
+    fn gauge_vec_replaces_all_series() {
+        let m = Metrics::new();
+        m.conf_namespaces.replace([(vec!["app", "ok"], 1), (vec!["web", "last_good"], 1)]);
+        m.conf_namespaces.replace([(vec!["web", "ok"], 1)]);
+        assert_eq!(m.conf_namespaces.get(&["app", "ok"]), None);
+        let out = m.render_prometheus();
+        assert!(out.contains("# TYPE fbi_proxy_conf_namespace gauge\nfbi_proxy_conf_namespace{namespace=\"web\",state=\"ok\"} 1\n"));
+        assert!(!out.contains("last_good"));
+    }
+
+    #[tokio::test]
+    async fn counting_body_reports_total_once() {
+        use http_body_util::{BodyExt, Full};
+        let total = Arc::new(AtomicU64::new(0));
+        let calls = Arc::new(AtomicU64::new(0));
+        let (t, c) = (total.clone(), calls.clone());
+        let body = CountingBody::new(Full::new(Bytes::from_static(b"hello")), move |n| {
+            t.store(n, Ordering::Relaxed);
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+        let collected = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&collected[..], b"hello");
+        assert_eq!(total.load(Ordering::Relaxed), 5);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
 }
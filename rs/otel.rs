@@ -0,0 +1,700 @@
+//! OpenTelemetry trace export (OTLP) for proxied requests.
+//!
+//! The proxy emits a `SERVER` span per request, a `CLIENT` span per
+//! upstream attempt, and an `INTERNAL` span covering the lifetime of each
+//! forwarded WebSocket. Spans are queued without blocking the request
+//! path, batched by a background task, and shipped to a collector over
+//!
+//! * `http/json`     — OTLP/HTTP with the JSON encoding,
+//! * `http/protobuf` — OTLP/HTTP with the protobuf encoding,
+//! * `grpc`          — OTLP/gRPC (`TraceService/Export`, h2c or TLS).
+//!
+//! No OTel SDK dependency: the three encodings of the handful of messages
+//! we need are small enough to write by hand (see [`pb`]).
+//!
+//! Sampling is parent-based: a request carrying a `traceparent` follows
+//! its sampled flag; new root traces are kept with probability
+//! `sample_ratio`, decided from the trace ID like the SDK's
+//! `TraceIdRatioBased` sampler so every hop agrees.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Request, Uri};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use crate::trace::{TraceContext, hex};
+
+/// Spans per export request.
+pub const DEFAULT_BATCH_SIZE: usize = 512;
+/// Longest a span waits in a partial batch.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// Spans buffered ahead of the exporter before new ones are dropped.
+pub const DEFAULT_MAX_QUEUE: usize = 2048;
+/// Per-export timeout.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const GRPC_PATH: &str = "/opentelemetry.proto.collector.trace.v1.TraceService/Export";
+const HTTP_PATH: &str = "/v1/traces";
+
+/// Wire protocol towards the collector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    HttpJson,
+    HttpProtobuf,
+    Grpc,
+}
+
+impl Protocol {
+    /// Accepts the `OTEL_EXPORTER_OTLP_PROTOCOL` spellings.
+    pub fn parse(s: &str) -> Result<Protocol, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "http/json" | "json" => Ok(Protocol::HttpJson),
+            "http/protobuf" | "protobuf" | "http" => Ok(Protocol::HttpProtobuf),
+            "grpc" => Ok(Protocol::Grpc),
+            other => Err(format!(
+                "unknown OTLP protocol '{}' (expected http/json, http/protobuf or grpc)",
+                other
+            )),
+        }
+    }
+}
+
+/// Exporter configuration, assembled from CLI flags.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Collector base URL, e.g. `http://127.0.0.1:4318` (HTTP) or
+    /// `http://127.0.0.1:4317` (gRPC). For HTTP a full `.../v1/traces`
+    /// URL is used as-is.
+    pub endpoint: String,
+    pub protocol: Protocol,
+    /// Probability of keeping a new root trace, `0.0..=1.0`.
+    pub sample_ratio: f64,
+    pub service_name: String,
+    /// Extra request headers (e.g. collector auth).
+    pub headers: Vec<(String, String)>,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub max_queue: usize,
+}
+
+impl Config {
+    pub fn new(endpoint: impl Into<String>, protocol: Protocol) -> Config {
+        Config {
+            endpoint: endpoint.into(),
+            protocol,
+            sample_ratio: 1.0,
+            service_name: "fbi-proxy".to_string(),
+            headers: Vec::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            max_queue: DEFAULT_MAX_QUEUE,
+        }
+    }
+
+    /// The URL spans are POSTed to.
+    fn export_url(&self) -> String {
+        let base = self.endpoint.trim_end_matches('/');
+        match self.protocol {
+            Protocol::Grpc => format!("{}{}", base, GRPC_PATH),
+            _ if base.ends_with(HTTP_PATH) => base.to_string(),
+            _ => format!("{}{}", base, HTTP_PATH),
+        }
+    }
+}
+
+/// Parse `k=v,k2=v2` (the `OTEL_EXPORTER_OTLP_HEADERS` format).
+pub fn parse_headers(s: &str) -> Result<Vec<(String, String)>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.split_once('=') {
+            Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.trim().to_string())),
+            _ => Err(format!("invalid header '{}' (expected key=value)", kv)),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Internal = 1,
+    Server = 2,
+    Client = 3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl From<&str> for AttrValue {
+    fn from(v: &str) -> Self {
+        AttrValue::Str(v.to_string())
+    }
+}
+impl From<String> for AttrValue {
+    fn from(v: String) -> Self {
+        AttrValue::Str(v)
+    }
+}
+impl From<i64> for AttrValue {
+    fn from(v: i64) -> Self {
+        AttrValue::Int(v)
+    }
+}
+impl From<u64> for AttrValue {
+    fn from(v: u64) -> Self {
+        AttrValue::Int(v.min(i64::MAX as u64) as i64)
+    }
+}
+impl From<u16> for AttrValue {
+    fn from(v: u16) -> Self {
+        AttrValue::Int(v as i64)
+    }
+}
+impl From<bool> for AttrValue {
+    fn from(v: bool) -> Self {
+        AttrValue::Bool(v)
+    }
+}
+
+/// One finished span.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub parent_span_id: Option<[u8; 8]>,
+    pub trace_state: Option<String>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, AttrValue)>,
+    /// `Some` marks the span as failed (OTLP status `ERROR`).
+    pub error: Option<String>,
+}
+
+impl Span {
+    /// A span in `trace` with the given id and parent; `end` defaults to
+    /// `start` until set.
+    pub fn new(
+        trace: &TraceContext,
+        span_id: [u8; 8],
+        parent_span_id: Option<[u8; 8]>,
+        name: impl Into<String>,
+        kind: SpanKind,
+        start: SystemTime,
+    ) -> Span {
+        Span {
+            trace_id: trace.trace_id,
+            span_id,
+            parent_span_id,
+            trace_state: trace.tracestate.clone(),
+            name: name.into(),
+            kind,
+            start,
+            end: start,
+            attributes: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn attr(&mut self, key: &str, value: impl Into<AttrValue>) -> &mut Self {
+        self.attributes.push((key.to_string(), value.into()));
+        self
+    }
+
+    /// Add the attribute only when a value is present.
+    pub fn attr_opt(&mut self, key: &str, value: Option<impl Into<AttrValue>>) -> &mut Self {
+        if let Some(v) = value {
+            self.attr(key, v);
+        }
+        self
+    }
+}
+
+/// Handle used by the proxy to sample and record spans. Cheap to share;
+/// the exporter runs on its own task.
+pub struct Tracer {
+    sample_ratio: f64,
+    tx: mpsc::Sender<Span>,
+    dropped: AtomicU64,
+    exported: Arc<AtomicU64>,
+}
+
+impl Tracer {
+    /// Validate `cfg` and spawn the batching exporter on the current
+    /// tokio runtime.
+    pub fn start(cfg: Config) -> Result<Arc<Tracer>, String> {
+        if !(0.0..=1.0).contains(&cfg.sample_ratio) {
+            return Err(format!("sample ratio must be within 0..=1, got {}", cfg.sample_ratio));
+        }
+        let url: Uri = cfg
+            .export_url()
+            .parse()
+            .map_err(|e| format!("invalid OTLP endpoint '{}': {}", cfg.endpoint, e))?;
+        match url.scheme_str() {
+            Some("http") | Some("https") => {}
+            _ => return Err(format!("OTLP endpoint '{}' must start with http:// or https://", cfg.endpoint)),
+        }
+        let (tx, rx) = mpsc::channel(cfg.max_queue.max(1));
+        let exported = Arc::new(AtomicU64::new(0));
+        let tracer = Arc::new(Tracer { sample_ratio: cfg.sample_ratio, tx, dropped: AtomicU64::new(0), exported: exported.clone() });
+        tokio::spawn(run_exporter(cfg, url, rx, exported));
+        Ok(tracer)
+    }
+
+    /// Make the sampling decision for a new root trace (sets or clears
+    /// the sampled flag); continued traces keep their parent's decision.
+    pub fn sample(&self, trace: &mut TraceContext) {
+        if trace.parent_id.is_none() {
+            if ratio_sampled(&trace.trace_id, self.sample_ratio) {
+                trace.flags |= 0x01;
+            } else {
+                trace.flags &= !0x01;
+            }
+        }
+    }
+
+    /// Queue a finished span; dropped (and counted) if the queue is full.
+    pub fn record(&self, span: Span) {
+        if self.tx.try_send(span).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Spans dropped because the export queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Spans accepted by the collector so far.
+    pub fn exported(&self) -> u64 {
+        self.exported.load(Ordering::Relaxed)
+    }
+}
+
+/// `TraceIdRatioBased`: keep iff the trace ID's low 8 bytes fall below
+/// `ratio * 2^64`.
+pub fn ratio_sampled(trace_id: &[u8; 16], ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    if ratio <= 0.0 {
+        return false;
+    }
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&trace_id[8..]);
+    let bound = (ratio * u64::MAX as f64) as u64;
+    u64::from_be_bytes(low) < bound
+}
+
+type ExportClient = Client<hyper_rustls::HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+async fn run_exporter(cfg: Config, url: Uri, mut rx: mpsc::Receiver<Span>, exported: Arc<AtomicU64>) {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_all_versions()
+        .build();
+    let mut builder = Client::builder(TokioExecutor::new());
+    if cfg.protocol == Protocol::Grpc {
+        builder.http2_only(true);
+    }
+    let client: ExportClient = builder.build(connector);
+
+    let mut batch: Vec<Span> = Vec::with_capacity(cfg.batch_size);
+    let mut tick = tokio::time::interval(cfg.flush_interval);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            span = rx.recv() => match span {
+                Some(span) => {
+                    batch.push(span);
+                    if batch.len() >= cfg.batch_size {
+                        flush(&client, &cfg, &url, &mut batch, &exported).await;
+                    }
+                }
+                None => {
+                    flush(&client, &cfg, &url, &mut batch, &exported).await;
+                    return;
+                }
+            },
+            _ = tick.tick() => {
+                if !batch.is_empty() {
+                    flush(&client, &cfg, &url, &mut batch, &exported).await;
+                }
+            }
+        }
+    }
+}
+
+async fn flush(client: &ExportClient, cfg: &Config, url: &Uri, batch: &mut Vec<Span>, exported: &AtomicU64) {
+    if batch.is_empty() {
+        return;
+    }
+    let spans = std::mem::take(batch);
+    let n = spans.len() as u64;
+    match tokio::time::timeout(EXPORT_TIMEOUT, export(client, cfg, url, &spans)).await {
+        Ok(Ok(())) => {
+            exported.fetch_add(n, Ordering::Relaxed);
+        }
+        Ok(Err(e)) => log::warn!("[otel] export of {} span(s) to {} failed: {}", n, url, e),
+        Err(_) => log::warn!("[otel] export of {} span(s) to {} timed out", n, url),
+    }
+}
+
+async fn export(client: &ExportClient, cfg: &Config, url: &Uri, spans: &[Span]) -> Result<(), String> {
+    let (content_type, body) = match cfg.protocol {
+        Protocol::HttpJson => ("application/json", encode_json(&cfg.service_name, spans).to_string().into_bytes()),
+        Protocol::HttpProtobuf => ("application/x-protobuf", encode_protobuf(&cfg.service_name, spans)),
+        Protocol::Grpc => ("application/grpc", grpc_frame(&encode_protobuf(&cfg.service_name, spans))),
+    };
+    let mut req = Request::post(url.clone()).header("content-type", content_type);
+    if cfg.protocol == Protocol::Grpc {
+        req = req.header("te", "trailers");
+    }
+    for (k, v) in &cfg.headers {
+        req = req.header(k.as_str(), v.as_str());
+    }
+    let req = req.body(Full::new(Bytes::from(body))).map_err(|e| e.to_string())?;
+    let resp = client.request(req).await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let header_grpc_status = resp.headers().get("grpc-status").cloned();
+    let collected = resp.into_body().collect().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("collector answered HTTP {}", status.as_u16()));
+    }
+    if cfg.protocol == Protocol::Grpc {
+        // Trailers-only responses carry grpc-status in the headers.
+        let grpc_status = header_grpc_status.or_else(|| collected.trailers().and_then(|t| t.get("grpc-status").cloned()));
+        match grpc_status.as_ref().and_then(|v| v.to_str().ok()) {
+            Some("0") => {}
+            Some(code) => return Err(format!("collector answered grpc-status {}", code)),
+            None => return Err("collector response carried no grpc-status".to_string()),
+        }
+    }
+    Ok(())
+}
+
+fn unix_nanos(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+}
+
+fn scope_version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+/// OTLP/JSON `ExportTraceServiceRequest`. IDs are hex, 64-bit integers
+/// are strings, as the OTLP JSON mapping requires.
+pub fn encode_json(service_name: &str, spans: &[Span]) -> Value {
+    let attr_json = |k: &str, v: &AttrValue| {
+        let value = match v {
+            AttrValue::Str(s) => json!({ "stringValue": s }),
+            AttrValue::Int(i) => json!({ "intValue": i.to_string() }),
+            AttrValue::Bool(b) => json!({ "boolValue": b }),
+        };
+        json!({ "key": k, "value": value })
+    };
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|s| {
+            let mut v = json!({
+                "traceId": hex(&s.trace_id),
+                "spanId": hex(&s.span_id),
+                "name": s.name,
+                "kind": s.kind as i32,
+                "startTimeUnixNano": unix_nanos(s.start).to_string(),
+                "endTimeUnixNano": unix_nanos(s.end).to_string(),
+                "attributes": s.attributes.iter().map(|(k, v)| attr_json(k, v)).collect::<Vec<_>>(),
+                "status": match &s.error {
+                    Some(msg) => json!({ "code": 2, "message": msg }),
+                    None => json!({}),
+                },
+            });
+            if let Some(p) = s.parent_span_id {
+                v["parentSpanId"] = json!(hex(&p));
+            }
+            if let Some(ts) = &s.trace_state {
+                v["traceState"] = json!(ts);
+            }
+            v
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [attr_json("service.name", &AttrValue::Str(service_name.to_string()))] },
+            "scopeSpans": [{
+                "scope": { "name": "fbi-proxy", "version": scope_version() },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// OTLP protobuf `ExportTraceServiceRequest`.
+pub fn encode_protobuf(service_name: &str, spans: &[Span]) -> Vec<u8> {
+    use pb::*;
+    let key_value = |buf: &mut Vec<u8>, field: u32, k: &str, v: &AttrValue| {
+        message(buf, field, |kv| {
+            string(kv, 1, k);
+            message(kv, 2, |any| match v {
+                AttrValue::Str(s) => string(any, 1, s),
+                AttrValue::Bool(b) => uint(any, 2, *b as u64),
+                AttrValue::Int(i) => uint(any, 3, *i as u64),
+            });
+        });
+    };
+    let mut out = Vec::new();
+    // ExportTraceServiceRequest.resource_spans = 1
+    message(&mut out, 1, |rs| {
+        // ResourceSpans.resource = 1 { Resource.attributes = 1 }
+        message(rs, 1, |res| key_value(res, 1, "service.name", &AttrValue::Str(service_name.to_string())));
+        // ResourceSpans.scope_spans = 2
+        message(rs, 2, |ss| {
+            message(ss, 1, |scope| {
+                string(scope, 1, "fbi-proxy");
+                string(scope, 2, scope_version());
+            });
+            for s in spans {
+                message(ss, 2, |sp| {
+                    bytes(sp, 1, &s.trace_id);
+                    bytes(sp, 2, &s.span_id);
+                    if let Some(ts) = &s.trace_state {
+                        string(sp, 3, ts);
+                    }
+                    if let Some(p) = &s.parent_span_id {
+                        bytes(sp, 4, p);
+                    }
+                    string(sp, 5, &s.name);
+                    uint(sp, 6, s.kind as u64);
+                    fixed64(sp, 7, unix_nanos(s.start));
+                    fixed64(sp, 8, unix_nanos(s.end));
+                    for (k, v) in &s.attributes {
+                        key_value(sp, 9, k, v);
+                    }
+                    if let Some(msg) = &s.error {
+                        // Span.status = 15 { message = 2, code = 3 (ERROR = 2) }
+                        message(sp, 15, |st| {
+                            string(st, 2, msg);
+                            uint(st, 3, 2);
+                        });
+                    }
+                });
+            }
+        });
+    });
+    out
+}
+
+/// gRPC length-prefixed message: uncompressed flag + big-endian length.
+fn grpc_frame(msg: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(msg.len() + 5);
+    out.push(0);
+    out.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+    out.extend_from_slice(msg);
+    out
+}
+
+/// Minimal protobuf writer: just the wire types OTLP spans use.
+pub mod pb {
+    const VARINT: u32 = 0;
+    const FIXED64: u32 = 1;
+    const LEN: u32 = 2;
+
+    pub fn varint(buf: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            buf.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        buf.push(v as u8);
+    }
+
+    fn tag(buf: &mut Vec<u8>, field: u32, wire: u32) {
+        varint(buf, ((field << 3) | wire) as u64);
+    }
+
+    pub fn uint(buf: &mut Vec<u8>, field: u32, v: u64) {
+        tag(buf, field, VARINT);
+        varint(buf, v);
+    }
+
+    pub fn fixed64(buf: &mut Vec<u8>, field: u32, v: u64) {
+        tag(buf, field, FIXED64);
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn bytes(buf: &mut Vec<u8>, field: u32, data: &[u8]) {
+        tag(buf, field, LEN);
+        varint(buf, data.len() as u64);
+        buf.extend_from_slice(data);
+    }
+
+    pub fn string(buf: &mut Vec<u8>, field: u32, s: &str) {
+        bytes(buf, field, s.as_bytes());
+    }
+
+    /// Length-delimited sub-message written by `f`.
+    pub fn message(buf: &mut Vec<u8>, field: u32, f: impl FnOnce(&mut Vec<u8>)) {
+        let mut inner = Vec::new();
+        f(&mut inner);
+        bytes(buf, field, &inner);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::{http1, http2};
+    use hyper::service::service_fn;
+    use hyper::{Response, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpListener;
+
+    fn sample_span() -> Span {
+        let trace = TraceContext::from_headers(Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"), None);
+        let start = UNIX_EPOCH + Duration::from_millis(1_792_346_981_923);
+        let mut s = Span::new(&trace, [7; 8], trace.parent_id, "GET port-as-host", SpanKind::Server, start);
+        s.end = start + Duration::from_millis(3);
+        s.attr("http.response.status_code", 502u16).attr("fbi.route", "port-as-host");
+        s.error = Some("connect".into());
+        s
+    }
+
+    #[test]
+    fn protocol_and_headers_parse() {
+        assert_eq!(Protocol::parse("http/json").unwrap(), Protocol::HttpJson);
+        assert_eq!(Protocol::parse("GRPC").unwrap(), Protocol::Grpc);
+        assert!(Protocol::parse("thrift").is_err());
+        assert_eq!(parse_headers("a=1, b = x=y").unwrap(), vec![("a".into(), "1".into()), ("b".into(), "x=y".into())]);
+        assert!(parse_headers("novalue").is_err());
+        let mut cfg = Config::new("http://c:4318/", Protocol::HttpJson);
+        assert_eq!(cfg.export_url(), "http://c:4318/v1/traces");
+        cfg.endpoint = "http://c:4318/v1/traces".into();
+        assert_eq!(cfg.export_url(), "http://c:4318/v1/traces");
+        cfg.protocol = Protocol::Grpc;
+        cfg.endpoint = "http://c:4317".into();
+        assert_eq!(cfg.export_url(), "http://c:4317/opentelemetry.proto.collector.trace.v1.TraceService/Export");
+    }
+
+    #[test]
+    fn ratio_sampler_is_deterministic_per_trace() {
+        let low = [0u8; 16];
+        let mut high = [0u8; 16];
+        high[8..].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(ratio_sampled(&low, 0.5));
+        assert!(!ratio_sampled(&high, 0.5));
+        assert!(ratio_sampled(&high, 1.0));
+        assert!(!ratio_sampled(&low, 0.0));
+    }
+
+    #[test]
+    fn json_encoding_follows_otlp_mapping() {
+        let v = encode_json("svc", &[sample_span()]);
+        let span = &v["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(v["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"], "svc");
+        assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(span["kind"], 2);
+        assert_eq!(span["startTimeUnixNano"], "1792346981923000000");
+        assert_eq!(span["attributes"][0]["value"]["intValue"], "502");
+        assert_eq!(span["status"]["code"], 2);
+    }
+
+    #[test]
+    fn protobuf_writer_basics() {
+        let mut buf = Vec::new();
+        pb::uint(&mut buf, 1, 300);
+        assert_eq!(buf, [0x08, 0xac, 0x02]);
+        let mut buf = Vec::new();
+        pb::message(&mut buf, 2, |m| pb::string(m, 1, "hi"));
+        assert_eq!(buf, [0x12, 0x04, 0x0a, 0x02, b'h', b'i']);
+        let frame = grpc_frame(&[1, 2, 3]);
+        assert_eq!(frame, [0, 0, 0, 0, 3, 1, 2, 3]);
+        let body = encode_protobuf("svc", &[sample_span()]);
+        let needle = b"port-as-host";
+        assert!(body.windows(needle.len()).any(|w| w == needle));
+    }
+
+    /// Loopback collector stand-in: answers every request with 200 (plus
+    /// grpc-status 0) and forwards (content-type, body) to the test.
+    async fn collector(h2: bool) -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let tx = tx.clone();
+                let svc = service_fn(move |req: Request<hyper::body::Incoming>| {
+                    let tx = tx.clone();
+                    async move {
+                        let ct = req.headers().get("content-type").and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+                        let body = req.into_body().collect().await.unwrap().to_bytes().to_vec();
+                        let _ = tx.send((ct, body));
+                        Ok::<_, std::convert::Infallible>(
+                            Response::builder().status(StatusCode::OK).header("grpc-status", "0").body(Full::new(Bytes::new())).unwrap(),
+                        )
+                    }
+                });
+                let io = TokioIo::new(stream);
+                if h2 {
+                    tokio::spawn(http2::Builder::new(TokioExecutor::new()).serve_connection(io, svc));
+                } else {
+                    tokio::spawn(http1::Builder::new().serve_connection(io, svc));
+                }
+            }
+        });
+        (format!("http://{}", addr), rx)
+    }
+
+    async fn export_one(protocol: Protocol, h2: bool) -> (Arc<Tracer>, String, Vec<u8>) {
+        let (endpoint, mut rx) = collector(h2).await;
+        let mut cfg = Config::new(endpoint, protocol);
+        cfg.batch_size = 2;
+        cfg.flush_interval = Duration::from_millis(50);
+        let tracer = Tracer::start(cfg).unwrap();
+        tracer.record(sample_span());
+        let got = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        (tracer, got.0, got.1)
+    }
+
+    /// `tracer.exported()` once it is non-zero, or at `timeout`: the
+    /// count moves only after the collector has answered.
+    async fn exported_within(tracer: &Tracer, timeout: Duration) -> u64 {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while tracer.exported() == 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        tracer.exported()
+    }
+
+    #[tokio::test]
+    async fn exports_batches_over_http_json() {
+        let (tracer, ct, body) = export_one(Protocol::HttpJson, false).await;
+        assert_eq!(ct, "application/json");
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"], "GET port-as-host");
+        assert_eq!(exported_within(&tracer, Duration::from_secs(5)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn exports_over_grpc() {
+        let (tracer, ct, body) = export_one(Protocol::Grpc, true).await;
+        assert_eq!(ct, "application/grpc");
+        assert_eq!(body[0], 0);
+        let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+        assert_eq!(len, body.len() - 5);
+        assert_eq!(&body[5..], encode_protobuf("fbi-proxy", &[sample_span()]).as_slice());
+        assert_eq!(exported_within(&tracer, Duration::from_secs(5)).await, 1);
+    }
+}
@@ -0,0 +1,111 @@
+//! Per-rule hit counters for `GET /rules` and `fbi-proxy ps`.
+//!
+//! Counters are keyed by `(namespace, rule name)` rather than by position
+//! in the compiled table, so they survive hot reloads for every rule
+//! whose namespace and name are unchanged. Entries for rules that no
+//! longer exist are dropped the next time a snapshot is taken against the
+//! live table.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::routes::CompiledRoute;
+
+/// Runtime counters for one rule.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RouteCounters {
+    pub hits: u64,
+    /// Hits that ended in a 5xx or a proxy-side failure.
+    pub errors: u64,
+    /// Unix epoch milliseconds of the last hit.
+    pub last_hit_ms: Option<u64>,
+    pub last_status: Option<u16>,
+}
+
+#[derive(Default)]
+pub struct RouteStats {
+    map: Mutex<HashMap<(String, String), RouteCounters>>,
+}
+
+impl RouteStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one request served by `namespace/name`.
+    pub fn record(&self, namespace: &str, name: &str, status: u16, failed: bool, at_ms: u64) {
+        let mut map = self.map.lock().unwrap_or_else(|p| p.into_inner());
+        let c = map.entry((namespace.to_string(), name.to_string())).or_default();
+        c.hits += 1;
+        if failed || status >= 500 {
+            c.errors += 1;
+        }
+        c.last_hit_ms = Some(at_ms);
+        c.last_status = Some(status);
+    }
+
+    pub fn get(&self, namespace: &str, name: &str) -> RouteCounters {
+        let map = self.map.lock().unwrap_or_else(|p| p.into_inner());
+        map.get(&(namespace.to_string(), name.to_string())).cloned().unwrap_or_default()
+    }
+
+    /// Counters for each rule in `routes` (same order), pruning entries
+    /// for rules that are gone.
+    pub fn snapshot(&self, routes: &[CompiledRoute]) -> Vec<RouteCounters> {
+        let mut map = self.map.lock().unwrap_or_else(|p| p.into_inner());
+        let live: HashSet<(&str, &str)> = routes.iter().map(|r| (r.namespace.as_str(), r.name.as_str())).collect();
+        map.retain(|(ns, name), _| live.contains(&(ns.as_str(), name.as_str())));
+        routes
+            .iter()
+            .map(|r| map.get(&(r.namespace.clone(), r.name.clone())).cloned().unwrap_or_default())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::{self, RouteConfig};
+
+    fn compiled(ns: &str, names: &[&str]) -> Vec<CompiledRoute> {
+        let cfgs: Vec<RouteConfig> = names
+            .iter()
+            .map(|n| RouteConfig {
+                name: n.to_string(),
+                r#match: format!("{}.{{domain}}", n),
+                target: "localhost:3000".into(),
+                ..Default::default()
+            })
+            .collect();
+        routes::compile_in_namespace(cfgs, ns).unwrap()
+    }
+
+    #[test]
+    fn counts_hits_errors_and_last_status() {
+        let stats = RouteStats::new();
+        stats.record("default", "web", 200, false, 10);
+        stats.record("default", "web", 502, true, 20);
+        stats.record("default", "web", 404, false, 30);
+        let c = stats.get("default", "web");
+        assert_eq!(c.hits, 3);
+        assert_eq!(c.errors, 1);
+        assert_eq!(c.last_hit_ms, Some(30));
+        assert_eq!(c.last_status, Some(404));
+    }
+
+    #[test]
+    fn snapshot_keeps_unchanged_rules_and_prunes_removed_ones() {
+        let stats = RouteStats::new();
+        stats.record("app", "web", 200, false, 1);
+        stats.record("app", "api", 200, false, 1);
+        stats.record("other", "web", 200, false, 1);
+
+        // Reload: `app/api` removed, `app/web` kept (now declared second).
+        let mut routes = compiled("app", &["new", "web"]);
+        routes.extend(compiled("other", &["web"]));
+        let snap = stats.snapshot(&routes);
+        assert_eq!(snap.iter().map(|c| c.hits).collect::<Vec<_>>(), [0, 1, 1]);
+        assert_eq!(stats.get("app", "api"), RouteCounters::default());
+    }
+}
@@ -17,9 +17,9 @@
 //! * `{name:int}`   — matches one numeric segment: `\d+`
 //! * `{name:slug}`  — matches `[a-z0-9-]+`
 //! * `{name:multi}` — matches one or more dot-separated segments:
-//!                    `[^.]+(\.[^.]+)*`. Use this for DNS-passthrough
-//!                    patterns like `{upstream:multi}.{domain}` that
-//!                    need to capture e.g. `github.com` as one value.
+//!   `[^.]+(\.[^.]+)*`. Use this for DNS-passthrough patterns like
+//!   `{upstream:multi}.{domain}` that need to capture e.g. `github.com`
+//!   as one value.
 //!
 //! A given placeholder name can appear in both the `match` pattern
 //! (where it captures) and in the `target` / `headers` templates
@@ -56,11 +56,26 @@
 //! the convenience helper: it strips `.fbi.example.com` from the host
 //! before matching, then re-injects the value as the `{domain}`
 //! capture for template expansion.
+//!
+//! # Variables
+//!
+//! `${NAME}` and `${NAME:-default}` are a separate, compile-time layer:
+//! [`RoutesFile::rules`] substitutes them in `match`, `path`, `target`
+//! and header values before the placeholders above are parsed. `NAME`
+//! is looked up in the file's `vars:` block, then the process
+//! environment (except in header values, which `GET /rules` shows; use
+//! a secret reference there), then the default; with none of those the
+//! rule fails to compile ([`CompileError::UndefinedVariable`]). `$${`
+//! is a literal `${`.
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::sync::Arc;
+
+use crate::launch::{CommandLine, Launch, LaunchConfig};
+use crate::secrets::{self, Secret, Segment};
 
 /// Placeholder kind — controls the regex fragment used to match.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -112,7 +127,7 @@ pub struct Placeholder {
 }
 
 /// User-supplied route configuration (e.g. from `routes.yaml`).
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 pub struct RouteConfig {
     pub name: String,
     /// Pattern matched against the Host header (without port).
@@ -131,23 +146,263 @@ pub struct RouteConfig {
     /// is surfaced separately on `RouteHit::host_header`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
+    /// Per-route access-log policy (enable + sampling). Absent means
+    /// "log every request" whenever the access log is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<AccessLogPolicy>,
+    /// Evaluation-order priority: among rules with the same path
+    /// priority, higher goes first (default 0, so the bundled defaults
+    /// win ties). Overrides the file-level `priority`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Command that serves `target`, started on the first request and
+    /// stopped when idle (see [`crate::launch`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch: Option<LaunchConfig>,
+}
+
+/// Per-route access-log settings (`access_log:` on a rule).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct AccessLogPolicy {
+    /// Set `false` to never write access-log lines for this rule.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Fraction of requests to log, `0.0..=1.0`.
+    #[serde(default = "default_sample")]
+    pub sample: f64,
 }
 
-/// Top-level shape of `routes.yaml`.
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl Default for AccessLogPolicy {
+    fn default() -> Self {
+        Self { enabled: true, sample: 1.0 }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_sample() -> f64 {
+    1.0
+}
+
+/// A routes file, flattened to the version 1 shape it deserializes
+/// from. [`parse_yaml`] reads every version; serializing writes the
+/// current one (see [`crate::schema`]).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(into = "crate::schema::FileV2")]
 pub struct RoutesFile {
+    /// The schema version the file was written in.
     #[serde(default = "default_version")]
     pub version: u32,
+    /// Default `priority` for this file's rules (see
+    /// [`RouteConfig::priority`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Names of bundled default rules to switch off while this fragment
+    /// is loaded (conf.d only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable_defaults: Vec<String>,
+    /// Values for `${NAME}` in this file's rules (see the module docs).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    /// Other routes files (or globs) whose rules, tests and vars are
+    /// merged in ahead of this file's own, relative to this file (see
+    /// [`crate::include`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
     pub routes: Vec<RouteConfig>,
+    /// Routing assertions checked against the merged rule set before it
+    /// goes live (see [`run_tests`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<RouteTest>,
+}
+
+/// One embedded routing assertion (`tests:` in a routes file).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteTest {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Only `CONNECT` changes routing (it carries no path).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    pub expect: Expect,
+}
+
+/// What a [`RouteTest`] expects: `reject`, `landing`, or a hit whose
+/// given fields must all match.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Expect {
+    Outcome(Outcome),
+    Hit(ExpectHit),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Reject,
+    Landing,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Reject => "reject",
+            Outcome::Landing => "landing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectHit {
+    /// Rule name, optionally qualified as `namespace/name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    /// Expanded target, e.g. `localhost:3000`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The `Host` header the upstream receives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_header: Option<String>,
 }
 
 fn default_version() -> u32 {
     1
 }
 
-/// Parse a `routes.yaml`-style document.
-pub fn parse_yaml(src: &str) -> Result<RoutesFile, serde_yaml::Error> {
-    serde_yaml::from_str(src)
+impl RoutesFile {
+    /// The rules ready to compile: variables substituted and the
+    /// file-level `priority` applied to those that don't set their own.
+    pub fn rules(&self) -> Result<Vec<RouteConfig>, CompileError> {
+        self.routes.iter().map(|r| self.rule(r)).collect()
+    }
+
+    /// [`rules`](Self::rules) for one of this file's `routes`.
+    pub fn rule(&self, r: &RouteConfig) -> Result<RouteConfig, CompileError> {
+        let undefined =
+            |name, location: &str| CompileError::UndefinedVariable { route: r.name.clone(), name, location: location.to_string() };
+        let expand = |template: &str, location: &str| interpolate(template, &self.vars).map_err(|n| undefined(n, location));
+        let mut headers = None;
+        if let Some(h) = &r.headers {
+            let mut out = HashMap::with_capacity(h.len());
+            for (k, v) in h {
+                let location = format!("header '{}'", k);
+                out.insert(k.clone(), interpolate_vars(v, &self.vars).map_err(|n| undefined(n, &location))?);
+            }
+            headers = Some(out);
+        }
+        let mut launch = None;
+        if let Some(l) = &r.launch {
+            let command = match &l.command {
+                CommandLine::Shell(line) => CommandLine::Shell(expand(line, "launch.command")?),
+                CommandLine::Argv(argv) => {
+                    CommandLine::Argv(argv.iter().map(|a| expand(a, "launch.command")).collect::<Result<_, _>>()?)
+                }
+            };
+            let mut env = BTreeMap::new();
+            for (k, v) in &l.env {
+                env.insert(k.clone(), expand(v, &format!("launch.env '{}'", k))?);
+            }
+            let cwd = l.cwd.as_deref().map(|c| expand(c, "launch.cwd")).transpose()?;
+            launch = Some(LaunchConfig { command, cwd, env, ..l.clone() });
+        }
+        Ok(RouteConfig {
+            r#match: expand(&r.r#match, "match pattern")?,
+            path: r.path.as_deref().map(|p| expand(p, "path")).transpose()?,
+            target: expand(&r.target, "target template")?,
+            headers,
+            priority: r.priority.or(self.priority),
+            launch,
+            ..r.clone()
+        })
+    }
+}
+
+/// Substitute `${NAME}` / `${NAME:-default}` in `template` from `vars`,
+/// then the environment, then the default. `$${` is a literal `${`;
+/// anything else that isn't a well-formed reference is left as written.
+/// Errors with the name of a variable that has no value.
+pub fn interpolate(template: &str, vars: &BTreeMap<String, String>) -> Result<String, String> {
+    substitute(template, vars, true)
+}
+
+/// [`interpolate`] without the environment: only `vars` and defaults.
+pub fn interpolate_vars(template: &str, vars: &BTreeMap<String, String>) -> Result<String, String> {
+    substitute(template, vars, false)
+}
+
+/// The `${NAME}` / `${NAME:-default}` references in `template`: names
+/// and defaults.
+pub fn var_refs(template: &str) -> Vec<(&str, Option<&str>)> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        rest = &rest[i..];
+        if let Some(r) = rest.strip_prefix("$${") {
+            rest = r;
+        } else if let Some((name, default, len)) = var_ref(rest) {
+            names.push((name, default));
+            rest = &rest[len..];
+        } else {
+            rest = &rest[1..];
+        }
+    }
+    names
+}
+
+fn substitute(template: &str, vars: &BTreeMap<String, String>, env: bool) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(r) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = r;
+            continue;
+        }
+        let Some((name, default, len)) = var_ref(rest) else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let value = vars
+            .get(name)
+            .cloned()
+            .or_else(|| env.then(|| std::env::var(name).ok()).flatten())
+            .or_else(|| default.map(String::from))
+            .ok_or_else(|| name.to_string())?;
+        out.push_str(&value);
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// `${NAME}` or `${NAME:-default}` at the start of `s`: the name, the
+/// default and the reference's byte length.
+fn var_ref(s: &str) -> Option<(&str, Option<&str>, usize)> {
+    let body = s.strip_prefix("${")?;
+    let end = body.find('}')?;
+    let inner = &body[..end];
+    let (name, default) = match inner.split_once(":-") {
+        Some((n, d)) => (n, Some(d)),
+        None => (inner, None),
+    };
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some((name, default, end + 3))
+}
+
+/// Parse a `routes.yaml`-style document of any schema version, quietly
+/// (see [`crate::schema::parse`] for what it notices).
+pub fn parse_yaml(src: &str) -> Result<RoutesFile, crate::schema::ParseError> {
+    crate::schema::parse(src, crate::schema::Format::Yaml).map(|p| p.file)
 }
 
 /// A compiled route — regex + templates — ready to evaluate per request.
@@ -157,7 +412,12 @@ pub struct CompiledRoute {
     pub pattern: Regex,
     pub placeholders: Vec<Placeholder>,
     pub target_template: String,
+    /// Header templates as written, secret references included.
     pub header_templates: HashMap<String, String>,
+    /// Values of the `${env:..}` / `${file:..}` references in
+    /// `header_templates`, keyed by the reference text (see
+    /// [`crate::secrets`]). Read when the rule compiled.
+    pub secrets: HashMap<String, Secret>,
     /// Original (uncompiled) `match` pattern, retained so the admin API
     /// can report and round-trip the source rule.
     pub match_pattern: String,
@@ -167,18 +427,71 @@ pub struct CompiledRoute {
     /// Namespace this route belongs to — the conf.d fragment stem, or
     /// `"default"` for the bundled defaults. Used for `ps` grouping.
     pub namespace: String,
+    /// Access-log policy (defaults to "log everything").
+    pub access_log: AccessLogPolicy,
+    /// Evaluation-order priority (see [`RouteConfig::priority`]).
+    pub priority: i32,
+    /// The process serving `target` on demand, if the rule has `launch`.
+    pub launch: Option<Arc<Launch>>,
 }
 
 /// Result of a successful match.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct RouteHit {
     pub route_name: String,
+    /// Namespace of the matched route (see [`CompiledRoute::namespace`]).
+    pub namespace: String,
     /// Expanded `target` template (e.g. `"api:3001"`).
     pub target: String,
     /// Expanded `Host` header from the `headers` map, if any.
     pub host_header: Option<String>,
     /// Other expanded headers, excluding `Host` (case-insensitive).
+    /// Secret references are left as written.
     pub other_headers: HashMap<String, String>,
+    /// The headers of `other_headers` that hold secrets, with the
+    /// secrets filled in. Never serialized.
+    #[serde(skip)]
+    pub secret_headers: Vec<(String, Secret)>,
+    /// The matched route's access-log policy.
+    pub access_log: AccessLogPolicy,
+    /// The matched route's on-demand process, if any.
+    #[serde(skip)]
+    pub launch: Option<Arc<Launch>>,
+}
+
+impl RouteHit {
+    /// The headers to add to the upstream request, besides `Host`, with
+    /// secrets filled in.
+    pub fn upstream_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.other_headers.iter().map(|(k, v)| {
+            let secret = self.secret_headers.iter().find(|(name, _)| name == k);
+            let v = secret.map_or(v.as_str(), |(_, s)| s.expose());
+            (k.as_str(), v)
+        })
+    }
+
+    /// The `Host` header sent upstream: the rule's `Host` rewrite, else
+    /// the target's hostname.
+    pub fn upstream_host(&self) -> String {
+        if let Some(h) = &self.host_header {
+            return h.clone();
+        }
+        let authority = parse_target_scheme(&self.target).1;
+        match authority.find(':') {
+            Some(i) => authority[..i].to_string(),
+            None => authority.to_string(),
+        }
+    }
+}
+
+/// What the proxy does with a request (see [`decide`]).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Hit(RouteHit),
+    /// The apex host with no rule claiming it: the built-in landing page.
+    Landing,
+    /// Host not allowed or no matching rule: 502.
+    Reject,
 }
 
 /// Compile-time error from `compile`.
@@ -198,6 +511,12 @@ pub enum CompileError {
     UndeclaredPlaceholder { route: String, name: String, location: String },
     /// Unbalanced braces in pattern or template.
     UnbalancedBraces { route: String, location: String },
+    /// A non-template field has an out-of-range or malformed value.
+    InvalidField { route: String, field: String, reason: String },
+    /// A `${NAME}` had no `vars:` entry, environment variable or default.
+    UndefinedVariable { route: String, name: String, location: String },
+    /// A `${env:..}` / `${file:..}` secret reference could not be read.
+    UnresolvedSecret { route: String, reference: String, location: String, reason: String },
 }
 
 impl fmt::Display for CompileError {
@@ -221,6 +540,20 @@ impl fmt::Display for CompileError {
             CompileError::UnbalancedBraces { route, location } => {
                 write!(f, "route '{}': unbalanced braces in {}", route, location)
             }
+            CompileError::InvalidField { route, field, reason } => {
+                write!(f, "route '{}': invalid {}: {}", route, field, reason)
+            }
+            CompileError::UndefinedVariable { route, name, location } if location.starts_with("header ") => write!(
+                f,
+                "route '{}': ${{{}}} in {} is not set (no `vars:` entry or `:-` default; headers read the environment only through ${{env:{}}})",
+                route, name, location, name
+            ),
+            CompileError::UndefinedVariable { route, name, location } => {
+                write!(f, "route '{}': ${{{}}} in {} is not set (no `vars:` entry, environment variable or `:-` default)", route, name, location)
+            }
+            CompileError::UnresolvedSecret { route, reference, location, reason } => {
+                write!(f, "route '{}': secret {} in {}: {}", route, reference, location, reason)
+            }
         }
     }
 }
@@ -233,14 +566,14 @@ impl std::error::Error for CompileError {}
 
 /// A token of a parsed pattern / template string.
 #[derive(Debug, Clone, PartialEq, Eq)]
-enum Token {
+pub(crate) enum Token {
     Literal(String),
     Placeholder { name: String, kind: Option<String> },
 }
 
 /// Tokenize a `{name[:kind]}`-style template. Returns the token list
 /// or `Err(UnbalancedBraces)` on malformed input.
-fn tokenize(s: &str, route: &str, location: &str) -> Result<Vec<Token>, CompileError> {
+pub(crate) fn tokenize(s: &str, route: &str, location: &str) -> Result<Vec<Token>, CompileError> {
     let mut out = Vec::new();
     let mut buf = String::new();
     let mut chars = s.chars().peekable();
@@ -289,7 +622,7 @@ fn tokenize(s: &str, route: &str, location: &str) -> Result<Vec<Token>, CompileE
     Ok(out)
 }
 
-fn parse_kind(route: &str, name: &str, kind: Option<&str>) -> Result<PlaceholderKind, CompileError> {
+pub(crate) fn parse_kind(route: &str, name: &str, kind: Option<&str>) -> Result<PlaceholderKind, CompileError> {
     match kind {
         None | Some("") => Ok(PlaceholderKind::Any),
         Some("int") => Ok(PlaceholderKind::Int),
@@ -368,6 +701,22 @@ fn normalize_path_prefix(p: &str) -> String {
 
 fn compile_one(cfg: RouteConfig, namespace: &str) -> Result<CompiledRoute, CompileError> {
     let route_name = cfg.name.clone();
+    let host_header = cfg.headers.iter().flatten().find(|(k, _)| k.eq_ignore_ascii_case("host"));
+    let no_secrets = [
+        ("match", Some(&cfg.r#match)),
+        ("path", cfg.path.as_ref()),
+        ("target", Some(&cfg.target)),
+        ("headers.Host", host_header.map(|(_, v)| v)),
+    ];
+    for (field, value) in no_secrets {
+        if value.is_some_and(|v| secrets::has_refs(v)) {
+            return Err(CompileError::InvalidField {
+                route: route_name,
+                field: field.to_string(),
+                reason: "secret references are only allowed in header values other than Host".to_string(),
+            });
+        }
+    }
     let match_pattern = cfg.r#match.clone();
     let tokens = tokenize(&cfg.r#match, &route_name, "match pattern")?;
 
@@ -437,18 +786,34 @@ fn compile_one(cfg: RouteConfig, namespace: &str) -> Result<CompiledRoute, Compi
     }
 
     let mut header_templates: HashMap<String, String> = HashMap::new();
+    let mut secrets: HashMap<String, Secret> = HashMap::new();
     if let Some(headers) = cfg.headers {
         for (k, v) in headers {
-            let header_tokens = tokenize(&v, &route_name, &format!("header '{}'", k))?;
-            for tok in &header_tokens {
-                if let Token::Placeholder { name, .. } = tok {
-                    validate_name(&route_name, name, name)?;
-                    if !declared.iter().any(|p| p.name == *name) {
-                        return Err(CompileError::UndeclaredPlaceholder {
+            let location = format!("header '{}'", k);
+            for seg in secrets::split(&v) {
+                let text = match seg {
+                    Segment::Text(text) => text,
+                    Segment::Ref(reference, source) => {
+                        let secret = source.resolve().map_err(|reason| CompileError::UnresolvedSecret {
                             route: route_name.clone(),
-                            name: name.clone(),
-                            location: format!("header '{}'", k),
-                        });
+                            reference: reference.to_string(),
+                            location: location.clone(),
+                            reason,
+                        })?;
+                        secrets.insert(reference.to_string(), secret);
+                        continue;
+                    }
+                };
+                for tok in &tokenize(text, &route_name, &location)? {
+                    if let Token::Placeholder { name, .. } = tok {
+                        validate_name(&route_name, name, name)?;
+                        if !declared.iter().any(|p| p.name == *name) {
+                            return Err(CompileError::UndeclaredPlaceholder {
+                                route: route_name.clone(),
+                                name: name.clone(),
+                                location,
+                            });
+                        }
                     }
                 }
             }
@@ -458,18 +823,59 @@ fn compile_one(cfg: RouteConfig, namespace: &str) -> Result<CompiledRoute, Compi
 
     let path_prefix = cfg.path.as_deref().map(normalize_path_prefix);
 
+    let access_log = cfg.access_log.unwrap_or_default();
+    if !(0.0..=1.0).contains(&access_log.sample) {
+        return Err(CompileError::InvalidField {
+            route: route_name,
+            field: "access_log.sample".to_string(),
+            reason: format!("{} is not within 0.0..=1.0", access_log.sample),
+        });
+    }
+
+    let launch = match cfg.launch {
+        Some(l) => Some(Arc::new(Launch::new(l, &cfg.target).map_err(|(field, reason)| CompileError::InvalidField {
+            route: route_name.clone(),
+            field: field.to_string(),
+            reason,
+        })?)),
+        None => None,
+    };
+
     Ok(CompiledRoute {
         name: route_name,
         pattern,
         placeholders: declared,
         target_template: cfg.target,
         header_templates,
+        secrets,
         match_pattern,
         path_prefix,
         namespace: namespace.to_string(),
+        access_log,
+        priority: cfg.priority.unwrap_or(0),
+        launch,
     })
 }
 
+/// Put a merged rule set into evaluation order: stably by descending
+/// `priority`, so equal priorities keep merge order (bundled defaults,
+/// then fragments by filename).
+pub fn sort_by_priority(routes: &mut [CompiledRoute]) {
+    routes.sort_by_key(|r| std::cmp::Reverse(r.priority));
+}
+
+/// Drop the rules of namespace `"default"` named in `disabled`. Returns
+/// the names that matched no such rule.
+pub fn disable_defaults<'a>(routes: &mut Vec<CompiledRoute>, disabled: &[&'a str]) -> Vec<&'a str> {
+    let unknown = disabled
+        .iter()
+        .copied()
+        .filter(|n| !routes.iter().any(|r| r.namespace == "default" && r.name == *n))
+        .collect();
+    routes.retain(|r| r.namespace != "default" || !disabled.contains(&r.name.as_str()));
+    unknown
+}
+
 // ---------------------------------------------------------------------------
 // Match
 // ---------------------------------------------------------------------------
@@ -494,7 +900,7 @@ fn normalize(host: &str) -> String {
 }
 
 /// Expand a template string using captured placeholders.
-fn expand(template: &str, captures: &HashMap<String, String>) -> String {
+pub(crate) fn expand(template: &str, captures: &HashMap<String, String>) -> String {
     // We can re-use the tokenizer here, but since we already validated
     // at compile-time, this is purely substitution: scan for {name[:kind]}
     // and replace.
@@ -567,7 +973,8 @@ pub fn match_host_with_domain(
 ///
 /// Among all routes whose host pattern matches (and whose `path_prefix`
 /// matches `req_path`, if any), the one with the **longest matching path
-/// prefix** wins; ties are broken by declaration order (earliest wins).
+/// prefix** wins; ties are broken by position in `routes` (earliest
+/// wins), which [`sort_by_priority`] orders by rule `priority`.
 /// A route with no `path_prefix` has the lowest path priority, so an
 /// explicit `path: /` rule still beats a path-less rule for the same
 /// host.
@@ -595,74 +1002,381 @@ pub fn match_request_opts(
     require_explicit_path: bool,
 ) -> Option<RouteHit> {
     let host = normalize(host);
+    if !domain_allows(&host, default_domain) {
+        return None;
+    }
+    let verdicts = routes.iter().map(|r| evaluate(r, &host, req_path, require_explicit_path));
+    let (best_idx, _) = select_best(verdicts)?;
+    let route = routes.get(best_idx)?;
+    Some(expand_hit(route, &capture_values(route, &host)?))
+}
 
-    if let Some(domain) = default_domain {
-        if !domain.is_empty() {
+/// With a domain filter, the host must be the domain itself or end with
+/// `.domain` (case-insensitive).
+fn domain_allows(host: &str, default_domain: Option<&str>) -> bool {
+    match default_domain {
+        Some(domain) if !domain.is_empty() => {
             let domain_lc = domain.to_ascii_lowercase();
-            if host != domain_lc && !host.ends_with(&format!(".{}", domain_lc)) {
-                return None;
-            }
+            host == domain_lc || host.ends_with(&format!(".{}", domain_lc))
         }
+        _ => true,
     }
+}
 
-    // Select the best candidate by path-prefix length. `priority` is the
-    // prefix byte length, or 0 for a path-less route. We require a
-    // strictly-greater priority to replace the current best, so the
-    // earliest declaration wins on ties.
-    let mut best_idx: Option<usize> = None;
-    let mut best_priority: i64 = -1;
-    for (i, route) in routes.iter().enumerate() {
-        if !route.pattern.is_match(&host) {
-            continue;
-        }
-        let priority: i64 = match &route.path_prefix {
-            None => {
-                if require_explicit_path {
-                    continue;
-                }
-                0
-            }
-            Some(prefix) => {
-                if !path_matches(prefix, req_path) {
-                    continue;
-                }
-                prefix.len() as i64
-            }
-        };
-        if priority > best_priority {
-            best_priority = priority;
-            best_idx = Some(i);
+/// How a single route fared against a request (see [`explain_request`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Verdict {
+    /// Host and path matched. `priority` is the path-prefix byte length,
+    /// or 0 for a path-less route.
+    Matched { priority: usize },
+    /// The host regex did not match.
+    HostMismatch,
+    /// The host matched but the request path is outside `path_prefix`.
+    PathMismatch,
+    /// Path-less route skipped because only explicit-path routes are
+    /// eligible (the apex host).
+    NeedsExplicitPath,
+}
+
+fn evaluate(route: &CompiledRoute, host: &str, req_path: &str, require_explicit_path: bool) -> Verdict {
+    if !route.pattern.is_match(host) {
+        return Verdict::HostMismatch;
+    }
+    match &route.path_prefix {
+        None if require_explicit_path => Verdict::NeedsExplicitPath,
+        None => Verdict::Matched { priority: 0 },
+        Some(prefix) if path_matches(prefix, req_path) => Verdict::Matched { priority: prefix.len() },
+        Some(_) => Verdict::PathMismatch,
+    }
+}
+
+/// Select the best candidate by path-prefix length. A strictly-greater
+/// priority is required to replace the current best, so the earliest
+/// declaration wins on ties. Returns `(index, priority)`.
+fn select_best(verdicts: impl Iterator<Item = Verdict>) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    for (i, v) in verdicts.enumerate() {
+        if let Verdict::Matched { priority } = v
+            && best.is_none_or(|(_, p)| priority > p)
+        {
+            best = Some((i, priority));
         }
     }
+    best
+}
 
-    let route = routes.get(best_idx?)?;
-    let caps = route.pattern.captures(&host)?;
+fn capture_values(route: &CompiledRoute, host: &str) -> Option<HashMap<String, String>> {
+    let caps = route.pattern.captures(host)?;
     let mut values: HashMap<String, String> = HashMap::new();
     for p in &route.placeholders {
         if let Some(m) = caps.name(&p.name) {
             values.insert(p.name.clone(), m.as_str().to_string());
         }
     }
+    Some(values)
+}
 
-    let target = expand(&route.target_template, &values);
+fn expand_hit(route: &CompiledRoute, values: &HashMap<String, String>) -> RouteHit {
+    let target = expand(&route.target_template, values);
 
     let mut host_header: Option<String> = None;
     let mut other_headers: HashMap<String, String> = HashMap::new();
+    let mut secret_headers: Vec<(String, Secret)> = Vec::new();
     for (k, tmpl) in &route.header_templates {
-        let v = expand(tmpl, &values);
         if k.eq_ignore_ascii_case("host") {
-            host_header = Some(v);
-        } else {
-            other_headers.insert(k.clone(), v);
+            host_header = Some(expand(tmpl, values));
+            continue;
         }
+        if route.secrets.is_empty() {
+            other_headers.insert(k.clone(), expand(tmpl, values));
+            continue;
+        }
+        // Expand around the references: shown as written, sent filled in.
+        let (mut shown, mut sent, mut secret) = (String::new(), String::new(), false);
+        for seg in secrets::split(tmpl) {
+            match seg {
+                Segment::Text(text) => {
+                    let v = expand(text, values);
+                    shown.push_str(&v);
+                    sent.push_str(&v);
+                }
+                Segment::Ref(reference, _) => {
+                    secret = true;
+                    shown.push_str(reference);
+                    sent.push_str(route.secrets.get(reference).map_or("", Secret::expose));
+                }
+            }
+        }
+        if secret {
+            secret_headers.push((k.clone(), Secret::from(sent)));
+        }
+        other_headers.insert(k.clone(), shown);
     }
 
-    Some(RouteHit {
+    RouteHit {
         route_name: route.name.clone(),
+        namespace: route.namespace.clone(),
         target,
         host_header,
         other_headers,
-    })
+        secret_headers,
+        access_log: route.access_log,
+        launch: route.launch.clone(),
+    }
+}
+
+/// Parse a route target into (scheme, authority). Supports an optional
+/// `http://` or `https://` prefix; defaults to `http` so existing
+/// `host:port`-style targets keep working unchanged. Used by both the
+/// HTTP forwarder (chooses URL scheme) and the WebSocket upgrade path
+/// (chooses `ws` vs `wss`).
+pub fn parse_target_scheme(target: &str) -> (&'static str, &str) {
+    if let Some(rest) = target.strip_prefix("https://") {
+        ("https", rest)
+    } else if let Some(rest) = target.strip_prefix("http://") {
+        ("http", rest)
+    } else {
+        ("http", target)
+    }
+}
+
+/// The effective path and apex flag for routing `host_header`, shared by
+/// [`decide`] and the explain endpoint so the two never disagree.
+pub fn routing_inputs<'a>(domain_filter: Option<&str>, host_header: &str, req_path: &'a str) -> (&'a str, bool) {
+    // Drop port if present.
+    let host_without_port = match host_header.find(':') {
+        Some(i) => &host_header[..i],
+        None => host_header,
+    };
+
+    // CONNECT and some clients carry an empty path — treat it as "/"
+    // so host-level rules (path "/" or path-less) still match. Path
+    // routing only applies to L7 requests we terminate ourselves.
+    let req_path = if req_path.is_empty() { "/" } else { req_path };
+
+    // Is this the exact apex host (e.g. `fbi.com` itself, not a
+    // subdomain)? The apex is reserved for the landing page unless a
+    // rule *explicitly* claims it with a `path` — otherwise a bundled
+    // placeholder rule like `{host}.{domain}` would swallow
+    // `https://fbi.com/` and break `fbi-proxy setup`'s verification.
+    let is_apex = match domain_filter {
+        Some(d) => !d.is_empty() && host_without_port.eq_ignore_ascii_case(d),
+        None => false,
+    };
+    (req_path, is_apex)
+}
+
+/// Route a request the way the proxy does: domain filter, apex handling
+/// (only explicit-path rules; otherwise the landing page), then
+/// longest-path-prefix matching.
+pub fn decide(routes: &[CompiledRoute], host_header: &str, req_path: &str, domain_filter: Option<&str>) -> Decision {
+    let (req_path, is_apex) = routing_inputs(domain_filter, host_header, req_path);
+    match match_request_opts(routes, host_header, req_path, domain_filter, is_apex) {
+        Some(hit) => Decision::Hit(hit),
+        None if is_apex => Decision::Landing,
+        None => Decision::Reject,
+    }
+}
+
+/// A failing embedded route test, as reported by [`run_tests`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestFailure {
+    /// The `label` the test was passed in with (usually its file).
+    pub label: String,
+    /// Zero-based position in that file's `tests:` list.
+    pub index: usize,
+    /// `METHOD host/path`, for messages.
+    pub request: String,
+    pub reason: String,
+}
+
+impl fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} test #{} ({}): {}", self.label, self.index + 1, self.request, self.reason)
+    }
+}
+
+/// Evaluate embedded route tests against `routes` (the merged set).
+/// Returns one entry per failing test; `label` names each test's
+/// source.
+pub fn run_tests<'a>(
+    routes: &[CompiledRoute],
+    tests: impl IntoIterator<Item = (&'a str, usize, &'a RouteTest)>,
+    domain_filter: Option<&str>,
+) -> Vec<TestFailure> {
+    tests
+        .into_iter()
+        .filter_map(|(label, i, t)| {
+            check_test(routes, t, domain_filter).err().map(|reason| {
+                let method = t.method.as_deref().unwrap_or("GET");
+                TestFailure {
+                    label: label.to_string(),
+                    index: i,
+                    request: format!("{} {}{}", method, t.host, t.path.as_deref().unwrap_or("/")),
+                    reason,
+                }
+            })
+        })
+        .collect()
+}
+
+fn check_test(routes: &[CompiledRoute], t: &RouteTest, domain_filter: Option<&str>) -> Result<(), String> {
+    let connect = t.method.as_deref().is_some_and(|m| m.eq_ignore_ascii_case("CONNECT"));
+    let path = if connect { "" } else { t.path.as_deref().unwrap_or("/") };
+    let got = decide(routes, &t.host, path, domain_filter);
+    let describe = |d: &Decision| match d {
+        Decision::Hit(h) => format!("'{}/{}' -> {} (Host: {})", h.namespace, h.route_name, h.target, h.upstream_host()),
+        Decision::Landing => "landing".to_string(),
+        Decision::Reject => "reject".to_string(),
+    };
+    let (want, got_hit) = match (&t.expect, &got) {
+        (Expect::Outcome(Outcome::Reject), Decision::Reject) | (Expect::Outcome(Outcome::Landing), Decision::Landing) => {
+            return Ok(());
+        }
+        (Expect::Hit(want), Decision::Hit(hit)) => (want, hit),
+        (Expect::Outcome(o), _) => return Err(format!("expected {}, got {}", o.as_str(), describe(&got))),
+        (Expect::Hit(_), _) => return Err(format!("expected a rule to match, got {}", describe(&got))),
+    };
+    let mut diffs = Vec::new();
+    if let Some(route) = &want.route {
+        let matches = match route.rsplit_once('/') {
+            Some((ns, name)) => ns == got_hit.namespace && name == got_hit.route_name,
+            None => *route == got_hit.route_name,
+        };
+        if !matches {
+            diffs.push(format!("route '{}'", route));
+        }
+    }
+    if let Some(target) = want.target.as_ref().filter(|t| **t != got_hit.target) {
+        diffs.push(format!("target '{}'", target));
+    }
+    if let Some(host) = want.host_header.as_ref().filter(|h| **h != got_hit.upstream_host()) {
+        diffs.push(format!("host_header '{}'", host));
+    }
+    if diffs.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("expected {}, got {}", diffs.join(", "), describe(&got)))
+    }
+}
+
+/// One route's entry in an [`Explanation`], in declaration order.
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub namespace: String,
+    pub name: String,
+    #[serde(rename = "match")]
+    pub match_pattern: String,
+    /// The compiled host regex the `match` pattern became.
+    pub regex: String,
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub verdict: Verdict,
+    pub winner: bool,
+    /// Human-readable reason for the verdict.
+    pub reason: String,
+}
+
+/// Step-by-step account of how [`match_request_opts`] routes a request.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    /// The host as matched: port and trailing slash stripped, lowercased.
+    pub host: String,
+    pub path: String,
+    pub domain: Option<String>,
+    /// `false` when the domain filter rejected the host before any rule
+    /// was considered (`candidates` is then empty).
+    pub domain_allowed: bool,
+    pub require_explicit_path: bool,
+    pub candidates: Vec<Candidate>,
+    /// Placeholder values captured by the winning rule.
+    pub captures: std::collections::BTreeMap<String, String>,
+    pub hit: Option<RouteHit>,
+}
+
+/// Run the same matching as [`match_request_opts`], recording the verdict
+/// for every route. `explanation.hit` is always equal to what
+/// `match_request_opts` returns for the same arguments.
+pub fn explain_request(
+    routes: &[CompiledRoute],
+    host: &str,
+    req_path: &str,
+    default_domain: Option<&str>,
+    require_explicit_path: bool,
+) -> Explanation {
+    let host = normalize(host);
+    let mut out = Explanation {
+        host: host.clone(),
+        path: req_path.to_string(),
+        domain: default_domain.filter(|d| !d.is_empty()).map(str::to_string),
+        domain_allowed: domain_allows(&host, default_domain),
+        require_explicit_path,
+        candidates: Vec::new(),
+        captures: Default::default(),
+        hit: None,
+    };
+    if !out.domain_allowed {
+        return out;
+    }
+
+    let verdicts: Vec<Verdict> =
+        routes.iter().map(|r| evaluate(r, &host, req_path, require_explicit_path)).collect();
+    let best = select_best(verdicts.iter().copied());
+    for (i, (route, verdict)) in routes.iter().zip(&verdicts).enumerate() {
+        let reason = match (*verdict, best) {
+            (Verdict::HostMismatch, _) => format!("host '{}' does not match {}", host, route.pattern.as_str()),
+            (Verdict::PathMismatch, _) => format!(
+                "path '{}' is not under '{}'",
+                req_path,
+                route.path_prefix.as_deref().unwrap_or("/")
+            ),
+            (Verdict::NeedsExplicitPath, _) => {
+                "apex host: only rules with an explicit path are eligible".to_string()
+            }
+            (Verdict::Matched { priority }, Some((w, _))) if w == i => {
+                format!("matched with priority {} and won", priority)
+            }
+            (Verdict::Matched { priority }, Some((w, wp))) => {
+                let winner = &routes[w];
+                if priority == wp && winner.priority != route.priority {
+                    format!(
+                        "matched with priority {} but '{}/{}' ties and has rule priority {} (vs {})",
+                        priority, winner.namespace, winner.name, winner.priority, route.priority
+                    )
+                } else if priority == wp {
+                    format!(
+                        "matched with priority {} but '{}/{}' ties and is declared first",
+                        priority, winner.namespace, winner.name
+                    )
+                } else {
+                    format!(
+                        "matched with priority {} but '{}/{}' has priority {}",
+                        priority, winner.namespace, winner.name, wp
+                    )
+                }
+            }
+            (Verdict::Matched { priority }, None) => format!("matched with priority {}", priority),
+        };
+        out.candidates.push(Candidate {
+            namespace: route.namespace.clone(),
+            name: route.name.clone(),
+            match_pattern: route.match_pattern.clone(),
+            regex: route.pattern.as_str().to_string(),
+            path: route.path_prefix.clone(),
+            verdict: *verdict,
+            winner: best.is_some_and(|(w, _)| w == i),
+            reason,
+        });
+    }
+
+    if let Some((w, _)) = best
+        && let Some(values) = capture_values(&routes[w], &host)
+    {
+        out.hit = Some(expand_hit(&routes[w], &values));
+        out.captures = values.into_iter().collect();
+    }
+    out
 }
 
 // ---------------------------------------------------------------------------
@@ -681,6 +1395,7 @@ mod tests {
                 path: None,
                 target: "127.0.0.1:{port}".into(),
                 headers: None,
+                ..Default::default()
             },
             RouteConfig {
                 name: "host-double-dash-port".into(),
@@ -692,6 +1407,7 @@ mod tests {
                     h.insert("Host".into(), "{host}".into());
                     h
                 }),
+                ..Default::default()
             },
             RouteConfig {
                 name: "subdomain-hoisting".into(),
@@ -703,6 +1419,7 @@ mod tests {
                     h.insert("Host".into(), "{prefix}".into());
                     h
                 }),
+                ..Default::default()
             },
             RouteConfig {
                 name: "direct-forward".into(),
@@ -714,6 +1431,7 @@ mod tests {
                     h.insert("Host".into(), "{host}".into());
                     h
                 }),
+                ..Default::default()
             },
         ];
         compile(configs).expect("compile default routes")
@@ -842,6 +1560,7 @@ mod tests {
                 path: None,
                 target: "first-target".into(),
                 headers: None,
+                ..Default::default()
             },
             RouteConfig {
                 name: "second".into(),
@@ -849,6 +1568,7 @@ mod tests {
                 path: None,
                 target: "second-target".into(),
                 headers: None,
+                ..Default::default()
             },
         ])
         .unwrap();
@@ -865,6 +1585,7 @@ mod tests {
             path: None,
             target: "x".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap_err();
         match err {
@@ -881,6 +1602,7 @@ mod tests {
             path: None,
             target: "x".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap_err();
         match err {
@@ -899,6 +1621,7 @@ mod tests {
             path: None,
             target: "y".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap_err();
         match err {
@@ -915,6 +1638,7 @@ mod tests {
             path: None,
             target: "{z}".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap_err();
         match err {
@@ -934,6 +1658,7 @@ mod tests {
             path: None,
             target: "x".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap_err();
         match err {
@@ -979,6 +1704,7 @@ mod tests {
             path: None,
             target: "{host}:80".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap();
         let hit =
@@ -995,6 +1721,7 @@ mod tests {
             path: None,
             target: "{host}:80".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap();
         let hit = match_host_with_domain(&routes, "myserver.other.com", Some("fbi.example.com"));
@@ -1009,6 +1736,7 @@ mod tests {
             path: None,
             target: "{upstream}:80".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap();
 
@@ -1031,6 +1759,7 @@ mod tests {
             path: None,
             target: "{upstream}:443".into(),
             headers: Some(HashMap::from([("Host".into(), "{upstream}".into())])),
+            ..Default::default()
         }])
         .unwrap();
         let hit = match_host(&routes, "api.example.com.fbi.com").unwrap();
@@ -1060,6 +1789,7 @@ routes:
             path: None,
             target: "{name}".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap();
         assert!(match_host(&routes, "my-service.example").is_some());
@@ -1117,6 +1847,7 @@ routes:
                     path: Some("/".into()),
                     target: "localhost:3001".into(),
                     headers: None,
+                    ..Default::default()
                 },
                 RouteConfig {
                     name: "vscode".into(),
@@ -1124,6 +1855,7 @@ routes:
                     path: Some("/_vscode/".into()),
                     target: "localhost:9999".into(),
                     headers: None,
+                    ..Default::default()
                 },
             ],
             "web-code",
@@ -1162,6 +1894,7 @@ routes:
                 path: None,
                 target: "localhost:1".into(),
                 headers: None,
+                ..Default::default()
             },
             RouteConfig {
                 name: "rooted".into(),
@@ -1169,6 +1902,7 @@ routes:
                 path: Some("/".into()),
                 target: "localhost:2".into(),
                 headers: None,
+                ..Default::default()
             },
         ])
         .unwrap();
@@ -1176,6 +1910,96 @@ routes:
         assert_eq!(hit.target, "localhost:2");
     }
 
+    #[test]
+    fn explain_reports_every_verdict_and_agrees_with_match() {
+        let mut routes = default_routes();
+        routes.extend(web_code_routes());
+        let ex = explain_request(&routes, "Admin.App.fbi.com:443", "/", Some("fbi.com"), false);
+        assert_eq!(ex.host, "admin.app.fbi.com");
+        assert!(ex.domain_allowed);
+        assert_eq!(ex.hit, match_request(&routes, "admin.app.fbi.com", "/", Some("fbi.com")));
+        let winner: Vec<_> = ex.candidates.iter().filter(|c| c.winner).collect();
+        assert_eq!(winner.len(), 1);
+        assert_eq!(winner[0].name, "subdomain-hoisting");
+        assert_eq!(ex.captures["prefix"], "admin");
+        let root = ex.candidates.iter().find(|c| c.name == "root").unwrap();
+        assert_eq!(root.verdict, Verdict::HostMismatch);
+
+        // At the apex only explicit-path rules are eligible.
+        let ex = explain_request(&routes, "fbi.com", "/_vscode/x", Some("fbi.com"), true);
+        let by_name = |n: &str| ex.candidates.iter().find(|c| c.name == n).unwrap().clone();
+        assert_eq!(by_name("vscode").verdict, Verdict::Matched { priority: 9 });
+        assert!(by_name("vscode").winner);
+        assert_eq!(by_name("root").verdict, Verdict::Matched { priority: 1 });
+        assert!(by_name("root").reason.contains("'web-code/vscode' has priority 9"));
+        assert_eq!(ex.hit.unwrap().target, "localhost:9999");
+
+        let ex = explain_request(&routes, "fbi.org", "/", Some("fbi.com"), false);
+        assert!(!ex.domain_allowed);
+        assert!(ex.candidates.is_empty() && ex.hit.is_none());
+    }
+
+    #[test]
+    fn explain_serializes_verdict_inline() {
+        let routes = web_code_routes();
+        let ex = explain_request(&routes, "fbi.com", "/x", None, false);
+        let v = serde_json::to_value(&ex.candidates[1]).unwrap();
+        assert_eq!(v["result"], "path_mismatch");
+        assert_eq!(v["match"], "fbi.com");
+        let v = serde_json::to_value(&ex.candidates[0]).unwrap();
+        assert_eq!(v["result"], "matched");
+        assert_eq!(v["priority"], 1);
+    }
+
+    #[test]
+    fn embedded_tests_parse_and_run_against_merged_set() {
+        let yaml = r#"
+routes:
+  - name: web
+    match: fbi.com
+    path: /
+    target: localhost:3000
+tests:
+  - host: fbi.com
+    path: /docs
+    expect: { route: web-code/web, target: "localhost:3000", host_header: localhost }
+  - host: example.org
+    expect: reject
+  - host: fbi.com
+    method: CONNECT
+    expect: landing
+  - host: 3000.fbi.com
+    expect: { route: web }
+"#;
+        let parsed = parse_yaml(yaml).unwrap();
+        assert_eq!(parsed.tests.len(), 4);
+        assert_eq!(parsed.tests[1].expect, Expect::Outcome(Outcome::Reject));
+
+        let mut merged = default_routes();
+        merged.extend(compile_in_namespace(parsed.routes.clone(), "web-code").unwrap());
+        let tests = parsed.tests.iter().enumerate().map(|(i, t)| ("app.yaml", i, t));
+        let failures = run_tests(&merged, tests, Some("fbi.com"));
+        // CONNECT carries no path, and `path: /` still claims it.
+        assert_eq!(failures.len(), 2, "{:?}", failures);
+        assert_eq!((failures[0].label.as_str(), failures[0].index), ("app.yaml", 2));
+        assert!(failures[0].to_string().starts_with("app.yaml test #3 (CONNECT fbi.com/): expected landing, got 'web-code/web'"));
+        assert!(failures[1].reason.contains("expected route 'web', got 'default/port-as-host' -> 127.0.0.1:3000"));
+
+        // Unknown keys are typos, not silently-passing tests.
+        assert!(parse_yaml("routes: []\ntests:\n  - host: a\n    expect: { rout: x }\n").is_err());
+    }
+
+    #[test]
+    fn decide_serves_landing_only_at_the_apex() {
+        let routes = default_routes();
+        assert_eq!(decide(&routes, "fbi.com:443", "/", Some("fbi.com")), Decision::Landing);
+        assert_eq!(decide(&routes, "other.org", "/", Some("fbi.com")), Decision::Reject);
+        match decide(&routes, "api--3001.fbi.com", "", Some("fbi.com")) {
+            Decision::Hit(hit) => assert_eq!((hit.target.as_str(), hit.upstream_host().as_str()), ("api:3001", "api")),
+            other => panic!("expected a hit, got {:?}", other),
+        }
+    }
+
     #[test]
     fn namespace_is_tagged_on_compiled_route() {
         let routes = web_code_routes();
@@ -1186,11 +2010,44 @@ routes:
             path: None,
             target: "{host}:80".into(),
             headers: None,
+            ..Default::default()
         }])
         .unwrap();
         assert_eq!(bundled[0].namespace, "default");
     }
 
+    #[test]
+    fn access_log_policy_parses_and_validates() {
+        let yaml = r#"
+routes:
+  - name: quiet
+    match: "health.{domain}"
+    target: "localhost:9000"
+    access_log:
+      sample: 0.1
+  - name: silent
+    match: "{host}.{domain}"
+    target: "{host}:80"
+    access_log:
+      enabled: false
+"#;
+        let routes = compile(parse_yaml(yaml).unwrap().routes).unwrap();
+        assert_eq!(routes[0].access_log, AccessLogPolicy { enabled: true, sample: 0.1 });
+        assert!(!routes[1].access_log.enabled);
+        let hit = match_host(&routes, "health.fbi.com").unwrap();
+        assert_eq!(hit.access_log.sample, 0.1);
+
+        let err = compile(vec![RouteConfig {
+            name: "bad".into(),
+            r#match: "x".into(),
+            target: "y".into(),
+            access_log: Some(AccessLogPolicy { enabled: true, sample: 2.0 }),
+            ..Default::default()
+        }])
+        .unwrap_err();
+        assert!(matches!(err, CompileError::InvalidField { .. }), "{err:?}");
+    }
+
     // ----- apex landing protection (regression: codex P1) -----
 
     #[test]
@@ -1208,6 +2065,135 @@ routes:
         assert_eq!(sub.unwrap().target, "localhost:3000");
     }
 
+    #[test]
+    fn priority_orders_ties_and_defaults_can_be_disabled() {
+        let parsed = parse_yaml(include_str!("../routes.yaml")).unwrap();
+        let bundled = compile(parsed.rules().unwrap()).unwrap();
+        let frag = parse_yaml("priority: 5\nroutes:\n  - name: app\n    match: \"app.{domain}\"\n    target: localhost:4000\n  - name: low\n    match: \"low.{domain}\"\n    target: localhost:4001\n    priority: -1\n").unwrap();
+        assert_eq!(frag.rules().unwrap().iter().map(|r| r.priority).collect::<Vec<_>>(), vec![Some(5), Some(-1)]);
+        let mut merged = bundled.clone();
+        merged.extend(compile_in_namespace(frag.rules().unwrap(), "app").unwrap());
+
+        // Unsorted, the bundled subdomain rule claims app.fbi.com first.
+        let hit = match_request(&merged, "app.fbi.com", "/", Some("fbi.com")).unwrap();
+        assert_eq!(hit.namespace, "default");
+        sort_by_priority(&mut merged);
+        assert_eq!((merged[0].name.as_str(), merged.last().unwrap().name.as_str()), ("app", "low"));
+        let hit = match_request(&merged, "app.fbi.com", "/", Some("fbi.com")).unwrap();
+        assert_eq!((hit.namespace.as_str(), hit.target.as_str()), ("app", "localhost:4000"));
+        let ex = explain_request(&merged, "low.fbi.com", "/", Some("fbi.com"), false);
+        let low = ex.candidates.iter().find(|c| c.name == "low").unwrap();
+        assert!(low.reason.contains("has rule priority 0 (vs -1)"), "{}", low.reason);
+
+        let unknown = disable_defaults(&mut merged, &["subdomain-hoisting", "nope"]);
+        assert_eq!(unknown, vec!["nope"]);
+        assert!(!merged.iter().any(|r| r.name == "subdomain-hoisting"));
+        assert_eq!(merged.len(), bundled.len() + 1);
+    }
+
+    #[test]
+    fn secret_headers_are_sent_but_never_shown() {
+        let yaml = r#"
+routes:
+  - name: api
+    match: "{svc}.{domain}"
+    target: "localhost:3000"
+    headers:
+      Authorization: "Bearer ${env:CARGO_PKG_NAME}"
+      X-Service: "{svc}"
+"#;
+        let routes = compile(parse_yaml(yaml).unwrap().rules().unwrap()).unwrap();
+        assert_eq!(routes[0].header_templates["Authorization"], "Bearer ${env:CARGO_PKG_NAME}");
+        let hit = match_host(&routes, "api.fbi.com").unwrap();
+        assert_eq!(hit.other_headers["Authorization"], "Bearer ${env:CARGO_PKG_NAME}");
+        let sent: HashMap<&str, &str> = hit.upstream_headers().collect();
+        assert_eq!(sent["Authorization"], "Bearer fbi-proxy");
+        assert_eq!(sent["X-Service"], "api");
+        assert!(!format!("{:?}", hit).contains("Bearer fbi-proxy"));
+        assert!(!serde_json::to_string(&hit).unwrap().contains("Bearer fbi-proxy"));
+
+        let bad = |headers: &str, target: &str| {
+            let yaml = format!("routes:\n  - name: x\n    match: \"{{h}}.{{domain}}\"\n    target: \"{}\"\n    headers:\n      {}\n", target, headers);
+            compile(parse_yaml(&yaml).unwrap().rules().unwrap()).unwrap_err().to_string()
+        };
+        let err = bad("X-Token: \"${file:/nonexistent/fbi-proxy-secret}\"", "{h}:80");
+        assert!(err.starts_with("route 'x': secret ${file:/nonexistent/fbi-proxy-secret} in header 'X-Token': read "), "{err}");
+        let err = bad("Host: \"${env:CARGO_PKG_NAME}\"", "{h}:80");
+        assert!(err.contains("invalid headers.Host: secret references are only allowed"), "{err}");
+        assert!(bad("X: y", "${env:CARGO_PKG_NAME}:80").contains("invalid target"));
+    }
+
+    #[test]
+    fn variables_expand_from_vars_then_env_then_default_but_headers_skip_env() {
+        let yaml = r#"
+vars:
+  api_port: "4000"
+routes:
+  - name: api
+    match: "${CARGO_PKG_NAME}.{domain}"
+    path: /${prefix:-v1}
+    target: "localhost:${api_port}"
+    headers:
+      X-Port: "${api_port}"
+      X-Raw: "$${kept} and $5"
+"#;
+        let file = parse_yaml(yaml).unwrap();
+        let rule = &file.rules().unwrap()[0];
+        assert_eq!(rule.r#match, "fbi-proxy.{domain}");
+        assert_eq!(rule.target, "localhost:4000");
+        assert_eq!(rule.path.as_deref(), Some("/v1"));
+        let headers = rule.headers.as_ref().unwrap();
+        assert_eq!(headers["X-Port"], "4000");
+        assert_eq!(headers["X-Raw"], "${kept} and $5");
+        assert_eq!(var_refs("${a}$${b}${c:-d}$e"), [("a", None), ("c", Some("d"))]);
+
+        // Headers don't read the environment: `GET /rules` shows them.
+        let in_header = parse_yaml("routes:\n  - name: x\n    match: a\n    target: b\n    headers: { X-Pkg: \"${CARGO_PKG_NAME}\" }\n").unwrap();
+        let err = in_header.rules().unwrap_err();
+        assert!(err.to_string().contains("through ${env:CARGO_PKG_NAME}"), "{err}");
+        // The source file is left untouched, so it round-trips as written.
+        assert_eq!(file.routes[0].target, "localhost:${api_port}");
+
+        let missing = parse_yaml("routes:\n  - name: x\n    match: \"{h}.${FBI_PROXY_TEST_UNSET_VAR}\"\n    target: y\n").unwrap();
+        let err = missing.rules().unwrap_err();
+        assert!(matches!(&err, CompileError::UndefinedVariable { name, location, .. }
+            if name == "FBI_PROXY_TEST_UNSET_VAR" && location == "match pattern"), "{err:?}");
+        assert!(err.to_string().contains("${FBI_PROXY_TEST_UNSET_VAR} in match pattern is not set"), "{err}");
+    }
+
+    #[test]
+    fn launched_rules_expand_vars_and_need_a_fixed_target() {
+        let yaml = r#"
+vars:
+  tools: /opt/tools
+routes:
+  - name: wiki
+    match: "wiki.{domain}"
+    target: "localhost:4100"
+    launch:
+      command: "${tools}/wiki --port $PORT"
+      cwd: "${tools}"
+      env: { WIKI_HOME: "${tools}/data" }
+"#;
+        let rules = parse_yaml(yaml).unwrap().rules().unwrap();
+        let launch = rules[0].launch.as_ref().unwrap();
+        assert_eq!(launch.command.to_string(), "/opt/tools/wiki --port $PORT");
+        assert_eq!((launch.cwd.as_deref(), launch.env["WIKI_HOME"].as_str()), (Some("/opt/tools"), "/opt/tools/data"));
+        let routes = compile(rules).unwrap();
+        let hit = match_host(&routes, "wiki.fbi.com").unwrap();
+        assert_eq!(hit.launch.as_ref().map(|l| l.addr.as_str()), Some("localhost:4100"));
+
+        let err = compile(vec![RouteConfig {
+            name: "each-port".into(),
+            r#match: "{port:int}.{domain}".into(),
+            target: "localhost:{port}".into(),
+            launch: Some(serde_yaml::from_str("command: serve").unwrap()),
+            ..Default::default()
+        }])
+        .unwrap_err();
+        assert!(matches!(&err, CompileError::InvalidField { field, .. } if field == "target"), "{err:?}");
+    }
+
     #[test]
     fn explicit_path_rule_owns_apex() {
         // The web-code lab's `match: fbi.com, path: /` rule explicitly
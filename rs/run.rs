@@ -0,0 +1,71 @@
+//! `fbi-proxy run <name> -- <command>`: serve a dev server at
+//! `name.{domain}` for as long as it runs.
+//!
+//! The wrapper picks a free loopback port, hands it to the child as
+//! `PORT`, and leases namespace `name` through the running proxy's admin
+//! API (see [`crate::lease`]) with one rule sending `name.{domain}` to
+//! that port. It renews the lease while the child runs and removes the
+//! namespace when the child exits, so even a wrapper killed outright
+//! only leaves the route behind until the lease lapses. This module
+//! holds the parts that don't touch processes; the supervision loop is
+//! in the binary.
+
+use std::net::{Ipv4Addr, TcpListener};
+
+use crate::routes::{self, RoutesFile};
+
+/// Lease TTL unless `--ttl` says otherwise.
+pub const DEFAULT_TTL: &str = "30s";
+
+/// Whether `name` works as both a namespace and a host label: 1-63
+/// characters of `A-Za-z0-9-`, not starting or ending with `-`.
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=63).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// A loopback port nothing is listening on right now.
+pub fn free_port() -> Result<u16, String> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| format!("find a free port: {}", e))?;
+    listener.local_addr().map(|a| a.port()).map_err(|e| format!("find a free port: {}", e))
+}
+
+/// The fragment `run` leases: `name.{domain}` → `localhost:port`,
+/// ahead of the bundled `{host}.{domain}` rule.
+pub fn routes_file(name: &str, port: u16) -> Result<RoutesFile, String> {
+    let yaml = format!("priority: 1\nroutes:\n  - name: {}\n    match: \"{}.{{domain}}\"\n    target: localhost:{}\n", name, name, port);
+    routes::parse_yaml(&yaml).map_err(|e| format!("routes for '{}': {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::confd;
+
+    #[test]
+    fn names_are_host_labels_and_route_to_the_port() {
+        for good in ["web", "my-app", "App2"] {
+            assert!(is_valid_name(good), "{good}");
+        }
+        for bad in ["", "-web", "web-", "team/web", "my_app", "a.b", &"x".repeat(64)] {
+            assert!(!is_valid_name(bad), "{bad}");
+        }
+
+        let fragment = confd::FragmentFile {
+            namespace: "my-app".into(),
+            path: "my-app.yaml".into(),
+            parsed: routes_file("my-app", 4123),
+            warnings: Vec::new(),
+        };
+        let mut loader = confd::Loader::new(include_str!("../routes.yaml")).unwrap();
+        let merged = loader.load(&[fragment], Some("fbi.com"));
+        let tests = routes::parse_yaml("routes: []\ntests:\n  - host: my-app.fbi.com\n    expect: { route: my-app/my-app, target: \"localhost:4123\" }\n")
+            .unwrap()
+            .tests;
+        let failures = routes::run_tests(&merged, tests.iter().enumerate().map(|(i, t)| ("run", i, t)), Some("fbi.com"));
+        assert!(failures.is_empty(), "{failures:?}");
+        assert_ne!(free_port().unwrap(), 0);
+    }
+}
@@ -0,0 +1,822 @@
+//! Routes file schema versions.
+//!
+//! Version 2 gives each rule a matcher, an action and its policies:
+//!
+//! ```yaml
+//! version: 2
+//! routes:
+//!   - name: api
+//!     match: { host: "api.{domain}", path: /v1 }
+//!     action:
+//!       proxy: "localhost:3000"
+//!       headers: { Host: api }
+//!     policy: { priority: 5, access_log: { sample: 0.1 } }
+//! ```
+//!
+//! Version 1 — `match`, `path`, `target`, `headers`, `priority` and
+//! `access_log` side by side on the rule — still loads: [`parse`] migrates
+//! it in memory and reports a deprecation [`Finding`], and `fbi-proxy
+//! schema --migrate FILE` prints the v2 rewrite. A file without
+//! `version:` is read as v1 if any rule has a plain-string `match`, else
+//! as v2. Everything downstream works on the flat [`RoutesFile`];
+//! serializing one always writes v2.
+//!
+//! serde skips keys it doesn't know, so a typo like `header:` would
+//! silently do nothing. [`parse`] reports each skipped key, with its
+//! position, as a finding; [`load`] in strict mode refuses the file.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::check;
+use crate::launch::LaunchConfig;
+use crate::routes::{AccessLogPolicy, RouteConfig, RouteTest, RoutesFile};
+
+/// The newest schema version, the one files are written in.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// A version 2 routes file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FileV2 {
+    #[serde(default = "current_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable_defaults: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    pub routes: Vec<RouteV2>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<RouteTest>,
+}
+
+/// One version 2 rule.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RouteV2 {
+    pub name: String,
+    #[serde(rename = "match")]
+    pub matcher: Matcher,
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Policy::is_default")]
+    pub policy: Policy,
+}
+
+/// Which requests a rule takes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Matcher {
+    /// Host pattern, e.g. `"{port:int}.{domain}"`.
+    pub host: String,
+    /// Path prefix; the longest matching prefix wins.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// What a rule does with a request it takes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Action {
+    /// Forward to this target template.
+    pub proxy: String,
+    /// Header templates for the upstream request.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Start this command on demand to serve `proxy`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch: Option<LaunchConfig>,
+}
+
+/// Per-rule policies.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Policy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<AccessLogPolicy>,
+}
+
+impl Policy {
+    fn is_default(&self) -> bool {
+        *self == Policy::default()
+    }
+}
+
+fn current_version() -> u32 {
+    CURRENT_VERSION
+}
+
+impl From<RouteV2> for RouteConfig {
+    fn from(r: RouteV2) -> Self {
+        RouteConfig {
+            name: r.name,
+            r#match: r.matcher.host,
+            path: r.matcher.path,
+            target: r.action.proxy,
+            headers: (!r.action.headers.is_empty()).then(|| r.action.headers.into_iter().collect()),
+            access_log: r.policy.access_log,
+            priority: r.policy.priority,
+            launch: r.action.launch,
+        }
+    }
+}
+
+impl From<RouteConfig> for RouteV2 {
+    fn from(r: RouteConfig) -> Self {
+        RouteV2 {
+            name: r.name,
+            matcher: Matcher { host: r.r#match, path: r.path },
+            action: Action {
+                proxy: r.target,
+                headers: r.headers.unwrap_or_default().into_iter().collect(),
+                launch: r.launch,
+            },
+            policy: Policy { priority: r.priority, access_log: r.access_log },
+        }
+    }
+}
+
+impl From<FileV2> for RoutesFile {
+    fn from(f: FileV2) -> Self {
+        RoutesFile {
+            version: f.version,
+            priority: f.priority,
+            disable_defaults: f.disable_defaults,
+            vars: f.vars,
+            include: f.include,
+            routes: f.routes.into_iter().map(RouteConfig::from).collect(),
+            tests: f.tests,
+        }
+    }
+}
+
+impl From<RoutesFile> for FileV2 {
+    fn from(f: RoutesFile) -> Self {
+        FileV2 {
+            version: CURRENT_VERSION,
+            priority: f.priority,
+            disable_defaults: f.disable_defaults,
+            vars: f.vars,
+            include: f.include,
+            routes: f.routes.into_iter().map(RouteV2::from).collect(),
+            tests: f.tests,
+        }
+    }
+}
+
+/// One step of a path into a document: a map key or a list index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Renders as `routes[0].header`.
+pub struct KeyPath<'a>(pub &'a [Step]);
+
+impl fmt::Display for KeyPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.0.iter().enumerate() {
+            match step {
+                Step::Key(k) if i == 0 => f.write_str(k)?,
+                Step::Key(k) => write!(f, ".{}", k)?,
+                Step::Index(n) => write!(f, "[{}]", n)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    /// A key no version of the schema knows; ignored unless strict.
+    UnknownKey,
+    /// The file uses a version that still loads but is on its way out.
+    Deprecated,
+}
+
+/// Something about a file that loaded but deserves a look, at a 1-based
+/// `line` / `col`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {} column {}", self.message, self.line, self.col)
+    }
+}
+
+/// How a routes file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// By file extension: `.yaml` / `.yml`, `.json` or `.toml`.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    /// By media type, e.g. a `Content-Type` (parameters are ignored).
+    pub fn from_media_type(media_type: &str) -> Option<Format> {
+        let essence = media_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => Some(Format::Yaml),
+            "application/json" | "text/json" => Some(Format::Json),
+            "application/toml" | "text/toml" | "text/x-toml" => Some(Format::Toml),
+            e if e.ends_with("+yaml") => Some(Format::Yaml),
+            e if e.ends_with("+json") => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    /// The one of `offered` an `Accept` header prefers: highest `q`
+    /// first, then the order listed. A wildcard picks `offered[0]`.
+    /// `None` if it accepts none of them.
+    pub fn negotiate(accept: &str, offered: &[Format]) -> Option<Format> {
+        let mut ranges: Vec<(&str, f32)> = accept
+            .split(',')
+            .map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let media_type = parts.next().unwrap_or("");
+                let q = parts.find_map(|p| p.strip_prefix("q=")).and_then(|q| q.parse().ok()).unwrap_or(1.0);
+                (media_type, q)
+            })
+            .filter(|&(_, q)| q > 0.0)
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges.iter().find_map(|&(media_type, _)| match media_type {
+            "*/*" | "application/*" | "text/*" => offered.first().copied(),
+            m => Format::from_media_type(m).filter(|f| offered.contains(f)),
+        })
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Format::Yaml => "application/yaml",
+            Format::Json => "application/json",
+            Format::Toml => "application/toml",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Yaml => "yaml",
+            Format::Json => "json",
+            Format::Toml => "toml",
+        }
+    }
+
+    /// Write `value` in this format.
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            Format::Json => serde_json::to_string_pretty(value).map(|s| s + "\n").map_err(|e| e.to_string()),
+            Format::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Why a routes file didn't parse, and where, if the parser said.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// 1-based line and column.
+    pub at: Option<(usize, usize)>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)?;
+        match self.at {
+            Some((line, col)) => write!(f, " at line {} column {}", line, col),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// serde_yaml and serde_json append ` at line N column M` to messages
+/// that have a position; it is kept separately here.
+fn without_position(message: String, known: bool) -> String {
+    match message.rfind(" at line ") {
+        Some(i) if known => message[..i].to_string(),
+        _ => message,
+    }
+}
+
+impl From<serde_yaml::Error> for ParseError {
+    fn from(e: serde_yaml::Error) -> Self {
+        let at = e.location().map(|l| (l.line(), l.column()));
+        ParseError { message: without_position(e.to_string(), at.is_some()), at }
+    }
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        let at = (e.line() > 0).then(|| (e.line(), e.column()));
+        ParseError { message: without_position(e.to_string(), at.is_some()), at }
+    }
+}
+
+impl ParseError {
+    fn toml(e: toml::de::Error, src: &str) -> Self {
+        ParseError { message: e.message().to_string(), at: e.span().map(|s| line_col(src, s.start)) }
+    }
+}
+
+/// 1-based line and column of byte `offset` in `src`.
+fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// A parsed routes file and what [`parse`] noticed about it.
+#[derive(Debug, Clone)]
+pub struct Parsed {
+    pub file: RoutesFile,
+    pub findings: Vec<Finding>,
+}
+
+/// Parse a routes file of any supported version.
+pub fn parse(src: &str, format: Format) -> Result<Parsed, ParseError> {
+    let mut ignored = Vec::new();
+    let version = version_of(src, format);
+    let file: RoutesFile = match version {
+        1 => deserialize(src, format, &mut ignored)?,
+        2 => deserialize::<FileV2>(src, format, &mut ignored)?.into(),
+        v => {
+            let at = locate(src, format, &[Step::Key("version".into())]);
+            return Err(ParseError { message: format!("unsupported version {} (expected 1 or 2)", v), at: Some(at) });
+        }
+    };
+    let mut findings: Vec<Finding> = ignored
+        .iter()
+        .map(|path| {
+            let (line, col) = locate(src, format, path);
+            let message = format!("unknown key `{}`", KeyPath(path));
+            Finding { kind: FindingKind::UnknownKey, line, col, message }
+        })
+        .collect();
+    if version == 1 {
+        let (line, col) = locate(src, format, &[Step::Key("version".into())]);
+        let message = "routes file version 1 is deprecated; `fbi-proxy schema --migrate FILE` prints it as version 2".to_string();
+        findings.insert(0, Finding { kind: FindingKind::Deprecated, line, col, message });
+    }
+    Ok(Parsed { file, findings })
+}
+
+/// [`parse`] for the loaders: in `strict` mode an unknown key is an
+/// error. Returns the file and the findings that didn't fail it.
+pub fn load(src: &str, format: Format, strict: bool) -> Result<(RoutesFile, Vec<Finding>), String> {
+    let Parsed { file, findings } = parse(src, format).map_err(|e| e.to_string())?;
+    if strict {
+        let unknown: Vec<String> =
+            findings.iter().filter(|f| f.kind == FindingKind::UnknownKey).map(ToString::to_string).collect();
+        if !unknown.is_empty() {
+            return Err(unknown.join("; "));
+        }
+    }
+    Ok((file, findings))
+}
+
+/// The version `src` is written in: its `version:`, else guessed from
+/// the shape of its rules. Anything unreadable is left to the v1 parser
+/// to report.
+fn version_of(src: &str, format: Format) -> u32 {
+    #[derive(Deserialize)]
+    struct Probe {
+        version: Option<u32>,
+        #[serde(default)]
+        routes: Vec<Value>,
+    }
+    let probe: Option<Probe> = match format {
+        Format::Yaml => serde_yaml::from_str(src).ok(),
+        Format::Json => serde_json::from_str(src).ok(),
+        Format::Toml => toml::from_str(src).ok(),
+    };
+    let Some(probe) = probe else { return 1 };
+    probe.version.unwrap_or_else(|| {
+        let v1 = probe.routes.iter().any(|r| r.get("match").is_some_and(Value::is_string));
+        if v1 { 1 } else { CURRENT_VERSION }
+    })
+}
+
+fn deserialize<T: DeserializeOwned>(src: &str, format: Format, ignored: &mut Vec<Vec<Step>>) -> Result<T, ParseError> {
+    let record = |path: serde_ignored::Path| {
+        let mut steps = Vec::new();
+        steps_of(&path, &mut steps);
+        ignored.push(steps);
+    };
+    match format {
+        Format::Yaml => Ok(serde_ignored::deserialize(serde_yaml::Deserializer::from_str(src), record)?),
+        Format::Json => {
+            let mut de = serde_json::Deserializer::from_str(src);
+            let value = serde_ignored::deserialize(&mut de, record)?;
+            de.end()?;
+            Ok(value)
+        }
+        Format::Toml => {
+            serde_ignored::deserialize(toml::Deserializer::new(src), record).map_err(|e| ParseError::toml(e, src))
+        }
+    }
+}
+
+fn steps_of(path: &serde_ignored::Path, out: &mut Vec<Step>) {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => {}
+        Path::Seq { parent, index } => {
+            steps_of(parent, out);
+            out.push(Step::Index(*index));
+        }
+        Path::Map { parent, key } => {
+            steps_of(parent, out);
+            out.push(Step::Key(key.clone()));
+        }
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => {
+            steps_of(parent, out)
+        }
+    }
+}
+
+/// Position of what `path` names in `src`. YAML is scanned by
+/// structure; in JSON and TOML each key of the path is looked up in turn
+/// after the previous one, which lands on the right line for files laid
+/// out one key per line.
+fn locate(src: &str, format: Format, path: &[Step]) -> (usize, usize) {
+    if format == Format::Yaml {
+        return check::locate_path(src, path);
+    }
+    let mut at = None;
+    let mut from = 0;
+    for step in path {
+        let Step::Key(key) = step else { continue };
+        let Some(i) = find_key(&src[from..], key, format) else { break };
+        at = Some(from + i);
+        from += i + key.len();
+    }
+    at.map_or((1, 1), |i| line_col(src, i))
+}
+
+/// Byte offset of `key` written as a key (not a value) in JSON or TOML.
+fn find_key(src: &str, key: &str, format: Format) -> Option<usize> {
+    src.match_indices(key).map(|(i, _)| i).find(|&i| {
+        let (before, rest) = (&src[..i], &src[i + key.len()..]);
+        match format {
+            Format::Json => before.ends_with('"') && rest.strip_prefix('"').is_some_and(|r| r.trim_start().starts_with(':')),
+            _ => {
+                (before.is_empty() || before.ends_with([' ', '\t', '\n', '.', '[', '{', ',']))
+                    && rest.trim_start_matches([' ', '\t']).starts_with(['=', '.', ']'])
+            }
+        }
+    })
+}
+
+/// A single-rule body in `format` (e.g. for the admin API), as a
+/// generic value to patch or read with [`route_from_value`].
+pub fn parse_value(src: &str, format: Format) -> Result<Value, ParseError> {
+    match format {
+        Format::Yaml => Ok(serde_yaml::from_str(src)?),
+        Format::Json => Ok(serde_json::from_str(src)?),
+        Format::Toml => toml::from_str(src).map_err(|e| ParseError::toml(e, src)),
+    }
+}
+
+/// One rule in the current version. Unlike a whole file, a key the
+/// schema doesn't know is always an error.
+pub fn route_from_value(value: Value) -> Result<RouteConfig, String> {
+    let mut unknown = Vec::new();
+    let route: RouteV2 = serde_ignored::deserialize(value, |path| {
+        let mut steps = Vec::new();
+        steps_of(&path, &mut steps);
+        unknown.push(format!("unknown key `{}`", KeyPath(&steps)));
+    })
+    .map_err(|e| e.to_string())?;
+    if !unknown.is_empty() {
+        return Err(unknown.join("; "));
+    }
+    Ok(route.into())
+}
+
+/// `route` in the current version, as [`route_from_value`] reads it.
+pub fn route_to_value(route: &RouteConfig) -> Value {
+    serde_json::to_value(RouteV2::from(route.clone())).unwrap_or(Value::Null)
+}
+
+/// Apply a JSON merge patch (RFC 7396) to `target`: objects merge key by
+/// key, `null` removes a key, anything else replaces.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else { return };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// The current version as a JSON Schema, for editors (e.g. the YAML
+/// language server's `# yaml-language-server: $schema=` comment).
+pub fn json_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "fbi-proxy routes file",
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["routes"],
+        "properties": {
+            "version": { "const": CURRENT_VERSION },
+            "priority": {
+                "type": "integer",
+                "description": "Default priority for this file's rules; higher is evaluated first."
+            },
+            "disable_defaults": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Bundled default rules to switch off while this fragment is loaded."
+            },
+            "vars": {
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+                "description": "Values for ${NAME} in this file's rules."
+            },
+            "include": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Routes files (or globs) merged in ahead of this one, relative to it."
+            },
+            "routes": { "type": "array", "items": { "$ref": "#/$defs/route" } },
+            "tests": { "type": "array", "items": { "$ref": "#/$defs/test" } }
+        },
+        "$defs": {
+            "route": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["name", "match", "action"],
+                "properties": {
+                    "name": { "type": "string" },
+                    "match": { "$ref": "#/$defs/match" },
+                    "action": { "$ref": "#/$defs/action" },
+                    "policy": { "$ref": "#/$defs/policy" }
+                }
+            },
+            "match": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["host"],
+                "properties": {
+                    "host": {
+                        "type": "string",
+                        "description": "Host pattern with {name}, {name:int}, {name:slug} or {name:multi} placeholders."
+                    },
+                    "path": { "type": "string", "description": "Path prefix; the longest matching prefix wins." }
+                }
+            },
+            "action": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["proxy"],
+                "properties": {
+                    "proxy": { "type": "string", "description": "Target template, e.g. \"localhost:{port}\"." },
+                    "headers": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" },
+                        "description": "Header templates for the upstream request; values may use ${env:NAME} or ${file:PATH}."
+                    },
+                    "launch": { "$ref": "#/$defs/launch" }
+                }
+            },
+            "launch": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["command"],
+                "description": "Start this command on the first request and stop it when idle; it gets the target's port as PORT.",
+                "properties": {
+                    "command": {
+                        "oneOf": [
+                            { "type": "string", "description": "Run with sh -c." },
+                            { "type": "array", "items": { "type": "string" }, "minItems": 1 }
+                        ]
+                    },
+                    "cwd": { "type": "string", "description": "Working directory; ~/ is the home directory." },
+                    "env": { "type": "object", "additionalProperties": { "type": "string" } },
+                    "ready": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "http": { "type": "string", "description": "Path to GET; ready on any status below 500. Default: a TCP connect." },
+                            "timeout": { "type": "string", "default": "30s" }
+                        }
+                    },
+                    "idle_timeout": { "type": "string", "default": "10m", "description": "Stop after this long without requests, e.g. 15m." }
+                }
+            },
+            "policy": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "priority": { "type": "integer", "description": "Overrides the file-level priority." },
+                    "access_log": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "enabled": { "type": "boolean", "default": true },
+                            "sample": { "type": "number", "minimum": 0, "maximum": 1, "default": 1 }
+                        }
+                    }
+                }
+            },
+            "test": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["host", "expect"],
+                "properties": {
+                    "host": { "type": "string" },
+                    "path": { "type": "string" },
+                    "method": { "type": "string" },
+                    "expect": {
+                        "oneOf": [
+                            { "enum": ["reject", "landing"] },
+                            {
+                                "type": "object",
+                                "additionalProperties": false,
+                                "properties": {
+                                    "route": { "type": "string" },
+                                    "target": { "type": "string" },
+                                    "host_header": { "type": "string" }
+                                }
+                            }
+                        ]
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1: &str = "\
+version: 1
+priority: 2
+routes:
+  - name: api
+    match: \"api.{domain}\"
+    path: /v1
+    target: localhost:3000
+    headers:
+      Host: api
+    priority: 5
+    access_log: { sample: 0.5 }
+";
+
+    #[test]
+    fn v1_migrates_to_v2_and_back_without_loss() {
+        let v1 = parse(V1, Format::Yaml).unwrap();
+        assert_eq!(v1.findings.len(), 1);
+        assert_eq!((v1.findings[0].kind, v1.findings[0].line), (FindingKind::Deprecated, 1));
+
+        let migrated = serde_yaml::to_string(&v1.file).unwrap();
+        assert!(migrated.starts_with("version: 2\n"), "{migrated}");
+        assert!(migrated.contains("  match:\n    host: api.{domain}\n    path: /v1\n"), "{migrated}");
+        let v2 = parse(&migrated, Format::Yaml).unwrap();
+        assert!(v2.findings.is_empty(), "{:?}", v2.findings);
+        assert_eq!(v2.file.routes, v1.file.routes);
+        assert_eq!(v2.file.rules().unwrap(), v1.file.rules().unwrap());
+    }
+
+    #[test]
+    fn versions_are_detected_and_unknown_keys_reported() {
+        // No `version:` — the shape decides.
+        let unversioned = parse("routes:\n  - name: a\n    match: { host: a }\n    action: { proxy: b }\n", Format::Yaml).unwrap();
+        assert!(unversioned.findings.is_empty());
+        assert_eq!(unversioned.file.routes[0].target, "b");
+        assert_eq!(parse("routes:\n  - name: a\n    match: a\n    target: b\n", Format::Yaml).unwrap().findings[0].kind, FindingKind::Deprecated);
+        assert!(parse("version: 3\nroutes: []\n", Format::Yaml).unwrap_err().to_string().contains("unsupported version 3"));
+
+        let typo = "version: 2\nroutes:\n  - name: a\n    match:\n      host: a\n      pth: /x\n    action: { proxy: b }\nrouts: []\n";
+        let found: Vec<String> = parse(typo, Format::Yaml).unwrap().findings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            found,
+            vec!["unknown key `routes[0].match.pth` at line 6 column 7", "unknown key `routs` at line 8 column 1"]
+        );
+        assert!(load(typo, Format::Yaml, false).is_ok());
+        assert_eq!(
+            load(typo, Format::Yaml, true).unwrap_err(),
+            "unknown key `routes[0].match.pth` at line 6 column 7; unknown key `routs` at line 8 column 1"
+        );
+    }
+
+    #[test]
+    fn json_and_toml_files_parse_like_yaml() {
+        let yaml = parse(V1, Format::Yaml).unwrap().file;
+        for format in [Format::Json, Format::Toml] {
+            let text = format.serialize(&yaml).unwrap();
+            let parsed = parse(&text, format).unwrap();
+            assert!(parsed.findings.is_empty(), "{format:?}: {:?}", parsed.findings);
+            assert_eq!(parsed.file.routes, yaml.routes, "{format:?}");
+        }
+
+        let json = "{\n  \"routes\": [\n    {\n      \"name\": \"a\",\n      \"match\": { \"host\": \"a\" },\n      \"action\": { \"proxy\": \"b\", \"header\": {} }\n    }\n  ]\n}\n";
+        let found: Vec<String> = parse(json, Format::Json).unwrap().findings.iter().map(ToString::to_string).collect();
+        assert_eq!(found, vec!["unknown key `routes[0].action.header` at line 6 column 34"]);
+        let toml = "version = 2\n\n[[routes]]\nname = \"a\"\nmatch = { host = \"a\" }\n\n[routes.action]\nproxy = \"b\"\nheader = {}\n";
+        let found: Vec<String> = parse(toml, Format::Toml).unwrap().findings.iter().map(ToString::to_string).collect();
+        assert_eq!(found, vec!["unknown key `routes[0].action.header` at line 9 column 1"]);
+        assert_eq!(parse("version = 2\nroutes = [", Format::Toml).unwrap_err().at, Some((2, 11)));
+        assert_eq!(parse("{\"routes\": 1}", Format::Json).unwrap_err().at, Some((1, 12)));
+    }
+
+    #[test]
+    fn formats_come_from_extensions_and_media_types() {
+        assert_eq!(Format::from_path(Path::new("conf.d/app.yml")), Some(Format::Yaml));
+        assert_eq!(Format::from_path(Path::new("app.toml")), Some(Format::Toml));
+        assert_eq!(Format::from_path(Path::new("app.yaml.bak")), None);
+        assert_eq!(Format::from_media_type("application/json; charset=utf-8"), Some(Format::Json));
+        assert_eq!(Format::from_media_type("application/vnd.fbi+yaml"), Some(Format::Yaml));
+        assert_eq!(Format::from_media_type("application/x-www-form-urlencoded"), None);
+
+        let offered = [Format::Json, Format::Yaml];
+        assert_eq!(Format::negotiate("", &offered), None);
+        assert_eq!(Format::negotiate("*/*", &offered), Some(Format::Json));
+        assert_eq!(Format::negotiate("application/yaml", &offered), Some(Format::Yaml));
+        assert_eq!(Format::negotiate("application/json;q=0.5, text/yaml", &offered), Some(Format::Yaml));
+        assert_eq!(Format::negotiate("application/toml", &offered), None);
+    }
+
+    #[test]
+    fn single_rules_patch_in_the_current_version() {
+        let route = parse(V1, Format::Yaml).unwrap().file.routes.remove(0);
+        let mut value = route_to_value(&route);
+        assert_eq!(value["action"]["proxy"], "localhost:3000");
+        merge_patch(&mut value, &json!({ "action": { "proxy": "localhost:4000", "headers": null }, "policy": { "priority": 1 } }));
+        let patched = route_from_value(value).unwrap();
+        assert_eq!((patched.target.as_str(), patched.headers.is_none(), patched.priority), ("localhost:4000", true, Some(1)));
+        assert_eq!(patched.path, route.path);
+
+        let body = parse_value("name: x\nmatch: { host: a, pth: /x }\naction: { proxy: b }\n", Format::Yaml).unwrap();
+        assert_eq!(route_from_value(body).unwrap_err(), "unknown key `match.pth`");
+        let missing = parse_value("{\"name\": \"x\", \"match\": {\"host\": \"a\"}}", Format::Json).unwrap();
+        assert!(route_from_value(missing).unwrap_err().contains("missing field `action`"));
+    }
+
+    #[test]
+    fn launch_blocks_move_under_the_action() {
+        let v1 = "routes:\n  - name: wiki\n    match: \"wiki.{domain}\"\n    target: localhost:4100\n    launch:\n      command: [npm, run, dev]\n      cwd: ~/tools/wiki\n      env: { NODE_ENV: development }\n      ready: { http: /health }\n      idle_timeout: 15m\n";
+        let file = parse(v1, Format::Yaml).unwrap().file;
+        let launch = file.routes[0].launch.clone().unwrap();
+        assert_eq!((launch.ready.http.as_deref(), launch.idle_timeout.as_deref()), (Some("/health"), Some("15m")));
+        for format in [Format::Yaml, Format::Json, Format::Toml] {
+            let text = format.serialize(&file).unwrap();
+            let parsed = parse(&text, format).unwrap();
+            assert!(parsed.findings.is_empty(), "{format:?}: {:?}", parsed.findings);
+            assert_eq!(parsed.file.routes, file.routes, "{format:?}");
+        }
+        assert_eq!(route_to_value(&file.routes[0])["action"]["launch"]["command"], json!(["npm", "run", "dev"]));
+
+        let typo = "version: 2\nroutes:\n  - name: a\n    match: { host: a }\n    action:\n      proxy: b\n      launch: { command: x, idle: 5m }\n";
+        let found: Vec<String> = parse(typo, Format::Yaml).unwrap().findings.iter().map(|f| f.message.clone()).collect();
+        assert_eq!(found, vec!["unknown key `routes[0].action.launch.idle`"]);
+    }
+
+    #[test]
+    fn json_schema_describes_v2_rules() {
+        let schema = json_schema();
+        assert_eq!(schema["properties"]["version"]["const"], 2);
+        assert_eq!(schema["$defs"]["route"]["required"], json!(["name", "match", "action"]));
+        assert_eq!(schema["$defs"]["action"]["additionalProperties"], false);
+        assert_eq!(schema["$defs"]["action"]["properties"]["launch"]["$ref"], "#/$defs/launch");
+        assert_eq!(schema["$defs"]["launch"]["required"], json!(["command"]));
+    }
+}
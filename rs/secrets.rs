@@ -0,0 +1,138 @@
+//! Secret references in header templates.
+//!
+//! A header value may embed `${env:NAME}` (an environment variable) or
+//! `${file:/run/secrets/x}` (a file's contents, trailing newline
+//! trimmed). They are resolved when the rule compiles — so again on
+//! every reload — into a [`Secret`], and only spliced into the header
+//! sent upstream. Everything else that shows a rule or a hit (`GET
+//! /rules`, `explain`, routes files written back by the admin API) keeps
+//! the reference text, and a `Secret` prints as `[REDACTED]`.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::access_log::REDACTED;
+
+/// A resolved secret value. `Debug` never shows it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(Arc<str>);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value.into())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Where a secret comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source<'a> {
+    Env(&'a str),
+    File(&'a str),
+}
+
+impl Source<'_> {
+    /// Read the value now.
+    pub fn resolve(&self) -> Result<Secret, String> {
+        match self {
+            Source::Env(name) => std::env::var(name)
+                .map(|v| Secret(v.into()))
+                .map_err(|_| format!("environment variable {} is not set", name)),
+            Source::File(path) => std::fs::read_to_string(path)
+                .map(|v| Secret(v.trim_end_matches(['\r', '\n']).into()))
+                .map_err(|e| format!("read {}: {}", path, e)),
+        }
+    }
+}
+
+/// A piece of a template: literal template text, or a secret reference
+/// (with the reference as written).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Ref(&'a str, Source<'a>),
+}
+
+/// Split `template` at its secret references.
+pub fn split(template: &str) -> Vec<Segment<'_>> {
+    let mut out = Vec::new();
+    let mut rest = template;
+    let mut text_start = 0;
+    let mut offset = 0;
+    while let Some(i) = rest.find("${") {
+        let at = offset + i;
+        let Some((len, source)) = reference(&template[at..]) else {
+            offset = at + 2;
+            rest = &template[offset..];
+            continue;
+        };
+        if text_start < at {
+            out.push(Segment::Text(&template[text_start..at]));
+        }
+        out.push(Segment::Ref(&template[at..at + len], source));
+        offset = at + len;
+        text_start = offset;
+        rest = &template[offset..];
+    }
+    if text_start < template.len() {
+        out.push(Segment::Text(&template[text_start..]));
+    }
+    out
+}
+
+/// Whether `template` contains a secret reference.
+pub fn has_refs(template: &str) -> bool {
+    split(template).iter().any(|s| matches!(s, Segment::Ref(..)))
+}
+
+/// `${env:NAME}` or `${file:PATH}` at the start of `s`: its byte length
+/// and source.
+fn reference(s: &str) -> Option<(usize, Source<'_>)> {
+    let body = s.strip_prefix("${")?;
+    let inner = &body[..body.find('}')?];
+    let source = if let Some(name) = inner.strip_prefix("env:") {
+        Source::Env(name)
+    } else {
+        Source::File(inner.strip_prefix("file:")?)
+    };
+    let arg = match source {
+        Source::Env(a) | Source::File(a) => a,
+    };
+    (!arg.is_empty()).then_some((inner.len() + 3, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_references_and_resolves_them_without_printing() {
+        let segs = split("Bearer ${env:CARGO_PKG_NAME}; ${file:/nonexistent/x} {host} ${other}");
+        assert_eq!(
+            segs,
+            vec![
+                Segment::Text("Bearer "),
+                Segment::Ref("${env:CARGO_PKG_NAME}", Source::Env("CARGO_PKG_NAME")),
+                Segment::Text("; "),
+                Segment::Ref("${file:/nonexistent/x}", Source::File("/nonexistent/x")),
+                Segment::Text(" {host} ${other}"),
+            ]
+        );
+        let secret = Source::Env("CARGO_PKG_NAME").resolve().unwrap();
+        assert_eq!(secret.expose(), "fbi-proxy");
+        assert_eq!(format!("{:?}", secret), "[REDACTED]");
+        assert!(Source::File("/nonexistent/x").resolve().unwrap_err().starts_with("read /nonexistent/x: "));
+        assert!(!has_refs("${env:} ${NAME}"));
+    }
+}
@@ -12,12 +12,16 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use rcgen::{CertificateParams, DnType, DistinguishedName, KeyPair, SanType};
+use serde::Serialize;
 use tokio_rustls::TlsAcceptor;
 use tokio_rustls::rustls::ServerConfig;
 use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
 
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
+/// Validity window of generated certs, in days from issuance.
+const VALIDITY_DAYS: i64 = 365;
+
 /// Where on-disk certs live. Layout: `{base}/certs/{domain}.{pem,key}`.
 /// `XDG_CONFIG_HOME` wins if set; otherwise `<home>/.config`, resolving
 /// home via `HOME` (Unix) or `USERPROFILE` (Windows) so the layout
@@ -42,6 +46,149 @@ pub fn cert_pem_path(domain: &str, cert_dir: &Path) -> PathBuf {
     cert_dir.join(format!("{slug}.pem"))
 }
 
+/// Summary of the cert served in `--tls` mode, reported by the admin
+/// API's `GET /tls` (and the dashboard).
+#[derive(Debug, Clone, Serialize)]
+pub struct CertInfo {
+    pub domain: String,
+    pub cert_path: String,
+    /// The cert's SAN entries (DNS names and IP addresses); empty if it
+    /// doesn't exist or can't be read.
+    pub sans: Vec<String>,
+    /// The cert's `notBefore` (Unix epoch ms), if it exists and parses.
+    pub issued_ms: Option<u64>,
+    /// The cert's `notAfter` (Unix epoch ms).
+    pub expires_ms: Option<u64>,
+    pub trusted: bool,
+}
+
+/// Describe the persisted cert for `domain`. Timestamps are `None` if
+/// the cert has not been generated yet or can't be read.
+pub fn cert_info(domain: &str, cert_dir: &Path) -> CertInfo {
+    let cert_path = cert_pem_path(domain, cert_dir);
+    let der = CertificateDer::from_pem_file(&cert_path).ok();
+    let validity = der.as_deref().and_then(validity_ms);
+    CertInfo {
+        domain: domain.to_string(),
+        cert_path: cert_path.to_string_lossy().into_owned(),
+        sans: der.as_deref().and_then(subject_alt_names).unwrap_or_default(),
+        issued_ms: validity.map(|(from, _)| from),
+        expires_ms: validity.map(|(_, to)| to),
+        trusted: is_trusted(&cert_path),
+    }
+}
+
+/// `(notBefore, notAfter)` of a DER certificate, in Unix epoch ms.
+fn validity_ms(der: &[u8]) -> Option<(u64, u64)> {
+    // serialNumber, signature, issuer, validity.
+    let (tag, validity) = *tbs_fields(der)?.iter().filter(|(tag, _)| *tag != 0xa0).nth(3)?;
+    if tag != 0x30 {
+        return None;
+    }
+    let (tag, not_before, rest) = der_read(validity)?;
+    let not_before = der_time_ms(tag, not_before)?;
+    let (tag, not_after, _) = der_read(rest)?;
+    Some((not_before, der_time_ms(tag, not_after)?))
+}
+
+/// The DNS names and IP addresses in a DER certificate's
+/// subjectAltName extension.
+fn subject_alt_names(der: &[u8]) -> Option<Vec<String>> {
+    // id-ce-subjectAltName, 2.5.29.17.
+    const SAN_OID: &[u8] = &[0x55, 0x1d, 0x11];
+    let (_, extensions) = *tbs_fields(der)?.iter().find(|(tag, _)| *tag == 0xa3)?;
+    let (_, mut list, _) = der_read(extensions).filter(|(tag, _, _)| *tag == 0x30)?;
+    while !list.is_empty() {
+        let (_, extension, rest) = der_read(list)?;
+        list = rest;
+        let (_, oid, mut fields) = der_read(extension)?;
+        if oid != SAN_OID {
+            continue;
+        }
+        // critical BOOLEAN (optional), then the value's OCTET STRING.
+        if fields.first() == Some(&0x01) {
+            fields = der_read(fields)?.2;
+        }
+        let (_, value, _) = der_read(fields).filter(|(tag, _, _)| *tag == 0x04)?;
+        let (_, mut names, _) = der_read(value).filter(|(tag, _, _)| *tag == 0x30)?;
+        let mut out = Vec::new();
+        while !names.is_empty() {
+            let (tag, name, rest) = der_read(names)?;
+            names = rest;
+            match (tag, name.len()) {
+                // dNSName [2] IA5String
+                (0x82, _) => out.push(String::from_utf8_lossy(name).into_owned()),
+                // iPAddress [7] OCTET STRING
+                (0x87, 4) => out.push(std::net::Ipv4Addr::from(<[u8; 4]>::try_from(name).ok()?).to_string()),
+                (0x87, 16) => out.push(std::net::Ipv6Addr::from(<[u8; 16]>::try_from(name).ok()?).to_string()),
+                _ => {}
+            }
+        }
+        return Some(out);
+    }
+    Some(Vec::new())
+}
+
+/// The elements of a DER certificate's `tbsCertificate`: `(tag, contents)`.
+fn tbs_fields(der: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let (_, cert, _) = der_read(der).filter(|(tag, _, _)| *tag == 0x30)?;
+    let (_, mut tbs, _) = der_read(cert).filter(|(tag, _, _)| *tag == 0x30)?;
+    let mut fields = Vec::new();
+    while !tbs.is_empty() {
+        let (tag, contents, rest) = der_read(tbs)?;
+        fields.push((tag, contents));
+        tbs = rest;
+    }
+    Some(fields)
+}
+
+/// One DER element: `(tag, contents, rest)`.
+fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || input.len() < n {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(n);
+        input = rest;
+        bytes.iter().fold(0usize, |acc, &b| acc << 8 | b as usize)
+    };
+    (input.len() >= len).then(|| (tag, &input[..len], &input[len..]))
+}
+
+/// A `UTCTime` (`YYMMDDHHMMSSZ`) or `GeneralizedTime`
+/// (`YYYYMMDDHHMMSSZ`) in Unix epoch ms.
+fn der_time_ms(tag: u8, value: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+    let (year, rest) = match (tag, text.len()) {
+        (0x17, 12) => {
+            let yy: i32 = text[..2].parse().ok()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, &text[2..])
+        }
+        (0x18, 14) => (text[..4].parse().ok()?, &text[4..]),
+        _ => return None,
+    };
+    let field = |i: usize| rest.get(i..i + 2)?.parse::<u8>().ok();
+    let month = time::Month::try_from(field(0)?).ok()?;
+    let date = time::Date::from_calendar_date(year, month, field(2)?).ok()?;
+    let at = date.with_hms(field(4)?, field(6)?, field(8)?).ok()?.assume_utc();
+    u64::try_from(at.unix_timestamp()).ok().map(|s| s * 1_000)
+}
+
+/// SAN entries (as text) issued for `domain`: apex + wildcard, or
+/// `localhost` + `127.0.0.1` when no domain is configured.
+fn san_names(domain: &str) -> Vec<String> {
+    if domain.is_empty() || domain == "localhost" {
+        vec!["localhost".to_string(), "127.0.0.1".to_string()]
+    } else {
+        vec![domain.to_string(), format!("*.{domain}")]
+    }
+}
+
 /// Whether the given cert is currently a trusted anchor on this
 /// system. Returns `false` if the check itself can't be performed
 /// (unsupported platform, missing tool) — callers should treat that
@@ -179,12 +326,11 @@ fn write_private(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
 /// — modern browsers ignore CN and only honor SAN entries.
 pub fn generate_self_signed(domain: &str) -> Result<(String, String), BoxError> {
     let mut sans: Vec<SanType> = Vec::new();
-    if domain.is_empty() || domain == "localhost" {
-        sans.push(SanType::DnsName("localhost".try_into()?));
-        sans.push(SanType::IpAddress("127.0.0.1".parse()?));
-    } else {
-        sans.push(SanType::DnsName(domain.try_into()?));
-        sans.push(SanType::DnsName(format!("*.{domain}").try_into()?));
+    for name in san_names(domain) {
+        match name.parse::<std::net::IpAddr>() {
+            Ok(ip) => sans.push(SanType::IpAddress(ip)),
+            Err(_) => sans.push(SanType::DnsName(name.try_into()?)),
+        }
     }
 
     let mut params = CertificateParams::default();
@@ -198,7 +344,7 @@ pub fn generate_self_signed(domain: &str) -> Result<(String, String), BoxError>
 
     let now = time::OffsetDateTime::now_utc();
     params.not_before = now - time::Duration::days(1);
-    params.not_after = now + time::Duration::days(365);
+    params.not_after = now + time::Duration::days(VALIDITY_DAYS);
 
     let key_pair = KeyPair::generate()?;
     let cert = params.self_signed(&key_pair)?;
@@ -237,6 +383,47 @@ mod tests {
         assert!(cert.contains("BEGIN CERTIFICATE"));
     }
 
+    #[test]
+    fn cert_info_reports_sans_and_expiry() {
+        let tmp = std::env::temp_dir().join(format!(
+            "fbi-tls-info-test-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&tmp);
+
+        let before = cert_info("info.dev", &tmp);
+        assert!(before.sans.is_empty());
+        assert!(before.issued_ms.is_none());
+
+        load_or_generate("info.dev", &tmp).unwrap();
+        let after = cert_info("info.dev", &tmp);
+        assert_eq!(after.sans, ["info.dev", "*.info.dev"]);
+        let issued = after.issued_ms.expect("cert written");
+        // Issued a day back-dated, valid for VALIDITY_DAYS from now.
+        assert_eq!(after.expires_ms, Some(issued + (VALIDITY_DAYS as u64 + 1) * 86_400_000));
+
+        // Touching the file doesn't move the dates; they come from the cert.
+        let pem = std::fs::read(cert_pem_path("info.dev", &tmp)).unwrap();
+        std::fs::write(cert_pem_path("info.dev", &tmp), pem).unwrap();
+        assert_eq!(cert_info("info.dev", &tmp).expires_ms, after.expires_ms);
+
+        // A cert with fixed dates reports exactly those.
+        let mut params = CertificateParams::new(vec!["old.dev".to_string()]).unwrap();
+        // 2020-01-02 03:04:05 (a UTCTime) to 2051-06-07 08:09:10 (GeneralizedTime).
+        params.not_before = time::OffsetDateTime::from_unix_timestamp(1_577_934_245).unwrap();
+        params.not_after = time::OffsetDateTime::from_unix_timestamp(2_569_738_150).unwrap();
+        let cert = params.self_signed(&KeyPair::generate().unwrap()).unwrap();
+        assert_eq!(validity_ms(cert.der()), Some((1_577_934_245_000, 2_569_738_150_000)));
+
+        // The SANs are the served cert's, not the ones `--domain` would get.
+        std::fs::write(cert_pem_path("info.dev", &tmp), cert.pem()).unwrap();
+        assert_eq!(cert_info("info.dev", &tmp).sans, ["old.dev"]);
+        load_or_generate("", &tmp).unwrap();
+        assert_eq!(cert_info("", &tmp).sans, ["localhost", "127.0.0.1"]);
+
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn load_or_generate_round_trips_persisted_certs() {
         let tmp = std::env::temp_dir().join(format!(
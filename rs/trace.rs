@@ -0,0 +1,253 @@
+//! Request IDs and W3C Trace Context (`traceparent` / `tracestate`).
+//!
+//! Every proxied request gets an `X-Request-Id` — the client's own when it
+//! sent a sane one, otherwise the request's trace ID — which is echoed on
+//! the response, printed in every log line and embedded in the proxy's
+//! error bodies. So a pasted 502 page leads straight to the log line and,
+//! through `traceparent`, to the upstream's trace.
+//!
+//! The proxy acts as a server span: an incoming `traceparent` becomes its
+//! parent (same trace ID, fresh span ID). Each upstream attempt is a
+//! child client span, and the upstream receives a `traceparent` naming
+//! that client span. `tracestate` is passed through unchanged. Without
+//! a valid incoming header a new sampled trace starts here.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Longest client-supplied request ID we propagate; longer (or oddly
+/// shaped) ones are replaced rather than trusted into logs.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// `tracestate` is capped at 512 bytes by the spec; drop anything longer.
+const MAX_TRACESTATE_LEN: usize = 512;
+
+/// Trace context for one request as seen by the proxy span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    /// The proxy's own span.
+    pub span_id: [u8; 8],
+    /// The caller's span, when the request carried a valid `traceparent`.
+    pub parent_id: Option<[u8; 8]>,
+    /// Trace flags (bit 0 = sampled).
+    pub flags: u8,
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Continue the incoming trace, or start a new sampled one if
+    /// `traceparent` is missing or malformed (a bad `tracestate` alone is
+    /// dropped, per the spec).
+    pub fn from_headers(traceparent: Option<&str>, tracestate: Option<&str>) -> TraceContext {
+        match traceparent.and_then(parse_traceparent) {
+            Some((trace_id, parent, flags)) => TraceContext {
+                trace_id,
+                span_id: new_span_id(),
+                parent_id: Some(parent),
+                flags,
+                tracestate: tracestate
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty() && s.len() <= MAX_TRACESTATE_LEN)
+                    .map(String::from),
+            },
+            None => TraceContext::new_root(),
+        }
+    }
+
+    /// A fresh, sampled trace with no parent.
+    pub fn new_root() -> TraceContext {
+        TraceContext {
+            trace_id: new_trace_id(),
+            span_id: new_span_id(),
+            parent_id: None,
+            flags: 0x01,
+            tracestate: None,
+        }
+    }
+
+    pub fn sampled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    pub fn span_id_hex(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    pub fn parent_id_hex(&self) -> Option<String> {
+        self.parent_id.map(|p| hex(&p))
+    }
+
+    /// `traceparent` naming the proxy span as the parent.
+    pub fn traceparent(&self) -> String {
+        self.traceparent_for(&self.span_id)
+    }
+
+    /// `traceparent` naming another span of this trace (e.g. the client
+    /// span of one upstream attempt) as the parent.
+    pub fn traceparent_for(&self, span_id: &[u8; 8]) -> String {
+        format!("00-{}-{}-{:02x}", hex(&self.trace_id), hex(span_id), self.flags)
+    }
+}
+
+/// Parse a version-00 `traceparent` into (trace ID, parent span ID,
+/// flags). Rejects all-zero IDs and uppercase hex as the spec requires;
+/// future versions are accepted if their first four fields parse.
+pub fn parse_traceparent(s: &str) -> Option<([u8; 16], [u8; 8], u8)> {
+    let s = s.trim();
+    let mut parts = s.split('-');
+    let version = parts.next()?;
+    let trace = parts.next()?;
+    let parent = parts.next()?;
+    let flags = parts.next()?;
+    if version.len() != 2 || version == "ff" || !is_lower_hex(version) {
+        return None;
+    }
+    // Version 00 has exactly four fields; later versions may append more.
+    if version == "00" && parts.next().is_some() {
+        return None;
+    }
+    if flags.len() != 2 || !is_lower_hex(flags) {
+        return None;
+    }
+    let trace_id: [u8; 16] = unhex(trace)?;
+    let parent_id: [u8; 8] = unhex(parent)?;
+    if trace_id == [0; 16] || parent_id == [0; 8] {
+        return None;
+    }
+    Some((trace_id, parent_id, u8::from_str_radix(flags, 16).ok()?))
+}
+
+/// The request ID to use: the client's `X-Request-Id` if it is 1..=128
+/// printable, space-free ASCII characters, else `fallback` (callers pass
+/// the trace ID so the two line up).
+pub fn request_id(incoming: Option<&str>, fallback: impl FnOnce() -> String) -> String {
+    match incoming.map(str::trim) {
+        Some(id) if is_valid_request_id(id) => id.to_string(),
+        _ => fallback(),
+    }
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn unhex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.len() != N * 2 || !is_lower_hex(s) {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Non-cryptographic random u64: SipHash with per-process random keys
+/// over a counter. Plenty for trace IDs without pulling in `rand`.
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut h = RandomState::new().build_hasher();
+    h.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    h.finish()
+}
+
+fn nonzero_u64() -> u64 {
+    loop {
+        let v = random_u64();
+        if v != 0 {
+            return v;
+        }
+    }
+}
+
+pub fn new_trace_id() -> [u8; 16] {
+    let mut id = [0u8; 16];
+    id[..8].copy_from_slice(&nonzero_u64().to_be_bytes());
+    id[8..].copy_from_slice(&random_u64().to_be_bytes());
+    id
+}
+
+pub fn new_span_id() -> [u8; 8] {
+    nonzero_u64().to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parses_valid_traceparent() {
+        let (trace, parent, flags) = parse_traceparent(TP).unwrap();
+        assert_eq!(hex(&trace), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(hex(&parent), "00f067aa0ba902b7");
+        assert_eq!(flags, 1);
+    }
+
+    #[test]
+    fn rejects_malformed_traceparent() {
+        for bad in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert!(parse_traceparent(bad).is_none(), "{bad}");
+        }
+        // Future versions may carry extra fields.
+        assert!(parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-zz").is_some());
+    }
+
+    #[test]
+    fn continues_incoming_trace_with_new_span() {
+        let ctx = TraceContext::from_headers(Some(TP), Some("vendor=abc"));
+        assert_eq!(ctx.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(ctx.parent_id_hex().as_deref(), Some("00f067aa0ba902b7"));
+        assert_ne!(ctx.span_id_hex(), "00f067aa0ba902b7");
+        assert_eq!(ctx.tracestate.as_deref(), Some("vendor=abc"));
+        let out = ctx.traceparent();
+        assert!(out.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(out.ends_with("-01"));
+        assert_eq!(parse_traceparent(&out).unwrap().1, ctx.span_id);
+    }
+
+    #[test]
+    fn starts_new_sampled_root_without_valid_parent() {
+        let ctx = TraceContext::from_headers(Some("garbage"), Some("vendor=abc"));
+        assert!(ctx.parent_id.is_none());
+        assert!(ctx.sampled());
+        assert!(ctx.tracestate.is_none());
+        assert_ne!(TraceContext::new_root().trace_id, ctx.trace_id);
+    }
+
+    #[test]
+    fn request_id_propagates_sane_values_only() {
+        assert_eq!(request_id(Some("abc-123"), || "gen".into()), "abc-123");
+        assert_eq!(request_id(Some("has space"), || "gen".into()), "gen");
+        assert_eq!(request_id(Some(""), || "gen".into()), "gen");
+        assert_eq!(request_id(Some(&"x".repeat(129)), || "gen".into()), "gen");
+        assert_eq!(request_id(None, || "gen".into()), "gen");
+    }
+}
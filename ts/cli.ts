@@ -53,6 +53,20 @@ const originalCwd = process.cwd();
     process.exit(code);
   }
 
+  // Offline rule validation, routing traces and `run` live in the Rust
+  // binary; forward them as-is (from the caller's cwd, so relative paths
+  // resolve).
+  if (
+    firstPositional === "check" ||
+    firstPositional === "route" ||
+    firstPositional === "run"
+  ) {
+    process.chdir(path.resolve(import.meta.dir, ".."));
+    const proxy = path.resolve(await getFbiProxyBinary({ originalCwd }));
+    const r = spawnSync(proxy, rawArgs, { stdio: "inherit", cwd: originalCwd });
+    process.exit(r.status ?? 1);
+  }
+
   const FOREGROUND_FLAGS = [
     "--dev",
     "--with-caddy",
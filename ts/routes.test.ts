@@ -1,5 +1,11 @@
 import { describe, expect, it } from "vitest";
-import { parseRoutesYaml, validateRoute, type RouteConfig } from "./routes.ts";
+import {
+  interpolate,
+  parseRoutesYaml,
+  routeToV2,
+  validateRoute,
+  type RouteConfig,
+} from "./routes.ts";
 
 const defaultYaml = `
 version: 1
@@ -48,8 +54,71 @@ describe("parseRoutesYaml", () => {
     expect(f.version).toBe(1);
   });
 
+  it("keeps priorities and disabled defaults", () => {
+    const f = parseRoutesYaml(
+      `priority: 10\ndisable_defaults: [direct-forward]\nroutes:\n  - name: x\n    match: "{a}"\n    target: "b"\n    priority: -1\n`,
+    );
+    expect(f.priority).toBe(10);
+    expect(f.disable_defaults).toEqual(["direct-forward"]);
+    expect(f.routes[0].priority).toBe(-1);
+    expect(() =>
+      parseRoutesYaml(`priority: high\nroutes: []\n`),
+    ).toThrow(/priority/);
+  });
+
+  it("keeps vars and includes", () => {
+    const f = parseRoutesYaml(
+      `include: [base.yaml]\nvars:\n  port: "3000"\nroutes: []\n`,
+    );
+    expect(f.include).toEqual(["base.yaml"]);
+    expect(f.vars).toEqual({ port: "3000" });
+    expect(() => parseRoutesYaml(`vars: [a]\nroutes: []\n`)).toThrow(/vars/);
+  });
+
+  it("flattens version 2 rules", () => {
+    const f = parseRoutesYaml(
+      `version: 2\nroutes:\n  - name: x\n    match: { host: "{a}", path: /api }\n    action:\n      proxy: b\n      headers: { Host: "{a}" }\n    policy: { priority: 3 }\n`,
+    );
+    expect(f.version).toBe(2);
+    expect(f.routes[0]).toEqual({
+      name: "x",
+      match: "{a}",
+      path: "/api",
+      target: "b",
+      headers: { Host: "{a}" },
+      priority: 3,
+    });
+    expect(routeToV2(f.routes[0])).toEqual({
+      name: "x",
+      match: { host: "{a}", path: "/api" },
+      action: { proxy: "b", headers: { Host: "{a}" } },
+      policy: { priority: 3 },
+    });
+    // Without `version:`, the rule shape decides.
+    expect(
+      parseRoutesYaml(`routes:\n  - name: x\n    match: { host: a }\n    action: { proxy: b }\n`)
+        .version,
+    ).toBe(2);
+  });
+
+  it("keeps launch blocks under the action", () => {
+    const f = parseRoutesYaml(
+      `version: 2\nroutes:\n  - name: wiki\n    match: { host: "wiki.{domain}" }\n    action:\n      proxy: localhost:4100\n      launch: { command: [npm, run, dev], idle_timeout: 15m }\n`,
+    );
+    expect(f.routes[0].launch).toEqual({
+      command: ["npm", "run", "dev"],
+      idle_timeout: "15m",
+    });
+    expect(routeToV2(f.routes[0]).action.launch).toEqual(f.routes[0].launch);
+    expect(() =>
+      parseRoutesYaml(
+        `routes:\n  - name: x\n    match: a\n    target: b\n    launch: { cwd: /tmp }\n`,
+      ),
+    ).toThrow(/launch/);
+  });
+
   it("rejects unsupported version", () => {
-    expect(() => parseRoutesYaml(`version: 2\nroutes: []\n`)).toThrow(
+    expect(() => parseRoutesYaml(`version: 3\nroutes: []\n`)).toThrow(
       /unsupported version/,
     );
   });
@@ -87,6 +156,18 @@ describe("parseRoutesYaml", () => {
   });
 });
 
+describe("interpolate", () => {
+  it("looks in vars, then env, then the default", () => {
+    const env = { HOST: "db", PORT: "1" };
+    expect(interpolate("${HOST}:${PORT}", { PORT: "2" }, env)).toBe("db:2");
+    expect(interpolate("${X:-x}.{domain}", {}, env)).toBe("x.{domain}");
+    expect(interpolate("$${HOST} $5", {}, env)).toBe("${HOST} $5");
+    expect(() => interpolate("${MISSING}", {}, env)).toThrow(
+      /\$\{MISSING\} is not set/,
+    );
+  });
+});
+
 describe("validateRoute", () => {
   const good: RouteConfig = {
     name: "ok",
@@ -184,6 +265,21 @@ describe("validateRoute", () => {
     }
   });
 
+  it("accepts secret references only in non-Host headers", () => {
+    expect(
+      validateRoute({
+        ...good,
+        headers: { Authorization: "Bearer ${file:/run/secrets/api}" },
+      }),
+    ).toEqual({ valid: true });
+    expect(
+      validateRoute({ ...good, target: "${env:TARGET}" }),
+    ).toMatchObject({ valid: false });
+    expect(
+      validateRoute({ ...good, headers: { Host: "${env:HOST}" } }),
+    ).toMatchObject({ valid: false });
+  });
+
   it("accepts {name:multi} for DNS-passthrough patterns", () => {
     const dnsRoute: RouteConfig = {
       name: "dns-passthrough",
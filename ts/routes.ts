@@ -44,12 +44,84 @@ export type RouteConfig = {
    * added to the upstream request as-is.
    */
   headers?: Record<string, string>;
+  /**
+   * Evaluation-order priority: among rules with the same path priority,
+   * higher goes first. Default 0 (the file's `priority`, if set), so
+   * the bundled defaults win ties unless a fragment raises it.
+   */
+  priority?: number;
+  /**
+   * Command that serves `target`, started by the proxy on the first
+   * request and stopped once idle. The target can't use placeholders.
+   */
+  launch?: LaunchConfig;
+};
+
+/** A rule's `launch:` block (`action.launch` in version 2). */
+export type LaunchConfig = {
+  /** Run with `sh -c`, or an argv list run as is. Gets `PORT`. */
+  command: string | string[];
+  /** Working directory; a leading `~/` is the home directory. */
+  cwd?: string;
+  env?: Record<string, string>;
+  /**
+   * Ready once the target accepts a connection, or with `http`, once a
+   * `GET` of that path answers below 500. `timeout` defaults to `30s`.
+   */
+  ready?: { http?: string; timeout?: string };
+  /** Stop after this long with no request in flight (default `10m`). */
+  idle_timeout?: string;
 };
 
-/** Top-level shape of `routes.yaml`. */
+/**
+ * An embedded routing assertion. The proxy evaluates every file's
+ * `tests` against the merged rule set and refuses a reload (or an `up`)
+ * that would make any of them fail.
+ */
+export type RouteTest = {
+  host: string;
+  path?: string;
+  method?: string;
+  expect:
+    | "reject"
+    | "landing"
+    | {
+        /** Rule name, optionally `namespace/name`. */
+        route?: string;
+        target?: string;
+        /** The `Host` header the upstream receives. */
+        host_header?: string;
+      };
+};
+
+/**
+ * A rule as written in a version 2 routes file: what it matches, what it
+ * does, and its policies. `parseRoutesYaml` flattens it to `RouteConfig`.
+ */
+export type RouteV2 = {
+  name: string;
+  match: { host: string; path?: string };
+  action: {
+    proxy: string;
+    headers?: Record<string, string>;
+    launch?: LaunchConfig;
+  };
+  policy?: { priority?: number };
+};
+
+/** Top-level shape of `routes.yaml` (either schema version). */
 export type RoutesFile = {
-  version: 1;
+  version: 1 | 2;
+  /** Default `priority` for this file's rules. */
+  priority?: number;
+  /** Bundled default rules to switch off while this fragment is loaded. */
+  disable_defaults?: string[];
+  /** Values for `${NAME}` in this file's rules (see `interpolate`). */
+  vars?: Record<string, string>;
+  /** Routes files or globs merged in ahead of this one, relative to it. */
+  include?: string[];
   routes: RouteConfig[];
+  tests?: RouteTest[];
 };
 
 /**
@@ -58,7 +130,11 @@ export type RoutesFile = {
  */
 export type ComposeFile = {
   name?: string;
+  priority?: number;
+  disable_defaults?: string[];
+  vars?: Record<string, string>;
   routes: RouteConfig[];
+  tests?: RouteTest[];
 };
 
 /** Result type for `validateRoute`. */
@@ -77,16 +153,23 @@ export function parseRoutesYaml(yaml: string): RoutesFile {
     throw new Error("routes.yaml must be a YAML mapping at the top level");
   }
   const obj = raw as Record<string, unknown>;
-  const version = (obj.version ?? 1) as number;
-  if (version !== 1) {
-    throw new Error(`routes.yaml: unsupported version ${version} (expected 1)`);
+  // Without `version:`, a plain-string `match` means version 1.
+  const v1Shaped =
+    Array.isArray(obj.routes) &&
+    obj.routes.some((r) => typeof r?.match === "string");
+  const version = (obj.version ?? (v1Shaped ? 1 : 2)) as number;
+  if (version !== 1 && version !== 2) {
+    throw new Error(
+      `routes.yaml: unsupported version ${version} (expected 1 or 2)`,
+    );
   }
   if (!Array.isArray(obj.routes)) {
     throw new Error("routes.yaml: `routes` must be a list");
   }
   const routes: RouteConfig[] = [];
   for (let i = 0; i < obj.routes.length; i++) {
-    const entry = obj.routes[i];
+    const entry =
+      version === 2 ? flattenRouteV2(obj.routes[i]) : obj.routes[i];
     if (entry == null || typeof entry !== "object") {
       throw new Error(`routes.yaml: entry #${i} must be a mapping`);
     }
@@ -132,15 +215,170 @@ export function parseRoutesYaml(yaml: string): RoutesFile {
       }
       path = e.path;
     }
+    if (e.priority != null && !Number.isInteger(e.priority)) {
+      throw new Error(
+        `routes.yaml: entry '${e.name}': \`priority\` must be an integer`,
+      );
+    }
+    if (e.launch != null) {
+      const l = e.launch as Record<string, unknown>;
+      const command = l.command;
+      const valid =
+        typeof command === "string"
+          ? command.trim().length > 0
+          : Array.isArray(command) &&
+            command.length > 0 &&
+            command.every((c) => typeof c === "string");
+      if (typeof e.launch !== "object" || Array.isArray(e.launch) || !valid) {
+        throw new Error(
+          `routes.yaml: entry '${e.name}': \`launch\` needs a \`command\` (a string or a list of strings)`,
+        );
+      }
+    }
     routes.push({
       name: e.name,
       match: e.match,
       ...(path != null ? { path } : {}),
       target: e.target,
       headers,
+      ...(e.priority != null ? { priority: e.priority as number } : {}),
+      ...(e.launch != null ? { launch: e.launch as LaunchConfig } : {}),
     });
   }
-  return { version: 1, routes };
+  const file: RoutesFile = { version, routes };
+  if (obj.priority != null) {
+    if (!Number.isInteger(obj.priority)) {
+      throw new Error("routes.yaml: `priority` must be an integer");
+    }
+    file.priority = obj.priority as number;
+  }
+  if (obj.disable_defaults != null) {
+    if (
+      !Array.isArray(obj.disable_defaults) ||
+      !obj.disable_defaults.every((n) => typeof n === "string")
+    ) {
+      throw new Error("routes.yaml: `disable_defaults` must be a list of names");
+    }
+    file.disable_defaults = obj.disable_defaults as string[];
+  }
+  if (obj.vars != null) {
+    if (
+      typeof obj.vars !== "object" ||
+      Array.isArray(obj.vars) ||
+      !Object.values(obj.vars).every((v) => typeof v === "string")
+    ) {
+      throw new Error("routes.yaml: `vars` must be a mapping of strings");
+    }
+    file.vars = obj.vars as Record<string, string>;
+  }
+  if (obj.include != null) {
+    if (
+      !Array.isArray(obj.include) ||
+      !obj.include.every((p) => typeof p === "string")
+    ) {
+      throw new Error("routes.yaml: `include` must be a list of paths");
+    }
+    file.include = obj.include as string[];
+  }
+  return file;
+}
+
+/** A version 2 rule in the flat version 1 shape, for validation. */
+function flattenRouteV2(entry: unknown): unknown {
+  if (entry == null || typeof entry !== "object") return entry;
+  const { match, action, policy, ...rest } = entry as Record<string, unknown>;
+  const m = (match ?? {}) as Record<string, unknown>;
+  const a = (action ?? {}) as Record<string, unknown>;
+  const p = (policy ?? {}) as Record<string, unknown>;
+  return {
+    ...rest,
+    match: m.host,
+    path: m.path,
+    target: a.proxy,
+    headers: a.headers,
+    priority: p.priority,
+    launch: a.launch,
+  };
+}
+
+/** `r` as a version 2 rule, the shape the proxy writes. */
+export function routeToV2(r: RouteConfig): RouteV2 {
+  return {
+    name: r.name,
+    match: { host: r.match, ...(r.path != null ? { path: r.path } : {}) },
+    action: {
+      proxy: r.target,
+      ...(r.headers ? { headers: r.headers } : {}),
+      ...(r.launch ? { launch: r.launch } : {}),
+    },
+    ...(r.priority != null ? { policy: { priority: r.priority } } : {}),
+  };
+}
+
+/**
+ * Substitute `${NAME}` / `${NAME:-default}` in `template` from `vars`,
+ * then `env`, then the default — the same compile-time expansion the
+ * Rust engine runs. `$${` is a literal `${`. Throws on a variable with
+ * no value.
+ */
+export function interpolate(
+  template: string,
+  vars: Record<string, string> = {},
+  env: Record<string, string | undefined> = process.env,
+): string {
+  return template.replace(
+    /\$\$\{|\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}/g,
+    (_whole, name: string | undefined, def: string | undefined) => {
+      if (name == null) return "${";
+      const value = vars[name] ?? env[name] ?? def;
+      if (value == null) {
+        throw new Error(
+          `\${${name}} is not set (no \`vars:\` entry, environment variable or \`:-\` default)`,
+        );
+      }
+      return value;
+    },
+  );
+}
+
+/** `r` with its templates expanded by `interpolate`. */
+export function interpolateRoute(
+  r: RouteConfig,
+  vars: Record<string, string> = {},
+): RouteConfig {
+  return {
+    ...r,
+    match: interpolate(r.match, vars),
+    ...(r.path != null ? { path: interpolate(r.path, vars) } : {}),
+    target: interpolate(r.target, vars),
+    headers:
+      r.headers &&
+      Object.fromEntries(
+        Object.entries(r.headers).map(([k, v]) => [k, interpolate(v, vars)]),
+      ),
+    ...(r.launch ? { launch: interpolateLaunch(r.launch, vars) } : {}),
+  };
+}
+
+function interpolateLaunch(
+  l: LaunchConfig,
+  vars: Record<string, string>,
+): LaunchConfig {
+  return {
+    ...l,
+    command:
+      typeof l.command === "string"
+        ? interpolate(l.command, vars)
+        : l.command.map((c) => interpolate(c, vars)),
+    ...(l.cwd != null ? { cwd: interpolate(l.cwd, vars) } : {}),
+    ...(l.env
+      ? {
+          env: Object.fromEntries(
+            Object.entries(l.env).map(([k, v]) => [k, interpolate(v, vars)]),
+          ),
+        }
+      : {}),
+  };
 }
 
 /**
@@ -159,13 +397,34 @@ export function parseComposeYaml(yaml: string): ComposeFile {
   }
   // parseRoutesYaml validates the `routes` list + each entry; version is
   // optional in a compose file so default it in.
-  const { routes } = parseRoutesYaml(
-    YAML.stringify({ version: 1, routes: obj.routes ?? [] }),
+  const { routes, priority, disable_defaults, vars } = parseRoutesYaml(
+    YAML.stringify({
+      version: 1,
+      routes: obj.routes ?? [],
+      priority: obj.priority,
+      disable_defaults: obj.disable_defaults,
+      vars: obj.vars,
+    }),
   );
-  return { name: obj.name as string | undefined, routes };
+  // Test entries are checked by the proxy when the namespace is applied.
+  if (obj.tests != null && !Array.isArray(obj.tests)) {
+    throw new Error("fbi-proxy.yaml: `tests` must be a list");
+  }
+  const tests = obj.tests as RouteTest[] | undefined;
+  return {
+    name: obj.name as string | undefined,
+    ...(priority != null ? { priority } : {}),
+    ...(disable_defaults ? { disable_defaults } : {}),
+    ...(vars ? { vars } : {}),
+    routes,
+    ...(tests ? { tests } : {}),
+  };
 }
 
 const PLACEHOLDER_NAME_RE = /^[A-Za-z_][A-Za-z0-9_]*$/;
+/** `${env:NAME}` / `${file:/path}` — secrets the proxy reads at load time. */
+const SECRET_REF_RE = /\$\{(?:env|file):[^}]+\}/;
+const SECRET_REF_GLOBAL_RE = new RegExp(SECRET_REF_RE.source, "g");
 const VALID_KINDS = new Set(["", "int", "slug", "multi"]);
 
 /** Find all `{name[:kind]}` placeholders in `s`. */
@@ -214,6 +473,22 @@ export function validateRoute(r: RouteConfig): ValidationResult {
   if (r.path != null && !r.path.startsWith("/"))
     return { valid: false, reason: "route `path` must start with '/'" };
 
+  const hostHeader = Object.entries(r.headers ?? {}).find(
+    ([k]) => k.toLowerCase() === "host",
+  );
+  for (const [field, value] of [
+    ["match", r.match],
+    ["path", r.path],
+    ["target", r.target],
+    ["headers.Host", hostHeader?.[1]],
+  ] as const) {
+    if (value != null && SECRET_REF_RE.test(value))
+      return {
+        valid: false,
+        reason: `secret references are only allowed in header values other than Host (found in \`${field}\`)`,
+      };
+  }
+
   if (!bracesBalanced(r.match))
     return { valid: false, reason: "unbalanced braces in `match`" };
   if (!bracesBalanced(r.target))
@@ -263,7 +538,9 @@ export function validateRoute(r: RouteConfig): ValidationResult {
       };
   }
   if (r.headers) {
-    for (const [hk, hv] of Object.entries(r.headers)) {
+    for (const [hk, raw] of Object.entries(r.headers)) {
+      // Secret references are resolved by the proxy, not placeholders.
+      const hv = raw.replace(SECRET_REF_GLOBAL_RE, "");
       if (!bracesBalanced(hv))
         return {
           valid: false,
@@ -1,6 +1,6 @@
 /**
- * `fbi-proxy up | down | ps | config` — compose-style management of
- * runtime routing rules. Each project ships an `fbi-proxy.yaml` whose
+ * `fbi-proxy up | down | ps | config | explain` — compose-style management
+ * of runtime routing rules. Each project ships an `fbi-proxy.yaml` whose
  * top-level `name` is the namespace; rules are stored as conf.d
  * fragments and applied live via the loopback admin API.
  */
@@ -10,17 +10,33 @@ import path from "node:path";
 import yargs from "yargs";
 import { hideBin } from "yargs/helpers";
 import YAML from "yaml";
-import { parseComposeYaml, validateRoute, type RouteConfig } from "./routes";
+import {
+  parseComposeYaml,
+  interpolateRoute,
+  routeToV2,
+  validateRoute,
+  type ComposeFile,
+} from "./routes";
 import {
   applyRules,
   deleteRules,
+  explainRoute,
+  getStatus,
+  lintRules,
   listRules,
+  type ExplainResult,
   type RuleInfo,
 } from "./adminClient";
 
 const DEFAULT_FILE = "fbi-proxy.yaml";
 
-export const RULES_SUBCOMMANDS = new Set(["up", "down", "ps", "config"]);
+export const RULES_SUBCOMMANDS = new Set([
+  "up",
+  "down",
+  "ps",
+  "config",
+  "explain",
+]);
 
 /** Derive the namespace: explicit `-p` > compose `name` > directory name. */
 function resolveNamespace(
@@ -34,7 +50,7 @@ function resolveNamespace(
   return path.basename(path.dirname(path.resolve(filePath)));
 }
 
-function loadCompose(file: string): { name?: string; routes: RouteConfig[] } {
+function loadCompose(file: string): ComposeFile {
   if (!existsSync(file)) {
     throw new Error(
       `[fbi-proxy] ${file} not found. Create one (compose-style):\n` +
@@ -43,7 +59,12 @@ function loadCompose(file: string): { name?: string; routes: RouteConfig[] } {
   }
   const compose = parseComposeYaml(readFileSync(file, "utf8"));
   for (const r of compose.routes) {
-    const v = validateRoute(r);
+    let v: ReturnType<typeof validateRoute>;
+    try {
+      v = validateRoute(interpolateRoute(r, compose.vars));
+    } catch (e) {
+      v = { valid: false, reason: (e as Error).message };
+    }
     if (!v.valid) {
       throw new Error(`[fbi-proxy] ${file}: rule '${r.name}': ${v.reason}`);
     }
@@ -51,19 +72,50 @@ function loadCompose(file: string): { name?: string; routes: RouteConfig[] } {
   return compose;
 }
 
+/** `12s ago (200)`, or `never` for a rule that has not been hit. */
+export function formatLastHit(
+  lastHitMs: number | null | undefined,
+  lastStatus: number | null | undefined,
+  now = Date.now(),
+): string {
+  if (lastHitMs == null) return "never";
+  const secs = Math.max(0, Math.round((now - lastHitMs) / 1000));
+  const ago =
+    secs < 60
+      ? `${secs}s`
+      : secs < 3600
+        ? `${Math.floor(secs / 60)}m`
+        : secs < 86400
+          ? `${Math.floor(secs / 3600)}h`
+          : `${Math.floor(secs / 86400)}d`;
+  return lastStatus == null ? `${ago} ago` : `${ago} ago (${lastStatus})`;
+}
+
 function printRulesTable(rules: RuleInfo[]): void {
   if (rules.length === 0) {
     console.log("(no rules)");
     return;
   }
   const rows = rules.map((r) => ({
-    NAMESPACE: r.namespace,
+    NAMESPACE: r.stale ? `${r.namespace} (stale)` : r.namespace,
     NAME: r.name,
     MATCH: r.match,
     PATH: r.path ?? "*",
     TARGET: r.target,
+    HITS: String(r.hits ?? 0),
+    ERRORS: String(r.errors ?? 0),
+    "LAST HIT": formatLastHit(r.last_hit_ms, r.last_status),
   }));
-  const cols = ["NAMESPACE", "NAME", "MATCH", "PATH", "TARGET"] as const;
+  const cols = [
+    "NAMESPACE",
+    "NAME",
+    "MATCH",
+    "PATH",
+    "TARGET",
+    "HITS",
+    "ERRORS",
+    "LAST HIT",
+  ] as const;
   const widths = Object.fromEntries(
     cols.map((c) => [
       c,
@@ -76,9 +128,42 @@ function printRulesTable(rules: RuleInfo[]): void {
   for (const row of rows) console.log(fmt(row));
 }
 
+function printExplanation(ex: ExplainResult): void {
+  console.log(`${ex.method} ${ex.host}${ex.path}`);
+  if (!ex.domain_allowed) {
+    console.log(`  rejected: host is not under the domain '${ex.domain}'`);
+    return;
+  }
+  if (ex.apex) {
+    console.log("  apex host: only rules with an explicit path are eligible");
+  }
+  for (const c of ex.candidates) {
+    const mark = c.winner ? "=>" : c.result === "matched" ? " ~" : "  ";
+    console.log(
+      `${mark} ${c.namespace}/${c.name}  ${c.match}${c.path ? ` ${c.path}` : ""}`,
+    );
+    console.log(`     ${c.reason}`);
+  }
+  if (ex.hit) {
+    console.log(
+      `\nwinner: ${ex.hit.namespace}/${ex.hit.route_name} -> ${ex.hit.target} (Host: ${ex.upstream_host})`,
+    );
+    for (const [k, v] of Object.entries(ex.captures)) {
+      console.log(`  {${k}} = ${v}`);
+    }
+    for (const [k, v] of Object.entries(ex.hit.other_headers)) {
+      console.log(`  ${k}: ${v}`);
+    }
+  } else if (ex.decision === "landing") {
+    console.log("\nno rule matched: serves the built-in landing page");
+  } else {
+    console.log("\nno rule matched: rejected with 502");
+  }
+}
+
 /**
  * Entry point dispatched from cli.ts when the first positional arg is one
- * of `up|down|ps|config`. Returns the process exit code.
+ * of `up|down|ps|config|explain`. Returns the process exit code.
  */
 export async function runRulesCli(rawArgs: string[]): Promise<number> {
   const argv = await yargs(rawArgs)
@@ -87,6 +172,16 @@ export async function runRulesCli(rawArgs: string[]): Promise<number> {
     .command("down", "Remove this project's rules from the running proxy")
     .command("ps", "List active rules across all namespaces")
     .command("config", "Print the merged resolved routing table")
+    .command(
+      "explain <host> [path]",
+      "Show how the running proxy would route a request, rule by rule",
+    )
+    .option("method", {
+      alias: "X",
+      type: "string",
+      default: "GET",
+      description: "Request method for explain",
+    })
     .option("file", {
       alias: "f",
       type: "string",
@@ -104,7 +199,7 @@ export async function runRulesCli(rawArgs: string[]): Promise<number> {
       type: "string",
       choices: ["table", "json", "yaml"] as const,
       default: "table",
-      description: "Output format for ps/config",
+      description: "Output format for ps/config/explain",
     })
     .help().argv;
 
@@ -115,12 +210,26 @@ export async function runRulesCli(rawArgs: string[]): Promise<number> {
       case "up": {
         const compose = loadCompose(argv.file);
         const ns = resolveNamespace(argv.project, compose.name, argv.file);
-        const body = YAML.stringify({ version: 1, routes: compose.routes });
+        const body = YAML.stringify({
+          version: 2,
+          ...(compose.priority != null ? { priority: compose.priority } : {}),
+          ...(compose.disable_defaults
+            ? { disable_defaults: compose.disable_defaults }
+            : {}),
+          ...(compose.vars ? { vars: compose.vars } : {}),
+          routes: compose.routes.map(routeToV2),
+          ...(compose.tests ? { tests: compose.tests } : {}),
+        });
         const applied = await applyRules(ns, body);
         console.log(
           `[fbi-proxy] up: namespace '${ns}' (${compose.routes.length} rule(s))`,
         );
         printRulesTable(applied.filter((r) => r.namespace === ns));
+        for (const l of await lintRules(ns)) {
+          console.warn(
+            `[fbi-proxy] warning: ${l.namespace}/${l.route}: ${l.message}`,
+          );
+        }
         return 0;
       }
       case "down": {
@@ -148,8 +257,30 @@ export async function runRulesCli(rawArgs: string[]): Promise<number> {
         } else {
           printRulesTable(rules);
         }
+        const status = await getStatus();
+        for (const ns of status.namespaces) {
+          if (ns.state === "ok") continue;
+          console.error(
+            `[fbi-proxy] warning: namespace '${ns.namespace}' failed to load (${ns.state}): ${ns.error ?? ""}`,
+          );
+        }
         return 0;
       }
+      case "explain": {
+        const ex = await explainRoute(
+          String(argv.host),
+          argv.path === undefined ? "/" : String(argv.path),
+          argv.method,
+        );
+        if (argv.output === "json") {
+          console.log(JSON.stringify(ex, null, 2));
+        } else if (argv.output === "yaml") {
+          console.log(YAML.stringify(ex));
+        } else {
+          printExplanation(ex);
+        }
+        return ex.decision === "reject" ? 1 : 0;
+      }
       default:
         console.error(`[fbi-proxy] unknown command '${cmd}'`);
         return 2;